@@ -0,0 +1,69 @@
+/**
+ * GhostFAT C bindings, enabled with the `ffi` feature
+ *
+ * Functions are not re-entrant, and must be called from a single context
+ * (ie. the USB task) or with the USB interrupt masked.
+ */
+
+#ifndef GHOSTFAT_H
+#define GHOSTFAT_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/** Maximum number of files in the file table */
+#define GHOSTFAT_MAX_FILES 15
+
+/** File table entry, names and data must be valid for the program lifetime */
+typedef struct {
+    /** NUL-terminated 8.3 file name */
+    const char *name;
+    /** File data, must be valid for `len` bytes */
+    uint8_t *data;
+    /** File length in bytes */
+    size_t len;
+    /** Allow host writes to the file data */
+    bool writable;
+} ghostfat_file_t;
+
+/** Function status codes */
+typedef enum {
+    GHOSTFAT_OK = 0,
+    GHOSTFAT_HARDWARE_ERROR = -1,
+    GHOSTFAT_WRITE_ERROR = -2,
+    GHOSTFAT_ERASE_ERROR = -3,
+    GHOSTFAT_INVALID_ADDRESS = -4,
+    GHOSTFAT_INVALID_FILES = -5,
+    GHOSTFAT_NOT_INITIALISED = -6,
+    GHOSTFAT_ALREADY_INITIALISED = -7,
+    GHOSTFAT_NOT_READY = -8,
+} ghostfat_status_t;
+
+/** Initialise the file system from a static file table, with `num_blocks` blocks (or sized to fit where zero) */
+ghostfat_status_t ghostfat_init(const ghostfat_file_t *files, size_t count, uint32_t num_blocks);
+
+/** Read the block at `lba`, `len` must be the block size */
+ghostfat_status_t ghostfat_read_block(uint32_t lba, uint8_t *block, size_t len);
+
+/** Write the block at `lba`, `len` must be the block size */
+ghostfat_status_t ghostfat_write_block(uint32_t lba, const uint8_t *block, size_t len);
+
+/** Fetch the number of blocks in the file system, or zero if not initialised */
+uint32_t ghostfat_block_count(void);
+
+/** Fetch the file system block size */
+uint32_t ghostfat_block_size(void);
+
+/** Advance the host session timer, returning true where a host session has timed out */
+bool ghostfat_tick(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
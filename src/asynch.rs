@@ -0,0 +1,313 @@
+//! Async file objects and block device path
+
+use crate::{BlockDevice, BlockDeviceError};
+
+use crate::{FileContent, FileError, GhostFat, Lba};
+use crate::perms::{self, Access};
+use crate::metrics::Area;
+
+/// Async ReadWrite trait for file objects backed by async drivers
+///
+/// As async traits are not object safe these are not stored in the file
+/// table, instead [`File::new_async`](crate::File::new_async) entries
+/// reference files by index into the list passed to
+/// [`GhostFat::read_block_async`] and [`GhostFat::write_block_async`].
+#[allow(async_fn_in_trait)]
+pub trait AsyncDynamicFile<const BLOCK_SIZE: usize = 512> {
+    /// Return the maximum length of the virtual file in bytes
+    fn len(&self) -> usize;
+
+    /// Check whether the virtual file is empty
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read a chunk of the virtual file, returning the read length
+    async fn read_chunk(&mut self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError>;
+
+    /// Write a chunk of the virtual file, returning the write length
+    async fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError>;
+}
+
+/// Async block device trait, for use with async USB mass storage drivers,
+/// see [`AsyncGhostFat`]
+#[allow(async_fn_in_trait)]
+pub trait AsyncBlockDevice {
+    /// Number of bytes per block, setting the size of buffers passed to
+    /// [`AsyncBlockDevice::read_block`] and [`AsyncBlockDevice::write_block`]
+    const BLOCK_BYTES: usize;
+
+    /// Read the block at `lba` into the provided buffer
+    async fn read_block(&mut self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError>;
+
+    /// Write the provided buffer to the block at `lba`
+    async fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError>;
+
+    /// Fetch the maximum valid LBA
+    fn max_lba(&self) -> u32;
+}
+
+/// Async front-end for [`GhostFat`] owning the [`AsyncDynamicFile`]s
+/// referenced by [`File::new_async`](crate::File::new_async) entries,
+/// implementing [`AsyncBlockDevice`]
+pub struct AsyncGhostFat<'a, F, const BLOCK_SIZE: usize = 512, const FILES: usize = 0> {
+    fs: GhostFat<'a, BLOCK_SIZE, FILES>,
+    files: &'a mut [F],
+}
+
+impl <'a, F: AsyncDynamicFile<BLOCK_SIZE>, const BLOCK_SIZE: usize, const FILES: usize> AsyncGhostFat<'a, F, BLOCK_SIZE, FILES> {
+    /// Create a new async front-end over the provided file system and async files
+    pub fn new(fs: GhostFat<'a, BLOCK_SIZE, FILES>, files: &'a mut [F]) -> Self {
+        Self { fs, files }
+    }
+
+    /// Fetch the underlying file system
+    pub fn inner(&self) -> &GhostFat<'a, BLOCK_SIZE, FILES> {
+        &self.fs
+    }
+
+    /// Fetch the underlying file system for modification
+    pub fn inner_mut(&mut self) -> &mut GhostFat<'a, BLOCK_SIZE, FILES> {
+        &mut self.fs
+    }
+
+    /// Consume the front-end, returning the file system and async files
+    pub fn into_inner(self) -> (GhostFat<'a, BLOCK_SIZE, FILES>, &'a mut [F]) {
+        (self.fs, self.files)
+    }
+}
+
+impl <'a, F: AsyncDynamicFile<BLOCK_SIZE>, const BLOCK_SIZE: usize, const FILES: usize> AsyncBlockDevice for AsyncGhostFat<'a, F, BLOCK_SIZE, FILES> {
+    const BLOCK_BYTES: usize = BLOCK_SIZE;
+
+    async fn read_block(&mut self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError> {
+        self.fs.read_block_async(lba, block, self.files).await
+    }
+
+    async fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError> {
+        self.fs.write_block_async(lba, block, self.files).await
+    }
+
+    fn max_lba(&self) -> u32 {
+        BlockDevice::max_lba(&self.fs)
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Read a file system block, awaiting the provided [`AsyncDynamicFile`]s
+    /// for blocks within async files.
+    ///
+    /// All other blocks are served via the synchronous
+    /// [`BlockDevice::read_block`] path
+    pub async fn read_block_async<F: AsyncDynamicFile<BLOCK_SIZE>>(&self, lba: u32, block: &mut [u8], files: &mut [F]) -> Result<(), BlockDeviceError> {
+        let (file, index, offset) = match self.async_chunk(Lba(lba)) {
+            Some(v) => v,
+            None => return BlockDevice::read_block(self, lba, block),
+        };
+        let lba = Lba(lba);
+
+        if let Some(h) = self.fat_files[file].hooks {
+            h.on_read(offset);
+        }
+
+        let r = self.read_async_chunk(lba, index, offset, block, files).await;
+
+        if let Some(m) = self.metrics {
+            m.read(Area::Data, r.is_err());
+        }
+
+        r
+    }
+
+    /// Read a chunk of an async file
+    async fn read_async_chunk<F: AsyncDynamicFile<BLOCK_SIZE>>(&self, lba: Lba, index: usize, offset: usize, block: &mut [u8], files: &mut [F]) -> Result<(), BlockDeviceError> {
+        if block.len() != BLOCK_SIZE {
+            crate::error!("Invalid read length {} from lba: {} (expected {})", block.len(), lba, BLOCK_SIZE);
+            return Err(BlockDeviceError::InvalidAddress);
+        }
+
+        self.pacer.consume(block.len());
+        self.watchdog.access();
+
+        if perms::access(self.config.access_map, lba) == Access::NoAccess {
+            crate::warn!("Attempted read from no-access lba: {}", lba);
+            return Err(BlockDeviceError::InvalidAddress);
+        }
+
+        let f = files.get_mut(index).ok_or(BlockDeviceError::HardwareError)?;
+
+        crate::debug!("Read async file: {} chunk: 0x{:02x}", index, offset);
+
+        // Only the bytes read from the file are written
+        match f.read_chunk(offset, block).await {
+            Ok(0) => {
+                crate::warn!("Empty read from async file: {} chunk: {}", index, offset);
+                block.fill(0);
+            },
+            Ok(n) => if let Some(b) = block.get_mut(n..) {
+                b.fill(0);
+            },
+            Err(e) => {
+                crate::error!("Failed to read async file: {} chunk: {}", index, offset);
+                return Err(e.into());
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Write a file system block, awaiting the provided [`AsyncDynamicFile`]s
+    /// for blocks within async files.
+    ///
+    /// All other blocks are served via the synchronous
+    /// [`BlockDevice::write_block`] path
+    pub async fn write_block_async<F: AsyncDynamicFile<BLOCK_SIZE>>(&mut self, lba: u32, block: &[u8], files: &mut [F]) -> Result<(), BlockDeviceError> {
+        let (file, index, offset) = match self.async_chunk(Lba(lba)) {
+            Some(v) if !block.is_empty() => v,
+            _ => return BlockDevice::write_block(self, lba, block),
+        };
+        let lba = Lba(lba);
+
+        let r = self.write_async_chunk(lba, index, offset, block, files).await;
+
+        if let (Some(h), Ok(_)) = (self.fat_files[file].hooks, &r) {
+            h.on_write(offset, block);
+        }
+
+        if let Some(m) = self.metrics {
+            m.write(Area::Data, r.is_err());
+        }
+
+        r
+    }
+
+    /// Write a chunk of an async file
+    async fn write_async_chunk<F: AsyncDynamicFile<BLOCK_SIZE>>(&mut self, lba: Lba, index: usize, offset: usize, block: &[u8], files: &mut [F]) -> Result<(), BlockDeviceError> {
+        if block.len() != BLOCK_SIZE {
+            crate::error!("Invalid write length {} to lba: {} (expected {})", block.len(), lba, BLOCK_SIZE);
+            return Err(BlockDeviceError::InvalidAddress);
+        }
+
+        self.pacer.consume(block.len());
+        self.watchdog.access();
+
+        if self.config.write_protected {
+            crate::warn!("Attempted write to write-protected volume, lba: {}", lba);
+            return Err(BlockDeviceError::WriteError);
+        }
+
+        if perms::access(self.config.access_map, lba) != Access::ReadWrite {
+            crate::warn!("Attempted write to protected lba: {}", lba);
+            return Err(BlockDeviceError::WriteError);
+        }
+
+        let f = files.get_mut(index).ok_or(BlockDeviceError::HardwareError)?;
+
+        crate::debug!("Write async file: {} block: {}, {} bytes", index, offset, block.len());
+
+        match f.write_chunk(offset, block).await {
+            Ok(0) => {
+                crate::error!("Failed to write async file: {} chunk: {}", index, offset);
+                Err(BlockDeviceError::WriteError)
+            },
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crate::error!("Failed to write async file: {} chunk: {}", index, offset);
+                Err(e.into())
+            },
+        }
+    }
+
+    /// Resolve an LBA to a file table index, async file index and chunk offset
+    fn async_chunk(&self, lba: Lba) -> Option<(usize, usize, usize)> {
+        let section_index = lba.index_from(self.config.start_clusters())?;
+        let (file, offset) = self.locate(section_index)?;
+
+        match &self.fat_files[file].data {
+            FileContent::Async{ index, .. } => Some((file, *index, offset)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BlockDevice, BlockDeviceError};
+
+    use futures::executor::block_on;
+
+    use crate::{Config, File, GhostFat};
+    use super::*;
+
+    struct MemFile([u8; 16]);
+
+    impl AsyncDynamicFile<8> for MemFile {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        async fn read_chunk(&mut self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+            let d = self.0.chunks(8).nth(chunk_index).ok_or(FileError::ReadError)?;
+            buff[..d.len()].copy_from_slice(d);
+            Ok(d.len())
+        }
+
+        async fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+            let d = self.0.chunks_mut(8).nth(chunk_index).ok_or(FileError::WriteError)?;
+            d.copy_from_slice(&data[..d.len()]);
+            Ok(d.len())
+        }
+    }
+
+    #[test]
+    fn async_read_write() {
+        let data = [0xAAu8; 8];
+        let mut f = [
+            File::<8>::new_ro("A.BIN", &data),
+            File::new_async("B.BIN", 0, 16),
+        ];
+        let mut files = [MemFile([0x55; 16])];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        // Sync files are served via the sync path
+        let mut block = [0u8; 8];
+        block_on(fs.read_block_async(lba, &mut block, &mut files)).unwrap();
+        assert_eq!(block, [0xAA; 8]);
+
+        // Async files are awaited
+        block_on(fs.write_block_async(lba + 2, &[0x11; 8], &mut files)).unwrap();
+        block_on(fs.read_block_async(lba + 2, &mut block, &mut files)).unwrap();
+        assert_eq!(block, [0x11; 8]);
+        assert_eq!(&files[0].0[8..], &[0x11; 8]);
+
+        // And cannot be accessed from the sync path
+        assert_eq!(fs.read_block(lba + 1, &mut block), Err(BlockDeviceError::HardwareError));
+    }
+
+    #[test]
+    fn async_block_device() {
+        let data = [0xAAu8; 8];
+        let mut f = [
+            File::<8>::new_ro("A.BIN", &data),
+            File::new_async("B.BIN", 0, 16),
+        ];
+        let mut files = [MemFile([0x55; 16])];
+        let mut dev = AsyncGhostFat::new(GhostFat::new(&mut f, Config::default()), &mut files);
+        let lba = dev.inner().config.start_clusters().0;
+
+        let mut block = [0u8; 8];
+        block_on(AsyncBlockDevice::read_block(&mut dev, lba + 1, &mut block)).unwrap();
+        assert_eq!(block, [0x55; 8]);
+
+        block_on(AsyncBlockDevice::write_block(&mut dev, lba + 1, &[0x11; 8])).unwrap();
+        assert_eq!(AsyncBlockDevice::max_lba(&dev), 7999);
+
+        // Other blocks are served via the sync path
+        block_on(AsyncBlockDevice::read_block(&mut dev, lba, &mut block)).unwrap();
+        assert_eq!(block, [0xAA; 8]);
+
+        let (_fs, files) = dev.into_inner();
+        assert_eq!(&files[0].0[..8], &[0x11; 8]);
+    }
+}
@@ -1,5 +1,5 @@
 
-use packing::Packed;
+use packing::{Packed, PackedSize};
 
 use crate::Config;
 
@@ -97,17 +97,29 @@ impl FatBootBlock {
             filesystem_identifier: [0x20; 8],
         };
 
-        let len = usize::min(fat.oem_info.len() - 1, config.oem_info.as_bytes().len());
+        let len = usize::min(fat.oem_info.len() - 1, config.oem_info.len());
         fat.oem_info[..len].copy_from_slice(&config.oem_info.as_bytes()[..len]);
 
-        let len = usize::min(fat.volume_label.len() - 1, config.volume_label.as_bytes().len());
+        let len = usize::min(fat.volume_label.len() - 1, config.volume_label.len());
         fat.volume_label[..len].copy_from_slice(&config.volume_label.as_bytes()[..len]);
 
-        let len = usize::min(fat.filesystem_identifier.len() - 1, config.filesystem_identifier.as_bytes().len());
+        let len = usize::min(fat.filesystem_identifier.len() - 1, config.filesystem_identifier.len());
         fat.filesystem_identifier[..len].copy_from_slice(&config.filesystem_identifier.as_bytes()[..len]);
 
         crate::debug!("BootBlock: {:?}", fat);
 
         fat
     }
+
+    /// Encode the boot block, for caching and copying to boot sector reads
+    pub fn encode(&self) -> [u8; Self::BYTES] {
+        let mut b = [0u8; Self::BYTES];
+
+        // Packing fixed size fields to an exactly sized buffer does not fail
+        if self.pack(&mut b).is_err() {
+            crate::error!("Failed to encode boot block");
+        }
+
+        b
+    }
 }
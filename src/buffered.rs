@@ -0,0 +1,193 @@
+use crate::{DynamicFile, FileError};
+
+/// Write policy for [`BufferedFile`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature="defmt", derive(defmt::Format))]
+pub enum WritePolicy {
+    /// Pass each write through to the backing store
+    WriteThrough,
+    /// Stage writes in RAM, committing them on [`DynamicFile::flush`]
+    /// or when the staging buffer is full
+    WriteBack,
+}
+
+/// Buffered write wrapper for [`DynamicFile`]s
+///
+/// With [`WritePolicy::WriteBack`] host writes are staged in RAM (up to `N`
+/// blocks, ie. one erase page) and committed to the inner file in ascending
+/// block order as a batch, either when the staging buffer is full or on
+/// [`DynamicFile::flush`] (called by [`GhostFat::flush`](crate::GhostFat::flush)),
+/// rather than forcing a backing store operation per block.
+///
+/// Reads are served from the staging buffer where a write is pending.
+pub struct BufferedFile<F, const N: usize, const BLOCK_SIZE: usize = 512> {
+    inner: F,
+    policy: WritePolicy,
+    staged: [Option<(usize, usize)>; N],
+    buff: [[u8; BLOCK_SIZE]; N],
+}
+
+impl <F: DynamicFile<BLOCK_SIZE>, const N: usize, const BLOCK_SIZE: usize> BufferedFile<F, N, BLOCK_SIZE> {
+    /// Create a new buffered file over the provided file with the provided write policy
+    pub fn new(inner: F, policy: WritePolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            staged: [None; N],
+            buff: [[0u8; BLOCK_SIZE]; N],
+        }
+    }
+
+    /// Fetch the write policy
+    pub fn policy(&self) -> WritePolicy {
+        self.policy
+    }
+
+    /// Fetch the number of blocks awaiting flush
+    pub fn pending(&self) -> usize {
+        self.staged.iter().filter(|s| s.is_some()).count()
+    }
+
+    /// Fetch a reference to the inner file
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Commit staged blocks and return the inner file
+    pub fn free(mut self) -> Result<F, FileError> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Stage a chunk, returning `None` if the staging buffer is full
+    fn stage(&mut self, chunk_index: usize, data: &[u8]) -> Option<usize> {
+        // Re-use the existing slot when re-writing a pending block
+        let slot = self.staged.iter().position(|s| matches!(s, Some((i, _)) if *i == chunk_index))
+            .or_else(|| self.staged.iter().position(|s| s.is_none()))?;
+
+        let len = usize::min(data.len(), BLOCK_SIZE);
+        self.buff[slot][..len].copy_from_slice(&data[..len]);
+        self.staged[slot] = Some((chunk_index, len));
+
+        Some(len)
+    }
+}
+
+impl <F: DynamicFile<BLOCK_SIZE>, const N: usize, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for BufferedFile<F, N, BLOCK_SIZE> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        // Serve pending writes from the staging buffer
+        for (slot, s) in self.staged.iter().enumerate() {
+            if let Some((i, len)) = s {
+                if *i == chunk_index {
+                    let len = usize::min(buff.len(), *len);
+                    buff[..len].copy_from_slice(&self.buff[slot][..len]);
+                    return Ok(len);
+                }
+            }
+        }
+
+        self.inner.read_chunk(chunk_index, buff)
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        if self.policy == WritePolicy::WriteThrough || chunk_index * BLOCK_SIZE >= self.len() {
+            return self.inner.write_chunk(chunk_index, data);
+        }
+
+        if let Some(n) = self.stage(chunk_index, data) {
+            return Ok(n);
+        }
+
+        // Commit the full batch and retry
+        self.flush()?;
+        self.stage(chunk_index, data).ok_or(FileError::NoSpace)
+    }
+
+    fn poll(&mut self) -> bool {
+        self.inner.poll()
+    }
+
+    fn flush(&mut self) -> Result<(), FileError> {
+        // Commit in ascending block order
+        while let Some(slot) = self.staged.iter().enumerate()
+                .filter_map(|(slot, s)| s.map(|(i, _)| (slot, i)))
+                .min_by_key(|(_slot, i)| *i)
+                .map(|(slot, _i)| slot) {
+            let (index, len) = self.staged[slot].unwrap_or_default();
+
+            crate::debug!("Committing buffered block {}", index);
+            self.inner.write_chunk(index, &self.buff[slot][..len])?;
+            self.staged[slot] = None;
+        }
+
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Backend recording write order
+    struct Mem {
+        data: [u8; 32],
+        writes: [usize; 8],
+        count: usize,
+    }
+
+    impl DynamicFile<8> for Mem {
+        fn len(&self) -> usize {
+            self.data.len()
+        }
+
+        fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+            buff[..8].copy_from_slice(&self.data[chunk_index * 8..][..8]);
+            Ok(8)
+        }
+
+        fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+            self.data[chunk_index * 8..][..8].copy_from_slice(&data[..8]);
+            self.writes[self.count] = chunk_index;
+            self.count += 1;
+            Ok(8)
+        }
+    }
+
+    #[test]
+    fn write_back() {
+        let mem = Mem{ data: [0; 32], writes: [0; 8], count: 0 };
+        let mut f = BufferedFile::<_, 2, 8>::new(mem, WritePolicy::WriteBack);
+        let mut buff = [0u8; 8];
+
+        // Writes are staged and readable
+        assert_eq!(f.write_chunk(3, &[3; 8]), Ok(8));
+        assert_eq!(f.write_chunk(1, &[1; 8]), Ok(8));
+        assert_eq!(f.read_chunk(3, &mut buff), Ok(8));
+        assert_eq!(buff, [3; 8]);
+        assert_eq!((f.pending(), f.inner().count), (2, 0));
+
+        // Full buffers are committed as a batch in block order
+        assert_eq!(f.write_chunk(0, &[9; 8]), Ok(8));
+        assert_eq!(&f.inner().writes[..2], &[1, 3]);
+
+        assert_eq!(f.flush(), Ok(()));
+        assert_eq!(f.pending(), 0);
+        assert_eq!(&f.inner().writes[..3], &[1, 3, 0]);
+
+        let mem = f.free().unwrap();
+        assert_eq!(&mem.data[..8], &[9; 8]);
+    }
+
+    #[test]
+    fn write_through() {
+        let mem = Mem{ data: [0; 32], writes: [0; 8], count: 0 };
+        let mut f = BufferedFile::<_, 2, 8>::new(mem, WritePolicy::WriteThrough);
+
+        assert_eq!(f.write_chunk(2, &[2; 8]), Ok(8));
+        assert_eq!((f.pending(), f.inner().count), (0, 1));
+    }
+}
@@ -0,0 +1,183 @@
+use packing::PackedSize;
+
+use crate::{Config, File, GhostFat};
+use crate::dir::DirectoryEntry;
+
+/// Minimum number of clusters in a FAT16 volume, with smaller volumes
+/// detected by hosts as FAT12
+pub(crate) const MIN_CLUSTERS: u32 = 4085;
+
+/// Maximum number of clusters in a FAT16 volume
+const MAX_CLUSTERS: u32 = 65524;
+
+/// Volume layout errors, see [`GhostFatBuilder`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LayoutError {
+    /// File at the provided index does not have a valid 8.3 name
+    InvalidName(usize),
+    /// File at the provided index duplicates the name of an earlier file
+    DuplicateName(usize),
+    /// Files exceed the available root directory entries
+    TooManyFiles { files: usize, entries: usize },
+    /// Files exceed the volume data clusters
+    NoSpace { required: usize, available: usize },
+    /// Volume cluster count is outside FAT16 limits
+    ClusterCount(u32),
+    /// Volume exceeds the 16-bit total sector count
+    TooManyBlocks(u32),
+    /// Files exceed the entries of the attached cluster table
+    ClusterTable { files: usize, entries: usize },
+}
+
+/// Builder for [`GhostFat`] instances, validating the file layout against
+/// the volume configuration
+pub struct GhostFatBuilder<'a, const BLOCK_SIZE: usize = 512> {
+    files: &'a mut [File<'a, BLOCK_SIZE>],
+    config: Config<BLOCK_SIZE>,
+    cluster_table: Option<&'a mut [u32]>,
+}
+
+impl <'a, const BLOCK_SIZE: usize> GhostFatBuilder<'a, BLOCK_SIZE> {
+    /// Create a new builder with the provided files and default configuration
+    pub fn new(files: &'a mut [File<'a, BLOCK_SIZE>]) -> Self {
+        Self { files, config: Config::default(), cluster_table: None }
+    }
+
+    /// Set the volume configuration
+    pub fn with_config(mut self, config: Config<BLOCK_SIZE>) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Attach a cluster table buffer with an entry per file, see
+    /// [`GhostFat::with_cluster_table`]
+    pub fn with_cluster_table(mut self, table: &'a mut [u32]) -> Self {
+        self.cluster_table = Some(table);
+        self
+    }
+
+    /// Validate the file layout against the volume configuration and
+    /// any attached cluster table
+    pub fn validate(&self) -> Result<(), LayoutError> {
+        validate(&self.config, self.files)?;
+
+        match self.cluster_table.as_deref() {
+            Some(t) if t.len() < self.files.len() => Err(LayoutError::ClusterTable { files: self.files.len(), entries: t.len() }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Validate the file layout and build the file system
+    pub fn build(self) -> Result<GhostFat<'a, BLOCK_SIZE>, LayoutError> {
+        self.validate()?;
+
+        let fs = GhostFat::new(self.files, self.config);
+        Ok(match self.cluster_table {
+            Some(t) => fs.with_cluster_table(t),
+            None => fs,
+        })
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize> GhostFat<'a, BLOCK_SIZE> {
+    /// Create a [`GhostFatBuilder`] with the provided files
+    pub fn builder(files: &'a mut [File<'a, BLOCK_SIZE>]) -> GhostFatBuilder<'a, BLOCK_SIZE> {
+        GhostFatBuilder::new(files)
+    }
+}
+
+/// Validate a file layout against the provided configuration
+pub(crate) fn validate<const BLOCK_SIZE: usize>(config: &Config<BLOCK_SIZE>, files: &[File<BLOCK_SIZE>]) -> Result<(), LayoutError> {
+    let entries = file_entries(config);
+    if files.len() > entries {
+        return Err(LayoutError::TooManyFiles { files: files.len(), entries });
+    }
+
+    for (i, f) in files.iter().enumerate() {
+        let name = f.short_name().map_err(|_| LayoutError::InvalidName(i))?;
+        if files[..i].iter().any(|o| o.short_name() == Ok(name)) {
+            return Err(LayoutError::DuplicateName(i));
+        }
+    }
+
+    validate_volume(config, files.iter().map(|f| f.alloc_blocks()).sum())
+}
+
+/// Fetch the number of root directory entries available for files, as
+/// files are listed in the first root directory sector following the volume label
+pub(crate) const fn file_entries<const BLOCK_SIZE: usize>(config: &Config<BLOCK_SIZE>) -> usize {
+    let entries = if config.root_dir_sectors > 0 { BLOCK_SIZE / DirectoryEntry::BYTES } else { 0 };
+    entries.saturating_sub(1)
+}
+
+/// Validate the volume size against FAT16 limits and the `required` file clusters
+pub(crate) const fn validate_volume<const BLOCK_SIZE: usize>(config: &Config<BLOCK_SIZE>, required: usize) -> Result<(), LayoutError> {
+    if config.num_blocks.saturating_sub(2) > u16::MAX as u32 {
+        return Err(LayoutError::TooManyBlocks(config.num_blocks));
+    }
+
+    let clusters = config.data_clusters();
+    if clusters < MIN_CLUSTERS || clusters > MAX_CLUSTERS {
+        return Err(LayoutError::ClusterCount(clusters));
+    }
+
+    if required > clusters as usize {
+        return Err(LayoutError::NoSpace { required, available: clusters as usize });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_layout() {
+        let (a, b) = ([0u8; 8], [0u8; 8]);
+        let mut f = [File::<512>::new_ro("A.TXT", &a), File::new_ro("B.TXT", &b)];
+        assert!(GhostFat::builder(&mut f).build().is_ok());
+
+        // Cluster tables must cover all files
+        let mut f = [File::<512>::new_ro("A.TXT", &a), File::new_ro("B.TXT", &b)];
+        let mut table = [0u32; 1];
+        assert_eq!(GhostFat::builder(&mut f).with_cluster_table(&mut table).validate(), Err(LayoutError::ClusterTable { files: 2, entries: 1 }));
+
+        let mut f = [File::<512>::new_ro("A.TXT", &a), File::new_ro("B.TXT", &b)];
+        let mut table = [0u32; 2];
+        assert!(GhostFat::builder(&mut f).with_cluster_table(&mut table).build().is_ok());
+
+        let mut f = [File::<512>::new_ro("A.TXT", &a), File::new_ro("A.TXT", &b)];
+        assert_eq!(GhostFatBuilder::new(&mut f).validate(), Err(LayoutError::DuplicateName(1)));
+
+        let mut f = [File::<512>::new_ro("ATXTFILE.TEXT", &a)];
+        assert_eq!(GhostFatBuilder::new(&mut f).validate(), Err(LayoutError::InvalidName(0)));
+
+        let names = ["0.TXT", "1.TXT", "2.TXT", "3.TXT", "4.TXT", "5.TXT", "6.TXT", "7.TXT",
+            "8.TXT", "9.TXT", "10.TXT", "11.TXT", "12.TXT", "13.TXT", "14.TXT", "15.TXT"];
+        let mut f = names.map(|n| File::<512>::new_ro(n, &a));
+        assert_eq!(GhostFatBuilder::new(&mut f).validate(), Err(LayoutError::TooManyFiles { files: 16, entries: 15 }));
+
+        let big = [0u8; 2048];
+        let mut config = Config::default();
+        config.num_blocks = 4000;
+        let mut f = [File::<512>::new_ro("A.BIN", &big)];
+        let b = GhostFatBuilder::new(&mut f).with_config(config);
+        assert_eq!(b.validate(), Err(LayoutError::ClusterCount(3963)));
+
+        let mut f = [File::<512>::new_ro("A.BIN", &big).with_reserved(8000 * 512)];
+        assert!(matches!(GhostFatBuilder::new(&mut f).validate(), Err(LayoutError::NoSpace { required: 8000, .. })));
+
+        // Volumes may be sized to fit
+        let mut f = [File::<512>::new_ro("A.BIN", &big).with_reserved(8000 * 512)];
+        let config = Config::for_files(&f, 16);
+        assert_eq!(config.num_blocks - config.start_clusters().0, 8016);
+        assert!(GhostFatBuilder::new(&mut f).with_config(config).validate().is_ok());
+
+        let mut f = [File::<512>::new_ro("A.BIN", &big)];
+        let config = Config::for_files(&f, 16);
+        assert_eq!(config.num_blocks - config.start_clusters().0, MIN_CLUSTERS);
+        assert!(GhostFatBuilder::new(&mut f).with_config(config).validate().is_ok());
+    }
+}
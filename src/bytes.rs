@@ -0,0 +1,68 @@
+use crate::{BlockDevice, BlockDeviceError, GhostFat};
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Fetch the volume size in bytes
+    pub(crate) fn volume_len(&self) -> u64 {
+        self.config.num_blocks as u64 * BLOCK_SIZE as u64
+    }
+
+    /// Read volume bytes from `offset`, spanning blocks as required
+    pub(crate) fn read_bytes(&self, offset: u64, buff: &mut [u8]) -> Result<(), BlockDeviceError> {
+        if offset + buff.len() as u64 > self.volume_len() {
+            return Err(BlockDeviceError::InvalidAddress);
+        }
+
+        let mut block = [0u8; BLOCK_SIZE];
+        let mut n = 0;
+
+        while n < buff.len() {
+            let (lba, o) = Self::split_offset(offset + n as u64);
+            let len = usize::min(BLOCK_SIZE - o, buff.len() - n);
+
+            // Whole blocks are read in place
+            if len == BLOCK_SIZE {
+                self.read_block(lba, &mut buff[n..][..BLOCK_SIZE])?;
+            } else {
+                self.read_block(lba, &mut block)?;
+                buff[n..][..len].copy_from_slice(&block[o..][..len]);
+            }
+
+            n += len;
+        }
+
+        Ok(())
+    }
+
+    /// Write volume bytes from `offset`, reading back partially written
+    /// blocks to merge the provided data
+    pub(crate) fn write_bytes(&mut self, offset: u64, data: &[u8]) -> Result<(), BlockDeviceError> {
+        if offset + data.len() as u64 > self.volume_len() {
+            return Err(BlockDeviceError::InvalidAddress);
+        }
+
+        let mut block = [0u8; BLOCK_SIZE];
+        let mut n = 0;
+
+        while n < data.len() {
+            let (lba, o) = Self::split_offset(offset + n as u64);
+            let len = usize::min(BLOCK_SIZE - o, data.len() - n);
+
+            if len == BLOCK_SIZE {
+                self.write_block(lba, &data[n..][..BLOCK_SIZE])?;
+            } else {
+                self.read_block(lba, &mut block)?;
+                block[o..][..len].copy_from_slice(&data[n..][..len]);
+                self.write_block(lba, &block)?;
+            }
+
+            n += len;
+        }
+
+        Ok(())
+    }
+
+    /// Split a volume byte offset into an LBA and offset within the block
+    fn split_offset(offset: u64) -> (u32, usize) {
+        ((offset / BLOCK_SIZE as u64) as u32, (offset % BLOCK_SIZE as u64) as usize)
+    }
+}
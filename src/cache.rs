@@ -0,0 +1,157 @@
+use core::cell::{Cell, RefCell};
+
+/// Warm cache for sectors read during the host mount sequence, holding
+/// root directory sectors followed by leading FAT sectors
+pub(crate) struct WarmCache<'a> {
+    buff: RefCell<&'a mut [u8]>,
+    dir_sectors: usize,
+    fat_sectors: usize,
+    filled: Cell<usize>,
+}
+
+/// Cached sector types
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Sector {
+    /// Root directory sector
+    Dir(usize),
+    /// FAT sector
+    Fat(usize),
+}
+
+impl <'a> WarmCache<'a> {
+    /// Create a new warm cache over the provided buffer, splitting sectors
+    /// between root directory and FAT entries
+    pub fn new<const BLOCK_SIZE: usize>(buff: &'a mut [u8], root_dir_sectors: usize, sectors_per_fat: usize) -> Self {
+        let sectors = buff.len() / BLOCK_SIZE;
+        let dir_sectors = usize::min(root_dir_sectors, sectors);
+        let fat_sectors = usize::min(sectors - dir_sectors, sectors_per_fat);
+
+        Self {
+            buff: RefCell::new(buff),
+            dir_sectors,
+            fat_sectors,
+            filled: Cell::new(0),
+        }
+    }
+
+    /// Refill the cache, calling the provided functions to generate
+    /// root directory sectors and (contiguous) FAT sectors
+    /// 
+    /// The cache is left invalid if directory generation fails
+    pub fn fill<const BLOCK_SIZE: usize, E>(&self, mut dir: impl FnMut(usize, &mut [u8]) -> Result<(), E>, fat: impl FnOnce(&mut [u8])) -> Result<(), E> {
+        self.filled.set(0);
+
+        let mut b = self.buff.borrow_mut();
+        let (d, f) = b.split_at_mut(self.dir_sectors * BLOCK_SIZE);
+
+        for (i, s) in d.chunks_mut(BLOCK_SIZE).enumerate() {
+            dir(i, s)?;
+        }
+        fat(&mut f[..self.fat_sectors * BLOCK_SIZE]);
+
+        self.filled.set(self.dir_sectors + self.fat_sectors);
+
+        Ok(())
+    }
+
+    /// Incrementally refill the cache, generating the next missing sector
+    /// and returning true while sectors remain to be generated
+    /// 
+    /// Generation stops (leaving remaining sectors uncached) if directory
+    /// generation fails
+    pub fn fill_step<const BLOCK_SIZE: usize, E>(&self, dir: impl FnOnce(usize, &mut [u8]) -> Result<(), E>, fat: impl FnOnce(usize, &mut [u8])) -> Result<bool, E> {
+        let total = self.dir_sectors + self.fat_sectors;
+        let slot = self.filled.get();
+        if slot >= total {
+            return Ok(false);
+        }
+
+        let mut b = self.buff.borrow_mut();
+        let block = &mut b[slot * BLOCK_SIZE..][..BLOCK_SIZE];
+        if slot < self.dir_sectors {
+            dir(slot, block)?;
+        } else {
+            fat(slot - self.dir_sectors, block);
+        }
+
+        self.filled.set(slot + 1);
+        Ok(slot + 1 < total)
+    }
+
+    /// Fetch the number of sectors currently cached
+    #[cfg(test)]
+    pub fn filled(&self) -> usize {
+        self.filled.get()
+    }
+
+    /// Invalidate cached sectors
+    pub fn invalidate(&self) {
+        self.filled.set(0);
+    }
+
+    /// Copy a sector from the cache, returning true on a cache hit
+    pub fn read<const BLOCK_SIZE: usize>(&self, sector: Sector, block: &mut [u8]) -> bool {
+        let slot = match sector {
+            Sector::Dir(i) if i < self.dir_sectors => i,
+            Sector::Fat(i) if i < self.fat_sectors => self.dir_sectors + i,
+            _ => return false,
+        };
+
+        if slot >= self.filled.get() {
+            return false;
+        }
+
+        let b = self.buff.borrow();
+        block[..BLOCK_SIZE].copy_from_slice(&b[slot * BLOCK_SIZE..][..BLOCK_SIZE]);
+
+        true
+    }
+}
+
+/// Generated root directory sector cache, keyed by a fingerprint of file
+/// lengths so entries are only regenerated when files change
+pub(crate) struct DirCache<'a, const BLOCK_SIZE: usize> {
+    block: RefCell<&'a mut [u8; BLOCK_SIZE]>,
+    layout: Cell<Option<u64>>,
+}
+
+impl <'a, const BLOCK_SIZE: usize> DirCache<'a, BLOCK_SIZE> {
+    /// Create a new empty directory cache over the provided buffer
+    pub fn new(block: &'a mut [u8; BLOCK_SIZE]) -> Self {
+        Self {
+            block: RefCell::new(block),
+            layout: Cell::new(None),
+        }
+    }
+
+    /// Invalidate the cached sector, ie. where file names or the volume
+    /// label have changed
+    pub fn invalidate(&self) {
+        self.layout.set(None);
+    }
+
+    /// Regenerate the cached sector if the provided layout fingerprint
+    /// does not match, calling the provided function to generate the sector
+    /// 
+    /// The cache is left invalid if generation fails
+    pub fn fill<E>(&self, layout: u64, generate: impl FnOnce(&mut [u8]) -> Result<(), E>) -> Result<(), E> {
+        if self.layout.get() == Some(layout) {
+            return Ok(());
+        }
+
+        self.layout.set(None);
+        generate(&mut self.block.borrow_mut()[..])?;
+        self.layout.set(Some(layout));
+
+        Ok(())
+    }
+
+    /// Copy the cached sector for the provided layout fingerprint,
+    /// regenerating the sector on a cache miss, see [`DirCache::fill`]
+    pub fn read<E>(&self, layout: u64, block: &mut [u8], generate: impl FnOnce(&mut [u8]) -> Result<(), E>) -> Result<(), E> {
+        self.fill(layout, generate)?;
+        block[..BLOCK_SIZE].copy_from_slice(&self.block.borrow()[..]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use crate::{DynamicFile, FileError};
+
+/// Empty cache slot marker
+const EMPTY: usize = usize::MAX;
+
+/// Block cache wrapper for slow [`DynamicFile`] backends (ie. SPI flash or I2C EEPROM)
+///
+/// Holds up to `N` recently-read blocks in RAM so repeated host reads do not
+/// hit the backing store, with slots replaced in round-robin order. Writes
+/// are passed through to the inner file and invalidate the cached block.
+///
+/// The cache is updated from the block device context, call
+/// [`CachedFile::invalidate`] where firmware modifies the backing store.
+pub struct CachedFile<F, const N: usize, const BLOCK_SIZE: usize = 512> {
+    inner: F,
+    index: [AtomicUsize; N],
+    len: [AtomicUsize; N],
+    data: [[AtomicU8; BLOCK_SIZE]; N],
+    next: AtomicUsize,
+}
+
+impl <F: DynamicFile<BLOCK_SIZE>, const N: usize, const BLOCK_SIZE: usize> CachedFile<F, N, BLOCK_SIZE> {
+    /// Create a new cache over the provided file
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            index: [const { AtomicUsize::new(EMPTY) }; N],
+            len: [const { AtomicUsize::new(0) }; N],
+            data: [const { [const { AtomicU8::new(0) }; BLOCK_SIZE] }; N],
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Fetch a reference to the inner file
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Fetch a mutable reference to the inner file, invalidating the cache
+    pub fn inner_mut(&mut self) -> &mut F {
+        self.invalidate();
+        &mut self.inner
+    }
+
+    /// Invalidate all cached blocks
+    pub fn invalidate(&self) {
+        for i in &self.index {
+            i.store(EMPTY, Ordering::Relaxed);
+        }
+    }
+
+    /// Fetch the number of cached blocks
+    pub fn cached(&self) -> usize {
+        self.index.iter().filter(|i| i.load(Ordering::Relaxed) != EMPTY).count()
+    }
+
+    /// Find the cache slot containing a block
+    fn slot(&self, chunk_index: usize) -> Option<usize> {
+        self.index.iter().position(|i| i.load(Ordering::Relaxed) == chunk_index)
+    }
+}
+
+impl <F: DynamicFile<BLOCK_SIZE>, const N: usize, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for CachedFile<F, N, BLOCK_SIZE> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        // Serve cached blocks
+        if let Some(slot) = self.slot(chunk_index) {
+            let len = usize::min(buff.len(), self.len[slot].load(Ordering::Relaxed));
+            for (b, d) in buff[..len].iter_mut().zip(&self.data[slot]) {
+                *b = d.load(Ordering::Relaxed);
+            }
+            return Ok(len);
+        }
+
+        let len = self.inner.read_chunk(chunk_index, buff)?;
+
+        // Cache complete reads
+        if N > 0 && len <= BLOCK_SIZE {
+            let slot = self.next.load(Ordering::Relaxed) % N;
+            self.next.store(slot + 1, Ordering::Relaxed);
+
+            for (d, b) in self.data[slot].iter().zip(&buff[..len]) {
+                d.store(*b, Ordering::Relaxed);
+            }
+            self.len[slot].store(len, Ordering::Relaxed);
+            self.index[slot].store(chunk_index, Ordering::Relaxed);
+        }
+
+        Ok(len)
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        if let Some(slot) = self.slot(chunk_index) {
+            self.index[slot].store(EMPTY, Ordering::Relaxed);
+        }
+
+        self.inner.write_chunk(chunk_index, data)
+    }
+
+    fn poll(&mut self) -> bool {
+        self.inner.poll()
+    }
+
+    fn flush(&mut self) -> Result<(), FileError> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Backend counting reads
+    struct Slow {
+        data: [u8; 32],
+        reads: AtomicUsize,
+    }
+
+    impl DynamicFile<8> for Slow {
+        fn len(&self) -> usize {
+            self.data.len()
+        }
+
+        fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+            self.reads.store(self.reads.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+            buff[..8].copy_from_slice(&self.data[chunk_index * 8..][..8]);
+            Ok(8)
+        }
+
+        fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+            self.data[chunk_index * 8..][..8].copy_from_slice(&data[..8]);
+            Ok(8)
+        }
+    }
+
+    #[test]
+    fn cache_reads() {
+        let mut f = CachedFile::<_, 2, 8>::new(Slow{ data: [0xAA; 32], reads: AtomicUsize::new(0) });
+        let mut buff = [0u8; 8];
+
+        for i in [0, 1, 0, 1] {
+            assert_eq!(f.read_chunk(i, &mut buff), Ok(8));
+        }
+        assert_eq!(f.inner().reads.load(Ordering::Relaxed), 2);
+        assert_eq!(f.cached(), 2);
+
+        // Writes invalidate cached blocks
+        assert_eq!(f.write_chunk(1, &[0x11; 8]), Ok(8));
+        assert_eq!(f.read_chunk(1, &mut buff), Ok(8));
+        assert_eq!(buff, [0x11; 8]);
+        assert_eq!(f.inner().reads.load(Ordering::Relaxed), 3);
+
+        // Slots are replaced in order
+        assert_eq!(f.read_chunk(2, &mut buff), Ok(8));
+        assert_eq!(f.read_chunk(1, &mut buff), Ok(8));
+        assert_eq!(f.inner().reads.load(Ordering::Relaxed), 4);
+    }
+}
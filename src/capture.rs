@@ -0,0 +1,413 @@
+use packing::{Packed, PackedSize};
+
+use crate::{Cluster, GhostFat, Router, SectorIndex};
+use crate::reassembly::Reassembly;
+use crate::dir::DirectoryEntry;
+use crate::file::Attrs;
+
+/// Long file name entry attributes
+const ATTR_LFN: u8 = 0x0F;
+
+/// Host-created file being captured
+pub(crate) struct Capture {
+    name: [u8; 11],
+    start: Cluster,
+    size: usize,
+    written: usize,
+    next: u32,
+    done: bool,
+}
+
+impl Capture {
+    /// Fetch the number of clusters spanned by the file
+    fn clusters<const BLOCK_SIZE: usize>(&self) -> u32 {
+        self.size.div_ceil(BLOCK_SIZE) as u32
+    }
+
+    /// Fetch the position of a cluster within the file, following host
+    /// chains where reassembly is enabled or assuming contiguous clusters
+    fn position<const BLOCK_SIZE: usize>(&self, r: Option<&Reassembly<BLOCK_SIZE>>, cluster: Cluster) -> Option<u32> {
+        let count = self.clusters::<BLOCK_SIZE>();
+        match r {
+            Some(r) => r.position(self.start, count, cluster),
+            None if cluster >= self.start && cluster.0 < self.start.0 + count => Some(cluster.0 - self.start.0),
+            None => None,
+        }
+    }
+}
+
+/// Check whether a directory entry describes a regular file, rather than
+/// a free, deleted, label, directory, long name or dot entry
+pub(crate) fn is_file_entry(e: &DirectoryEntry) -> bool {
+    !matches!(e.name[0], 0x00 | 0xE5 | b'.')
+        && e.attrs != ATTR_LFN
+        && e.attrs & (Attrs::VOLUME_LABEL | Attrs::SUBDIR).bits() == 0
+}
+
+/// Render an 8.3 directory entry name as `NAME.EXT`
+pub(crate) fn display_name<'b>(short: &[u8; 11], buff: &'b mut [u8; 12]) -> &'b str {
+    let prefix = short[..8].iter().take_while(|c| **c != b' ').count();
+    let ext = short[8..].iter().take_while(|c| **c != b' ').count();
+
+    buff[..prefix].copy_from_slice(&short[..prefix]);
+    let mut n = prefix;
+    if ext > 0 {
+        buff[n] = b'.';
+        buff[n + 1..][..ext].copy_from_slice(&short[8..][..ext]);
+        n += ext + 1;
+    }
+
+    core::str::from_utf8(&buff[..n]).unwrap_or("")
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Attach a [`Router`], delivering files created on the volume by the
+    /// host to the [`HostFileSink`](crate::HostFileSink) for matching routes.
+    ///
+    /// Host root directory writes are parsed to detect new files, with
+    /// subsequent writes to the file clusters delivered to the sink until
+    /// the file size has been received. One file is captured at a time, and
+    /// files are expected to be allocated contiguous clusters unless a
+    /// reassembly buffer is attached via [`GhostFat::with_reassembly`].
+    pub fn with_router(mut self, router: &'a mut Router<'a>) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    /// Detect host-created files in a root directory write
+    pub(crate) fn capture_dir(&mut self, block: &[u8]) {
+        if self.router.is_none() {
+            return;
+        }
+
+        for e in block.chunks_exact(DirectoryEntry::BYTES) {
+            let entry = match DirectoryEntry::unpack(e) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            if !is_file_entry(&entry) || self.fat_files.iter().any(|f| f.short_name().ok() == Some(entry.name)) {
+                continue;
+            }
+
+            // Hosts write a placeholder entry prior to allocating clusters
+            let (start, size) = (Cluster(entry.start_cluster as u32), entry.size as usize);
+            if start < Cluster::FIRST || size == 0 {
+                continue;
+            }
+
+            // Skip device files listed under a new name
+            if self.locate(start.index()).is_some_and(|(i, _)| !self.fat_files[i].deleted) {
+                continue;
+            }
+
+            let router = match self.router.as_mut() {
+                Some(r) => r,
+                None => return,
+            };
+
+            if let Some(c) = &self.capture {
+                if c.name == entry.name && c.start == start && c.size == size {
+                    continue;
+                }
+            }
+
+            let mut buff = [0u8; 12];
+            let name = display_name(&entry.name, &mut buff);
+
+            // Close any incomplete capture prior to starting a new file
+            if let Some(c) = self.capture.take() {
+                if !c.done {
+                    crate::warn!("Host file capture interrupted after {} of {} bytes", c.written, c.size);
+                    let mut b = [0u8; 12];
+                    if let Some(s) = router.route(display_name(&c.name, &mut b)) {
+                        let _ = s.close();
+                    }
+                }
+            }
+
+            let sink = match router.route(name) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            crate::debug!("Capturing host file: {} ({} bytes from cluster {})", name, size, start.0);
+
+            match sink.create(name, size) {
+                Ok(_) => self.capture = Some(Capture{ name: entry.name, start, size, written: 0, next: 0, done: false }),
+                Err(e) => {
+                    crate::warn!("Host file sink rejected file: {}", name);
+                    if let Some(s) = self.status {
+                        s.report(e as u32, format_args!("{} rejected: {:?}", name, e));
+                    }
+                },
+            }
+        }
+
+        // Deliver clusters written ahead of the directory entry
+        self.capture_drain();
+    }
+
+    /// Attach a reassembly buffer, used to order host uploads where
+    /// clusters are written out of order or ahead of the directory entry
+    /// (ie. by macOS).
+    ///
+    /// Host FAT writes are tracked to follow fragmented cluster chains, so
+    /// file data is delivered to the [`HostFileSink`](crate::HostFileSink)
+    /// in order. The last 256 bytes of the buffer track up to 32 chain links,
+    /// with the remainder split into `BLOCK_SIZE + 4` byte cluster slots.
+    /// Where the buffer is exhausted clusters are delivered as they arrive.
+    pub fn with_reassembly(mut self, buff: &'a mut [u8]) -> Self {
+        self.reassembly = Some(Reassembly::new(buff));
+        self
+    }
+
+    /// Track host FAT chain updates for reassembly
+    pub(crate) fn capture_fat(&mut self, index: SectorIndex, block: &[u8]) {
+        let first = index.0 * (BLOCK_SIZE / 2) as u32;
+
+        if let Some(mut r) = self.reassembly.take() {
+            r.update_links(first, block, |c| self.locate(c.index()).is_some());
+            self.reassembly = Some(r);
+        }
+
+        self.capture_drain();
+    }
+
+    /// Deliver a data region write to the captured file, returning true
+    /// if the write was consumed
+    pub(crate) fn capture_write(&mut self, index: SectorIndex, block: &[u8]) -> bool {
+        if self.router.is_none() {
+            return false;
+        }
+
+        let cluster = Cluster::from_index(index);
+        let pos = match &self.capture {
+            Some(c) if !c.done => c.position::<BLOCK_SIZE>(self.reassembly.as_ref(), cluster).map(|p| (p, c.next)),
+            _ => None,
+        };
+
+        let (p, next) = match pos {
+            Some(v) => v,
+            // Buffer clusters that may belong to a file not yet created
+            None if self.locate(index).is_none() => return self.capture_stash(cluster, block),
+            None => return false,
+        };
+
+        // Skip re-written clusters
+        if p < next {
+            crate::debug!("Ignoring re-written upload cluster {}", cluster);
+            return true;
+        }
+
+        // Buffer clusters written ahead of the next position
+        if p > next && self.capture_stash(cluster, block) {
+            return true;
+        }
+
+        self.capture_deliver(p, block);
+        self.capture_drain();
+
+        true
+    }
+
+    /// Buffer an upload cluster, preferring to evict clusters outside
+    /// the captured file
+    fn capture_stash(&mut self, cluster: Cluster, block: &[u8]) -> bool {
+        let r = match self.reassembly.as_mut() {
+            Some(r) => r,
+            None => return false,
+        };
+
+        let slot = r.slot(cluster, |b| match &self.capture {
+            Some(c) if !c.done => c.position::<BLOCK_SIZE>(Some(r), b).is_some(),
+            _ => true,
+        });
+
+        match slot {
+            Some(s) => {
+                r.store(s, cluster, block);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Deliver buffered clusters following the last delivered position
+    pub(crate) fn capture_drain(&mut self) {
+        let mut r = match self.reassembly.take() {
+            Some(r) => r,
+            None => return,
+        };
+
+        while let Some(c) = self.capture.as_ref().filter(|c| !c.done) {
+            let next = c.next;
+            let slot = match r.find(|b| c.position::<BLOCK_SIZE>(Some(&r), b) == Some(next)) {
+                Some((s, _)) => s,
+                None => break,
+            };
+
+            self.capture_deliver(next, r.data(slot));
+            r.release(slot);
+        }
+
+        self.reassembly = Some(r);
+    }
+
+    /// Deliver cluster data at the provided chain position to the sink
+    fn capture_deliver(&mut self, pos: u32, block: &[u8]) {
+        let (c, router) = match (self.capture.as_mut(), self.router.as_mut()) {
+            (Some(c), Some(r)) if !c.done => (c, r),
+            _ => return,
+        };
+
+        let mut buff = [0u8; 12];
+        let sink = match router.route(display_name(&c.name, &mut buff)) {
+            Some(s) => s,
+            None => return,
+        };
+
+        let offset = pos as usize * BLOCK_SIZE;
+        let n = usize::min(block.len(), c.size - offset);
+
+        if pos == c.next {
+            c.next += 1;
+        } else {
+            crate::warn!("Reassembly buffer exhausted, delivering upload cluster {} out of order", pos);
+        }
+
+        match sink.write(offset, &block[..n]) {
+            Ok(w) => c.written += w,
+            Err(e) => {
+                crate::error!("Host file sink write failed at offset {}", offset);
+                if let Some(s) = self.status {
+                    let mut b = [0u8; 12];
+                    s.report(e as u32, format_args!("{} write failed: {:?}", display_name(&c.name, &mut b), e));
+                }
+                c.done = true;
+                return;
+            }
+        }
+
+        if c.written >= c.size {
+            crate::debug!("Host file capture complete, {} bytes", c.written);
+            if sink.close().is_err() {
+                crate::warn!("Host file sink failed to close file");
+            }
+            c.done = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, File, FileError, HostFileSink, Route, ScratchSink};
+    use super::*;
+
+    /// Sink recording write offsets
+    #[derive(Default)]
+    struct OrderSink {
+        offsets: [usize; 4],
+        count: usize,
+        closed: bool,
+    }
+
+    impl HostFileSink for OrderSink {
+        fn create(&mut self, _name: &str, _size: usize) -> Result<(), FileError> {
+            Ok(())
+        }
+
+        fn write(&mut self, offset: usize, data: &[u8]) -> Result<usize, FileError> {
+            self.offsets[self.count] = offset;
+            self.count += 1;
+            Ok(data.len())
+        }
+
+        fn close(&mut self) -> Result<(), FileError> {
+            self.closed = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn names() {
+        let mut b = [0u8; 12];
+        assert_eq!(display_name(b"NOTES   TXT", &mut b), "NOTES.TXT");
+        assert_eq!(display_name(b"README     ", &mut b), "README");
+    }
+
+    #[test]
+    fn capture_files() {
+        let mut scratch = [0u8; 1024];
+        let mut sink = ScratchSink::new(&mut scratch);
+        let mut routes = [Route::new("*.TXT", &mut sink)];
+        let mut router = Router::new(&mut routes);
+
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("INFO.BIN", &data)];
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_router(&mut router);
+        let (rootdir, start) = (fs.config.start_rootdir().0, fs.config.start_clusters().0);
+
+        // Host adds an entry for a new file following the existing files
+        let mut block = [0u8; 512];
+        fs.read_block(rootdir, &mut block).unwrap();
+
+        let mut entry = DirectoryEntry::default();
+        entry.name = *b"NOTES   TXT";
+        entry.start_cluster = 10;
+        entry.size = 600;
+        entry.pack(&mut block[64..96]).unwrap();
+        fs.write_block(rootdir, &block).unwrap();
+
+        // Cluster writes are delivered to the sink
+        fs.write_block(start + 8, &[0x11; 512]).unwrap();
+        fs.write_block(start + 9, &[0x22; 512]).unwrap();
+        drop(fs);
+
+        assert_eq!(sink.name(), "NOTES.TXT");
+        assert_eq!(sink.data().len(), 600);
+        assert_eq!(&sink.data()[510..514], &[0x11, 0x11, 0x22, 0x22]);
+    }
+
+    #[test]
+    fn reassemble_uploads() {
+        let mut sink = OrderSink::default();
+        let mut routes = [Route::new("*", &mut sink)];
+        let mut router = Router::new(&mut routes);
+        let mut buff = [0u8; 256 + 516 * 2];
+
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("INFO.BIN", &data)];
+        let mut fs = GhostFat::new(&mut f, Config::default())
+            .with_router(&mut router)
+            .with_reassembly(&mut buff);
+        let (fat, rootdir, start) = (fs.config.start_fat0().0, fs.config.start_rootdir().0, fs.config.start_clusters().0);
+
+        // Host writes fragmented clusters out of order, ahead of the FAT and directory
+        fs.write_block(start + 12, &[0x33; 512]).unwrap();
+        fs.write_block(start + 8, &[0x11; 512]).unwrap();
+
+        let mut block = [0u8; 512];
+        fs.read_block(fat, &mut block).unwrap();
+        block[20..22].copy_from_slice(&14u16.to_le_bytes());
+        block[28..30].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        fs.write_block(fat, &block).unwrap();
+
+        fs.read_block(rootdir, &mut block).unwrap();
+        let mut entry = DirectoryEntry::default();
+        entry.name = *b"DATA    BIN";
+        entry.start_cluster = 10;
+        entry.size = 1000;
+        entry.pack(&mut block[64..96]).unwrap();
+        fs.write_block(rootdir, &block).unwrap();
+
+        // Then re-writes a buffered cluster
+        fs.write_block(start + 12, &[0x33; 512]).unwrap();
+        drop(fs);
+
+        assert_eq!(&sink.offsets[..sink.count], &[0, 512]);
+        assert!(sink.closed);
+    }
+}
@@ -0,0 +1,79 @@
+use core::ops::Range;
+
+use crate::GhostFat;
+
+/// Host modifications to a file since changes were last taken,
+/// see [`GhostFat::take_changes`]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileChange {
+    /// Index of the file in the file table
+    pub index: usize,
+    /// Range of modified blocks
+    pub blocks: Range<usize>,
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Record a host write to a file block
+    pub(crate) fn track_change(&mut self, index: usize, block_index: usize) {
+        let f = &mut self.fat_files[index];
+        f.dirty = Some(match f.dirty.take() {
+            Some(r) => usize::min(r.start, block_index)..usize::max(r.end, block_index + 1),
+            None => block_index..block_index + 1,
+        });
+
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Fetch the change generation, incremented on each host write to a
+    /// file, ie. to cheaply check for changes before calling
+    /// [`GhostFat::take_changes`]
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Take the files modified by the host since the last call, with the
+    /// range of modified blocks in each file.
+    /// 
+    /// Changes are cleared as the iterator is consumed, so firmware can
+    /// poll for host updates without instrumenting every write via
+    /// [`FileHooks`](crate::FileHooks)
+    pub fn take_changes(&mut self) -> impl Iterator<Item = FileChange> + use<'_, 'a, BLOCK_SIZE, FILES> {
+        self.fat_files.iter_mut().enumerate()
+            .filter_map(|(index, f)| f.dirty.take().map(|blocks| FileChange { index, blocks }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, File};
+    use super::*;
+
+    #[test]
+    fn track_changes() {
+        let (mut a, mut b) = ([0u8; 2048], [0u8; 512]);
+        let mut f = [
+            File::<512>::new("A.BIN", &mut a).unwrap(),
+            File::<512>::new("B.BIN", &mut b).unwrap(),
+        ];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        assert_eq!(fs.take_changes().count(), 0);
+
+        fs.write_block(lba + 3, &[0xAA; 512]).unwrap();
+        fs.write_block(lba + 1, &[0xAA; 512]).unwrap();
+        fs.write_block(lba + 4, &[0xBB; 512]).unwrap();
+        assert_eq!(fs.generation(), 3);
+
+        let mut changes = fs.take_changes();
+        assert_eq!(changes.next(), Some(FileChange { index: 0, blocks: 1..4 }));
+        assert_eq!(changes.next(), Some(FileChange { index: 1, blocks: 0..1 }));
+        assert_eq!(changes.next(), None);
+        drop(changes);
+
+        assert_eq!(fs.take_changes().count(), 0);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{GhostFat, SectorIndex};
+
+/// Boot sector offset of the volume dirty flags
+const BOOT_FLAGS: usize = 37;
+
+/// Bytes covered by the reserved FAT entries (clusters 0 and 1)
+pub(crate) const FAT_RESERVED: usize = 4;
+
+/// Volume state written by host disk checks (chkdsk / fsck), served back
+/// in place of generated values so repairs are not repeated
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DiskCheck {
+    /// Host-written boot sector dirty flags
+    flags: Option<u8>,
+    /// Host-written reserved FAT entries, holding the media descriptor
+    /// and clean shutdown / hard error bits
+    reserved: Option<[u8; FAT_RESERVED]>,
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Accept a host boot sector write, storing the volume dirty flags
+    /// where the write otherwise matches the generated boot sector
+    pub(crate) fn check_boot(&mut self, block: &[u8]) {
+        match self.boot_matches(block) {
+            true => {
+                crate::debug!("Host updated volume flags: 0x{:02x}", block[BOOT_FLAGS]);
+                self.check.flags = Some(block[BOOT_FLAGS]);
+            },
+            false => crate::warn!("Discarding boot sector write"),
+        }
+    }
+
+    /// Check whether a host boot sector write matches the generated boot
+    /// sector, ignoring the volume dirty flags
+    pub(crate) fn boot_matches(&self, block: &[u8]) -> bool {
+        let mut generated = [0u8; BLOCK_SIZE];
+        if self.boot(&mut generated).is_err() {
+            return false;
+        }
+
+        self.boot_matches_with(block, &generated)
+    }
+
+    /// Check whether a host boot sector write matches the provided boot
+    /// sector, ignoring the volume dirty flags
+    pub(crate) fn boot_matches_with(&self, block: &[u8], boot: &[u8]) -> bool {
+        block[..BOOT_FLAGS] == boot[..BOOT_FLAGS]
+            && block[BOOT_FLAGS + 1..] == boot[BOOT_FLAGS + 1..]
+    }
+
+    /// Accept a host FAT write, storing the reserved entries and returning
+    /// true where the remainder of the sector matches the generated FAT
+    pub(crate) fn check_fat(&mut self, section_index: SectorIndex, block: &[u8]) -> bool {
+        let mut generated = [0u8; BLOCK_SIZE];
+        Self::fat_range(section_index.as_usize(), &self.fat_files, &self.clusters, &mut generated);
+
+        let skip = match section_index {
+            SectorIndex(0) => {
+                let mut reserved = [0u8; FAT_RESERVED];
+                reserved.copy_from_slice(&block[..FAT_RESERVED]);
+                self.check.reserved = Some(reserved);
+                FAT_RESERVED
+            },
+            _ => 0,
+        };
+
+        block[skip..] == generated[skip..]
+    }
+
+    /// Check a host root directory write, returning true where the
+    /// sector matches the generated directory
+    pub(crate) fn check_dir(&self, section_index: SectorIndex, block: &[u8]) -> bool {
+        let mut generated = [0u8; BLOCK_SIZE];
+        self.dir(section_index, &mut generated).is_ok() && block == generated
+    }
+
+    /// Apply host-written volume flags to a generated boot sector
+    pub(crate) fn check_boot_read(&self, block: &mut [u8]) {
+        if let Some(f) = self.check.flags {
+            block[BOOT_FLAGS] = f;
+        }
+    }
+
+    /// Apply host-written reserved entries to a generated FAT sector
+    pub(crate) fn check_fat_read(&self, section_index: SectorIndex, block: &mut [u8]) {
+        if let (SectorIndex(0), Some(r)) = (section_index, self.check.reserved) {
+            block[..FAT_RESERVED].copy_from_slice(&r);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, File};
+    use super::*;
+
+    #[test]
+    fn disk_check() {
+        let mut buff = [0u8; 516];
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("INFO.TXT", &data)];
+
+        let mut config = Config::default();
+        config.disk_check = true;
+        let mut fs = GhostFat::new(&mut f, config).with_fat_shadow(&mut buff);
+        let (fat0, fat1, rootdir) = (fs.config.start_fat0().0, fs.config.start_fat1().0, fs.config.start_rootdir().0);
+
+        // Host marks the volume dirty and clears the clean shutdown bit
+        let mut boot = [0u8; 512];
+        fs.read_block(0, &mut boot).unwrap();
+        boot[BOOT_FLAGS] = 0x01;
+        fs.write_block(0, &boot).unwrap();
+
+        let mut fat = [0u8; 512];
+        fs.read_block(fat0, &mut fat).unwrap();
+        fat[3] = 0x7F;
+        fs.write_block(fat0, &fat).unwrap();
+
+        let mut read = [0u8; 512];
+        fs.read_block(0, &mut read).unwrap();
+        assert_eq!(read, boot);
+        fs.read_block(fat1, &mut read).unwrap();
+        assert_eq!(read, fat);
+
+        // Unchanged sectors rewritten by the check are not shadowed
+        fs.read_block(fat0 + 1, &mut read).unwrap();
+        for i in 1..4 {
+            fs.write_block(fat0 + i, &read).unwrap();
+        }
+        fs.read_block(rootdir, &mut read).unwrap();
+        fs.write_block(rootdir, &read).unwrap();
+
+        // Modified sectors are shadowed, with space remaining in the
+        // single sector shadow
+        fat[8..10].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        fs.write_block(fat1, &fat).unwrap();
+        fs.read_block(fat0, &mut read).unwrap();
+        assert_eq!(read, fat);
+
+        // Remounting restores the clean volume
+        fs.remount();
+        fs.read_block(0, &mut read).unwrap();
+        assert_eq!(read[BOOT_FLAGS], 0);
+        fs.read_block(fat0, &mut read).unwrap();
+        assert_eq!(&read[..4], &[0xf0, 0xff, 0xff, 0xff]);
+    }
+}
@@ -0,0 +1,195 @@
+use core::fmt::{self, Write};
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::{File, Hasher};
+use crate::text::SliceWriter;
+
+/// Maximum cached digest length in bytes
+const MAX_DIGEST: usize = 32;
+
+/// Read only trait for files generated from the content of other files in
+/// the file system, such as [`ChecksumFile`]
+pub trait CompanionFile<const BLOCK_SIZE: usize = 512>: Sync + Send {
+    /// Return the length of the generated file in bytes
+    fn len(&self) -> usize;
+
+    /// Check whether the generated file is empty
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Generate file content from the provided byte offset into the buffer,
+    /// returning the generated length
+    fn generate(&self, files: &[File<'_, BLOCK_SIZE>], offset: usize, buff: &mut [u8]) -> usize;
+}
+
+/// Checksum file listing digests of the named sibling files, in the
+/// `md5sum`/`sha256sum` check format (`<hex digest>  <name>` per line)
+///
+/// Digests are computed using the [`Hasher`] `H` on the first read of each
+/// line and cached until [`ChecksumFile::invalidate`] is called, so this
+/// should be called when firmware modifies the listed files. Files missing
+/// from the file system or not readable via the synchronous block device
+/// path are listed with a digest of `-` characters.
+pub struct ChecksumFile<'a, H, const N: usize> {
+    names: [&'a str; N],
+    digest_len: usize,
+    valid: [AtomicBool; N],
+    cache: [[AtomicU8; MAX_DIGEST]; N],
+    _hasher: PhantomData<fn() -> H>,
+}
+
+impl <'a, H: Hasher + Default, const N: usize> ChecksumFile<'a, H, N> {
+    /// Create a new checksum file listing the named files.
+    ///
+    /// Digests longer than 32 bytes are truncated.
+    pub fn new(names: [&'a str; N]) -> Self {
+        let digest_len = H::default().finish().as_ref().len().min(MAX_DIGEST);
+
+        Self {
+            names,
+            digest_len,
+            valid: [const { AtomicBool::new(false) }; N],
+            cache: [const { [const { AtomicU8::new(0) }; MAX_DIGEST] }; N],
+            _hasher: PhantomData,
+        }
+    }
+
+    /// Invalidate cached digests, recomputing them on the next read
+    pub fn invalidate(&self) {
+        for v in &self.valid {
+            v.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Fetch the digest for the file at `index`, computing and caching it if required
+    fn digest<const BLOCK_SIZE: usize>(&self, files: &[File<'_, BLOCK_SIZE>], index: usize) -> Option<[u8; MAX_DIGEST]> {
+        let mut d = [0u8; MAX_DIGEST];
+        let cache = &self.cache[index];
+
+        if !self.valid[index].load(Ordering::Relaxed) {
+            let f = files.iter().find(|f| f.name() == self.names[index])?;
+            let h = f.hash(&mut H::default()).ok()?;
+
+            for (c, b) in cache.iter().zip(h.as_ref()) {
+                c.store(*b, Ordering::Relaxed);
+            }
+            self.valid[index].store(true, Ordering::Relaxed);
+        }
+
+        for (b, c) in d.iter_mut().zip(cache) {
+            *b = c.load(Ordering::Relaxed);
+        }
+
+        Some(d)
+    }
+
+    /// Render checksums as text, computing digests only for lines within the window
+    fn render<const BLOCK_SIZE: usize>(&self, w: &mut dyn Write, files: Option<(&[File<'_, BLOCK_SIZE>], usize, usize)>) -> fmt::Result {
+        let mut pos = 0;
+
+        for (i, name) in self.names.iter().enumerate() {
+            let line_len = self.digest_len * 2 + 2 + name.len() + 1;
+
+            let digest = match files {
+                Some((files, start, end)) if pos < end && pos + line_len > start => self.digest(files, i),
+                _ => Some([0u8; MAX_DIGEST]),
+            };
+
+            match digest {
+                Some(d) => {
+                    for b in &d[..self.digest_len] {
+                        write!(w, "{:02x}", b)?;
+                    }
+                },
+                None => {
+                    for _ in 0..self.digest_len {
+                        w.write_str("--")?;
+                    }
+                },
+            }
+            writeln!(w, "  {}", name)?;
+
+            pos += line_len;
+        }
+
+        Ok(())
+    }
+}
+
+impl <'a, H: Hasher + Default, const N: usize, const BLOCK_SIZE: usize> CompanionFile<BLOCK_SIZE> for ChecksumFile<'a, H, N> {
+    fn len(&self) -> usize {
+        let mut w = SliceWriter::new(0, &mut []);
+        let _ = self.render::<BLOCK_SIZE>(&mut w, None);
+        w.len()
+    }
+
+    fn generate(&self, files: &[File<'_, BLOCK_SIZE>], offset: usize, buff: &mut [u8]) -> usize {
+        let window = (files, offset, offset + buff.len());
+        let mut w = SliceWriter::new(offset, buff);
+        let _ = self.render(&mut w, Some(window));
+        w.written()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, FileContent, GhostFat};
+    use super::*;
+
+    /// Byte sum hasher for testing, counting computations
+    #[derive(Default)]
+    struct Sum(u16);
+
+    static COMPUTED: AtomicU8 = AtomicU8::new(0);
+
+    impl Hasher for Sum {
+        type Output = [u8; 2];
+
+        fn reset(&mut self) {
+            self.0 = 0;
+            COMPUTED.store(COMPUTED.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+        }
+
+        fn update(&mut self, data: &[u8]) {
+            self.0 += data.iter().map(|b| *b as u16).sum::<u16>();
+        }
+
+        fn finish(&mut self) -> Self::Output {
+            let v = self.0;
+            self.0 = 0;
+            v.to_be_bytes()
+        }
+    }
+
+    #[test]
+    fn render_checksums() {
+        let sums = ChecksumFile::<Sum, 2>::new(["A.BIN", "MISSING.BIN"]);
+        let data = [0x01u8; 300];
+        let mut f = [
+            File::<512>::new_ro("A.BIN", &data),
+            File::new("SUMS.TXT", FileContent::Companion(&sums)).unwrap(),
+        ];
+        let fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0 + 1;
+
+        let text = "012c  A.BIN\n----  MISSING.BIN\n";
+        assert_eq!(fs.fat_files[1].len(), text.len());
+
+        COMPUTED.store(0, Ordering::Relaxed);
+        let mut block = [0u8; 512];
+        fs.read_block(lba, &mut block).unwrap();
+        assert_eq!(&block[..text.len()], text.as_bytes());
+
+        // Digests are cached after the first read
+        fs.read_block(lba, &mut block).unwrap();
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 1);
+
+        sums.invalidate();
+        fs.read_block(lba, &mut block).unwrap();
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,158 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{DynamicFile, FileError};
+
+/// Length-preserving cipher used for encrypted file content (ie. AES-XTS
+/// or AES-CTR with the block index as tweak or nonce)
+pub trait Cipher {
+    /// Encrypt a block in place
+    fn encrypt(&self, block_index: usize, data: &mut [u8]) -> Result<(), FileError>;
+
+    /// Decrypt a block in place
+    fn decrypt(&self, block_index: usize, data: &mut [u8]) -> Result<(), FileError>;
+}
+
+/// Encrypted-at-rest wrapper for [`DynamicFile`]s
+///
+/// Content is stored encrypted in the inner file, with each block
+/// decrypted on read and encrypted on write using the [`Cipher`] `C`, so
+/// sensitive data (ie. calibration or key material) is not exposed in
+/// flash dumps.
+///
+/// Files are created locked, with host reads and writes failing until
+/// [`CipherFile::unlock`] is called (ie. once the user is authorized).
+pub struct CipherFile<F, C, const BLOCK_SIZE: usize = 512> {
+    inner: F,
+    cipher: C,
+    unlocked: AtomicBool,
+}
+
+impl <F: DynamicFile<BLOCK_SIZE>, C: Cipher, const BLOCK_SIZE: usize> CipherFile<F, C, BLOCK_SIZE> {
+    /// Create a new locked cipher file over the provided encrypted file
+    pub fn new(inner: F, cipher: C) -> Self {
+        Self {
+            inner,
+            cipher,
+            unlocked: AtomicBool::new(false),
+        }
+    }
+
+    /// Allow host access to decrypted content
+    pub fn unlock(&self) {
+        self.unlocked.store(true, Ordering::Relaxed);
+    }
+
+    /// Prevent host access to decrypted content
+    pub fn lock(&self) {
+        self.unlocked.store(false, Ordering::Relaxed);
+    }
+
+    /// Check whether host access is allowed
+    pub fn is_unlocked(&self) -> bool {
+        self.unlocked.load(Ordering::Relaxed)
+    }
+
+    /// Fetch a reference to the inner (encrypted) file
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+}
+
+impl <F: DynamicFile<BLOCK_SIZE>, C: Cipher + Sync + Send, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for CipherFile<F, C, BLOCK_SIZE> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        if !self.is_unlocked() {
+            crate::warn!("Attempted read of locked file chunk {}", chunk_index);
+            return Err(FileError::ReadError);
+        }
+
+        let n = self.inner.read_chunk(chunk_index, buff)?;
+        self.cipher.decrypt(chunk_index, &mut buff[..n])?;
+
+        Ok(n)
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        if !self.is_unlocked() {
+            crate::warn!("Attempted write to locked file chunk {}", chunk_index);
+            return Err(FileError::WriteError);
+        }
+
+        let mut block = [0u8; BLOCK_SIZE];
+        let len = usize::min(data.len(), BLOCK_SIZE);
+        block[..len].copy_from_slice(&data[..len]);
+        self.cipher.encrypt(chunk_index, &mut block[..len])?;
+
+        self.inner.write_chunk(chunk_index, &block[..len])
+    }
+
+    fn poll(&mut self) -> bool {
+        self.inner.poll()
+    }
+
+    fn flush(&mut self) -> Result<(), FileError> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Block index keyed XOR cipher for testing
+    struct Xor(u8);
+
+    impl Cipher for Xor {
+        fn encrypt(&self, block_index: usize, data: &mut [u8]) -> Result<(), FileError> {
+            data.iter_mut().for_each(|b| *b ^= self.0 ^ block_index as u8);
+            Ok(())
+        }
+
+        fn decrypt(&self, block_index: usize, data: &mut [u8]) -> Result<(), FileError> {
+            self.encrypt(block_index, data)
+        }
+    }
+
+    /// Plain storage backend
+    struct Mem([u8; 16]);
+
+    impl DynamicFile<8> for Mem {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+            buff[..8].copy_from_slice(&self.0[chunk_index * 8..][..8]);
+            Ok(8)
+        }
+
+        fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+            self.0[chunk_index * 8..][..8].copy_from_slice(&data[..8]);
+            Ok(8)
+        }
+    }
+
+    #[test]
+    fn encrypt_blocks() {
+        let mut f = CipherFile::<_, _, 8>::new(Mem([0; 16]), Xor(0xA0));
+        let mut buff = [0u8; 8];
+
+        // Locked files are not accessible
+        assert_eq!(f.read_chunk(0, &mut buff), Err(FileError::ReadError));
+        assert_eq!(f.write_chunk(0, &[0; 8]), Err(FileError::WriteError));
+
+        // Content is stored encrypted and read decrypted
+        f.unlock();
+        assert_eq!(f.write_chunk(1, &[0x0F; 8]), Ok(8));
+        assert_eq!(&f.inner().0[8..], &[0xAE; 8]);
+
+        assert_eq!(f.read_chunk(1, &mut buff), Ok(8));
+        assert_eq!(buff, [0x0F; 8]);
+
+        f.lock();
+        assert_eq!(f.read_chunk(1, &mut buff), Err(FileError::ReadError));
+    }
+}
@@ -0,0 +1,236 @@
+use core::ops::Range;
+
+use crate::{Cluster, File, GhostFat, SectorIndex};
+
+/// FAT16 media descriptor entry (cluster 0)
+const FAT_MEDIA: u16 = 0xFFF0;
+
+/// FAT16 end of chain marker, also used for the reserved cluster 1
+const END_OF_CHAIN: u16 = 0xFFFF;
+
+/// Cumulative cluster allocation table, mapping data region sectors to
+/// files without walking the file table on each access.
+///
+/// Allocations (including reserved clusters) are captured in the attached
+/// buffer when the table is updated, so files keep their position until the
+/// volume is remounted. Without a buffer (or where the buffer has fewer
+/// entries than files) allocations are computed by walking the file table.
+#[derive(Default)]
+pub(crate) struct ClusterTable<'a> {
+    /// End (exclusive) data region sector allocated to each file
+    ends: Option<&'a mut [u32]>,
+}
+
+impl <'a> ClusterTable<'a> {
+    /// Create a table over the provided buffer, capturing the allocated
+    /// size of each file
+    pub fn new<const BLOCK_SIZE: usize>(ends: &'a mut [u32], files: &[File<BLOCK_SIZE>]) -> Self {
+        let mut t = Self { ends: Some(ends) };
+        t.update(files);
+        t
+    }
+
+    /// Capture the allocated size of each file, ie. on remount
+    pub fn update<const BLOCK_SIZE: usize>(&mut self, files: &[File<BLOCK_SIZE>]) {
+        if let Some(ends) = self.ends.as_deref_mut() {
+            let mut end = 0u32;
+            for (e, f) in ends.iter_mut().zip(files) {
+                end = end.saturating_add(f.alloc_blocks() as u32);
+                *e = end;
+            }
+        }
+    }
+
+    /// Fetch the captured allocations, where the table covers all files
+    fn ends<const BLOCK_SIZE: usize>(&self, files: &[File<BLOCK_SIZE>]) -> Option<&[u32]> {
+        self.ends.as_deref().and_then(|e| e.get(..files.len()))
+    }
+
+    /// Fetch the end (exclusive) data region sector allocated to the file at `index`
+    fn end<const BLOCK_SIZE: usize>(&self, files: &[File<BLOCK_SIZE>], index: usize) -> u32 {
+        match self.ends(files) {
+            Some(ends) => ends[index],
+            None => files[..=index].iter().fold(0u32, |e, f| e.saturating_add(f.alloc_blocks() as u32)),
+        }
+    }
+
+    /// Fetch the data region sectors allocated to the file at `index`
+    pub fn sectors<const BLOCK_SIZE: usize>(&self, files: &[File<BLOCK_SIZE>], index: usize) -> Range<u32> {
+        let start = match index {
+            0 => 0,
+            _ => self.end(files, index - 1),
+        };
+
+        start..self.end(files, index)
+    }
+
+    /// Fetch the first cluster allocated to the file at `index`
+    pub fn start<const BLOCK_SIZE: usize>(&self, files: &[File<BLOCK_SIZE>], index: usize) -> Cluster {
+        Cluster(Cluster::FIRST.0 + self.sectors(files, index).start)
+    }
+
+    /// Locate the file index and chunk offset for a data region sector
+    pub fn locate<const BLOCK_SIZE: usize>(&self, files: &[File<BLOCK_SIZE>], section_index: SectorIndex) -> Option<(usize, usize)> {
+        self.find(files, section_index.0).map(|i| (i, (section_index.0 - self.sectors(files, i).start) as usize))
+    }
+
+    /// Find the index of the first file with allocations at or following
+    /// the provided data region sector
+    pub fn first_from<const BLOCK_SIZE: usize>(&self, files: &[File<BLOCK_SIZE>], sector: u32) -> usize {
+        match self.ends(files) {
+            Some(ends) => ends.partition_point(|e| *e <= sector),
+            None => {
+                let mut end = 0u32;
+                files.iter()
+                    .position(|f| {
+                        end = end.saturating_add(f.alloc_blocks() as u32);
+                        end > sector
+                    })
+                    .unwrap_or(files.len())
+            },
+        }
+    }
+
+    /// Find the index of the file allocated the provided data region sector
+    fn find<const BLOCK_SIZE: usize>(&self, files: &[File<BLOCK_SIZE>], sector: u32) -> Option<usize> {
+        Some(self.first_from(files, sector)).filter(|i| *i < files.len())
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Attach a cluster table buffer with an entry per file, capturing the
+    /// clusters allocated to each file so data region accesses and FAT
+    /// generation locate files by binary search rather than walking the
+    /// file table on each access.
+    ///
+    /// Allocations are captured until the next [`GhostFat::remount`], so
+    /// files growing beyond their allocation do not move following files.
+    /// Buffers with fewer entries than files are not attached, see
+    /// [`GhostFatBuilder::with_cluster_table`](crate::GhostFatBuilder::with_cluster_table)
+    /// to reject these when validating the layout.
+    pub fn with_cluster_table(mut self, table: &'a mut [u32]) -> Self {
+        if table.len() < self.fat_files.len() {
+            crate::warn!("Cluster table of {} entries too small for {} files", table.len(), self.fat_files.len());
+            return self;
+        }
+
+        self.clusters = ClusterTable::new(table, &self.fat_files);
+        self
+    }
+}
+
+/// Incremental FAT16 entry generator, yielding consecutive entries from
+/// a start cluster with each entry derived from the cluster table.
+///
+/// Chains follow the live file length, clamped to the allocation captured
+/// in the table so growing files never overlap following files, with
+/// reserved clusters beyond the chain left free.
+pub(crate) struct FatEntries<'f, 'a, const BLOCK_SIZE: usize> {
+    files: &'f [File<'a, BLOCK_SIZE>],
+    clusters: &'f ClusterTable<'f>,
+    cluster: u32,
+    index: usize,
+}
+
+impl <'f, 'a, const BLOCK_SIZE: usize> FatEntries<'f, 'a, BLOCK_SIZE> {
+    /// Create a generator yielding entries from the provided cluster
+    pub fn new(files: &'f [File<'a, BLOCK_SIZE>], clusters: &'f ClusterTable<'f>, cluster: u32) -> Self {
+        let index = clusters.first_from(files, cluster.saturating_sub(Cluster::FIRST.0));
+        Self { files, clusters, cluster, index }
+    }
+
+    /// Compute the entry for the current cluster
+    fn entry(&mut self) -> u16 {
+        let sector = match self.cluster.checked_sub(Cluster::FIRST.0) {
+            Some(s) => s,
+            None if self.cluster == 0 => return FAT_MEDIA,
+            None => return END_OF_CHAIN,
+        };
+
+        // Advance past files allocated prior to this cluster
+        while self.index < self.files.len() && self.clusters.sectors(self.files, self.index).end <= sector {
+            self.index += 1;
+        }
+
+        let (r, f) = match self.files.get(self.index) {
+            Some(f) => (self.clusters.sectors(self.files, self.index), f),
+            None => return 0,
+        };
+
+        let chain = usize::min(f.num_blocks(), r.len()) as u32;
+        match sector - r.start {
+            o if o + 1 < chain => (self.cluster + 1) as u16,
+            o if o + 1 == chain => END_OF_CHAIN,
+            _ => 0,
+        }
+    }
+}
+
+impl <'f, 'a, const BLOCK_SIZE: usize> Iterator for FatEntries<'f, 'a, BLOCK_SIZE> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        let e = self.entry();
+        self.cluster += 1;
+        Some(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cluster_lookup() {
+        let data = [0u8; 20];
+        let files = [
+            File::<8>::new_ro("A.BIN", &data),
+            File::new_ro("EMPTY.BIN", &[]),
+            File::new_ro("B.BIN", &data[..8]).with_reserved(24),
+        ];
+        let (mut ends, mut short) = ([0u32; 3], [0u32; 2]);
+
+        // Captured tables match walking the file table, with short tables unused
+        for table in [ClusterTable::new(&mut ends, &files), ClusterTable::default(), ClusterTable::new(&mut short, &files)] {
+            assert_eq!((table.sectors(&files, 0), table.sectors(&files, 1), table.sectors(&files, 2)), (0..3, 3..3, 3..6));
+            assert_eq!(table.start(&files, 2), Cluster(5));
+
+            assert_eq!(table.locate(&files, SectorIndex(0)), Some((0, 0)));
+            assert_eq!(table.locate(&files, SectorIndex(2)), Some((0, 2)));
+            assert_eq!(table.locate(&files, SectorIndex(3)), Some((2, 0)));
+            assert_eq!(table.locate(&files, SectorIndex(5)), Some((2, 2)));
+            assert_eq!(table.locate(&files, SectorIndex(6)), None);
+        }
+        assert_eq!(ends, [3, 3, 6]);
+    }
+
+    #[test]
+    fn fat_entries() {
+        let data = [0u8; 40];
+        let files = [
+            File::<8>::new_ro("A.BIN", &data[..16]),
+            File::new_ro("B.BIN", &data[..8]).with_reserved(24),
+            File::new_ro("C.BIN", &data),
+        ];
+        let mut ends = [0u32; 3];
+        let table = ClusterTable::new(&mut ends, &files);
+
+        let entries: [u16; 15] = core::array::from_fn({
+            let mut g = FatEntries::new(&files, &table, 0);
+            move |_| g.next().unwrap()
+        });
+        assert_eq!(entries, [
+            0xFFF0, 0xFFFF,
+            3, 0xFFFF,
+            0xFFFF, 0, 0,
+            8, 9, 10, 11, 0xFFFF,
+            0, 0, 0,
+        ]);
+
+        // Generation from any cluster matches generation from the start
+        for (start, e) in entries.iter().enumerate() {
+            let mut g = FatEntries::new(&files, &table, start as u32);
+            assert_eq!(g.next(), Some(*e), "cluster {}", start);
+        }
+    }
+}
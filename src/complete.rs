@@ -0,0 +1,141 @@
+use packing::{Packed, PackedSize};
+
+use crate::GhostFat;
+use crate::dir::DirectoryEntry;
+
+/// Host write to a device file being tracked for completion
+pub(crate) struct Completion {
+    index: usize,
+    size: Option<usize>,
+    blocks: usize,
+    done: bool,
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Track a host write to a device file, detecting completion once the
+    /// final block of the committed size has been written
+    pub(crate) fn complete_write(&mut self, index: usize, offset: usize) {
+        match &mut self.completion {
+            Some(c) if c.index == index && !c.done => {
+                c.blocks = usize::max(c.blocks, offset + 1);
+            },
+            _ => {
+                crate::debug!("Tracking host write to file: {}", self.fat_files[index].name());
+                self.completion = Some(Completion { index, size: None, blocks: offset + 1, done: false });
+            },
+        }
+
+        self.complete_check(false);
+    }
+
+    /// Update the committed size of the tracked file from a host root
+    /// directory write
+    pub(crate) fn complete_dir(&mut self, block: &[u8]) {
+        let c = match &mut self.completion {
+            Some(c) if !c.done => c,
+            _ => return,
+        };
+
+        let f = &self.fat_files[c.index];
+        let name = match f.renamed.map(Ok).unwrap_or_else(|| f.short_name()) {
+            Ok(n) => n,
+            Err(_) => return,
+        };
+
+        let entry = block.chunks_exact(DirectoryEntry::BYTES)
+            .filter_map(|e| DirectoryEntry::unpack(e).ok())
+            .find(|e| e.name == name);
+
+        if let Some(e) = entry {
+            c.size = Some(e.size as usize);
+            self.complete_check(false);
+        }
+    }
+
+    /// Complete any tracked write on host idle timeout, where the size
+    /// may not have been committed
+    pub(crate) fn complete_timeout(&mut self) {
+        self.complete_check(true);
+    }
+
+    /// Check the tracked write for completion, calling
+    /// [`FileHooks::on_file_complete`](crate::FileHooks::on_file_complete)
+    fn complete_check(&mut self, idle: bool) {
+        let c = match &mut self.completion {
+            Some(c) if !c.done => c,
+            _ => return,
+        };
+
+        let len = match c.size {
+            Some(s) if c.blocks * BLOCK_SIZE >= s => s,
+            Some(s) if idle => usize::min(s, c.blocks * BLOCK_SIZE),
+            None if idle => c.blocks * BLOCK_SIZE,
+            _ => return,
+        };
+
+        c.done = true;
+
+        let f = &self.fat_files[c.index];
+        crate::debug!("Host write complete: {} ({} bytes)", f.name(), len);
+
+        if let Some(h) = f.hooks {
+            h.on_file_complete(f.name(), len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::BlockDevice;
+
+    use crate::{Config, File, FileHooks};
+    use super::*;
+
+    /// Hooks recording completed lengths
+    #[derive(Default)]
+    struct Complete(AtomicUsize);
+
+    impl FileHooks for Complete {
+        fn on_file_complete(&self, name: &str, len: usize) {
+            assert_eq!(name, "FW.BIN");
+            self.0.store(len, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn complete_files() {
+        let hooks = Complete::default();
+        let mut data = [0u8; 2048];
+        let mut f = [File::<512>::new("FW.BIN", &mut data).unwrap().with_hooks(&hooks)];
+
+        let mut config = Config::default();
+        config.host_timeout = Some(2);
+        let mut fs = GhostFat::new(&mut f, config);
+        let (rootdir, lba) = (fs.config.start_rootdir().0, fs.config.start_clusters().0);
+
+        // Host writes data then commits the size
+        fs.write_block(lba, &[0xAA; 512]).unwrap();
+        fs.write_block(lba + 1, &[0xAA; 512]).unwrap();
+        assert_eq!(hooks.0.load(Ordering::Relaxed), 0);
+
+        let mut block = [0u8; 512];
+        fs.read_block(rootdir, &mut block).unwrap();
+        block[60..64].copy_from_slice(&700u32.to_le_bytes());
+        fs.write_block(rootdir, &block).unwrap();
+        assert_eq!(hooks.0.load(Ordering::Relaxed), 700);
+
+        // Uncommitted writes complete on idle timeout
+        fs.remount();
+        fs.write_block(lba, &[0xAA; 512]).unwrap();
+        assert!(!fs.tick());
+        assert!(fs.tick());
+        assert_eq!(hooks.0.load(Ordering::Relaxed), 512);
+
+        // Or on eject
+        fs.write_block(lba + 2, &[0xAA; 512]).unwrap();
+        fs.eject().unwrap();
+        assert_eq!(hooks.0.load(Ordering::Relaxed), 1536);
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{DynamicFile, FileError, GeneratedFile};
+
+/// Compression codec used for compressed file content.
+/// 
+/// LZ4 and heatshrink implementations are provided behind the `lz4_flex`
+/// and `heatshrink` features.
+pub trait Codec {
+    /// Compress input into the output buffer, returning the compressed length
+    fn compress(&self, input: &[u8], output: &mut [u8]) -> Option<usize>;
+
+    /// Decompress input into the output buffer, returning the decompressed length
+    fn decompress(&self, input: &[u8], output: &mut [u8]) -> Option<usize>;
+}
+
+/// Compressed read-only file, decompressed on read.
+/// 
+/// Content is stored as a sequence of independently compressed `CHUNK` byte
+/// chunks so any block can be decompressed without decoding prior data,
+/// `index` contains the end offset of each compressed chunk in `data`.
+/// See [`compress_chunks`] to generate compressed content.
+pub struct CompressedFile<'a, C, const CHUNK: usize = 512> {
+    codec: C,
+    data: &'a [u8],
+    index: &'a [u32],
+    len: usize,
+}
+
+impl <'a, C: Codec, const CHUNK: usize> CompressedFile<'a, C, CHUNK> {
+    /// Create a new compressed file with the provided codec, compressed
+    /// data and chunk index, and uncompressed length
+    pub const fn new(codec: C, data: &'a [u8], index: &'a [u32], len: usize) -> Self {
+        Self { codec, data, index, len }
+    }
+
+    /// Decompress a chunk into the provided buffer
+    fn decompress_chunk(&self, chunk: usize, buff: &mut [u8; CHUNK]) -> Option<usize> {
+        let start = match chunk {
+            0 => 0,
+            _ => *self.index.get(chunk - 1)? as usize,
+        };
+        let end = *self.index.get(chunk)? as usize;
+
+        self.codec.decompress(self.data.get(start..end)?, buff)
+    }
+}
+
+impl <'a, C: Codec + Sync + Send, const CHUNK: usize> GeneratedFile for CompressedFile<'a, C, CHUNK> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn generate(&self, mut offset: usize, buff: &mut [u8]) -> usize {
+        let mut chunk = [0u8; CHUNK];
+        let mut index = 0;
+
+        while index < buff.len() && offset < self.len {
+            let n = match self.decompress_chunk(offset / CHUNK, &mut chunk) {
+                Some(n) => n,
+                None => {
+                    crate::warn!("Failed to decompress chunk {}", offset / CHUNK);
+                    break;
+                }
+            };
+
+            // Copy the requested window from the chunk
+            let o = offset % CHUNK;
+            if o >= n {
+                break;
+            }
+            let len = usize::min(n - o, buff.len() - index);
+            buff[index..][..len].copy_from_slice(&chunk[o..][..len]);
+
+            index += len;
+            offset += len;
+        }
+
+        index
+    }
+}
+
+/// Writable file compressing host writes into a (smaller) backing region.
+/// 
+/// The file presents `N` blocks to the host, each block is compressed on write
+/// and appended to the backing region, with superseded blocks reclaimed by
+/// compacting the region when full. Blocks that do not compress are stored
+/// raw, and blocks never written read as zeros. Writes fail once the
+/// compressed content no longer fits the backing region.
+pub struct CompressingFile<'a, C, const N: usize, const BLOCK_SIZE: usize = 512> {
+    codec: C,
+    backing: &'a mut [u8],
+    entries: [Entry; N],
+}
+
+/// Location of a compressed block within the backing region
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Entry {
+    offset: u32,
+    len: u16,
+    raw: bool,
+}
+
+impl <'a, C: Codec, const N: usize, const BLOCK_SIZE: usize> CompressingFile<'a, C, N, BLOCK_SIZE> {
+    /// Create a new empty compressing file using the provided codec and backing region
+    pub fn new(codec: C, backing: &'a mut [u8]) -> Self {
+        Self { codec, backing, entries: [Entry::default(); N] }
+    }
+
+    /// Fetch the number of backing bytes used by stored blocks
+    pub fn stored(&self) -> usize {
+        self.entries.iter().map(|e| e.len as usize).sum()
+    }
+
+    /// Fetch the end of used space in the backing region
+    fn end(&self) -> usize {
+        self.entries.iter().map(|e| e.offset as usize + e.len as usize).max().unwrap_or(0)
+    }
+
+    /// Compact stored blocks to the start of the backing region
+    pub fn compact(&mut self) {
+        while self.compact_step() {}
+    }
+
+    /// Move the first block following free space down, returning true
+    /// while the backing region remains fragmented
+    fn compact_step(&mut self) -> bool {
+        let mut end = 0;
+
+        // Find the first gap in offset order, as blocks do not overlap
+        // each move only overwrites reclaimed space
+        while let Some(i) = self.entries.iter().enumerate()
+                .filter(|(_i, e)| e.len > 0 && e.offset as usize >= end)
+                .min_by_key(|(_i, e)| e.offset)
+                .map(|(i, _e)| i) {
+
+            let e = &mut self.entries[i];
+            let (offset, len) = (e.offset as usize, e.len as usize);
+            if offset != end {
+                self.backing.copy_within(offset..offset + len, end);
+                e.offset = end as u32;
+                return self.end() > self.stored();
+            }
+            end += len;
+        }
+
+        false
+    }
+}
+
+impl <'a, C, const N: usize, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for CompressingFile<'a, C, N, BLOCK_SIZE>
+where
+    C: Codec + Sync + Send,
+{
+    fn len(&self) -> usize {
+        N * BLOCK_SIZE
+    }
+
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        let e = match self.entries.get(chunk_index) {
+            Some(e) => e,
+            None => return Ok(0),
+        };
+
+        let len = usize::min(buff.len(), BLOCK_SIZE);
+        let data = &self.backing[e.offset as usize..][..e.len as usize];
+
+        // Unwritten blocks read as zeros
+        if e.len == 0 {
+            buff[..len].fill(0);
+            return Ok(len);
+        }
+
+        if e.raw {
+            buff[..len].copy_from_slice(&data[..len]);
+            return Ok(len);
+        }
+
+        let mut block = [0u8; BLOCK_SIZE];
+        match self.codec.decompress(data, &mut block) {
+            Some(_n) => {
+                buff[..len].copy_from_slice(&block[..len]);
+                Ok(len)
+            },
+            None => {
+                crate::warn!("Failed to decompress block {}", chunk_index);
+                Err(FileError::ReadError)
+            },
+        }
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        if chunk_index >= N {
+            return Ok(0);
+        }
+
+        // Pad partial writes to a full block
+        let mut block = [0u8; BLOCK_SIZE];
+        let len = usize::min(data.len(), BLOCK_SIZE);
+        block[..len].copy_from_slice(&data[..len]);
+
+        // Compress, falling back to raw storage where data does not compress
+        let mut compressed = [0u8; BLOCK_SIZE];
+        let (d, raw) = match self.codec.compress(&block, &mut compressed) {
+            Some(n) if n < BLOCK_SIZE => (&compressed[..n], false),
+            _ => (&block[..], true),
+        };
+
+        // Check the block will fit, retaining the previous copy if not
+        let previous = self.entries[chunk_index].len as usize;
+        if self.stored() - previous + d.len() > self.backing.len() {
+            crate::warn!("Compressed file full, failed to store block {}", chunk_index);
+            return Err(FileError::NoSpace);
+        }
+
+        // Release the previous copy of the block, compacting if required
+        self.entries[chunk_index] = Entry::default();
+        if self.end() + d.len() > self.backing.len() {
+            self.compact();
+        }
+
+        let offset = self.end();
+
+        self.backing[offset..][..d.len()].copy_from_slice(d);
+        self.entries[chunk_index] = Entry{ offset: offset as u32, len: d.len() as u16, raw };
+
+        Ok(len)
+    }
+
+    fn poll(&mut self) -> bool {
+        self.compact_step()
+    }
+}
+
+/// Compress input into independent `chunk` byte chunks for use with
+/// [`CompressedFile`], writing compressed data and chunk end offsets into
+/// the provided buffers and returning the compressed data length
+pub fn compress_chunks<C: Codec>(codec: &C, chunk: usize, input: &[u8], data: &mut [u8], index: &mut [u32]) -> Option<usize> {
+    let mut len = 0;
+
+    for (i, c) in input.chunks(chunk).enumerate() {
+        len += codec.compress(c, &mut data[len..])?;
+        *index.get_mut(i)? = len as u32;
+    }
+
+    Some(len)
+}
+
+/// LZ4 block [`Codec`]
+#[cfg(feature = "lz4_flex")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lz4;
+
+#[cfg(feature = "lz4_flex")]
+impl Codec for Lz4 {
+    fn compress(&self, input: &[u8], output: &mut [u8]) -> Option<usize> {
+        lz4_flex::block::compress_into(input, output).ok()
+    }
+
+    fn decompress(&self, input: &[u8], output: &mut [u8]) -> Option<usize> {
+        lz4_flex::block::decompress_into(input, output).ok()
+    }
+}
+
+/// Heatshrink [`Codec`]
+#[cfg(feature = "heatshrink")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Heatshrink(pub heatshrink::Config);
+
+#[cfg(feature = "heatshrink")]
+impl Codec for Heatshrink {
+    fn compress(&self, input: &[u8], output: &mut [u8]) -> Option<usize> {
+        heatshrink::encode(input, output, &self.0).ok().map(|d| d.len())
+    }
+
+    fn decompress(&self, input: &[u8], output: &mut [u8]) -> Option<usize> {
+        let len = output.len();
+        match heatshrink::decode(input, output, &self.0) {
+            Ok(d) => Some(d.len()),
+            // The decoder reports a full output even when the data exactly fits
+            Err(heatshrink::DecodeError::OutputFull) => Some(len),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{File, FileContent};
+    use super::*;
+
+    /// Simple run-length codec for testing
+    struct Rle;
+
+    impl Codec for Rle {
+        fn compress(&self, input: &[u8], output: &mut [u8]) -> Option<usize> {
+            let mut n = 0;
+            for r in input.chunk_by(|a, b| a == b) {
+                for c in r.chunks(255) {
+                    output.get_mut(n..n + 2)?.copy_from_slice(&[c.len() as u8, c[0]]);
+                    n += 2;
+                }
+            }
+            Some(n)
+        }
+
+        fn decompress(&self, input: &[u8], output: &mut [u8]) -> Option<usize> {
+            let mut n = 0;
+            for c in input.chunks(2) {
+                output.get_mut(n..n + c[0] as usize)?.fill(c[1]);
+                n += c[0] as usize;
+            }
+            Some(n)
+        }
+    }
+
+    fn roundtrip<C: Codec + Sync + Send>(codec: C) {
+        let mut input = [0u8; 100];
+        for (i, b) in input.iter_mut().enumerate() {
+            *b = (i / 10) as u8;
+        }
+
+        let mut data = [0u8; 256];
+        let mut index = [0u32; 7];
+        let n = compress_chunks(&codec, 16, &input, &mut data, &mut index).unwrap();
+
+        let c = CompressedFile::<_, 16>::new(codec, &data[..n], &index, input.len());
+        let f = File::<8>::new("DATA.BIN", FileContent::Generated(&c)).unwrap();
+        assert_eq!(f.len(), input.len());
+
+        let mut buff = [0u8; 8];
+        for (i, d) in input.chunks(8).enumerate() {
+            assert_eq!(f.chunk(i, &mut buff), Ok(d.len()));
+            assert_eq!(&buff[..d.len()], d);
+        }
+    }
+
+    #[test]
+    fn compressing_writes() {
+        let mut backing = [0u8; 20];
+        let mut f = CompressingFile::<_, 4, 8>::new(Rle, &mut backing);
+        assert_eq!(f.len(), 32);
+
+        // Unwritten blocks read as zeros
+        let mut buff = [0xFFu8; 8];
+        assert_eq!(f.read_chunk(3, &mut buff), Ok(8));
+        assert_eq!(buff, [0; 8]);
+
+        // Compressible blocks are stored compactly
+        assert_eq!(f.write_chunk(0, &[1; 8]), Ok(8));
+        assert_eq!(f.write_chunk(1, &[2, 2, 2, 2, 3, 3, 3, 3]), Ok(8));
+        assert_eq!(f.stored(), 6);
+
+        // Incompressible blocks are stored raw
+        assert_eq!(f.write_chunk(2, &[1, 2, 3, 4, 5, 6, 7, 8]), Ok(8));
+        assert_eq!(f.stored(), 14);
+
+        // Rewrites append, then reclaim space via compaction once full
+        assert_eq!(f.write_chunk(1, &[0; 8]), Ok(8));
+        assert_eq!(f.write_chunk(3, &[9, 9, 9, 9, 9, 9, 9, 8]), Ok(8));
+        assert_eq!(f.write_chunk(1, &[3; 8]), Ok(8));
+        assert_eq!(f.stored(), 16);
+
+        // Polling compacts incrementally
+        assert_eq!(f.write_chunk(2, &[5; 8]), Ok(8));
+        assert_eq!((f.end(), f.stored()), (18, 10));
+        assert!(f.poll());
+        while f.poll() {}
+        assert_eq!(f.end(), f.stored());
+        assert_eq!(f.write_chunk(2, &[1, 2, 3, 4, 5, 6, 7, 8]), Ok(8));
+
+        // Writes that do not fit fail, retaining existing content
+        assert_eq!(f.write_chunk(0, &[8, 7, 6, 5, 4, 3, 2, 1]), Err(FileError::NoSpace));
+
+        let expected: [[u8; 8]; 4] = [[1; 8], [3; 8], [1, 2, 3, 4, 5, 6, 7, 8], [9, 9, 9, 9, 9, 9, 9, 8]];
+        for (i, e) in expected.iter().enumerate() {
+            assert_eq!(f.read_chunk(i, &mut buff), Ok(8));
+            assert_eq!(&buff, e);
+        }
+    }
+
+    #[test]
+    fn rle_roundtrip() {
+        roundtrip(Rle);
+    }
+
+    #[test]
+    #[cfg(feature = "lz4_flex")]
+    fn lz4_roundtrip() {
+        roundtrip(Lz4);
+    }
+
+    #[test]
+    #[cfg(feature = "heatshrink")]
+    fn heatshrink_roundtrip() {
+        roundtrip(Heatshrink::default());
+    }
+}
@@ -1,6 +1,20 @@
 
+use core::ops::Range;
+
+use crate::{AccessRange, Cluster, File, FileError, FixedStr, LayoutError, Lba, SectorIndex};
+use crate::builder::{MIN_CLUSTERS, file_entries, validate_volume};
+use crate::file::valid_short_name;
 
 /// Virtual file system configuration
+///
+/// With the `serde` feature, configurations may be persisted and loaded
+/// (ie. by host-side tooling), with missing fields taking default values.
+/// The [`Config::access_map`] is not loaded, and callback policies may not
+/// be persisted.
+// A private field is used rather than `#[non_exhaustive]`, which only
+// applies to other crates, so construction within the crate also goes
+// via `Default` / `Config::new` with fields assigned individually
+#[allow(clippy::manual_non_exhaustive)]
 pub struct Config<const BLOCK_SIZE: usize = 512> {
     /// Number of blocks in the file system
     pub num_blocks: u32,
@@ -10,13 +24,78 @@ pub struct Config<const BLOCK_SIZE: usize = 512> {
     pub root_dir_sectors: u32,
 
     /// OEM info, defaults to "UF2 UF2"
-    pub oem_info: &'static str,
+    pub oem_info: FixedStr<8>,
 
     /// Volume label, defaults to "GHOSTFAT"
-    pub volume_label: &'static str,
+    /// 
+    /// Labels may be built at runtime (ie. including a serial number), see [`FixedStr`]
+    pub volume_label: FixedStr<11>,
 
     /// FileSystem Identifier, defaults to "FAT16"
-    pub filesystem_identifier: &'static str,
+    pub filesystem_identifier: FixedStr<8>,
+
+    /// SCSI inquiry vendor identification, defaults to "GhostFAT"
+    /// 
+    /// See [`Config::inquiry`]
+    pub vendor: FixedStr<8>,
+
+    /// SCSI inquiry product identification, defaults to "Virtual FAT"
+    pub product: FixedStr<16>,
+
+    /// SCSI inquiry product revision, defaults to "1.0"
+    pub revision: FixedStr<4>,
+
+    /// Maximum bytes serviced per poll interval, defaults to `None` (unlimited)
+    /// 
+    /// See [`GhostFat::tick`](crate::GhostFat::tick) and [`GhostFat::ready`](crate::GhostFat::ready)
+    pub bytes_per_interval: Option<u32>,
+
+    /// LBA access permissions, enforced before dispatching reads and writes.
+    /// 
+    /// Blocks not covered by any range are read-write, defaults to empty
+    pub access_map: &'static [AccessRange],
+
+    /// Host session timeout in [`GhostFat::tick`](crate::GhostFat::tick) periods, defaults to `None` (disabled)
+    pub host_timeout: Option<u32>,
+
+    /// Automatically [`GhostFat::remount`](crate::GhostFat::remount) on host session timeout, defaults to `false`
+    pub remount_on_timeout: bool,
+
+    /// Restrict block device calls to bounded-time work, deferring warm cache
+    /// generation to [`GhostFat::poll`](crate::GhostFat::poll), defaults to `false`
+    pub bounded_time: bool,
+
+    /// Apply host renames to the file table, so the volume lists files under
+    /// their new names following a remount, defaults to `false`
+    #[cfg(feature = "alloc")]
+    pub apply_renames: bool,
+
+    /// Report the volume as write-protected, rejecting all host writes,
+    /// defaults to `false`
+    ///
+    /// See [`GhostFat::set_write_protected`](crate::GhostFat::set_write_protected)
+    /// to change this at runtime, and [`Scsi::with_write_protect`](crate::scsi::Scsi::with_write_protect)
+    /// to report write protection to the host
+    pub write_protected: bool,
+
+    /// Handling of host writes to data clusters outside device files,
+    /// defaults to [`UnmappedWrites::Scratch`]
+    pub unmapped_writes: UnmappedWrites,
+
+    /// Accept host disk check (chkdsk / fsck) writes, defaults to `false`
+    ///
+    /// Volume dirty flags written to the boot sector and reserved FAT entries
+    /// are served back until the next [`GhostFat::remount`](crate::GhostFat::remount),
+    /// and rewrites matching the generated FAT or root directory are
+    /// acknowledged without consuming shadow space, so check passes complete
+    /// without repeating repairs. Other repairs are merged into the shadows
+    /// where attached (see [`GhostFat::with_fat_shadow`](crate::GhostFat::with_fat_shadow)).
+    pub disk_check: bool,
+
+    /// Handling of host formats, detected where the host clears the root
+    /// directory following a boot sector rewrite or FAT clear, defaults
+    /// to [`FormatPolicy::Ignore`]
+    pub format_policy: FormatPolicy,
 
     /// Force use of Default::default() for construction
     _reserved: (),
@@ -24,19 +103,96 @@ pub struct Config<const BLOCK_SIZE: usize = 512> {
 
 impl <const BLOCK_SIZE: usize> Default for Config<BLOCK_SIZE> {
     fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <const BLOCK_SIZE: usize> Config<BLOCK_SIZE> {
+    /// Create a default configuration in const context, see [`Config::assert_layout`]
+    pub const fn new() -> Self {
         Self { 
             num_blocks: 8000,
             reserved_sectors: 1,
             root_dir_sectors: 4,
-            oem_info: "UF2 UF2",
-            volume_label: "GHOSTFAT",
-            filesystem_identifier: "FAT16",
+            oem_info: FixedStr::new("UF2 UF2"),
+            volume_label: FixedStr::new("GHOSTFAT"),
+            filesystem_identifier: FixedStr::new("FAT16"),
+            vendor: FixedStr::new("GhostFAT"),
+            product: FixedStr::new("Virtual FAT"),
+            revision: FixedStr::new("1.0"),
+            bytes_per_interval: None,
+            access_map: &[],
+            host_timeout: None,
+            remount_on_timeout: false,
+            bounded_time: false,
+            #[cfg(feature = "alloc")]
+            apply_renames: false,
+            write_protected: false,
+            unmapped_writes: UnmappedWrites::Scratch,
+            disk_check: false,
+            format_policy: FormatPolicy::Ignore,
             _reserved: (),
         }
     }
 }
 
+/// Policy for host writes to data clusters outside device files (ie. new
+/// host files or metadata), applied after [`WriteObserver`](crate::WriteObserver)s
+/// and captured host files
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnmappedWrites {
+    /// Discard writes, reporting success to the host
+    Ignore,
+    /// Reject writes with a write error, ie. for bootloaders accepting
+    /// only known files
+    Error,
+    /// Store writes in the scratch region attached via
+    /// [`GhostFat::with_scratch`](crate::GhostFat::with_scratch), discarding
+    /// writes with success where no region is attached or the region is full
+    Scratch,
+    /// Call the provided function with the data region sector index and
+    /// written data, returning the result to the host
+    #[cfg_attr(feature="serde", serde(skip))]
+    Callback(fn(SectorIndex, &[u8]) -> Result<(), FileError>),
+}
+
+/// Policy for host formats (ie. a quick format from the host OS), after
+/// which the host view of the volume diverges from the device files
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FormatPolicy {
+    /// Ignore formats, with the host view diverging until the next
+    /// [`GhostFat::remount`](crate::GhostFat::remount)
+    Ignore,
+    /// Remount the volume, re-presenting the device files to the host
+    Remount,
+    /// Call the provided function (ie. to reset device state) then remount
+    /// the volume
+    #[cfg_attr(feature="serde", serde(skip))]
+    Callback(fn()),
+}
+
 impl <const BLOCK_SIZE: usize> Config<BLOCK_SIZE> {
+    /// Create a default configuration with `num_blocks` sized to fit the
+    /// provided files, plus FAT, root directory and reserved overhead and
+    /// `margin` free clusters for host writes.
+    /// 
+    /// Volumes are padded to the FAT16 minimum cluster count where required,
+    /// as smaller volumes are detected by hosts as FAT12
+    pub fn for_files(files: &[File<BLOCK_SIZE>], margin: u32) -> Self {
+        let required = files.iter().map(|f| f.alloc_blocks() as u32).sum::<u32>() + margin;
+        let clusters = u32::max(required, MIN_CLUSTERS);
+
+        // FAT size depends on the number of blocks, so iterate until stable
+        let mut config = Self { num_blocks: clusters, ..Default::default() };
+        while config.num_blocks < config.start_clusters().0 + clusters {
+            config.num_blocks = config.start_clusters().0 + clusters;
+        }
+
+        config
+    }
+
 
     /// Fetch the block/sector size
     pub const fn sector_size(&self) -> u32 {
@@ -45,27 +201,124 @@ impl <const BLOCK_SIZE: usize> Config<BLOCK_SIZE> {
 
     /// Calculate number of sectors per FAT
     pub const fn sectors_per_fat(&self) -> u32 {
-        (self.num_blocks * 2 + BLOCK_SIZE as u32 - 1) / BLOCK_SIZE as u32
+        (self.num_blocks * 2).div_ceil(BLOCK_SIZE as u32)
     }
 
     /// Calculate FAT0 start
-    pub const fn start_fat0(&self) -> u32 {
-        self.reserved_sectors
+    pub const fn start_fat0(&self) -> Lba {
+        Lba(self.reserved_sectors)
     }
 
     /// Calculate FAT1 start
-    pub const fn start_fat1(&self) -> u32 {
-        self.start_fat0() + self.sectors_per_fat()
+    pub const fn start_fat1(&self) -> Lba {
+        Lba(self.start_fat0().0 + self.sectors_per_fat())
     }
 
     /// Calculate ROOTDIR start
-    pub const fn start_rootdir(&self) -> u32 {
-        self.start_fat1() + self.sectors_per_fat()
+    pub const fn start_rootdir(&self) -> Lba {
+        Lba(self.start_fat1().0 + self.sectors_per_fat())
     }
 
     /// Calculate cluster start
-    pub const fn start_clusters(&self) -> u32 {
-        self.start_rootdir() + self.root_dir_sectors
+    pub const fn start_clusters(&self) -> Lba {
+        Lba(self.start_rootdir().0 + self.root_dir_sectors)
+    }
+
+    /// Calculate the number of data clusters
+    pub const fn data_clusters(&self) -> u32 {
+        self.num_blocks.saturating_sub(self.start_clusters().0)
+    }
+
+    /// Calculate the LBA of a data cluster
+    pub const fn cluster_lba(&self, cluster: Cluster) -> Lba {
+        Lba(self.start_clusters().0 + cluster.index().0)
+    }
+
+    /// Calculate the clusters allocated to the file at `index`, where `sizes`
+    /// lists the allocated size of each file in bytes (ie. the file length,
+    /// or reserved size where larger)
+    pub const fn file_clusters(&self, sizes: &[usize], index: usize) -> Range<Cluster> {
+        let (mut start, mut i) = (Cluster::FIRST.0, 0);
+        while i < index {
+            start += sizes[i].div_ceil(BLOCK_SIZE) as u32;
+            i += 1;
+        }
+
+        Cluster(start)..Cluster(start + sizes[index].div_ceil(BLOCK_SIZE) as u32)
+    }
+
+    /// Validate a file layout in const context, where `files` lists the name
+    /// and allocated size in bytes of each file.
+    /// 
+    /// Names must be valid 8.3 short names (see [`File::new_ro_checked`]),
+    /// and are compared ignoring case as hosts would.
+    /// 
+    /// See [`GhostFatBuilder::validate`](crate::GhostFatBuilder::validate) for runtime validation
+    pub const fn check_layout(&self, files: &[(&str, usize)]) -> Result<(), LayoutError> {
+        let entries = file_entries(self);
+        if files.len() > entries {
+            return Err(LayoutError::TooManyFiles { files: files.len(), entries });
+        }
+
+        let (mut i, mut required) = (0, 0);
+        while i < files.len() {
+            if !valid_short_name(files[i].0) {
+                return Err(LayoutError::InvalidName(i));
+            }
+
+            // Valid names map directly to upper-case short names
+            let mut j = 0;
+            while j < i {
+                if files[j].0.eq_ignore_ascii_case(files[i].0) {
+                    return Err(LayoutError::DuplicateName(i));
+                }
+                j += 1;
+            }
+
+            required += files[i].1.div_ceil(BLOCK_SIZE);
+            i += 1;
+        }
+
+        validate_volume(self, required)
+    }
+
+    /// Assert a file layout fits the configured volume, failing the build
+    /// when evaluated in const context, see [`Config::check_layout`].
+    /// 
+    /// ```
+    /// use ghostfat::Config;
+    /// 
+    /// static FIRMWARE: [u8; 4096] = [0u8; 4096];
+    /// 
+    /// const CONFIG: Config = {
+    ///     let mut c = Config::new();
+    ///     c.num_blocks = 16000;
+    ///     c
+    /// };
+    /// 
+    /// const _: () = CONFIG.assert_layout(&[("FW.BIN", FIRMWARE.len()), ("LOG.TXT", 64 * 1024)]);
+    /// ```
+    pub const fn assert_layout(&self, files: &[(&str, usize)]) {
+        match self.check_layout(files) {
+            Ok(()) => (),
+            Err(LayoutError::InvalidName(_)) => panic!("file name is not a valid 8.3 short name"),
+            Err(LayoutError::DuplicateName(_)) => panic!("duplicate file name"),
+            Err(LayoutError::TooManyFiles { .. }) => panic!("files exceed available root directory entries"),
+            Err(LayoutError::NoSpace { .. }) => panic!("files exceed volume data clusters"),
+            Err(LayoutError::ClusterCount(_)) => panic!("volume cluster count is outside FAT16 limits"),
+            Err(LayoutError::TooManyBlocks(_)) => panic!("volume exceeds 16-bit sector count"),
+            Err(LayoutError::ClusterTable { .. }) => panic!("files exceed cluster table entries"),
+        }
+    }
+
+    /// Build standard SCSI INQUIRY data for a removable direct access device
+    /// from the configured vendor, product and revision, so the device
+    /// identity shown by the host is configured alongside the volume label.
+    /// 
+    /// Strings are space padded and truncated to 8, 16 and 4 bytes
+    /// respectively, see [`Scsi::with_inquiry`](crate::scsi::Scsi::with_inquiry)
+    pub fn inquiry(&self) -> [u8; 36] {
+        inquiry_data(self.vendor.as_bytes(), self.product.as_bytes(), self.revision.as_bytes())
     }
 
     /// Encode config to boot block
@@ -82,10 +335,194 @@ impl <const BLOCK_SIZE: usize> Config<BLOCK_SIZE> {
         // OEM info
         let len = usize::min(self.oem_info.len(), 8);
         block[index..][..len].copy_from_slice(&self.oem_info.as_bytes()[..len]);
-        index += 8;
-
 
         todo!();
     }
 
 }
+
+/// Build standard SCSI INQUIRY data for a removable direct access device
+pub(crate) fn inquiry_data(vendor: &[u8], product: &[u8], revision: &[u8]) -> [u8; 36] {
+    let mut inquiry = [b' '; 36];
+    inquiry[..8].copy_from_slice(&[0x00, 0x80, 0x04, 0x02, 31, 0x00, 0x00, 0x00]);
+    for (s, o, l) in [(vendor, 8, 8), (product, 16, 16), (revision, 32, 4)] {
+        let n = s.len().min(l);
+        inquiry[o..][..n].copy_from_slice(&s[..n]);
+    }
+    inquiry
+}
+
+/// Serde representation of [`Config`], as serde derives do not support
+/// const generic defaults
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "Config", default)]
+struct ConfigDef {
+    num_blocks: u32,
+    reserved_sectors: u32,
+    root_dir_sectors: u32,
+    oem_info: FixedStr<8>,
+    volume_label: FixedStr<11>,
+    filesystem_identifier: FixedStr<8>,
+    vendor: FixedStr<8>,
+    product: FixedStr<16>,
+    revision: FixedStr<4>,
+    bytes_per_interval: Option<u32>,
+    #[serde(skip_deserializing)]
+    access_map: &'static [AccessRange],
+    host_timeout: Option<u32>,
+    remount_on_timeout: bool,
+    bounded_time: bool,
+    #[cfg(feature = "alloc")]
+    apply_renames: bool,
+    write_protected: bool,
+    unmapped_writes: UnmappedWrites,
+    disk_check: bool,
+    format_policy: FormatPolicy,
+}
+
+#[cfg(feature = "serde")]
+impl Default for ConfigDef {
+    fn default() -> Self {
+        Self::from(&Config::<512>::default())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <const BLOCK_SIZE: usize> From<&Config<BLOCK_SIZE>> for ConfigDef {
+    fn from(c: &Config<BLOCK_SIZE>) -> Self {
+        Self {
+            num_blocks: c.num_blocks,
+            reserved_sectors: c.reserved_sectors,
+            root_dir_sectors: c.root_dir_sectors,
+            oem_info: c.oem_info,
+            volume_label: c.volume_label,
+            filesystem_identifier: c.filesystem_identifier,
+            vendor: c.vendor,
+            product: c.product,
+            revision: c.revision,
+            bytes_per_interval: c.bytes_per_interval,
+            access_map: c.access_map,
+            host_timeout: c.host_timeout,
+            remount_on_timeout: c.remount_on_timeout,
+            bounded_time: c.bounded_time,
+            #[cfg(feature = "alloc")]
+            apply_renames: c.apply_renames,
+            write_protected: c.write_protected,
+            unmapped_writes: c.unmapped_writes,
+            disk_check: c.disk_check,
+            format_policy: c.format_policy,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <const BLOCK_SIZE: usize> serde::Serialize for Config<BLOCK_SIZE> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfigDef::from(self).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <'de, const BLOCK_SIZE: usize> serde::Deserialize<'de> for Config<BLOCK_SIZE> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let c = ConfigDef::deserialize(deserializer)?;
+
+        Ok(Self {
+            num_blocks: c.num_blocks,
+            reserved_sectors: c.reserved_sectors,
+            root_dir_sectors: c.root_dir_sectors,
+            oem_info: c.oem_info,
+            volume_label: c.volume_label,
+            filesystem_identifier: c.filesystem_identifier,
+            vendor: c.vendor,
+            product: c.product,
+            revision: c.revision,
+            bytes_per_interval: c.bytes_per_interval,
+            access_map: c.access_map,
+            host_timeout: c.host_timeout,
+            remount_on_timeout: c.remount_on_timeout,
+            bounded_time: c.bounded_time,
+            #[cfg(feature = "alloc")]
+            apply_renames: c.apply_renames,
+            write_protected: c.write_protected,
+            unmapped_writes: c.unmapped_writes,
+            disk_check: c.disk_check,
+            format_policy: c.format_policy,
+            _reserved: (),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Layout evaluated at compile time
+    const CONFIG: Config = {
+        let mut c = Config::new();
+        c.num_blocks = 16000;
+        c
+    };
+
+    const _: () = CONFIG.assert_layout(&[("FW.BIN", 4096), ("LOG.TXT", 64 * 1024)]);
+
+    #[test]
+    fn const_layout() {
+        let sizes = [700, 0, 4096];
+        assert_eq!(CONFIG.file_clusters(&sizes, 0), Cluster(2)..Cluster(4));
+        assert_eq!(CONFIG.file_clusters(&sizes, 2), Cluster(4)..Cluster(12));
+        assert_eq!(CONFIG.cluster_lba(Cluster::FIRST), CONFIG.start_clusters());
+        assert_eq!(CONFIG.data_clusters(), 16000 - CONFIG.start_clusters().0);
+
+        // Layouts match runtime validation
+        let data = [0u8; 700];
+        let f = [File::<512>::new_ro("FW.BIN", &data).with_reserved(8000 * 512), File::new_ro("LOG.TXT", &data)];
+        let layout = [("FW.BIN", 8000 * 512), ("LOG.TXT", 700)];
+        assert_eq!(CONFIG.check_layout(&layout), crate::builder::validate(&CONFIG, &f));
+        assert_eq!(Config::<512>::new().check_layout(&layout), crate::builder::validate(&Config::new(), &f));
+
+        assert_eq!(CONFIG.check_layout(&[("FW.BIN", 700), ("fw.bin", 700)]), Err(LayoutError::DuplicateName(1)));
+        assert_eq!(CONFIG.check_layout(&[("FIRMWARE.IMAGE", 700)]), Err(LayoutError::InvalidName(0)));
+        assert_eq!(CONFIG.check_layout(&[("FW.BIN", 16000 * 512)]), Err(LayoutError::NoSpace { required: 16000, available: CONFIG.data_clusters() as usize }));
+        assert_eq!(Config::<512>::new().check_layout(&[]), Ok(()));
+
+        // Larger blocks list further files in the first root directory sector
+        let config = Config::<8192>::new();
+        assert_eq!(config.check_layout(&[("FW.BIN", 0); 256]), Err(LayoutError::TooManyFiles { files: 256, entries: 255 }));
+    }
+
+    #[test]
+    fn inquiry() {
+        let mut config = Config::<512>::new();
+        config.vendor = FixedStr::new("ACME Corporation");
+        config.product = FixedStr::new("Bootloader");
+
+        let inquiry = config.inquiry();
+        assert_eq!(&inquiry[..2], &[0x00, 0x80]);
+        assert_eq!(&inquiry[8..], b"ACME CorBootloader      1.0 ");
+    }
+
+    #[test]
+    #[cfg(feature = "serde-json-core")]
+    fn config_serde() {
+        use crate::file::Attrs;
+
+        let config = Config::<512> { num_blocks: 16000, volume_label: FixedStr::new("TEST"), ..Default::default() };
+
+        let mut buff = [0u8; 512];
+        let n = serde_json_core::to_slice(&config, &mut buff).unwrap();
+        assert!(buff[..n].starts_with(br#"{"num_blocks":16000,"reserved_sectors":1,"#));
+
+        // Missing fields take default values
+        let (c, _) = serde_json_core::from_slice::<Config>(br#"{"num_blocks":16000,"volume_label":"TEST","format_policy":"Remount"}"#).unwrap();
+        assert_eq!(c.num_blocks, 16000);
+        assert_eq!(c.volume_label, "TEST");
+        assert_eq!(c.root_dir_sectors, 4);
+        assert!(matches!(c.format_policy, FormatPolicy::Remount));
+
+        let n = serde_json_core::to_slice(&(Attrs::READ_ONLY | Attrs::ARCHIVE), &mut buff).unwrap();
+        assert_eq!(&buff[..n], b"33");
+        assert_eq!(serde_json_core::from_slice::<Attrs>(b"160"), Ok((Attrs::ARCHIVE, 3)));
+    }
+}
@@ -0,0 +1,156 @@
+//! Configuration file objects
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{DynamicFile, FileError};
+
+/// Read/write configuration file rendering a serde value as JSON.
+///
+/// Host reads return the rendered value padded with whitespace to the
+/// fixed `N` byte file length. Host writes are re-parsed once the file
+/// contains a complete document, with the `validate` callback called
+/// before committing the new value and re-rendering the file.
+pub struct ConfigFile<T, V, const N: usize, const BLOCK_SIZE: usize = 512> {
+    value: T,
+    validate: V,
+    buff: [u8; N],
+    updated: bool,
+}
+
+impl <T, V, const N: usize, const BLOCK_SIZE: usize> ConfigFile<T, V, N, BLOCK_SIZE>
+where
+    T: Serialize + DeserializeOwned,
+    V: FnMut(&T) -> bool,
+{
+    /// Create a new config file with the provided initial value and validation callback,
+    /// returning [`FileError::NoSpace`] if the rendered value exceeds `N` bytes
+    pub fn new(value: T, validate: V) -> Result<Self, FileError> {
+        let mut f = Self { value, validate, buff: [0u8; N], updated: false };
+        f.render()?;
+        Ok(f)
+    }
+
+    /// Fetch the current value
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Update the current value, re-rendering the file
+    pub fn set(&mut self, value: T) -> Result<(), FileError> {
+        self.value = value;
+        self.render()
+    }
+
+    /// Check whether the host has committed a new value since the last call
+    pub fn updated(&mut self) -> bool {
+        core::mem::take(&mut self.updated)
+    }
+
+    /// Render the current value into the file buffer
+    fn render(&mut self) -> Result<(), FileError> {
+        let n = serde_json_core::to_slice(&self.value, &mut self.buff)
+            .map_err(|_| FileError::NoSpace)?;
+
+        // Pad to the fixed file length
+        let pad = &mut self.buff[n..];
+        if let Some((last, rest)) = pad.split_last_mut() {
+            rest.fill(b' ');
+            *last = b'\n';
+        }
+
+        Ok(())
+    }
+
+    /// Attempt to parse and commit the file buffer
+    fn parse(&mut self) {
+        // Hosts zero-fill the remainder of the last written cluster
+        let len = self.buff.iter().rposition(|b| *b != 0).map(|i| i + 1).unwrap_or(0);
+
+        let value = match serde_json_core::from_slice::<T>(&self.buff[..len]) {
+            Ok((v, _n)) => v,
+            // Incomplete or invalid documents are retained until rewritten
+            Err(_) => return,
+        };
+
+        if !(self.validate)(&value) {
+            crate::warn!("Config file validation failed");
+            return;
+        }
+
+        crate::debug!("Config file updated");
+
+        self.value = value;
+        self.updated = true;
+        let _ = self.render();
+    }
+}
+
+impl <T, V, const N: usize, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for ConfigFile<T, V, N, BLOCK_SIZE>
+where
+    T: Serialize + DeserializeOwned + Sync + Send,
+    V: FnMut(&T) -> bool + Sync + Send,
+{
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        let d = match self.buff.chunks(BLOCK_SIZE).nth(chunk_index) {
+            Some(d) => d,
+            None => return Ok(0),
+        };
+
+        let len = usize::min(buff.len(), d.len());
+        buff[..len].copy_from_slice(&d[..len]);
+        Ok(len)
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        let d = match self.buff.chunks_mut(BLOCK_SIZE).nth(chunk_index) {
+            Some(d) => d,
+            None => return Ok(0),
+        };
+
+        let len = usize::min(data.len(), d.len());
+        d[..len].copy_from_slice(&data[..len]);
+
+        self.parse();
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Serialize, Deserialize};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        rate: u32,
+        enabled: bool,
+    }
+
+    #[test]
+    fn config_roundtrip() {
+        let mut f = ConfigFile::<_, _, 32, 16>::new(Settings{ rate: 10, enabled: false }, |s: &Settings| s.rate < 100).unwrap();
+        assert_eq!(DynamicFile::len(&f), 32);
+
+        let mut buff = [0u8; 16];
+        assert_eq!(f.read_chunk(0, &mut buff), Ok(16));
+        assert_eq!(&buff, b"{\"rate\":10,\"enab");
+
+        // Documents are committed once parsed
+        assert_eq!(f.write_chunk(0, b"{\"rate\":50,\"enab"), Ok(16));
+        assert_eq!(f.write_chunk(1, b"led\":true}\0\0\0\0\0\0"), Ok(16));
+        assert!(f.updated());
+        assert_eq!(f.value(), &Settings{ rate: 50, enabled: true });
+
+        // Invalid values are rejected
+        assert_eq!(f.write_chunk(0, b"{\"rate\":500,\"ena"), Ok(16));
+        assert_eq!(f.write_chunk(1, b"bled\":true}\0\0\0\0\0"), Ok(16));
+        assert!(!f.updated());
+        assert_eq!(f.value(), &Settings{ rate: 50, enabled: true });
+    }
+}
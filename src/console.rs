@@ -0,0 +1,175 @@
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use crate::{DynamicFile, File, FileError, GeneratedFile};
+use crate::text::SliceWriter;
+
+/// Command/response console over a pair of files, providing a driverless
+/// control channel via plain file copies.
+///
+/// The host writes command text into the command file (ie. `CMD.TXT`, see
+/// [`ConsoleFile::command`]), with the first line passed to the handler
+/// once a line ending, NUL or the end of the `N` byte command buffer is
+/// written. Handler output is exposed via the response file (ie. `RESP.TXT`,
+/// see [`ConsoleFile::file`]).
+///
+/// Both files are a fixed `N` bytes padded with spaces, as hosts cache
+/// directory entries. The handler is called from the block device context
+/// and should defer any long-running work.
+pub struct ConsoleFile<F, const N: usize = 256> {
+    handler: F,
+    command: [AtomicU8; N],
+    response: [AtomicU8; N],
+    count: AtomicUsize,
+}
+
+impl <F: Fn(&str, &mut dyn Write) -> fmt::Result + Sync + Send, const N: usize> ConsoleFile<F, N> {
+    /// Create a new console using the provided command handler
+    pub fn new(handler: F) -> Self {
+        Self {
+            handler,
+            command: [const { AtomicU8::new(b' ') }; N],
+            response: [const { AtomicU8::new(b' ') }; N],
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    /// Fetch a writable command file handle, for use with [`FileContent::Dynamic`](crate::FileContent::Dynamic)
+    pub fn command(&self) -> ConsoleCommand<'_, F, N> {
+        ConsoleCommand(self)
+    }
+
+    /// Create a read-only `RESP.TXT` file exposing command responses
+    pub fn file<const BLOCK_SIZE: usize>(&self) -> File<'_, BLOCK_SIZE> {
+        File::new_gen("RESP.TXT", self)
+    }
+
+    /// Fetch the number of executed commands
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Execute the first line of the command buffer, returning false if no
+    /// complete command is available
+    fn execute(&self, complete: bool) -> bool {
+        let mut c = [0u8; N];
+        for (b, a) in c.iter_mut().zip(&self.command) {
+            *b = a.load(Ordering::Relaxed);
+        }
+
+        let len = match c.iter().position(|b| matches!(b, b'\n' | b'\r' | 0)) {
+            Some(n) => n,
+            None if complete => N,
+            None => return false,
+        };
+
+        let cmd = match core::str::from_utf8(&c[..len]) {
+            Ok(s) => s.trim(),
+            Err(_) => {
+                crate::warn!("Ignoring non-UTF8 console command");
+                return false;
+            }
+        };
+
+        crate::debug!("Executing console command: {}", cmd);
+
+        // Render the response, truncated to the buffer length
+        let mut r = [b' '; N];
+        let mut w = SliceWriter::new(0, &mut r);
+        let _ = (self.handler)(cmd, &mut w);
+
+        for (a, b) in self.response.iter().zip(&r) {
+            a.store(*b, Ordering::Relaxed);
+        }
+        self.count.store(self.count() + 1, Ordering::Relaxed);
+
+        true
+    }
+}
+
+impl <F: Fn(&str, &mut dyn Write) -> fmt::Result + Sync + Send, const N: usize> GeneratedFile for ConsoleFile<F, N> {
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        let n = usize::min(buff.len(), N.saturating_sub(offset));
+        for (b, a) in buff[..n].iter_mut().zip(self.response.iter().skip(offset)) {
+            *b = a.load(Ordering::Relaxed);
+        }
+        n
+    }
+}
+
+/// Writable command file for a [`ConsoleFile`]
+pub struct ConsoleCommand<'a, F, const N: usize>(&'a ConsoleFile<F, N>);
+
+impl <'a, F: Fn(&str, &mut dyn Write) -> fmt::Result + Sync + Send, const N: usize, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for ConsoleCommand<'a, F, N> {
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        let offset = chunk_index * BLOCK_SIZE;
+        let n = usize::min(usize::min(buff.len(), BLOCK_SIZE), N.saturating_sub(offset));
+        for (b, a) in buff[..n].iter_mut().zip(self.0.command.iter().skip(offset)) {
+            *b = a.load(Ordering::Relaxed);
+        }
+        Ok(n)
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        let offset = chunk_index * BLOCK_SIZE;
+        let n = usize::min(usize::min(data.len(), BLOCK_SIZE), N.saturating_sub(offset));
+        for (a, b) in self.0.command.iter().skip(offset).zip(&data[..n]) {
+            a.store(*b, Ordering::Relaxed);
+        }
+
+        // Commands are executed once the first line is complete
+        if n > 0 {
+            self.0.execute(offset + n >= N);
+        }
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, FileContent, GhostFat};
+    use super::*;
+
+    #[test]
+    fn console_commands() {
+        let console = ConsoleFile::<_, 64>::new(|cmd: &str, w: &mut dyn Write| {
+            match cmd {
+                "version" => writeln!(w, "ghostfat {}", env!("CARGO_PKG_VERSION")),
+                _ => writeln!(w, "unknown command: {}", cmd),
+            }
+        });
+        let mut cmd = console.command();
+        let mut f = [
+            File::<512>::new("CMD.TXT", FileContent::Dynamic(&mut cmd)).unwrap(),
+            console.file(),
+        ];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let start = fs.config.start_clusters().0;
+
+        let mut block = [0u8; 512];
+        block[..9].copy_from_slice(b"version\r\n");
+        fs.write_block(start, &block).unwrap();
+        assert_eq!(console.count(), 1);
+
+        fs.read_block(start + 1, &mut block).unwrap();
+        let resp = core::str::from_utf8(&block[..64]).unwrap();
+        assert!(resp.starts_with(concat!("ghostfat ", env!("CARGO_PKG_VERSION"), "\n   ")));
+
+        let mut block = [0u8; 512];
+        block[..4].copy_from_slice(b"nope");
+        fs.write_block(start, &block).unwrap();
+        fs.read_block(start + 1, &mut block).unwrap();
+        assert!(block.starts_with(b"unknown command: nope\n"));
+    }
+}
@@ -0,0 +1,190 @@
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::GeneratedFile;
+use crate::text::SliceWriter;
+
+/// CSV file rendered on demand from a record callback
+///
+/// The callback is called with a row index and a [`CsvRecord`] to populate,
+/// returning false once there are no more rows. Rows are rendered as
+/// required for each block read, with the position of the last row read
+/// retained so sequential reads do not re-render the whole file (reads are
+/// expected from a single context, as with the USB stack).
+///
+/// As with [`TextFile`](crate::TextFile) the file length is computed by
+/// rendering all rows, so records should be stable between calls.
+pub struct CsvFile<'a, F> {
+    header: &'a [&'a str],
+    record: F,
+    resume_row: AtomicUsize,
+    resume_offset: AtomicUsize,
+}
+
+impl <'a, F: Fn(usize, &mut CsvRecord) -> bool + Sync + Send> CsvFile<'a, F> {
+    /// Create a new CSV file with the provided column names and record callback
+    pub const fn new(header: &'a [&'a str], record: F) -> Self {
+        Self {
+            header,
+            record,
+            resume_row: AtomicUsize::new(0),
+            resume_offset: AtomicUsize::new(0),
+        }
+    }
+
+    /// Render the file from `row` into the provided writer, storing the
+    /// start of the row in which the writer filled to resume following reads
+    fn render(&self, mut row: usize, mut offset: usize, w: &mut SliceWriter) -> usize {
+        let base = offset - w.len();
+
+        loop {
+            offset = base + w.len();
+
+            let mut r = CsvRecord{ w: &mut *w, first: true, res: Ok(()) };
+            if !(self.record)(row, &mut r) {
+                break;
+            }
+
+            if r.res.and_then(|_| w.write_str("\r\n")).is_err() {
+                self.resume_row.store(row, Ordering::Relaxed);
+                self.resume_offset.store(offset, Ordering::Relaxed);
+                break;
+            }
+
+            row += 1;
+        }
+
+        w.written()
+    }
+}
+
+impl <'a, F: Fn(usize, &mut CsvRecord) -> bool + Sync + Send> GeneratedFile for CsvFile<'a, F> {
+    fn len(&self) -> usize {
+        let mut w = SliceWriter::new(0, &mut []);
+        let _ = write_header(self.header, &mut w);
+        self.render(0, w.len(), &mut w);
+        w.len()
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        let row = self.resume_row.load(Ordering::Relaxed);
+        let resume = self.resume_offset.load(Ordering::Relaxed);
+
+        // Resume from the last row read where possible
+        if row > 0 && offset >= resume {
+            let mut w = SliceWriter::new(offset - resume, buff);
+            return self.render(row, resume, &mut w);
+        }
+
+        let mut w = SliceWriter::new(offset, buff);
+        if write_header(self.header, &mut w).is_err() {
+            return w.written();
+        }
+
+        self.render(0, w.len(), &mut w)
+    }
+}
+
+/// Write the CSV header line
+fn write_header(header: &[&str], w: &mut dyn Write) -> fmt::Result {
+    let mut r = CsvRecord{ w: &mut *w, first: true, res: Ok(()) };
+    for h in header {
+        r.text(h);
+    }
+    r.res?;
+    w.write_str("\r\n")
+}
+
+/// CSV record writer, inserting separators between fields
+pub struct CsvRecord<'w> {
+    w: &'w mut dyn Write,
+    first: bool,
+    res: fmt::Result,
+}
+
+impl <'w> CsvRecord<'w> {
+    /// Write a field using its [`Display`](fmt::Display) implementation
+    pub fn field(&mut self, value: impl fmt::Display) -> &mut Self {
+        if self.separator() {
+            self.res = write!(self.w, "{}", value);
+        }
+        self
+    }
+
+    /// Write a text field, quoting where required
+    pub fn text(&mut self, value: &str) -> &mut Self {
+        if !self.separator() {
+            return self;
+        }
+
+        if !value.contains([',', '"', '\r', '\n']) {
+            self.res = self.w.write_str(value);
+            return self;
+        }
+
+        self.res = (|| {
+            self.w.write_char('"')?;
+            for (i, s) in value.split('"').enumerate() {
+                if i > 0 {
+                    self.w.write_str("\"\"")?;
+                }
+                self.w.write_str(s)?;
+            }
+            self.w.write_char('"')
+        })();
+        self
+    }
+
+    /// Write a field separator if required, returning false on prior errors
+    fn separator(&mut self) -> bool {
+        if self.res.is_err() {
+            return false;
+        }
+
+        if !self.first {
+            self.res = self.w.write_char(',');
+        }
+        self.first = false;
+
+        self.res.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{File, FileContent};
+    use super::*;
+
+    const SAMPLES: [(u32, f32, &str); 4] = [
+        (0, 1.5, "ok"),
+        (10, -2.25, "low, battery"),
+        (20, 3.0, "say \"hi\""),
+        (30, 0.5, "ok"),
+    ];
+
+    #[test]
+    fn render_csv_chunks() {
+        let c = CsvFile::new(&["time", "value", "status"], |i, r| {
+            match SAMPLES.get(i) {
+                Some((t, v, s)) => { r.field(t).field(v).text(s); true },
+                None => false,
+            }
+        });
+        let f = File::<8>::new("LOG.CSV", FileContent::Generated(&c)).unwrap();
+
+        let text = "time,value,status\r\n0,1.5,ok\r\n10,-2.25,\"low, battery\"\r\n20,3,\"say \"\"hi\"\"\"\r\n30,0.5,ok\r\n";
+        assert_eq!(f.len(), text.len());
+
+        let mut buff = [0u8; 8];
+        for (i, d) in text.as_bytes().chunks(8).enumerate() {
+            assert_eq!(f.chunk(i, &mut buff), Ok(d.len()));
+            assert_eq!(&buff[..d.len()], d);
+        }
+
+        // Random access following sequential reads
+        assert_eq!(f.chunk(1, &mut buff), Ok(8));
+        assert_eq!(&buff, &text.as_bytes()[8..16]);
+        assert_eq!(f.chunk(5, &mut buff), Ok(8));
+        assert_eq!(&buff, &text.as_bytes()[40..48]);
+    }
+}
@@ -0,0 +1,105 @@
+use core::fmt::{self, Write};
+
+use crate::{File, GeneratedFile};
+use crate::text::SliceWriter;
+
+/// Device information rendered as a DAPLink-style `DETAILS.TXT` file
+///
+/// Empty fields are omitted from the rendered file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Details<'a> {
+    /// Device or firmware name, rendered as a comment header
+    pub name: &'a str,
+    /// Firmware version
+    pub version: &'a str,
+    /// Device unique ID, rendered as hex
+    pub unique_id: &'a [u8],
+    /// Firmware build date
+    pub build_date: &'a str,
+    /// Supported capabilities (ie. `UF2`, `CONFIG`)
+    pub capabilities: &'a [&'a str],
+}
+
+impl <'a> Details<'a> {
+    /// Create a read-only `DETAILS.TXT` file rendering these details
+    pub const fn file<const BLOCK_SIZE: usize>(&'a self) -> File<'a, BLOCK_SIZE> {
+        File::new_gen("DETAILS.TXT", self)
+    }
+
+    /// Render details as text
+    fn render(&self, w: &mut dyn Write) -> fmt::Result {
+        if !self.name.is_empty() {
+            writeln!(w, "# {}", self.name)?;
+        }
+
+        if !self.version.is_empty() {
+            writeln!(w, "Version: {}", self.version)?;
+        }
+
+        if !self.unique_id.is_empty() {
+            w.write_str("Unique ID: ")?;
+            for b in self.unique_id {
+                write!(w, "{:02x}", b)?;
+            }
+            writeln!(w)?;
+        }
+
+        if !self.build_date.is_empty() {
+            writeln!(w, "Build Date: {}", self.build_date)?;
+        }
+
+        if !self.capabilities.is_empty() {
+            w.write_str("Capabilities: ")?;
+            for (i, c) in self.capabilities.iter().enumerate() {
+                if i > 0 {
+                    w.write_str(", ")?;
+                }
+                w.write_str(c)?;
+            }
+            writeln!(w)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl <'a> GeneratedFile for Details<'a> {
+    fn len(&self) -> usize {
+        let mut w = SliceWriter::new(0, &mut []);
+        let _ = self.render(&mut w);
+        w.len()
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        let mut w = SliceWriter::new(offset, buff);
+        let _ = self.render(&mut w);
+        w.written()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file::Attrs;
+    use super::*;
+
+    #[test]
+    fn render_details() {
+        let d = Details {
+            name: "Example Bootloader",
+            version: "1.2.3",
+            unique_id: &[0xde, 0xad, 0x00, 0x01],
+            capabilities: &["UF2", "CONFIG"],
+            ..Default::default()
+        };
+        let f = d.file::<512>();
+
+        let text = "# Example Bootloader\nVersion: 1.2.3\nUnique ID: dead0001\nCapabilities: UF2, CONFIG\n";
+        assert_eq!(f.name(), "DETAILS.TXT");
+        assert_eq!(f.attrs(), Attrs::READ_ONLY);
+        assert_eq!(f.len(), text.len());
+
+        let mut buff = [0u8; 512];
+        assert_eq!(f.chunk(0, &mut buff), Ok(text.len()));
+        assert_eq!(&buff[..text.len()], text.as_bytes());
+    }
+}
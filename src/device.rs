@@ -0,0 +1,171 @@
+/// Block device errors, reported to the host
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature="defmt", derive(defmt::Format))]
+pub enum BlockDeviceError {
+    /// Hardware did not behave as expected, unrecoverable
+    HardwareError,
+    /// Error writing the block
+    WriteError,
+    /// Error erasing the block
+    EraseError,
+    /// Address is invalid or out of range
+    InvalidAddress,
+    /// Device is temporarily unavailable (ie. files are being updated),
+    /// the access may be retried.
+    /// 
+    /// Reported as NOT READY by the built-in `scsi` module.
+    /// `usbd_scsi` has no equivalent error, so this is reported as a
+    /// (non-retryable) hardware error via the `usbd_scsi` adapter.
+    NotReady,
+}
+
+/// Block device trait, implemented by [`GhostFat`](crate::GhostFat) for
+/// use with USB mass storage stacks or host-side tooling.
+///
+/// With the `usbd_scsi` feature file systems also implement
+/// `usbd_scsi::BlockDevice` for use with [`usbd_scsi`].
+pub trait BlockDevice {
+    /// Number of bytes per block, setting the size of buffers passed to
+    /// [`BlockDevice::read_block`] and [`BlockDevice::write_block`]
+    const BLOCK_BYTES: usize;
+
+    /// Read the block at `lba` into the provided buffer
+    fn read_block(&self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError>;
+
+    /// Read consecutive blocks from `lba` into the provided buffer, which
+    /// must be a multiple of [`BlockDevice::BLOCK_BYTES`]
+    fn read_blocks(&self, lba: u32, blocks: &mut [u8]) -> Result<(), BlockDeviceError> {
+        if !blocks.len().is_multiple_of(Self::BLOCK_BYTES) || !in_range(lba, blocks.len() / Self::BLOCK_BYTES, self.max_lba()) {
+            return Err(BlockDeviceError::InvalidAddress);
+        }
+
+        for (i, b) in blocks.chunks_exact_mut(Self::BLOCK_BYTES).enumerate() {
+            self.read_block(lba + i as u32, b)?;
+        }
+        Ok(())
+    }
+
+    /// Write the provided buffer to the block at `lba`
+    fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError>;
+
+    /// Write the provided buffer to consecutive blocks from `lba`, where the
+    /// buffer must be a multiple of [`BlockDevice::BLOCK_BYTES`]
+    fn write_blocks(&mut self, lba: u32, blocks: &[u8]) -> Result<(), BlockDeviceError> {
+        if !blocks.len().is_multiple_of(Self::BLOCK_BYTES) || !in_range(lba, blocks.len() / Self::BLOCK_BYTES, self.max_lba()) {
+            return Err(BlockDeviceError::InvalidAddress);
+        }
+
+        for (i, b) in blocks.chunks_exact(Self::BLOCK_BYTES).enumerate() {
+            self.write_block(lba + i as u32, b)?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the maximum valid LBA
+    fn max_lba(&self) -> u32;
+
+    /// Check whether the device is ready for access, failing with
+    /// [`BlockDeviceError::NotReady`] where accesses should be retried
+    /// later (ie. prior to initialisation)
+    fn check_ready(&self) -> Result<(), BlockDeviceError> {
+        Ok(())
+    }
+}
+
+/// Check whether a run of `count` blocks from `lba` lies within a device
+/// with the provided maximum LBA
+pub(crate) fn in_range(lba: u32, count: usize, max_lba: u32) -> bool {
+    match u32::try_from(count).ok().and_then(|c| lba.checked_add(c)) {
+        Some(end) => count == 0 || end - 1 <= max_lba,
+        None => false,
+    }
+}
+
+/// Lossy conversion to `usbd_scsi` errors, with [`BlockDeviceError::NotReady`]
+/// mapped to a hardware error as `usbd_scsi` cannot report a retryable state
+#[cfg(feature = "usbd_scsi")]
+impl From<BlockDeviceError> for usbd_scsi::BlockDeviceError {
+    fn from(e: BlockDeviceError) -> Self {
+        match e {
+            BlockDeviceError::HardwareError => usbd_scsi::BlockDeviceError::HardwareError,
+            BlockDeviceError::WriteError => usbd_scsi::BlockDeviceError::WriteError,
+            BlockDeviceError::EraseError => usbd_scsi::BlockDeviceError::EraseError,
+            BlockDeviceError::InvalidAddress => usbd_scsi::BlockDeviceError::InvalidAddress,
+            BlockDeviceError::NotReady => usbd_scsi::BlockDeviceError::HardwareError,
+        }
+    }
+}
+
+#[cfg(feature = "usbd_scsi")]
+impl From<usbd_scsi::BlockDeviceError> for BlockDeviceError {
+    fn from(e: usbd_scsi::BlockDeviceError) -> Self {
+        match e {
+            usbd_scsi::BlockDeviceError::HardwareError => BlockDeviceError::HardwareError,
+            usbd_scsi::BlockDeviceError::WriteError => BlockDeviceError::WriteError,
+            usbd_scsi::BlockDeviceError::EraseError => BlockDeviceError::EraseError,
+            usbd_scsi::BlockDeviceError::InvalidAddress => BlockDeviceError::InvalidAddress,
+        }
+    }
+}
+
+/// Implement `usbd_scsi::BlockDevice` by delegating to [`BlockDevice`].
+/// 
+/// Accesses failing with [`BlockDeviceError::NotReady`] (ie. overlapping a
+/// [`GhostFatWriter`](crate::GhostFatWriter) update or prior to
+/// [`SharedGhostFat`](crate::SharedGhostFat) initialisation) are reported
+/// to the host as hardware errors, use the built-in `scsi` module where
+/// hosts should retry these accesses.
+#[cfg(feature = "usbd_scsi")]
+macro_rules! impl_usbd_scsi {
+    ($($t:tt)*) => {
+        $($t)* {
+            const BLOCK_BYTES: usize = <Self as BlockDevice>::BLOCK_BYTES;
+
+            fn read_block(&self, lba: u32, block: &mut [u8]) -> Result<(), usbd_scsi::BlockDeviceError> {
+                BlockDevice::read_block(self, lba, block).map_err(Into::into)
+            }
+
+            fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), usbd_scsi::BlockDeviceError> {
+                BlockDevice::write_block(self, lba, block).map_err(Into::into)
+            }
+
+            fn max_lba(&self) -> u32 {
+                BlockDevice::max_lba(self)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "usbd_scsi")]
+impl_usbd_scsi!(impl <'a, const BLOCK_SIZE: usize, const FILES: usize> usbd_scsi::BlockDevice for crate::GhostFat<'a, BLOCK_SIZE, FILES>);
+
+#[cfg(feature = "usbd_scsi")]
+impl_usbd_scsi!(impl <'s, 'a, const BLOCK_SIZE: usize, const FILES: usize> usbd_scsi::BlockDevice for crate::GhostFatReader<'s, 'a, BLOCK_SIZE, FILES>);
+
+#[cfg(all(feature = "usbd_scsi", feature = "critical-section"))]
+impl_usbd_scsi!(impl <'r, 'a, const BLOCK_SIZE: usize, const FILES: usize> usbd_scsi::BlockDevice for &'r crate::SharedGhostFat<'a, BLOCK_SIZE, FILES>);
+
+#[cfg(all(test, feature = "usbd_scsi"))]
+mod tests {
+    use crate::{Config, File, GhostFat};
+
+    #[test]
+    fn usbd_scsi_device() {
+        let data = [0xAAu8; 16];
+        let mut f = [File::<512>::new_ro("DATA.BIN", &data)];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        fn read<D: usbd_scsi::BlockDevice>(d: &D, lba: u32, block: &mut [u8]) -> Result<(), usbd_scsi::BlockDeviceError> {
+            d.read_block(lba, block)
+        }
+
+        let mut block = [0u8; 512];
+        read(&fs, lba, &mut block).unwrap();
+        assert_eq!(&block[..16], &data);
+
+        assert_eq!(read(&fs, lba, &mut block[..8]), Err(usbd_scsi::BlockDeviceError::InvalidAddress));
+        assert_eq!(usbd_scsi::BlockDevice::max_lba(&fs), 7999);
+        assert!(usbd_scsi::BlockDevice::write_block(&mut fs, 0, &[0u8; 512]).is_ok());
+    }
+}
@@ -0,0 +1,177 @@
+use embedded_storage::Storage;
+
+use crate::{DynamicFile, FileError};
+
+/// Adapter exposing a small byte-addressable EEPROM (ie. an I2C or SPI
+/// config EEPROM implementing [`embedded_storage::Storage`]) as an
+/// editable file.
+///
+/// EEPROM drivers require exclusive access for reads, so the first `N`
+/// bytes are mirrored in RAM on creation with host reads served from the
+/// mirror. Host writes are split on `PAGE` byte page-write boundaries,
+/// with only pages that differ from the mirror written to save wear.
+pub struct EepromFile<S, const N: usize, const PAGE: usize> {
+    storage: S,
+    mirror: [u8; N],
+}
+
+impl <S: Storage, const N: usize, const PAGE: usize> EepromFile<S, N, PAGE> {
+    /// Create a new file over the provided storage, loading the mirror
+    pub fn new(storage: S) -> Result<Self, FileError> {
+        const { assert!(PAGE > 0, "page size must be non-zero") };
+
+        let mut s = Self { storage, mirror: [0u8; N] };
+        s.reload()?;
+
+        Ok(s)
+    }
+
+    /// Re-load the mirror from storage, ie. where firmware has modified the EEPROM
+    pub fn reload(&mut self) -> Result<(), FileError> {
+        if self.storage.capacity() < N {
+            crate::error!("EEPROM capacity {} smaller than file length {}", self.storage.capacity(), N);
+            return Err(FileError::NoSpace);
+        }
+
+        self.storage.read(0, &mut self.mirror)
+            .map_err(|_| FileError::ReadError)
+    }
+
+    /// Fetch the mirrored EEPROM content
+    pub fn data(&self) -> &[u8] {
+        &self.mirror
+    }
+
+    /// Fetch a mutable reference to the underlying storage, call
+    /// [`EepromFile::reload`] after modifying content
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    /// Free the underlying storage
+    pub fn free(self) -> S {
+        self.storage
+    }
+
+    /// Write data at the provided address, split on page boundaries
+    fn write(&mut self, mut address: usize, mut data: &[u8]) -> Result<(), FileError> {
+        while !data.is_empty() {
+            let n = usize::min(data.len(), PAGE - address % PAGE);
+
+            // Skip unchanged pages
+            if self.mirror[address..][..n] != data[..n] {
+                crate::trace!("EEPROM page write at 0x{:04x} ({} bytes)", address, n);
+
+                self.storage.write(address as u32, &data[..n])
+                    .map_err(|_| FileError::WriteError)?;
+                self.mirror[address..][..n].copy_from_slice(&data[..n]);
+            }
+
+            address += n;
+            data = &data[n..];
+        }
+
+        Ok(())
+    }
+}
+
+impl <S: Storage + Sync + Send, const N: usize, const PAGE: usize, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for EepromFile<S, N, PAGE> {
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        let d = match self.mirror.chunks(BLOCK_SIZE).nth(chunk_index) {
+            Some(d) => d,
+            None => return Ok(0),
+        };
+
+        let n = usize::min(buff.len(), d.len());
+        buff[..n].copy_from_slice(&d[..n]);
+        Ok(n)
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        let offset = chunk_index * BLOCK_SIZE;
+        if offset >= N {
+            return Ok(0);
+        }
+
+        let n = usize::min(usize::min(data.len(), BLOCK_SIZE), N - offset);
+        self.write(offset, &data[..n])?;
+
+        Ok(n)
+    }
+
+    fn write_chunks(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        let offset = chunk_index * BLOCK_SIZE;
+        if offset >= N {
+            return Ok(0);
+        }
+
+        let n = usize::min(data.len(), N - offset);
+        self.write(offset, &data[..n])?;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_storage::ReadStorage;
+
+    use super::*;
+
+    /// EEPROM recording page writes
+    struct Mem {
+        data: [u8; 32],
+        writes: usize,
+    }
+
+    impl ReadStorage for Mem {
+        type Error = ();
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            bytes.copy_from_slice(&self.data[offset as usize..][..bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl Storage for Mem {
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            // Writes must not cross 4 byte pages
+            assert_eq!(offset as usize / 4, (offset as usize + bytes.len() - 1) / 4);
+
+            self.data[offset as usize..][..bytes.len()].copy_from_slice(bytes);
+            self.writes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn eeprom_pages() {
+        let mem = Mem{ data: [0xAA; 32], writes: 0 };
+        let mut f = EepromFile::<_, 20, 4>::new(mem).unwrap();
+        let mut buff = [0u8; 8];
+
+        assert_eq!(DynamicFile::<8>::read_chunk(&f, 2, &mut buff), Ok(4));
+        assert_eq!(&buff[..4], &[0xAA; 4]);
+
+        // Only modified pages are written
+        let mut data = [0xAA; 8];
+        data[1] = 0x11;
+        data[7] = 0x22;
+        assert_eq!(DynamicFile::<8>::write_chunk(&mut f, 1, &data), Ok(8));
+
+        let mem = f.free();
+        assert_eq!(mem.writes, 2);
+        assert_eq!(&mem.data[8..16], &data);
+
+        // Files must fit the EEPROM
+        assert!(EepromFile::<_, 64, 4>::new(mem).is_err());
+    }
+}
@@ -0,0 +1,23 @@
+use crate::BlockDeviceError;
+
+/// Internal file system errors, reported to the host as [`BlockDeviceError`]s
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Error {
+    /// Block buffer length does not match the block size
+    InvalidLength(usize),
+    /// Failed to encode a boot sector or directory entry
+    Encode,
+    /// File at the provided index does not have a valid 8.3 name
+    InvalidName(usize),
+    /// Files exceed the generated root directory sector
+    DirectoryFull,
+}
+
+impl From<Error> for BlockDeviceError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::InvalidLength(_) => BlockDeviceError::InvalidAddress,
+            Error::Encode | Error::InvalidName(_) | Error::DirectoryFull => BlockDeviceError::HardwareError,
+        }
+    }
+}
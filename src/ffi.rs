@@ -0,0 +1,274 @@
+//! C bindings for mixed C/Rust firmware, see `include/ghostfat.h`
+//!
+//! A single file system instance is created from a static file table with
+//! [`ghostfat_init`], with block reads and writes (ie. from TinyUSB
+//! `tud_msc_read10_cb` / `tud_msc_write10_cb` or a vendor MSC class)
+//! serviced by [`ghostfat_read_block`] and [`ghostfat_write_block`].
+//!
+//! Functions are not re-entrant, and must be called from a single context
+//! (ie. the USB task) or with the USB interrupt masked.
+
+use core::cell::UnsafeCell;
+use core::ffi::{c_char, CStr};
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{BlockDevice, BlockDeviceError, Config, File, GhostFat};
+
+/// Maximum number of files in the C file table
+pub const GHOSTFAT_MAX_FILES: usize = 15;
+
+/// C block size
+const BLOCK_SIZE: usize = 512;
+
+/// C file table entry
+#[repr(C)]
+pub struct GhostFatFile {
+    /// NUL-terminated 8.3 file name
+    pub name: *const c_char,
+    /// File data, must be valid for `len` bytes
+    pub data: *mut u8,
+    /// File length in bytes
+    pub len: usize,
+    /// Allow host writes to the file data
+    pub writable: bool,
+}
+
+/// C function status codes
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GhostFatStatus {
+    Ok = 0,
+    HardwareError = -1,
+    WriteError = -2,
+    EraseError = -3,
+    InvalidAddress = -4,
+    /// Invalid file table (ie. invalid or duplicate names, or files exceeding the volume)
+    InvalidFiles = -5,
+    /// File system not initialised
+    NotInitialised = -6,
+    /// File system already initialised
+    AlreadyInitialised = -7,
+    /// File system busy, the access may be retried
+    NotReady = -8,
+}
+
+impl From<Result<(), BlockDeviceError>> for GhostFatStatus {
+    fn from(r: Result<(), BlockDeviceError>) -> Self {
+        match r {
+            Ok(()) => GhostFatStatus::Ok,
+            Err(BlockDeviceError::HardwareError) => GhostFatStatus::HardwareError,
+            Err(BlockDeviceError::WriteError) => GhostFatStatus::WriteError,
+            Err(BlockDeviceError::EraseError) => GhostFatStatus::EraseError,
+            Err(BlockDeviceError::InvalidAddress) => GhostFatStatus::InvalidAddress,
+            Err(BlockDeviceError::NotReady) => GhostFatStatus::NotReady,
+        }
+    }
+}
+
+/// Static storage for the C file system instance
+struct State {
+    init: AtomicBool,
+    ready: AtomicBool,
+    files: UnsafeCell<[MaybeUninit<File<'static, BLOCK_SIZE>>; GHOSTFAT_MAX_FILES]>,
+    fs: UnsafeCell<MaybeUninit<GhostFat<'static, BLOCK_SIZE>>>,
+}
+
+// Safety: access is serialised by the caller, see module documentation
+unsafe impl Sync for State {}
+
+static STATE: State = State {
+    init: AtomicBool::new(false),
+    ready: AtomicBool::new(false),
+    files: UnsafeCell::new([const { MaybeUninit::uninit() }; GHOSTFAT_MAX_FILES]),
+    fs: UnsafeCell::new(MaybeUninit::uninit()),
+};
+
+/// Fetch the initialised file system
+/// 
+/// # Safety
+/// Callers must not hold another reference to the file system
+unsafe fn fs() -> Option<&'static mut GhostFat<'static, BLOCK_SIZE>> {
+    match STATE.ready.load(Ordering::Acquire) {
+        true => Some((*STATE.fs.get()).assume_init_mut()),
+        false => None,
+    }
+}
+
+/// Convert a C file table entry to a file
+/// 
+/// # Safety
+/// Entry pointers must be valid for the program lifetime
+unsafe fn file(f: &GhostFatFile) -> Option<File<'static, BLOCK_SIZE>> {
+    if f.name.is_null() || (f.data.is_null() && f.len > 0) {
+        return None;
+    }
+
+    let name = CStr::from_ptr(f.name).to_str().ok()?;
+    let data: &'static mut [u8] = match f.len {
+        0 => &mut [],
+        n => core::slice::from_raw_parts_mut(f.data, n),
+    };
+
+    match f.writable {
+        true => File::new(name, data).ok(),
+        false => Some(File::new_ro(name, data)),
+    }
+}
+
+/// Initialise the file system from a static table of `count` files, with
+/// `num_blocks` 512 byte blocks (or sized to fit the files where zero).
+/// 
+/// # Safety
+/// `files` must point to `count` entries, with names and data valid for the
+/// program lifetime, and file data must not be accessed by C code while
+/// writable by the host.
+#[no_mangle]
+pub unsafe extern "C" fn ghostfat_init(files: *const GhostFatFile, count: usize, num_blocks: u32) -> GhostFatStatus {
+    if files.is_null() || count > GHOSTFAT_MAX_FILES {
+        return GhostFatStatus::InvalidFiles;
+    }
+
+    if STATE.init.swap(true, Ordering::AcqRel) {
+        return GhostFatStatus::AlreadyInitialised;
+    }
+
+    let table = &mut *STATE.files.get();
+    for (i, f) in core::slice::from_raw_parts(files, count).iter().enumerate() {
+        match file(f) {
+            Some(f) => { table[i].write(f); },
+            None => {
+                // Release storage for retry, files hold no resources
+                STATE.init.store(false, Ordering::Release);
+                return GhostFatStatus::InvalidFiles;
+            },
+        }
+    }
+
+    // Safety: the first `count` entries are initialised above
+    let files = core::slice::from_raw_parts_mut(table.as_mut_ptr() as *mut File<'static, BLOCK_SIZE>, count);
+
+    let config = match num_blocks {
+        0 => Config::for_files(files, 0),
+        n => {
+            let mut c = Config::new();
+            c.num_blocks = n;
+            c
+        },
+    };
+
+    if crate::builder::validate(&config, files).is_err() {
+        crate::error!("Invalid C file table layout");
+        STATE.init.store(false, Ordering::Release);
+        return GhostFatStatus::InvalidFiles;
+    }
+
+    (*STATE.fs.get()).write(GhostFat::new(files, config));
+    STATE.ready.store(true, Ordering::Release);
+
+    GhostFatStatus::Ok
+}
+
+/// Read the block at `lba` into `block`, which must be `len` (512) bytes
+/// 
+/// # Safety
+/// `block` must be valid for writes of `len` bytes, and calls must not
+/// overlap other `ghostfat_` calls
+#[no_mangle]
+pub unsafe extern "C" fn ghostfat_read_block(lba: u32, block: *mut u8, len: usize) -> GhostFatStatus {
+    let fs = match fs() {
+        Some(fs) => fs,
+        None => return GhostFatStatus::NotInitialised,
+    };
+
+    if block.is_null() {
+        return GhostFatStatus::InvalidAddress;
+    }
+
+    fs.read_block(lba, core::slice::from_raw_parts_mut(block, len)).into()
+}
+
+/// Write the block at `lba` from `block`, which must be `len` (512) bytes
+/// 
+/// # Safety
+/// `block` must be valid for reads of `len` bytes, and calls must not
+/// overlap other `ghostfat_` calls
+#[no_mangle]
+pub unsafe extern "C" fn ghostfat_write_block(lba: u32, block: *const u8, len: usize) -> GhostFatStatus {
+    let fs = match fs() {
+        Some(fs) => fs,
+        None => return GhostFatStatus::NotInitialised,
+    };
+
+    if block.is_null() {
+        return GhostFatStatus::InvalidAddress;
+    }
+
+    fs.write_block(lba, core::slice::from_raw_parts(block, len)).into()
+}
+
+/// Fetch the number of blocks in the file system, or zero if not initialised
+/// 
+/// # Safety
+/// Calls must not overlap other `ghostfat_` calls
+#[no_mangle]
+pub unsafe extern "C" fn ghostfat_block_count() -> u32 {
+    match fs() {
+        Some(fs) => fs.max_lba() + 1,
+        None => 0,
+    }
+}
+
+/// Fetch the file system block size
+#[no_mangle]
+pub extern "C" fn ghostfat_block_size() -> u32 {
+    BLOCK_SIZE as u32
+}
+
+/// Advance the host session timer, returning true where a host session
+/// has timed out, see [`GhostFat::tick`]
+/// 
+/// # Safety
+/// Calls must not overlap other `ghostfat_` calls
+#[no_mangle]
+pub unsafe extern "C" fn ghostfat_tick() -> bool {
+    match fs() {
+        Some(fs) => fs.tick(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static mut DATA: [u8; 16] = [0xAA; 16];
+
+    #[test]
+    fn c_bindings() {
+        let files = [
+            GhostFatFile { name: c"DATA.BIN".as_ptr(), data: &raw mut DATA as *mut u8, len: 16, writable: true },
+            GhostFatFile { name: c"README.TEXT".as_ptr(), data: core::ptr::null_mut(), len: 0, writable: false },
+        ];
+
+        let mut block = [0u8; 512];
+        unsafe {
+            assert_eq!(ghostfat_read_block(0, block.as_mut_ptr(), 512), GhostFatStatus::NotInitialised);
+            assert_eq!(ghostfat_init(files.as_ptr(), 2, 0), GhostFatStatus::InvalidFiles);
+            assert_eq!(ghostfat_init(files.as_ptr(), 1, 0), GhostFatStatus::Ok);
+            assert_eq!(ghostfat_init(files.as_ptr(), 1, 0), GhostFatStatus::AlreadyInitialised);
+
+            let lba = fs().unwrap().config.start_clusters().0;
+            assert_eq!(ghostfat_read_block(lba, block.as_mut_ptr(), 512), GhostFatStatus::Ok);
+            assert_eq!(&block[..16], &[0xAA; 16]);
+            assert_eq!(ghostfat_read_block(lba, block.as_mut_ptr(), 8), GhostFatStatus::InvalidAddress);
+
+            block[..16].fill(0x55);
+            assert_eq!(ghostfat_write_block(lba, block.as_ptr(), 512), GhostFatStatus::Ok);
+            let data = DATA;
+            assert_eq!(data, [0x55; 16]);
+
+            assert!(ghostfat_block_count() > 4085);
+        }
+    }
+}
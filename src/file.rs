@@ -1,10 +1,71 @@
 
+use core::ops::{Deref, DerefMut, Range};
+
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+
+use crate::BlockDeviceError;
+
 use crate::ASCII_SPACE;
+use crate::virgin::{Virgin, VirginPolicy, WriteMap};
+#[cfg(feature = "heapless")]
+use crate::registry::Registry;
 
 /// Virtual file object
 pub struct File<'a, const BLOCK_SIZE: usize = 512> {
-    pub(crate) name: &'a str,
+    pub(crate) name: Name<'a>,
     pub(crate) data: FileContent<'a, BLOCK_SIZE>,
+    pub(crate) virgin: Option<Virgin<'a>>,
+    pub(crate) hooks: Option<&'a dyn FileHooks>,
+    pub(crate) reserved: usize,
+    pub(crate) deleted: bool,
+    pub(crate) renamed: Option<[u8; 11]>,
+    pub(crate) dirty: Option<Range<usize>>,
+}
+
+/// File name storage
+pub(crate) enum Name<'a> {
+    Borrowed(&'a str),
+    #[cfg(feature = "alloc")]
+    Owned(String),
+}
+
+/// File table storage
+pub(crate) enum Files<'a, const BLOCK_SIZE: usize, const FILES: usize = 0> {
+    Borrowed(&'a mut [File<'a, BLOCK_SIZE>]),
+    Array([File<'a, BLOCK_SIZE>; FILES]),
+    #[cfg(feature = "alloc")]
+    Owned(Vec<File<'a, BLOCK_SIZE>>),
+    #[cfg(feature = "heapless")]
+    Registry(&'a mut dyn Registry<'a, BLOCK_SIZE>),
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> Deref for Files<'a, BLOCK_SIZE, FILES> {
+    type Target = [File<'a, BLOCK_SIZE>];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Files::Borrowed(f) => f,
+            Files::Array(f) => f,
+            #[cfg(feature = "alloc")]
+            Files::Owned(f) => f,
+            #[cfg(feature = "heapless")]
+            Files::Registry(r) => r.files(),
+        }
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> DerefMut for Files<'a, BLOCK_SIZE, FILES> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Files::Borrowed(f) => f,
+            Files::Array(f) => f,
+            #[cfg(feature = "alloc")]
+            Files::Owned(f) => f,
+            #[cfg(feature = "heapless")]
+            Files::Registry(r) => r.files_mut(),
+        }
+    }
 }
 
 /// Files may contain a read buffer, write buffer, or read/write trait
@@ -15,6 +76,31 @@ pub enum FileContent<'a, const BLOCK_SIZE: usize = 512> {
     Write(&'a mut [u8]),
     /// Read/write object
     Dynamic(&'a mut dyn DynamicFile<BLOCK_SIZE>),
+    /// Read only object generated on demand
+    Generated(&'a dyn GeneratedFile),
+    /// Read only buffer assembled from multiple non-contiguous segments
+    Segments(&'a [&'a [u8]]),
+    /// Read only placeholder of arbitrary length without backing storage,
+    /// reading as the fill byte
+    Sparse { len: usize, fill: u8 },
+    /// Read/write object of `len` bytes served by the async block device
+    /// path, referencing an [`AsyncDynamicFile`](crate::AsyncDynamicFile)
+    /// by index
+    Async { index: usize, len: usize },
+    /// Read only object generated from the content of other files in the
+    /// file system, rendered by the file system on read
+    Companion(&'a dyn crate::CompanionFile<BLOCK_SIZE>),
+    /// Read only listing of the other files in the file system, rendered
+    /// by the file system on read (see [`File::new_manifest`])
+    #[cfg(feature = "crc32fast")]
+    Manifest { format: crate::ManifestFormat, entries: usize },
+    /// Read/write buffer with a used length separate from its capacity,
+    /// allowing host writes to grow or shrink the file within the buffer
+    #[cfg(feature = "heapless")]
+    Vec(&'a mut dyn crate::VecBuffer),
+    /// Owned read/write buffer
+    #[cfg(feature = "alloc")]
+    Owned(Vec<u8>),
 }
 
 /// ReadWrite trait for generic file objects
@@ -22,17 +108,190 @@ pub trait DynamicFile<const BLOCK_SIZE: usize = 512>: Sync + Send {
     /// Return the maximum length of the virtual file in bytes
     fn len(&self) -> usize;
 
+    /// Check whether the virtual file is empty
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// Read a chunk of the virtual file, returning the read length
-    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> usize;
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError>;
+
+    /// Read consecutive chunks of the virtual file from `chunk_index` into
+    /// the provided buffer (a multiple of `BLOCK_SIZE`), returning the read
+    /// length with the buffer written up to this length.
+    ///
+    /// Multi-block host reads within the file are passed through in a single
+    /// call, so backends able to service ranges (ie. DMA or multi-block SD
+    /// card reads) may override this in place of per-chunk reads.
+    fn read_chunks(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        let mut n = 0;
+        for (i, b) in buff.chunks_mut(BLOCK_SIZE).enumerate() {
+            let m = self.read_chunk(chunk_index + i, b)?;
+
+            // Clear the remainder of short chunks within the read length
+            if let Some(r) = b.get_mut(m..) {
+                r.fill(0);
+            }
+            if m > 0 {
+                n = i * BLOCK_SIZE + m;
+            }
+        }
+        Ok(n)
+    }
 
     /// Write a chunk of the virtual file, returning the write length
-    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> usize;
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError>;
+
+    /// Write consecutive chunks of the virtual file from `chunk_index` from
+    /// the provided data (a multiple of `BLOCK_SIZE`), returning the total
+    /// write length.
+    ///
+    /// Multi-block host writes within the file are passed through in a single
+    /// call, so flash backed files may override this to erase and program
+    /// whole pages rather than a block at a time.
+    fn write_chunks(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        let mut n = 0;
+        for (i, d) in data.chunks(BLOCK_SIZE).enumerate() {
+            n += self.write_chunk(chunk_index + i, d)?;
+        }
+        Ok(n)
+    }
+
+    /// Perform a bounded step of deferred maintenance outside of interrupt
+    /// context, returning true while work remains
+    fn poll(&mut self) -> bool {
+        false
+    }
+
+    /// Commit any buffered writes to the backing store
+    fn flush(&mut self) -> Result<(), FileError> {
+        Ok(())
+    }
+}
+
+/// Read only trait for files generated on demand
+pub trait GeneratedFile: Sync + Send {
+    /// Return the length of the generated file in bytes
+    fn len(&self) -> usize;
+
+    /// Check whether the generated file is empty
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Generate file content from the provided byte offset into the buffer,
+    /// returning the generated length
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize;
+}
+
+/// Event hooks called when the host accesses a file, attached via [`File::with_hooks`]
+/// 
+/// Hooks are called from the block device context and should defer any
+/// long-running work (ie. by setting a flag for the main loop).
+pub trait FileHooks: Sync + Send {
+    /// Called when the host reads a block of the file
+    fn on_read(&self, block_index: usize) {
+        let _ = block_index;
+    }
+
+    /// Called to validate a host write prior to it being applied, returning
+    /// an error to reject the write (ie. invalid firmware magic or CRC) so
+    /// the error is reported to the host and the file is left unchanged
+    fn validate(&self, block_index: usize, data: &[u8]) -> Result<(), FileError> {
+        let _ = (block_index, data);
+        Ok(())
+    }
+
+    /// Called when the host has written a block of the file
+    fn on_write(&self, block_index: usize, data: &[u8]) {
+        let _ = (block_index, data);
+    }
+
+    /// Called once the host has finished writing the file, with the
+    /// committed length, ie. to verify and apply a firmware update.
+    ///
+    /// Completion is detected once the host has written the file size to
+    /// the directory entry and written the final block, or on host session
+    /// timeout (see [`Config::host_timeout`](crate::Config::host_timeout))
+    /// where the size has not been committed.
+    fn on_file_complete(&self, name: &str, len: usize) {
+        let _ = (name, len);
+    }
+
+    /// Called when the file system is flushed (ie. on host cache sync or
+    /// eject), see [`GhostFat::flush`](crate::GhostFat::flush)
+    fn on_flush(&self) {}
+
+    /// Called when the host deletes the file, ie. to clear stored data
+    fn on_delete(&self) {}
+
+    /// Called when the host renames the file, with the new `NAME.EXT`
+    fn on_rename(&self, name: &str) {
+        let _ = name;
+    }
+}
+
+/// Observer for host writes to the data region, attached via
+/// [`GhostFat::with_observer`](crate::GhostFat::with_observer)
+/// 
+/// Observers see every data region write, including writes to clusters not
+/// allocated to a file (ie. new files created by the host), prior to the
+/// write being applied.
+pub trait WriteObserver: Send {
+    /// Called when the host writes a block to the data region, with the
+    /// index of the block from the start of the data region
+    fn on_write(&mut self, block_index: usize, data: &[u8]);
+}
+
+/// [`GeneratedFile`] adapter for closures called with `(offset, buff)`
+pub struct GeneratorFn<F> {
+    len: usize,
+    f: F,
+}
+
+impl <F: Fn(usize, &mut [u8]) -> usize + Sync + Send> GeneratorFn<F> {
+    /// Create a new generated file of `len` bytes using the provided closure
+    pub const fn new(len: usize, f: F) -> Self {
+        Self { len, f }
+    }
+}
+
+impl <F: Fn(usize, &mut [u8]) -> usize + Sync + Send> GeneratedFile for GeneratorFn<F> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        (self.f)(offset, buff)
+    }
 }
 
 /// File error types
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum FileError {
     InvalidName,
+    /// Operation requires the async block device path
+    WouldBlock,
+    /// Error reading from the file backend
+    ReadError,
+    /// Error writing to the file backend
+    WriteError,
+    /// Error erasing the file backend
+    EraseError,
+    /// Insufficient space in the file backend
+    NoSpace,
+    /// Invalid file content (ie. malformed records)
+    InvalidData,
+}
+
+impl From<FileError> for BlockDeviceError {
+    fn from(e: FileError) -> Self {
+        match e {
+            FileError::InvalidName | FileError::WouldBlock | FileError::ReadError => BlockDeviceError::HardwareError,
+            FileError::WriteError | FileError::NoSpace | FileError::InvalidData => BlockDeviceError::WriteError,
+            FileError::EraseError => BlockDeviceError::EraseError,
+        }
+    }
 }
 
 bitflags::bitflags! {
@@ -48,6 +307,22 @@ bitflags::bitflags! {
     }
 }
 
+/// Attributes are serialized as raw attribute bits
+#[cfg(feature = "serde")]
+impl serde::Serialize for Attrs {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.bits())
+    }
+}
+
+/// Unknown attribute bits are discarded on deserialization
+#[cfg(feature = "serde")]
+impl <'de> serde::Deserialize<'de> for Attrs {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(Attrs::from_bits_truncate)
+    }
+}
+
 /// Create a file from an immutable buffer
 impl <'a, const BLOCK_SIZE: usize>From<&'a [u8]> for FileContent<'a, BLOCK_SIZE> {
     fn from(d: &'a [u8]) -> Self {
@@ -62,6 +337,13 @@ impl <'a, const BLOCK_SIZE: usize, const N: usize>From<&'a [u8; N]> for FileCont
     }
 }
 
+/// Create a file from a list of immutable segments
+impl <'a, const BLOCK_SIZE: usize>From<&'a [&'a [u8]]> for FileContent<'a, BLOCK_SIZE> {
+    fn from(d: &'a [&'a [u8]]) -> Self {
+        FileContent::Segments(d)
+    }
+}
+
 /// Create a file from a mutable buffer
 impl <'a, const BLOCK_SIZE: usize>From<&'a mut [u8]> for FileContent<'a, BLOCK_SIZE> {
     fn from(d: &'a mut [u8]) -> Self {
@@ -82,8 +364,14 @@ impl <'a, const BLOCK_SIZE: usize> File<'a, BLOCK_SIZE> {
 
         // Build object
         let f = Self {
-            name,
+            name: Name::Borrowed(name),
             data: data.into(),
+            virgin: None,
+            hooks: None,
+            reserved: 0,
+            deleted: false,
+            renamed: None,
+            dirty: None,
         };
 
         // Check short name generation
@@ -96,7 +384,21 @@ impl <'a, const BLOCK_SIZE: usize> File<'a, BLOCK_SIZE> {
     /// 
     /// Beware this function will not check short file name creation
     pub const fn new_ro(name: &'a str, data: &'a [u8]) -> Self {
-        Self{ name, data: FileContent::Read(data) }
+        Self{ name: Name::Borrowed(name), data: FileContent::Read(data), virgin: None, hooks: None, reserved: 0, deleted: false, renamed: None, dirty: None }
+    }
+
+    /// Constant helper to create read only files, checking the name is a
+    /// valid 8.3 short name.
+    /// 
+    /// When used to initialise a `static` or `const` file table (ie. with
+    /// `include_bytes!`) invalid names fail at compile time, otherwise this
+    /// will panic on invalid names.
+    pub const fn new_ro_checked(name: &'a str, data: &'a [u8]) -> Self {
+        if !valid_short_name(name) {
+            panic!("Invalid 8.3 file name");
+        }
+
+        Self::new_ro(name, data)
     }
 
     /// Constant helper to create read-write files.
@@ -104,7 +406,7 @@ impl <'a, const BLOCK_SIZE: usize> File<'a, BLOCK_SIZE> {
     /// Beware this function will not check short file name creation
     #[cfg(feature="nightly")]
     pub const fn new_rw(name: &'a str, data: &'a mut [u8]) -> Self {
-        Self{ name, data: FileContent::Write(data) }
+        Self{ name: Name::Borrowed(name), data: FileContent::Write(data), virgin: None, hooks: None, reserved: 0, deleted: false, renamed: None, dirty: None }
     }
 
     /// Constant helper to create dynamic files.
@@ -112,35 +414,75 @@ impl <'a, const BLOCK_SIZE: usize> File<'a, BLOCK_SIZE> {
     /// Beware this function will not check short file name creation
     #[cfg(feature="nightly")]
     pub const fn new_dyn(name: &'a str, data: &'a mut dyn DynamicFile<BLOCK_SIZE>) -> Self {
-        Self{ name, data: FileContent::Dynamic(data) }
+        Self{ name: Name::Borrowed(name), data: FileContent::Dynamic(data), virgin: None, hooks: None, reserved: 0, deleted: false, renamed: None, dirty: None }
     }
 
-    /// Fetch the file name
-    pub fn name(&self) -> &str {
-        self.name
+    /// Constant helper to create generated files.
+    /// 
+    /// Beware this function will not check short file name creation
+    pub const fn new_gen(name: &'a str, data: &'a dyn GeneratedFile) -> Self {
+        Self{ name: Name::Borrowed(name), data: FileContent::Generated(data), virgin: None, hooks: None, reserved: 0, deleted: false, renamed: None, dirty: None }
     }
 
-    /// Fetch short file name for directory entry
-    pub(crate) fn short_name(&self) -> Result<[u8; 11], FileError> {
-        // Split name by extension
-        let mut n = self.name.split(".");
-        let (prefix, ext) = match (n.next(), n.next()) {
-            (Some(p), Some(e)) => (p, e),
-            _ => return Err(FileError::InvalidName),
-        };
+    /// Constant helper to create sparse placeholder files of `len` bytes,
+    /// reading as the provided fill byte.
+    /// 
+    /// Beware this function will not check short file name creation
+    pub const fn new_sparse(name: &'a str, len: usize, fill: u8) -> Self {
+        Self{ name: Name::Borrowed(name), data: FileContent::Sparse{ len, fill }, virgin: None, hooks: None, reserved: 0, deleted: false, renamed: None, dirty: None }
+    }
 
-        // Check prefix and extension will fit FAT buffer
-        // TODO: long file names?
-        if prefix.len() + ext.len() > 11 {
-            return Err(FileError::InvalidName);
-        }
+    /// Constant helper to create async files of `len` bytes, served by the
+    /// [`AsyncDynamicFile`](crate::AsyncDynamicFile) at `index` in the list
+    /// provided to the async block device path.
+    /// 
+    /// Beware this function will not check short file name creation
+    pub const fn new_async(name: &'a str, index: usize, len: usize) -> Self {
+        Self{ name: Name::Borrowed(name), data: FileContent::Async{ index, len }, virgin: None, hooks: None, reserved: 0, deleted: false, renamed: None, dirty: None }
+    }
 
-        // Copy name
-        let mut short_name = [ASCII_SPACE; 11];
-        short_name[..prefix.len()].copy_from_slice(prefix.as_bytes());
-        short_name[11 - ext.len()..].copy_from_slice(ext.as_bytes());
+    /// Attach a policy for reads of never-written blocks, with writes
+    /// tracked in the provided map
+    pub fn with_virgin<const N: usize>(mut self, policy: VirginPolicy, written: &'a WriteMap<N>) -> Self {
+        self.virgin = Some(Virgin{ policy, written: written.as_slice() });
+        self
+    }
+
+    /// Attach event hooks, called when the host reads or writes the file
+    pub fn with_hooks(mut self, hooks: &'a dyn FileHooks) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Reserve clusters for up to `max_len` bytes, allowing files with a
+    /// runtime-variable length (ie. [`DynamicFile`]s or [`GeneratedFile`]s)
+    /// to grow without moving the clusters of following files.
+    /// 
+    /// The directory entry and FAT chain follow the live file length,
+    /// see [`GhostFat::refresh`](crate::GhostFat::refresh) to detect changes.
+    pub fn with_reserved(mut self, max_len: usize) -> Self {
+        self.reserved = max_len;
+        self
+    }
 
-        Ok(short_name)
+    /// Check whether the host has deleted the file since it was last
+    /// listed in a host directory write
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    /// Fetch the file name
+    pub fn name(&self) -> &str {
+        match &self.name {
+            Name::Borrowed(n) => n,
+            #[cfg(feature = "alloc")]
+            Name::Owned(n) => n,
+        }
+    }
+
+    /// Fetch short file name for directory entry, see [`encode_short_name`]
+    pub(crate) fn short_name(&self) -> Result<[u8; 11], FileError> {
+        encode_short_name(self.name())
     }
 
     /// Fetch the file length
@@ -149,16 +491,95 @@ impl <'a, const BLOCK_SIZE: usize> File<'a, BLOCK_SIZE> {
             FileContent::Read(r) => r.len(),
             FileContent::Write(w) => w.len(),
             FileContent::Dynamic(rw) => rw.len(),
+            FileContent::Generated(g) => g.len(),
+            FileContent::Segments(s) => s.iter().map(|d| d.len()).sum(),
+            FileContent::Sparse{ len, .. } => *len,
+            FileContent::Async{ len, .. } => *len,
+            FileContent::Companion(c) => c.len(),
+            #[cfg(feature = "crc32fast")]
+            FileContent::Manifest{ format, entries } => format.len(*entries),
+            #[cfg(feature = "heapless")]
+            FileContent::Vec(v) => v.as_slice().len(),
+            #[cfg(feature = "alloc")]
+            FileContent::Owned(o) => o.len(),
         }
     }
 
+    /// Check whether the file is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read file content from the provided byte offset, returning the read length.
+    /// 
+    /// This allows firmware to inspect file content via the same path as the host.
+    pub fn read_at(&self, offset: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        let mut block = [0u8; BLOCK_SIZE];
+        let mut n = 0;
+
+        while n < buff.len() {
+            let (index, start) = ((offset + n) / BLOCK_SIZE, (offset + n) % BLOCK_SIZE);
+
+            let len = self.chunk(index, &mut block)?;
+            if start >= len {
+                break;
+            }
+
+            let l = usize::min(len - start, buff.len() - n);
+            buff[n..][..l].copy_from_slice(&block[start..][..l]);
+            n += l;
+        }
+
+        Ok(n)
+    }
+
+    /// Write file content at the provided byte offset, returning the write length.
+    /// 
+    /// Partial blocks are read, modified and re-written, with writes truncated
+    /// to the file length. This allows firmware to update file content via
+    /// the same path as the host.
+    pub fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<usize, FileError> {
+        let mut block = [0u8; BLOCK_SIZE];
+        let mut n = 0;
+
+        while n < data.len() && offset + n < self.len() {
+            let (index, start) = ((offset + n) / BLOCK_SIZE, (offset + n) % BLOCK_SIZE);
+            let block_len = usize::min(BLOCK_SIZE, self.len() - index * BLOCK_SIZE);
+            let l = usize::min(block_len - start, data.len() - n);
+
+            let written = if start == 0 && l == block_len {
+                self.chunk_mut(index, &data[n..][..l])?
+            } else {
+                self.chunk(index, &mut block)?;
+                block[start..][..l].copy_from_slice(&data[n..][..l]);
+                self.chunk_mut(index, &block[..block_len])?
+            };
+
+            // Read-only files
+            if written == 0 {
+                break;
+            }
+
+            n += l;
+        }
+
+        Ok(n)
+    }
+
     /// Fetch number of blocks required to store file
     pub(crate) fn num_blocks(&self) -> usize {
-        let mut blocks = self.len() / BLOCK_SIZE;
-        if self.len() % BLOCK_SIZE != 0 {
-            blocks += 1;
-        }
-        blocks
+        self.len().div_ceil(BLOCK_SIZE)
+    }
+
+    /// Fetch number of blocks allocated to the file, including reserved blocks
+    pub(crate) fn alloc_blocks(&self) -> usize {
+        let reserved = match &self.data {
+            #[cfg(feature = "heapless")]
+            FileContent::Vec(v) => usize::max(self.reserved, v.capacity()),
+            _ => self.reserved,
+        };
+
+        usize::max(self.num_blocks(), reserved.div_ceil(BLOCK_SIZE))
     }
 
     /// Fetch file attributes
@@ -167,69 +588,409 @@ impl <'a, const BLOCK_SIZE: usize> File<'a, BLOCK_SIZE> {
             FileContent::Read(_r) => Attrs::READ_ONLY,
             FileContent::Write(_w) => Attrs::empty(),
             FileContent::Dynamic(_rw) => Attrs::empty(),
+            FileContent::Generated(_g) => Attrs::READ_ONLY,
+            FileContent::Segments(_s) => Attrs::READ_ONLY,
+            FileContent::Sparse{ .. } => Attrs::READ_ONLY,
+            FileContent::Async{ .. } => Attrs::empty(),
+            FileContent::Companion(_c) => Attrs::READ_ONLY,
+            #[cfg(feature = "crc32fast")]
+            FileContent::Manifest{ .. } => Attrs::READ_ONLY,
+            #[cfg(feature = "heapless")]
+            FileContent::Vec(_v) => Attrs::empty(),
+            #[cfg(feature = "alloc")]
+            FileContent::Owned(_o) => Attrs::empty(),
         }
     }
 
+    /// Fetch a reference to a complete chunk of buffer backed file content
+    /// (ie. [`FileContent::Read`] data in flash), allowing the chunk to be
+    /// transmitted without copying.
+    ///
+    /// Returns `None` for content generated on read and for partial chunks,
+    /// which must be read via a buffer to zero-fill the remainder of the block.
+    pub fn chunk_ref(&self, index: usize) -> Option<&[u8]> {
+        self.chunks_ref(index, 1)
+    }
+
+    /// Fetch a reference to `count` complete chunks from `index`, see [`File::chunk_ref`]
+    pub(crate) fn chunks_ref(&self, index: usize, count: usize) -> Option<&[u8]> {
+        // Never-written blocks are generated per the virgin policy
+        if let Some(v) = &self.virgin {
+            if (index..index + count).any(|i| v.fill(i).is_some()) {
+                return None;
+            }
+        }
+
+        let d: &[u8] = match &self.data {
+            FileContent::Read(r) => r,
+            FileContent::Write(w) => w,
+            #[cfg(feature = "heapless")]
+            FileContent::Vec(v) => v.as_slice(),
+            #[cfg(feature = "alloc")]
+            FileContent::Owned(o) => o,
+            _ => return None,
+        };
+
+        d.get(index * BLOCK_SIZE..(index + count) * BLOCK_SIZE)
+    }
+
     /// Read a <= BLOCK_SIZE chunk of the file into the provided buffer
-    pub(crate) fn chunk(&self, index: usize, buff: &mut [u8]) -> usize {
-        if let FileContent::Dynamic(rw) = &self.data {
-            return rw.read_chunk(index, buff)
+    pub(crate) fn chunk(&self, index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        // Serve never-written blocks per the virgin policy
+        if let Some(fill) = self.virgin.as_ref().and_then(|v| v.fill(index)) {
+            let offset = index * BLOCK_SIZE;
+            if offset >= self.len() {
+                return Ok(0);
+            }
+
+            let n = usize::min(buff.len(), usize::min(BLOCK_SIZE, self.len() - offset));
+            buff[..n].fill(fill);
+            return Ok(n);
         }
 
         let d = match &self.data {
             FileContent::Read(r) => r.chunks(BLOCK_SIZE).nth(index),
             FileContent::Write(w) => w.chunks(BLOCK_SIZE).nth(index),
-            _ => unreachable!(),
+            #[cfg(feature = "heapless")]
+            FileContent::Vec(v) => v.as_slice().chunks(BLOCK_SIZE).nth(index),
+            #[cfg(feature = "alloc")]
+            FileContent::Owned(o) => o.chunks(BLOCK_SIZE).nth(index),
+            FileContent::Dynamic(rw) => return rw.read_chunk(index, buff),
+            FileContent::Generated(g) => {
+                let offset = index * BLOCK_SIZE;
+                if offset >= g.len() {
+                    return Ok(0);
+                }
+
+                let len = usize::min(buff.len(), usize::min(BLOCK_SIZE, g.len() - offset));
+                return Ok(g.generate(offset, &mut buff[..len]));
+            },
+            FileContent::Segments(s) => {
+                let len = usize::min(buff.len(), BLOCK_SIZE);
+                return Ok(read_segments(s, index * BLOCK_SIZE, &mut buff[..len]));
+            },
+            FileContent::Sparse{ len, fill } => {
+                let offset = index * BLOCK_SIZE;
+                if offset >= *len {
+                    return Ok(0);
+                }
+
+                let n = usize::min(buff.len(), usize::min(BLOCK_SIZE, len - offset));
+                buff[..n].fill(*fill);
+                return Ok(n);
+            },
+            FileContent::Async{ .. } => return Err(FileError::WouldBlock),
+            // Companion files and manifests are rendered by the file system
+            FileContent::Companion(_) => return Ok(0),
+            #[cfg(feature = "crc32fast")]
+            FileContent::Manifest{ .. } => return Ok(0),
         };
 
         if let Some(d) = d {
             let len = usize::min(buff.len(), d.len());
             buff[..len].copy_from_slice(&d[..len]);
-            return len;
+            return Ok(len);
         }
 
-        return 0;
+        Ok(0)
     }
 
     /// Write a <= BLOCK_SIZE mutable chunk of the file from the provided buffer
-    pub(crate) fn chunk_mut(&mut self, index: usize, data: &[u8]) -> usize {
+    pub(crate) fn chunk_mut(&mut self, index: usize, data: &[u8]) -> Result<usize, FileError> {
+        let n = self.write_chunk(index, data)?;
+
+        if let (Some(v), true) = (&self.virgin, n > 0) {
+            v.mark(index);
+        }
+
+        Ok(n)
+    }
+
+    /// Write a chunk to the file backend
+    fn write_chunk(&mut self, index: usize, data: &[u8]) -> Result<usize, FileError> {
         match &mut self.data {
-            FileContent::Read(_r) => return 0,
+            FileContent::Read(_) | FileContent::Generated(_) | FileContent::Segments(_) | FileContent::Sparse{ .. }
+                | FileContent::Companion(_) => return Ok(0),
             FileContent::Write(w) => {
                 if let Some(b) = w.chunks_mut(BLOCK_SIZE).nth(index) {
                     let len = usize::min(b.len(), data.len());
                     b[..len].copy_from_slice(&data[..len]);
-                    return len;
+                    return Ok(len);
+                }
+            },
+            #[cfg(feature = "alloc")]
+            FileContent::Owned(o) => {
+                if let Some(b) = o.chunks_mut(BLOCK_SIZE).nth(index) {
+                    let len = usize::min(b.len(), data.len());
+                    b[..len].copy_from_slice(&data[..len]);
+                    return Ok(len);
                 }
             },
             FileContent::Dynamic(rw) => return rw.write_chunk(index, data),
+            #[cfg(feature = "heapless")]
+            FileContent::Vec(v) => return crate::vecbuf::write_chunk::<BLOCK_SIZE>(*v, index, data),
+            FileContent::Async{ .. } => return Err(FileError::WouldBlock),
+            #[cfg(feature = "crc32fast")]
+            FileContent::Manifest{ .. } => return Ok(0),
         }
 
-        return 0
-    } 
+        Ok(0)
+    }
 }
 
-pub struct ChunkIter {
-    offset: u32,
-    index: u32,
-    len: u32,
+/// Check whether a name is a valid 8.3 short name, see [`encode_short_name`]
+pub(crate) const fn valid_short_name(name: &str) -> bool {
+    encode_short_name(name).is_ok()
 }
 
-impl Iterator for ChunkIter {
-    type Item = u16;
+/// Encode a name as an 8.3 short name for directory entries
+///
+/// Following 8.3 rules leading and trailing dots are dropped (ie.
+/// `.config` or `README.`), the extension follows the last dot and is
+/// optional (ie. `LICENSE`), dots within the prefix are dropped (ie.
+/// `fw.v2.bin` maps to `FWV2.BIN`), and names are uppercased. Names must
+/// have a 1-8 character prefix, an up to 3 character extension, and no
+/// reserved characters.
+pub(crate) const fn encode_short_name(name: &str) -> Result<[u8; 11], FileError> {
+    let b = name.as_bytes();
+
+    // Trim leading and trailing dots
+    let (mut start, mut end) = (0, b.len());
+    while start < end && b[start] == b'.' {
+        start += 1;
+    }
+    while end > start && b[end - 1] == b'.' {
+        end -= 1;
+    }
+
+    // Split name by the last dot
+    let mut dot = end;
+    let mut i = start;
+    while i < end {
+        if b[i] == b'.' {
+            dot = i;
+        }
+        i += 1;
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.len {
-            let n = self.offset + self.index;
-            self.index += 1;
-            Some(n as u16)
-        } else {
-            None
+    let mut short_name = [ASCII_SPACE; 11];
+
+    // Copy prefix, dropping inner dots
+    let (mut i, mut n) = (start, 0usize);
+    while i < dot {
+        if b[i] != b'.' {
+            if n == 8 {
+                return Err(FileError::InvalidName);
+            }
+            short_name[n] = match short_char(b[i]) {
+                Some(c) => c,
+                None => return Err(FileError::InvalidName),
+            };
+            n += 1;
         }
+        i += 1;
     }
+
+    if n == 0 {
+        return Err(FileError::InvalidName);
+    }
+
+    // Copy extension
+    let (mut i, mut n) = (dot + 1, 8);
+    while i < end {
+        if n == 11 {
+            return Err(FileError::InvalidName);
+        }
+        short_name[n] = match short_char(b[i]) {
+            Some(c) => c,
+            None => return Err(FileError::InvalidName),
+        };
+        n += 1;
+        i += 1;
+    }
+
+    Ok(short_name)
+}
+
+/// Map a name character to a short name character, returning `None` for
+/// reserved characters
+pub(crate) const fn short_char(c: u8) -> Option<u8> {
+    match c {
+        b'.' | b' ' | b'"' | b'*' | b'+' | b',' | b'/' | b':' | b';' | b'<' | b'=' | b'>' | b'?' | b'[' | b'\\' | b']' | b'|' => None,
+        c if c < 0x20 || c > 0x7e => None,
+        c => Some(c.to_ascii_uppercase()),
+    }
+}
+
+/// Read from a list of segments at the provided byte offset, returning the read length
+fn read_segments(segments: &[&[u8]], mut offset: usize, buff: &mut [u8]) -> usize {
+    let mut index = 0;
+
+    for s in segments {
+        // Skip segments prior to the offset
+        if offset >= s.len() {
+            offset -= s.len();
+            continue;
+        }
+
+        // Copy from the segment, continuing into the next if required
+        let len = usize::min(s.len() - offset, buff.len() - index);
+        buff[index..][..len].copy_from_slice(&s[offset..][..len]);
+        index += len;
+        offset = 0;
+
+        if index == buff.len() {
+            break;
+        }
+    }
+
+    index
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
 
+    #[test]
+    fn sparse_chunks() {
+        let mut f = File::<8>::new_sparse("CAPTURE.BIN", 1_000_000, 0xFF);
+        assert_eq!(f.len(), 1_000_000);
+        assert_eq!(f.num_blocks(), 125_000);
+        assert_eq!(f.attrs(), Attrs::READ_ONLY);
+
+        let mut buff = [0u8; 8];
+        assert_eq!(f.chunk(124_999, &mut buff), Ok(8));
+        assert_eq!(buff, [0xFF; 8]);
+        assert_eq!(f.chunk(125_000, &mut buff), Ok(0));
+
+        assert_eq!(f.chunk_mut(0, &buff), Ok(0));
+    }
+
+    #[test]
+    fn checked_names() {
+        static DATA: [u8; 4] = [1, 2, 3, 4];
+        static FILE: File = File::new_ro_checked("README.TXT", &DATA);
+        assert_eq!(FILE.len(), 4);
+
+        for n in ["A.B", "ABCDEFGH.BIN", "test.bin", "README", ".BIN", "A.B.C", "A."] {
+            assert!(valid_short_name(n), "{}", n);
+        }
+        for n in ["", ".", "ABCDEFGHI", "ABCDEFGHI.BIN", "A.BINX", "A B.TXT", "A?.TXT", "firmware.v2.bin"] {
+            assert!(!valid_short_name(n), "{}", n);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn checked_names_panic() {
+        let _ = File::<512>::new_ro_checked("INVALID.NAME", &[]);
+    }
+
+    #[test]
+    fn short_names() {
+        let name = |n| File::<512>::new_ro(n, &[]).short_name();
+
+        assert_eq!(name("README.TXT"), Ok(*b"README  TXT"));
+        assert_eq!(name("A.B"), Ok(*b"A       B  "));
+        assert_eq!(name("LICENSE"), Ok(*b"LICENSE    "));
+        assert_eq!(name("README."), Ok(*b"README     "));
+        assert_eq!(name(".config"), Ok(*b"CONFIG     "));
+        assert_eq!(name("fw.v2.bin"), Ok(*b"FWV2    BIN"));
+
+        for n in ["", ".", "..", "ABCDEFGHI", "ABCDEFGHI.BIN", "A.BINX", "firmware.v2.bin"] {
+            assert_eq!(name(n), Err(FileError::InvalidName), "{}", n);
+        }
+    }
+
+    #[test]
+    fn byte_offsets() {
+        let mut data = [0u8; 20];
+        let mut f = File::<8>::new("DATA.BIN", &mut data).unwrap();
+
+        // Writes span partial blocks and are truncated to the file length
+        assert_eq!(f.write_at(6, &[1, 2, 3, 4]), Ok(4));
+        assert_eq!(f.write_at(18, &[5, 6, 7]), Ok(2));
+
+        let mut buff = [0u8; 16];
+        assert_eq!(f.read_at(5, &mut buff[..6]), Ok(6));
+        assert_eq!(&buff[..6], &[0, 1, 2, 3, 4, 0]);
+        assert_eq!(f.read_at(16, &mut buff), Ok(4));
+        assert_eq!(&buff[..4], &[0, 0, 5, 6]);
+
+        let f = File::<8>::new_ro("RO.BIN", &[1, 2, 3]);
+        assert_eq!(f.read_at(1, &mut buff), Ok(2));
+    }
+
+    #[test]
+    fn virgin_blocks() {
+        let written = WriteMap::<4>::new();
+        let mut data = [0xAAu8; 20];
+        let mut f = File::<8>::new("DATA.BIN", &mut data).unwrap()
+            .with_virgin(VirginPolicy::Fill(0xFF), &written);
+
+        let mut buff = [0u8; 8];
+        assert_eq!(f.chunk(0, &mut buff), Ok(8));
+        assert_eq!(buff, [0xFF; 8]);
+
+        // Host writes are tracked
+        assert_eq!(f.chunk_mut(1, &[0x11; 8]), Ok(8));
+        assert_eq!(f.chunk(1, &mut buff), Ok(8));
+        assert_eq!(buff, [0x11; 8]);
+
+        // As are firmware writes
+        written.mark(2);
+        assert_eq!(f.chunk(2, &mut buff), Ok(4));
+        assert_eq!(&buff[..4], &[0xAA; 4]);
+        assert_eq!(written.count(), 2);
+
+        written.clear();
+        assert_eq!(f.chunk(1, &mut buff), Ok(8));
+        assert_eq!(buff, [0xFF; 8]);
+    }
+
+    #[test]
+    fn segment_chunks() {
+        let header = [0x01u8; 3];
+        let payload = [0x02u8; 10];
+        let footer = [0x03u8; 1];
+        let segments: &[&[u8]] = &[&header, &[], &payload, &footer];
+
+        let f = File::<8>::new("SEG.BIN", segments).unwrap();
+        assert_eq!(f.len(), 14);
+        assert_eq!(f.attrs(), Attrs::READ_ONLY);
+
+        let mut buff = [0u8; 8];
+        assert_eq!(f.chunk(0, &mut buff), Ok(8));
+        assert_eq!(buff, [1, 1, 1, 2, 2, 2, 2, 2]);
+
+        assert_eq!(f.chunk(1, &mut buff), Ok(6));
+        assert_eq!(&buff[..6], &[2, 2, 2, 2, 2, 3]);
+
+        assert_eq!(f.chunk(2, &mut buff), Ok(0));
+    }
+
+    #[test]
+    fn generated_chunks() {
+        let g = GeneratorFn::new(20, |offset, buff: &mut [u8]| {
+            for (i, b) in buff.iter_mut().enumerate() {
+                *b = (offset + i) as u8;
+            }
+            buff.len()
+        });
+        let f = File::<8>::new("GEN.BIN", FileContent::Generated(&g)).unwrap();
+
+        assert_eq!(f.len(), 20);
+        assert_eq!(f.num_blocks(), 3);
+        assert_eq!(f.attrs(), Attrs::READ_ONLY);
+
+        let mut buff = [0u8; 8];
+        assert_eq!(f.chunk(1, &mut buff), Ok(8));
+        assert_eq!(buff, [8, 9, 10, 11, 12, 13, 14, 15]);
+
+        // Final chunk is truncated to the file length
+        assert_eq!(f.chunk(2, &mut buff), Ok(4));
+        assert_eq!(&buff[..4], &[16, 17, 18, 19]);
+
+        assert_eq!(f.chunk(3, &mut buff), Ok(0));
+    }
 }
@@ -0,0 +1,180 @@
+use core::fmt;
+use core::ops::Deref;
+
+/// Fixed capacity string, storing up to `N` bytes inline so configuration
+/// strings (ie. a volume label including a serial number) may be built at
+/// runtime without `'static` storage.
+///
+/// Strings are truncated to `N` bytes on a character boundary.
+///
+/// ```
+/// use core::fmt::Write;
+/// use ghostfat::{Config, FixedStr};
+///
+/// let serial = 0x1234;
+///
+/// let mut config = Config::<512>::new();
+/// config.volume_label = FixedStr::default();
+/// write!(config.volume_label, "GF{:04X}", serial).unwrap();
+/// assert_eq!(config.volume_label, "GF1234");
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct FixedStr<const N: usize> {
+    buff: [u8; N],
+    len: usize,
+}
+
+impl <const N: usize> FixedStr<N> {
+    /// Create a new fixed string, truncating to `N` bytes
+    pub const fn new(s: &str) -> Self {
+        let b = s.as_bytes();
+        let mut len = if b.len() < N { b.len() } else { N };
+
+        // Truncate on a character boundary
+        while len < b.len() && len > 0 && b[len] & 0xC0 == 0x80 {
+            len -= 1;
+        }
+
+        let mut buff = [0u8; N];
+        let mut i = 0;
+        while i < len {
+            buff[i] = b[i];
+            i += 1;
+        }
+
+        Self { buff, len }
+    }
+
+    /// Fetch the string contents
+    pub fn as_str(&self) -> &str {
+        // Contents are only ever copied from complete characters
+        core::str::from_utf8(&self.buff[..self.len]).unwrap_or_default()
+    }
+
+    /// Fetch the maximum length of the string in bytes
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Clear the string contents
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl <const N: usize> Default for FixedStr<N> {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl <const N: usize> Deref for FixedStr<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl <const N: usize> From<&str> for FixedStr<N> {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+impl <const N: usize> PartialEq<str> for FixedStr<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl <const N: usize> PartialEq<&str> for FixedStr<N> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Append to the string, failing once full (with the characters that fit written)
+impl <const N: usize> fmt::Write for FixedStr<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut n = usize::min(N - self.len, s.len());
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+
+        self.buff[self.len..][..n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+
+        match n == s.len() {
+            true => Ok(()),
+            false => Err(fmt::Error),
+        }
+    }
+}
+
+impl <const N: usize> fmt::Debug for FixedStr<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl <const N: usize> fmt::Display for FixedStr<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <const N: usize> serde::Serialize for FixedStr<N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <'de, const N: usize> serde::Deserialize<'de> for FixedStr<N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<const N: usize>;
+
+        impl <'de, const N: usize> serde::de::Visitor<'de> for Visitor<N> {
+            type Value = FixedStr<N>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a string of up to {} bytes", N)
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(FixedStr::new(v))
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use super::*;
+
+    #[test]
+    fn fixed_str() {
+        let s = FixedStr::<8>::new("GHOSTFAT VOLUME");
+        assert_eq!(s, "GHOSTFAT");
+        assert_eq!((s.len(), s.capacity()), (8, 8));
+
+        // Truncation keeps complete characters
+        assert_eq!(FixedStr::<4>::new("ab\u{e9}c"), "ab\u{e9}");
+        assert_eq!(FixedStr::<3>::new("ab\u{e9}c"), "ab");
+
+        let mut s = FixedStr::<6>::new("SN");
+        write!(s, "{:04X}", 0xBEEFu16).unwrap();
+        assert_eq!(s, "SNBEEF");
+        assert!(write!(s, "!").is_err());
+
+        s.clear();
+        assert!(write!(s, "abcde\u{e9}").is_err());
+        assert_eq!(s, "abcde");
+    }
+}
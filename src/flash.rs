@@ -0,0 +1,303 @@
+//! Flash backed file objects
+
+use embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash;
+
+use crate::{AsyncDynamicFile, DynamicFile, FileError};
+
+/// Async flash backed file using [`embedded_storage_async`]
+///
+/// Host writes are assembled in RAM one erase page (`N` blocks) at a time,
+/// with a bitmap tracking the blocks written, so hosts may write blocks in
+/// any order within a page. Pages are only erased and programmed when
+/// committed by awaiting [`AsyncFlashFile::flush`], allowing use with async
+/// (embassy-style) flash drivers without blocking the USB stack. Blocks
+/// of a committed page not written by the host are preserved from flash.
+///
+/// `N * BLOCK_SIZE` must equal the flash erase size, and the file region
+/// should be erase-page aligned.
+///
+/// Reads are served from the page buffer where a write is pending, otherwise
+/// from the memory-mapped view of the flash region provided at construction.
+///
+/// When used via [`DynamicFile`] writes to a page other than the one being
+/// assembled are rejected until the file is flushed, and
+/// [`DynamicFile::flush`] fails with [`FileError::WouldBlock`] while blocks
+/// are pending so hosts are not told staged data is committed. When used via
+/// [`AsyncDynamicFile`] pages are committed once fully assembled, or before
+/// staging a write to another page.
+pub struct AsyncFlashFile<'a, F, const N: usize, const BLOCK_SIZE: usize = 512> {
+    flash: F,
+    offset: u32,
+    mapped: &'a [u8],
+    page: Option<usize>,
+    staged: [bool; N],
+    buff: [[u8; BLOCK_SIZE]; N],
+}
+
+impl <'a, F: AsyncNorFlash, const N: usize, const BLOCK_SIZE: usize> AsyncFlashFile<'a, F, N, BLOCK_SIZE> {
+    /// Create a new flash file at `offset` in `flash`, with `mapped` the
+    /// memory-mapped view of the file region used for reads
+    pub fn new(flash: F, offset: u32, mapped: &'a [u8]) -> Self {
+        const { assert!(N * BLOCK_SIZE == F::ERASE_SIZE, "page buffer must match the flash erase size") };
+
+        Self {
+            flash,
+            offset,
+            mapped,
+            page: None,
+            staged: [false; N],
+            buff: [[0u8; BLOCK_SIZE]; N],
+        }
+    }
+
+    /// Commit the assembled page to flash, reading blocks not written by
+    /// the host from the mapped flash region
+    pub async fn flush(&mut self) -> Result<(), F::Error> {
+        let page = match self.page {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        for (slot, b) in self.buff.iter_mut().enumerate() {
+            if !self.staged[slot] {
+                let d = self.mapped.chunks(BLOCK_SIZE).nth(page * N + slot).unwrap_or(&[]);
+                b[..d.len()].copy_from_slice(d);
+                b[d.len()..].fill(0xFF);
+            }
+        }
+
+        let start = self.offset + (page * N * BLOCK_SIZE) as u32;
+        crate::debug!("Erasing flash page 0x{:08x}", start);
+        self.flash.erase(start, start + F::ERASE_SIZE as u32).await?;
+
+        crate::debug!("Writing flash page 0x{:08x}", start);
+        for (slot, b) in self.buff.iter().enumerate() {
+            self.flash.write(start + (slot * BLOCK_SIZE) as u32, b).await?;
+        }
+
+        self.page = None;
+        self.staged = [false; N];
+
+        Ok(())
+    }
+
+    /// Consume the file, returning the underlying flash driver
+    pub fn free(self) -> F {
+        self.flash
+    }
+}
+
+impl <'a, F, const N: usize, const BLOCK_SIZE: usize> AsyncFlashFile<'a, F, N, BLOCK_SIZE> {
+    /// Fetch the number of blocks awaiting [`AsyncFlashFile::flush`]
+    pub fn pending(&self) -> usize {
+        self.staged.iter().filter(|s| **s).count()
+    }
+
+    /// Check whether all blocks of the assembled page within the file have
+    /// been written, in which case the page is ready to commit
+    pub fn is_complete(&self) -> bool {
+        let page = match self.page {
+            Some(p) => p,
+            None => return false,
+        };
+
+        let blocks = self.mapped.len().div_ceil(BLOCK_SIZE);
+        self.staged.iter().enumerate()
+            .all(|(slot, s)| *s || page * N + slot >= blocks)
+    }
+
+    /// Read a chunk from the page buffer or mapped flash region
+    fn read_staged(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        let d = match self.mapped.chunks(BLOCK_SIZE).nth(chunk_index) {
+            Some(d) => d,
+            None => return Ok(0),
+        };
+
+        // Serve pending writes from the page buffer
+        let (page, slot) = (chunk_index / N, chunk_index % N);
+        let d = match self.page == Some(page) && self.staged[slot] {
+            true => &self.buff[slot][..d.len()],
+            false => d,
+        };
+
+        let len = usize::min(buff.len(), d.len());
+        buff[..len].copy_from_slice(&d[..len]);
+        Ok(len)
+    }
+
+    /// Stage a chunk in the page buffer
+    fn stage(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        if chunk_index * BLOCK_SIZE >= self.mapped.len() {
+            return Ok(0);
+        }
+
+        let (page, slot) = (chunk_index / N, chunk_index % N);
+        match self.page {
+            Some(p) if p != page => {
+                crate::warn!("Flash page {} pending, unable to stage block {}", p, chunk_index);
+                return Err(FileError::NoSpace);
+            },
+            Some(_) => (),
+            None => self.page = Some(page),
+        }
+
+        let len = usize::min(data.len(), BLOCK_SIZE);
+        self.buff[slot][..len].copy_from_slice(&data[..len]);
+        self.staged[slot] = true;
+
+        Ok(len)
+    }
+}
+
+impl <'a, F, const N: usize, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for AsyncFlashFile<'a, F, N, BLOCK_SIZE>
+where
+    F: AsyncNorFlash + Sync + Send,
+{
+    fn len(&self) -> usize {
+        self.mapped.len()
+    }
+
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        self.read_staged(chunk_index, buff)
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        self.stage(chunk_index, data)
+    }
+
+    /// Pages may only be committed via [`AsyncFlashFile::flush`], failing
+    /// while blocks are pending
+    fn flush(&mut self) -> Result<(), FileError> {
+        match self.pending() {
+            0 => Ok(()),
+            n => {
+                crate::warn!("Unable to flush {} pending flash blocks", n);
+                Err(FileError::WouldBlock)
+            },
+        }
+    }
+}
+
+/// Async writes commit pages as they are completed rather than failing
+impl <'a, F: AsyncNorFlash, const N: usize, const BLOCK_SIZE: usize> AsyncDynamicFile<BLOCK_SIZE> for AsyncFlashFile<'a, F, N, BLOCK_SIZE> {
+    fn len(&self) -> usize {
+        self.mapped.len()
+    }
+
+    async fn read_chunk(&mut self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        self.read_staged(chunk_index, buff)
+    }
+
+    async fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        let n = match self.stage(chunk_index, data) {
+            Err(FileError::NoSpace) => {
+                self.flush().await.map_err(|_e| FileError::WriteError)?;
+                self.stage(chunk_index, data)?
+            },
+            r => r?,
+        };
+
+        if self.is_complete() {
+            self.flush().await.map_err(|_e| FileError::WriteError)?;
+        }
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_storage_async::nor_flash::{ErrorType, NorFlash, ReadNorFlash, NorFlashErrorKind};
+
+    use super::*;
+
+    struct MockFlash {
+        data: [u8; 2048],
+        erases: usize,
+    }
+
+    impl ErrorType for MockFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for MockFlash {
+        const READ_SIZE: usize = 1;
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            bytes.copy_from_slice(&self.data[offset as usize..][..bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for MockFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 1024;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            self.data[from as usize..to as usize].fill(0xFF);
+            self.erases += 1;
+            Ok(())
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            self.data[offset as usize..][..bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stage_and_flush() {
+        let mut mapped = [0xFFu8; 2048];
+        mapped[..512].fill(0x22);
+        let flash = MockFlash{ data: [0xFF; 2048], erases: 0 };
+        let mut f = AsyncFlashFile::<_, 2, 512>::new(flash, 0, &mapped);
+
+        // Writes to other pages are rejected while a page is pending
+        assert_eq!(DynamicFile::write_chunk(&mut f, 1, &[0xAA; 512]), Ok(512));
+        assert!(!f.is_complete());
+        assert_eq!(DynamicFile::write_chunk(&mut f, 2, &[0x11; 512]), Err(FileError::NoSpace));
+
+        // Pending writes are visible on read
+        let mut buff = [0u8; 512];
+        assert_eq!(DynamicFile::read_chunk(&f, 1, &mut buff), Ok(512));
+        assert_eq!(buff, [0xAA; 512]);
+
+        // Synchronous flushes fail while blocks are pending
+        assert_eq!(DynamicFile::flush(&mut f), Err(FileError::WouldBlock));
+
+        // Flush commits the page, preserving blocks not written
+        futures::executor::block_on(f.flush()).unwrap();
+        assert_eq!(f.pending(), 0);
+        assert_eq!(DynamicFile::flush(&mut f), Ok(()));
+
+        let flash = f.free();
+        assert_eq!(flash.erases, 1);
+        assert_eq!(&flash.data[..512], &[0x22; 512]);
+        assert_eq!(&flash.data[512..1024], &[0xAA; 512]);
+    }
+
+    #[test]
+    fn async_page_assembly() {
+        let mapped = [0xFFu8; 2048];
+        let flash = MockFlash{ data: [0xFF; 2048], erases: 0 };
+        let mut f = AsyncFlashFile::<_, 2, 512>::new(flash, 0, &mapped);
+
+        // Out of order writes are assembled, with pages committed when complete
+        for i in [1, 0, 3, 2] {
+            let r = futures::executor::block_on(AsyncDynamicFile::write_chunk(&mut f, i, &[i as u8; 512]));
+            assert_eq!(r, Ok(512));
+        }
+        assert_eq!(f.pending(), 0);
+
+        let flash = f.free();
+        assert_eq!(flash.erases, 2);
+        assert_eq!(&flash.data[..512], &[0; 512]);
+        assert_eq!(&flash.data[512..1024], &[1; 512]);
+        assert_eq!(&flash.data[1024..1536], &[2; 512]);
+        assert_eq!(&flash.data[1536..], &[3; 512]);
+    }
+}
@@ -0,0 +1,118 @@
+use packing::PackedSize;
+
+use crate::{GhostFat, SectorIndex, FormatPolicy};
+use crate::check::FAT_RESERVED;
+use crate::dir::DirectoryEntry;
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Detect a host format rewriting the boot sector
+    pub(crate) fn format_boot(&mut self, block: &[u8]) {
+        if !self.boot_matches(block) {
+            crate::debug!("Host rewrote boot sector, possible format");
+            self.formatting = true;
+        }
+    }
+
+    /// Detect a host format clearing the FAT
+    pub(crate) fn format_fat(&mut self, section_index: SectorIndex, block: &[u8]) {
+        if section_index != SectorIndex(0) || block[FAT_RESERVED..].iter().any(|b| *b != 0) {
+            return;
+        }
+
+        // Clearing a FAT with no allocations is not a format
+        let mut generated = [0u8; BLOCK_SIZE];
+        Self::fat_range(0, &self.fat_files, &self.clusters, &mut generated);
+
+        if generated[FAT_RESERVED..].iter().any(|b| *b != 0) {
+            crate::debug!("Host cleared FAT, possible format");
+            self.formatting = true;
+        }
+    }
+
+    /// Detect a host format clearing the root directory following a boot
+    /// sector rewrite or FAT clear, applying the configured [`FormatPolicy`]
+    pub(crate) fn format_dir(&mut self, section_index: SectorIndex, block: &[u8]) {
+        if !self.formatting || section_index != SectorIndex(0) {
+            return;
+        }
+
+        // Formats write an empty root directory, with only the volume label
+        let cleared = block.chunks_exact(DirectoryEntry::BYTES)
+            .skip(1)
+            .all(|e| e[0] == 0);
+        if !cleared {
+            return;
+        }
+
+        crate::warn!("Detected host format");
+        self.formatting = false;
+
+        match self.config.format_policy {
+            FormatPolicy::Ignore => return,
+            FormatPolicy::Remount => (),
+            FormatPolicy::Callback(f) => f(),
+        }
+
+        // Formats may also relabel the volume
+        let label = self.config.volume_label;
+        if self.set_label(&label).is_err() {
+            crate::warn!("Invalid configured volume label");
+        }
+        self.remount();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::BlockDevice;
+
+    use crate::{Config, File};
+    use super::*;
+
+    static FORMATS: AtomicUsize = AtomicUsize::new(0);
+
+    fn on_format() {
+        FORMATS.store(FORMATS.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn detect_format() {
+        let mut buff = [0u8; 516];
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("INFO.TXT", &data)];
+
+        let mut config = Config::default();
+        config.format_policy = FormatPolicy::Callback(on_format);
+        let mut fs = GhostFat::new(&mut f, config).with_dir_shadow(&mut buff);
+        let (fat0, rootdir) = (fs.config.start_fat0().0, fs.config.start_rootdir().0);
+
+        let (mut boot, mut fat, mut dir) = ([0u8; 512], [0u8; 512], [0u8; 512]);
+        fs.read_block(0, &mut boot).unwrap();
+        fs.read_block(fat0, &mut fat).unwrap();
+        fs.read_block(rootdir, &mut dir).unwrap();
+        fs.take_changed();
+
+        // Clearing the directory alone is not a format
+        let mut cleared = [0u8; 512];
+        cleared[..32].copy_from_slice(&dir[..32]);
+        fs.write_block(rootdir, &cleared).unwrap();
+        assert_eq!(FORMATS.load(Ordering::Relaxed), 0);
+
+        // Host writes a new boot sector and empty FAT and root directory
+        boot[39..43].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        fs.write_block(0, &boot).unwrap();
+        fat.fill(0);
+        fat[..4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]);
+        fs.write_block(fat0, &fat).unwrap();
+        fs.write_block(rootdir, &cleared).unwrap();
+        assert_eq!(FORMATS.load(Ordering::Relaxed), 1);
+
+        // The canonical volume is re-presented
+        assert!(fs.take_changed());
+        let mut read = [0u8; 512];
+        fs.read_block(rootdir, &mut read).unwrap();
+        assert_eq!(read, dir);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{File, FileError};
+
+/// Incremental hash function used for checksum generation.
+/// 
+/// Software CRC32 and SHA-256 implementations are provided behind the
+/// `crc32fast` and `sha2` features, or this may be implemented over
+/// hardware accelerators.
+pub trait Hasher {
+    /// Hash output type
+    type Output: AsRef<[u8]>;
+
+    /// Reset the hasher state
+    fn reset(&mut self);
+
+    /// Update the hash with the provided data
+    fn update(&mut self, data: &[u8]);
+
+    /// Finalise and return the hash output, resetting the hasher state
+    fn finish(&mut self) -> Self::Output;
+}
+
+impl <'a, const BLOCK_SIZE: usize> File<'a, BLOCK_SIZE> {
+    /// Compute a hash over the file content using the provided [`Hasher`]
+    pub fn hash<H: Hasher>(&self, hasher: &mut H) -> Result<H::Output, FileError> {
+        let mut buff = [0u8; BLOCK_SIZE];
+
+        hasher.reset();
+        for i in 0..self.num_blocks() {
+            let n = self.chunk(i, &mut buff)?;
+            hasher.update(&buff[..n]);
+        }
+
+        Ok(hasher.finish())
+    }
+}
+
+/// Software CRC32 (IEEE) [`Hasher`], output is big-endian
+#[cfg(feature = "crc32fast")]
+#[derive(Clone, Default)]
+pub struct Crc32(crc32fast::Hasher);
+
+#[cfg(feature = "crc32fast")]
+impl Crc32 {
+    /// Create a new CRC32 hasher
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "crc32fast")]
+impl Hasher for Crc32 {
+    type Output = [u8; 4];
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finish(&mut self) -> Self::Output {
+        let h = core::mem::take(&mut self.0);
+        h.finalize().to_be_bytes()
+    }
+}
+
+/// Software SHA-256 [`Hasher`]
+#[cfg(feature = "sha2")]
+#[derive(Clone, Default)]
+pub struct Sha256(sha2::Sha256);
+
+#[cfg(feature = "sha2")]
+impl Sha256 {
+    /// Create a new SHA-256 hasher
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl Hasher for Sha256 {
+    type Output = [u8; 32];
+
+    fn reset(&mut self) {
+        sha2::Digest::reset(&mut self.0);
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(&mut self.0, data);
+    }
+
+    fn finish(&mut self) -> Self::Output {
+        sha2::Digest::finalize_reset(&mut self.0).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Byte sum hasher for testing
+    #[derive(Default)]
+    struct Sum(u32);
+
+    impl Hasher for Sum {
+        type Output = [u8; 4];
+
+        fn reset(&mut self) {
+            self.0 = 0;
+        }
+
+        fn update(&mut self, data: &[u8]) {
+            self.0 += data.iter().map(|b| *b as u32).sum::<u32>();
+        }
+
+        fn finish(&mut self) -> Self::Output {
+            let v = self.0;
+            self.0 = 0;
+            v.to_be_bytes()
+        }
+    }
+
+    #[test]
+    fn hash_file_blocks() {
+        let f = File::<4>::new_ro("SUM.BIN", &[1u8; 10]);
+        let mut h = Sum::default();
+
+        assert_eq!(f.hash(&mut h), Ok(10u32.to_be_bytes()));
+    }
+
+    #[test]
+    #[cfg(feature = "crc32fast")]
+    fn crc32_file() {
+        let f = File::<4>::new_ro("CHECK.TXT", b"123456789");
+        let mut h = Crc32::new();
+
+        assert_eq!(f.hash(&mut h), Ok(0xCBF43926u32.to_be_bytes()));
+        // Hasher is reset between uses
+        assert_eq!(f.hash(&mut h), Ok(0xCBF43926u32.to_be_bytes()));
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn sha256_file() {
+        let f = File::<2>::new_ro("ABC.TXT", b"abc");
+        let mut h = Sha256::new();
+
+        assert_eq!(&f.hash(&mut h).unwrap()[..4], &[0xba, 0x78, 0x16, 0xbf]);
+    }
+}
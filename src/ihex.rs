@@ -0,0 +1,241 @@
+use crate::{FileError, FlashWriter, HostFileSink};
+
+/// Maximum record length in characters, `:` followed by length, address,
+/// type, up to 255 data bytes and checksum as hex pairs
+const MAX_RECORD: usize = 1 + 2 * (1 + 2 + 1 + 255 + 1);
+
+/// Intel HEX record types
+const DATA: u8 = 0x00;
+const EOF: u8 = 0x01;
+const EXT_SEGMENT: u8 = 0x02;
+const START_SEGMENT: u8 = 0x03;
+const EXT_LINEAR: u8 = 0x04;
+const START_LINEAR: u8 = 0x05;
+
+/// Streaming Intel HEX parser for drag-and-drop flashing
+///
+/// Host-written file data is parsed as it arrives (via [`IntelHexFlasher::feed`],
+/// or as a [`HostFileSink`] for routed files), with records split across
+/// block boundaries buffered, extended addresses resolved, and data records
+/// passed to the [`FlashWriter`] `W`. [`FlashWriter::complete`] is called
+/// on the EOF record, or on the first malformed record or write error.
+pub struct IntelHexFlasher<W> {
+    writer: W,
+    line: [u8; MAX_RECORD],
+    len: usize,
+    base: u32,
+    parsed: usize,
+    size: Option<usize>,
+    state: State,
+}
+
+/// Parser state
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    Idle,
+    Record,
+    Done,
+}
+
+impl <W: FlashWriter> IntelHexFlasher<W> {
+    /// Create a new parser writing to the provided target
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            line: [0u8; MAX_RECORD],
+            len: 0,
+            base: 0,
+            parsed: 0,
+            size: None,
+            state: State::Idle,
+        }
+    }
+
+    /// Fetch a reference to the flash writer
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    /// Fetch a mutable reference to the flash writer
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Check whether parsing has completed, on EOF or error
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Reset parser state to accept a new file
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.base = 0;
+        self.parsed = 0;
+        self.size = None;
+        self.state = State::Idle;
+    }
+
+    /// Parse the next chunk of HEX file data, ignoring data following
+    /// completion (ie. cluster padding)
+    pub fn feed(&mut self, data: &[u8]) {
+        for c in data {
+            if self.state == State::Done {
+                return;
+            }
+
+            self.parsed += 1;
+
+            let r = match (self.state, *c) {
+                (State::Idle, b':') => {
+                    self.line[0] = b':';
+                    self.len = 1;
+                    self.state = State::Record;
+                    Ok(())
+                },
+                // Whitespace and padding between records
+                (State::Idle, b'\r' | b'\n' | b' ' | b'\t' | 0x00 | 0xFF) => Ok(()),
+                (State::Record, b'\r' | b'\n') => {
+                    self.state = State::Idle;
+                    self.record()
+                },
+                (State::Record, c) if self.len < MAX_RECORD => {
+                    self.line[self.len] = c;
+                    self.len += 1;
+                    Ok(())
+                },
+                _ => Err(FileError::InvalidData),
+            };
+
+            if let Err(e) = r {
+                crate::error!("Intel HEX parse failed at offset {}", self.parsed);
+                self.state = State::Done;
+                self.writer.complete(Err(e));
+            }
+        }
+
+        self.writer.progress(self.parsed, self.size);
+    }
+
+    /// Decode and handle a complete record
+    fn record(&mut self) -> Result<(), FileError> {
+        let hex = &self.line[1..self.len];
+        if hex.len() < 10 || !hex.len().is_multiple_of(2) {
+            return Err(FileError::InvalidData);
+        }
+
+        // Decode hex pairs, verifying the checksum
+        let mut b = [0u8; (MAX_RECORD - 1) / 2];
+        let n = hex.len() / 2;
+        for (i, p) in hex.chunks_exact(2).enumerate() {
+            b[i] = (nibble(p[0])? << 4) | nibble(p[1])?;
+        }
+        if b[..n].iter().fold(0u8, |a, v| a.wrapping_add(*v)) != 0 {
+            return Err(FileError::InvalidData);
+        }
+
+        let (len, offset, kind) = (b[0] as usize, u16::from_be_bytes([b[1], b[2]]), b[3]);
+        if n != len + 5 {
+            return Err(FileError::InvalidData);
+        }
+        let data = &b[4..][..len];
+
+        match (kind, len) {
+            (DATA, _) => {
+                let address = self.base.wrapping_add(offset as u32);
+                crate::trace!("Intel HEX data: {} bytes at 0x{:08x}", len, address);
+                self.writer.write(address, data)?;
+            },
+            (EOF, 0) => {
+                crate::debug!("Intel HEX transfer complete");
+                self.state = State::Done;
+                self.writer.complete(Ok(()));
+            },
+            (EXT_SEGMENT, 2) => self.base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+            (EXT_LINEAR, 2) => self.base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+            (START_SEGMENT, 4) | (START_LINEAR, 4) => (),
+            _ => return Err(FileError::InvalidData),
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode a hex character
+fn nibble(c: u8) -> Result<u8, FileError> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(FileError::InvalidData),
+    }
+}
+
+impl <W: FlashWriter + Send> HostFileSink for IntelHexFlasher<W> {
+    fn create(&mut self, _name: &str, size: usize) -> Result<(), FileError> {
+        self.reset();
+        self.size = Some(size);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<usize, FileError> {
+        if offset != self.parsed {
+            crate::warn!("Out of order Intel HEX write at offset {} (expected {})", offset, self.parsed);
+            return Err(FileError::WriteError);
+        }
+
+        self.feed(data);
+        Ok(data.len())
+    }
+
+    fn close(&mut self) -> Result<(), FileError> {
+        if self.state != State::Done {
+            crate::error!("Intel HEX file ended without EOF record");
+            self.state = State::Done;
+            self.writer.complete(Err(FileError::InvalidData));
+            return Err(FileError::InvalidData);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::uf2::tests::MockFlash;
+    use super::*;
+
+    const HEX: &[u8] = b":020000040000FA\r\n:040100001122334451\r\n:020000020020DC\r\n:02000200AABB97\n:00000001FF\r\n\0\0\0";
+
+    #[test]
+    fn parse_records() {
+        let mut hex = IntelHexFlasher::new(MockFlash::default());
+
+        // Records are split across blocks
+        for c in HEX.chunks(7) {
+            hex.feed(c);
+        }
+
+        let flash = hex.writer();
+        assert_eq!(flash.done, Some(Ok(())));
+        assert_eq!(flash.writes, 2);
+        assert_eq!(&flash.mem[0x100..0x104], &[0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(&flash.mem[0x202..0x204], &[0xAA, 0xBB]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut hex = IntelHexFlasher::new(MockFlash::default());
+
+        // Bad checksum
+        hex.create("FW.HEX", 32).unwrap();
+        assert_eq!(hex.write(0, b":0401000011223344FF\n"), Ok(20));
+        assert!(hex.is_done());
+        assert_eq!(hex.writer().done, Some(Err(FileError::InvalidData)));
+
+        // Missing EOF
+        hex.create("FW.HEX", 32).unwrap();
+        assert_eq!(hex.write(0, b":0401000011223344"), Ok(17));
+        assert_eq!(hex.write(4, b"51\n"), Err(FileError::WriteError));
+        assert_eq!(hex.close(), Err(FileError::InvalidData));
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{BlockDeviceError, GhostFat};
+
+/// [`std::io`] adapter for [`GhostFat`], exposing the volume as a seekable
+/// byte stream (ie. for use with `fatfs` or to dump the volume image).
+/// 
+/// Reads and writes may span blocks and need not be block aligned, with
+/// partial block writes merged with the current block content.
+pub struct GhostFatIo<'a, const BLOCK_SIZE: usize = 512, const FILES: usize = 0> {
+    fs: GhostFat<'a, BLOCK_SIZE, FILES>,
+    pos: u64,
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFatIo<'a, BLOCK_SIZE, FILES> {
+    /// Create a new stream over the provided file system
+    pub fn new(fs: GhostFat<'a, BLOCK_SIZE, FILES>) -> Self {
+        Self { fs, pos: 0 }
+    }
+
+    /// Fetch the current stream position
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Fetch the underlying file system
+    pub fn inner(&self) -> &GhostFat<'a, BLOCK_SIZE, FILES> {
+        &self.fs
+    }
+
+    /// Fetch the underlying file system for modification
+    pub fn inner_mut(&mut self) -> &mut GhostFat<'a, BLOCK_SIZE, FILES> {
+        &mut self.fs
+    }
+
+    /// Consume the stream, returning the underlying file system
+    pub fn into_inner(self) -> GhostFat<'a, BLOCK_SIZE, FILES> {
+        self.fs
+    }
+
+    /// Fetch the length of an access at the current position, clamped
+    /// to the end of the volume
+    fn clamp(&self, len: usize) -> usize {
+        usize::min(len, self.fs.volume_len().saturating_sub(self.pos) as usize)
+    }
+}
+
+impl From<BlockDeviceError> for io::Error {
+    fn from(e: BlockDeviceError) -> Self {
+        let kind = match e {
+            BlockDeviceError::InvalidAddress => io::ErrorKind::InvalidInput,
+            BlockDeviceError::NotReady => io::ErrorKind::WouldBlock,
+            _ => io::ErrorKind::Other,
+        };
+
+        io::Error::new(kind, format!("{:?}", e))
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> Read for GhostFatIo<'a, BLOCK_SIZE, FILES> {
+    fn read(&mut self, buff: &mut [u8]) -> io::Result<usize> {
+        let n = self.clamp(buff.len());
+        self.fs.read_bytes(self.pos, &mut buff[..n])?;
+        self.pos += n as u64;
+
+        Ok(n)
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> Write for GhostFatIo<'a, BLOCK_SIZE, FILES> {
+    fn write(&mut self, buff: &[u8]) -> io::Result<usize> {
+        let n = self.clamp(buff.len());
+        self.fs.write_bytes(self.pos, &buff[..n])?;
+        self.pos += n as u64;
+
+        Ok(n)
+    }
+
+    /// Writes are applied immediately, so no flush is required
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> Seek for GhostFatIo<'a, BLOCK_SIZE, FILES> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(v) => Some(v),
+            SeekFrom::End(v) => self.fs.volume_len().checked_add_signed(v),
+            SeekFrom::Current(v) => self.pos.checked_add_signed(v),
+        };
+
+        match pos {
+            Some(p) => {
+                self.pos = p;
+                Ok(p)
+            },
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start of volume")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BlockDevice, Config, File};
+    use super::*;
+
+    #[test]
+    fn stream_access() {
+        let data = [0xAAu8; 16];
+        let mut f = [File::<512>::new_ro("DATA.BIN", &data)];
+        let mut io = GhostFatIo::new(GhostFat::new(&mut f, Config::default()));
+
+        let mut image = Vec::new();
+        io.read_to_end(&mut image).unwrap();
+        assert_eq!(image.len(), 8000 * 512);
+
+        let lba = io.inner().config.start_clusters().0;
+        let mut block = [0u8; 512];
+        io.inner().read_block(lba, &mut block).unwrap();
+        assert_eq!(&image[lba as usize * 512..][..512], &block);
+
+        // Unaligned accesses span blocks
+        let mut b = [0u8; 20];
+        io.seek(SeekFrom::Start(lba as u64 * 512 - 4)).unwrap();
+        io.read_exact(&mut b).unwrap();
+        assert_eq!(&b[4..], &data);
+
+        assert_eq!(io.seek(SeekFrom::End(-1)).unwrap(), 8000 * 512 - 1);
+        assert_eq!(io.read(&mut b).unwrap(), 1);
+        assert_eq!(io.read(&mut b).unwrap(), 0);
+        assert!(io.seek(SeekFrom::Current(-(8000 * 512 + 1))).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use crate::GeneratedFile;
+
+/// Read only file over a restartable iterator of records
+///
+/// The provided function is called to create a new iterator over the file
+/// records, with records concatenated to form the file content. The total
+/// length is computed on creation along with up to `K` checkpoints, so
+/// random reads restart from the nearest preceding record rather than the
+/// start of the file (efficient where the iterator implements a fast
+/// [`Iterator::nth`], as with slice iterators).
+///
+/// Records must be stable for the life of the file.
+pub struct IterFile<F, const K: usize = 16> {
+    iter: F,
+    len: usize,
+    checkpoints: [(usize, usize); K],
+}
+
+impl <'a, F, I, const K: usize> IterFile<F, K>
+where
+    F: Fn() -> I,
+    I: Iterator<Item = &'a [u8]>,
+{
+    /// Create a new file over records produced by the provided iterator function
+    pub fn new(iter: F) -> Self {
+        let len = (iter)().map(|r| r.len()).sum();
+
+        // Record the (index, offset) of the record containing each 1/K of the file
+        let mut checkpoints = [(0, 0); K];
+        let (mut next, mut offset) = (1, 0);
+        for (i, r) in (iter)().enumerate() {
+            while next < K && next * len / K < offset + r.len() {
+                checkpoints[next] = (i, offset);
+                next += 1;
+            }
+            offset += r.len();
+        }
+
+        Self { iter, len, checkpoints }
+    }
+}
+
+impl <'a, F, I, const K: usize> GeneratedFile for IterFile<F, K>
+where
+    F: Fn() -> I + Sync + Send,
+    I: Iterator<Item = &'a [u8]>,
+{
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        if offset >= self.len {
+            return 0;
+        }
+
+        // Restart from the nearest checkpoint
+        let (index, mut pos) = self.checkpoints.iter().rev()
+            .find(|(_i, o)| *o <= offset)
+            .copied()
+            .unwrap_or((0, 0));
+
+        let mut iter = (self.iter)();
+        if index > 0 {
+            iter.nth(index - 1);
+        }
+
+        let mut n = 0;
+        for r in iter {
+            // Skip records prior to the offset
+            if pos + r.len() <= offset {
+                pos += r.len();
+                continue;
+            }
+
+            let start = offset + n - pos;
+            let len = usize::min(r.len() - start, buff.len() - n);
+            buff[n..][..len].copy_from_slice(&r[start..][..len]);
+
+            n += len;
+            pos += r.len();
+
+            if n == buff.len() {
+                break;
+            }
+        }
+
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{File, FileContent};
+    use super::*;
+
+    #[test]
+    fn iter_chunks() {
+        let records: [&[u8]; 5] = [b"abc", b"", b"defghij", b"k", b"lmnopqrstu"];
+        let text: &[u8] = b"abcdefghijklmnopqrstu";
+
+        let i = IterFile::<_, 4>::new(|| records.iter().copied());
+        assert_eq!(i.checkpoints, [(0, 0), (2, 3), (3, 10), (4, 11)]);
+
+        let f = File::<8>::new("DATA.BIN", FileContent::Generated(&i)).unwrap();
+        assert_eq!(f.len(), text.len());
+
+        let mut buff = [0u8; 8];
+        for (n, d) in text.chunks(8).enumerate().rev() {
+            assert_eq!(f.chunk(n, &mut buff), Ok(d.len()));
+            assert_eq!(&buff[..d.len()], d);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use core::ops::Range;
+
+use packing::PackedSize;
+
+use crate::{FileError, GhostFat};
+use crate::dir::DirectoryEntry;
+use crate::file::{Attrs, short_char};
+
+/// Boot sector bytes holding the volume label
+pub(crate) const BOOT_LABEL: Range<usize> = 43..54;
+
+/// Long file name entry attributes, which include the volume label bit
+const LONG_NAME: u8 = 0x0F;
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Fetch the volume label, including any host relabel, failing where
+    /// the host has written a non UTF-8 (ie. OEM code page) label
+    pub fn label(&self) -> Result<&str, core::str::Utf8Error> {
+        core::str::from_utf8(&self.boot_block[BOOT_LABEL]).map(str::trim_end)
+    }
+
+    /// Set the volume label (upper-cased and truncated to 11 characters),
+    /// ie. to restore a label persisted following a host relabel.
+    /// 
+    /// The label is applied to the boot sector and root directory, with
+    /// hosts seeing the new label following a remount. Labels must use
+    /// short name characters (see [`File::new_ro_checked`](crate::File::new_ro_checked))
+    /// or non-leading spaces, failing with [`FileError::InvalidName`] otherwise.
+    pub fn set_label(&mut self, label: &str) -> Result<(), FileError> {
+        self.boot_block[BOOT_LABEL].copy_from_slice(&encode_label(label)?);
+        self.invalidate_dir();
+
+        Ok(())
+    }
+
+    /// Apply a host relabel from a boot sector write, returning true if
+    /// the write otherwise matches the generated boot sector
+    pub(crate) fn label_boot(&mut self, block: &[u8]) -> bool {
+        let mut generated = [0u8; BLOCK_SIZE];
+        if self.boot(&mut generated).is_err() {
+            return false;
+        }
+
+        let label = &block[BOOT_LABEL];
+        if label == &generated[BOOT_LABEL] {
+            return false;
+        }
+
+        generated[BOOT_LABEL].copy_from_slice(label);
+        if !self.boot_matches_with(block, &generated) {
+            return false;
+        }
+
+        self.relabel(label);
+        true
+    }
+
+    /// Apply a host relabel from a write to the first root directory sector
+    pub(crate) fn label_dir(&mut self, block: &[u8]) {
+        let e = &block[..DirectoryEntry::BYTES];
+        let attrs = e[11];
+
+        if attrs == LONG_NAME || attrs & Attrs::VOLUME_LABEL.bits() == 0 || e[0] == 0 || e[0] == 0xE5 {
+            return;
+        }
+
+        if e[..11] != self.boot_block[BOOT_LABEL] {
+            self.relabel(&e[..11]);
+        }
+    }
+
+    /// Update the volume label following a host relabel
+    fn relabel(&mut self, label: &[u8]) {
+        self.boot_block[BOOT_LABEL].copy_from_slice(label);
+        self.invalidate_dir();
+        crate::debug!("Host relabelled volume: {:?}", label);
+    }
+}
+
+/// Encode a space padded volume label, with characters validated and
+/// upper-cased as for short names excepting non-leading spaces
+fn encode_label(label: &str) -> Result<[u8; BOOT_LABEL.end - BOOT_LABEL.start], FileError> {
+    let mut l = [crate::ASCII_SPACE; BOOT_LABEL.end - BOOT_LABEL.start];
+    if label.starts_with(' ') {
+        return Err(FileError::InvalidName);
+    }
+
+    for (i, c) in label.bytes().enumerate() {
+        let c = match c {
+            b' ' => c,
+            c => short_char(c).ok_or(FileError::InvalidName)?,
+        };
+
+        if let Some(b) = l.get_mut(i) {
+            *b = c;
+        }
+    }
+
+    Ok(l)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, File};
+    use super::*;
+
+    #[test]
+    fn relabel() {
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("INFO.TXT", &data)];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let rootdir = fs.config.start_rootdir().0;
+        assert_eq!(fs.label(), Ok("GHOSTFAT"));
+
+        // Host rewrites the volume label entry
+        let mut dir = [0u8; 512];
+        fs.read_block(rootdir, &mut dir).unwrap();
+        dir[..11].copy_from_slice(b"DEVICE     ");
+        fs.write_block(rootdir, &dir).unwrap();
+        assert_eq!(fs.label(), Ok("DEVICE"));
+
+        // And the boot sector label
+        let mut boot = [0u8; 512];
+        fs.read_block(0, &mut boot).unwrap();
+        assert_eq!(&boot[BOOT_LABEL], b"DEVICE     ");
+        boot[BOOT_LABEL].copy_from_slice(b"SENSOR 1   ");
+        fs.write_block(0, &boot).unwrap();
+        assert_eq!(fs.label(), Ok("SENSOR 1"));
+
+        // Labels are served back following a remount
+        fs.remount();
+        let mut read = [0u8; 512];
+        fs.read_block(rootdir, &mut read).unwrap();
+        assert_eq!(&read[..11], b"SENSOR 1   ");
+
+        // Other boot sector writes are discarded
+        boot[BOOT_LABEL].copy_from_slice(b"OTHER      ");
+        boot[13] = 4;
+        fs.write_block(0, &boot).unwrap();
+        assert_eq!(fs.label(), Ok("SENSOR 1"));
+
+        fs.set_label("GHOSTFAT").unwrap();
+        fs.read_block(0, &mut read).unwrap();
+        assert_eq!(&read[BOOT_LABEL], b"GHOSTFAT   ");
+
+        // Labels are upper-cased
+        fs.set_label("sensor 2").unwrap();
+        assert_eq!(fs.label(), Ok("SENSOR 2"));
+        fs.set_label("GHOSTFAT").unwrap();
+
+        // Non short name characters are rejected
+        for l in ["CAFÉ", "A*B", "A.B", "A:B", "A\tB", " LEADING"] {
+            assert_eq!(fs.set_label(l), Err(FileError::InvalidName));
+        }
+        assert_eq!(fs.label(), Ok("GHOSTFAT"));
+
+        // And non UTF-8 host labels reported
+        dir[..11].copy_from_slice(b"CAF\x90       ");
+        fs.write_block(rootdir, &dir).unwrap();
+        assert!(fs.label().is_err());
+    }
+}
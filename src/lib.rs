@@ -1,25 +1,61 @@
 //! GhostFAT Virtual FAT implementation for embedded USB SCSI devices
 //! 
+//! ## Interrupt context
+//! 
+//! [`BlockDevice::read_block`] and [`BlockDevice::write_block`] are commonly
+//! called from the USB interrupt, and do not block or allocate. With
+//! [`Config::bounded_time`] set, each call performs work bounded by the file
+//! count and `BLOCK_SIZE` (plus the cost of any [`DynamicFile`] or
+//! [`GeneratedFile`] implementations), with longer-running work deferred to
+//! [`GhostFat::poll`] for execution from thread context.
+//! 
+//! [`GhostFat::tick`] is bounded-time and may be called from a timer interrupt,
+//! while [`GhostFat::poll`] and [`GhostFat::remount`] should be called with
+//! the USB interrupt masked or from the same context as the USB stack.
+//! 
 // Based on: https://github.com/cs2dsb/stm32-usb.rs/blob/master/firmware/usb_bootloader/src/ghost_fat.rs
 
 #![cfg_attr(not(feature="std"), no_std)]
 #![cfg_attr(feature="nightly", feature(const_mut_refs))]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 #[cfg(feature = "defmt")]
-use defmt::{debug, info, trace, warn, error};
+use defmt::{debug, trace, warn, error};
 
 #[cfg(not(feature = "defmt"))]
-use log::{debug, info, trace, warn, error};
+use log::{debug, trace, warn, error};
 
 use packing::{Packed, PackedSize};
 
-use usbd_scsi::{BlockDevice, BlockDeviceError};
+mod device;
+pub use device::{BlockDevice, BlockDeviceError};
+
+mod lun;
+pub use lun::{DynBlockDevice, LunDevice, LunMux};
+
+#[cfg(any(feature = "embedded-storage", feature = "std"))]
+mod bytes;
 
 mod config;
-pub use config::Config;
+pub use config::{Config, UnmappedWrites, FormatPolicy};
+
+mod fixed;
+pub use fixed::FixedStr;
+
+mod types;
+pub use types::{Lba, Cluster, SectorIndex};
+
+mod error;
+use error::Error;
 
 mod file;
-pub use file::{File, FileContent, DynamicFile};
+pub use file::{File, FileContent, FileError, FileHooks, WriteObserver, DynamicFile, GeneratedFile, GeneratorFn};
+use file::Files;
+
+mod virgin;
+pub use virgin::{VirginPolicy, WriteMap};
 
 mod boot;
 use boot::FatBootBlock;
@@ -27,321 +63,2075 @@ use boot::FatBootBlock;
 mod dir;
 use dir::DirectoryEntry;
 
+mod text;
+pub use text::TextFile;
+
+mod csv;
+pub use csv::{CsvFile, CsvRecord};
+
+mod iterfile;
+pub use iterfile::IterFile;
+
+mod details;
+pub use details::Details;
+
+mod redirect;
+pub use redirect::IndexHtmFile;
+
+mod checksum;
+pub use checksum::{ChecksumFile, CompanionFile};
+
+#[cfg(feature = "crc32fast")]
+mod manifest;
+#[cfg(feature = "crc32fast")]
+pub use manifest::ManifestFormat;
+
+mod logfile;
+pub use logfile::LogFile;
+
+mod rotate;
+pub use rotate::{RotatingLog, RotatingLogFile};
+
+#[cfg(feature = "serde-json-core")]
+mod configfile;
+#[cfg(feature = "serde-json-core")]
+pub use configfile::ConfigFile;
+
+mod cached;
+pub use cached::CachedFile;
+
+mod buffered;
+pub use buffered::{BufferedFile, WritePolicy};
+
+#[cfg(feature = "heapless")]
+mod vecbuf;
+#[cfg(feature = "heapless")]
+pub use vecbuf::VecBuffer;
+
+mod console;
+pub use console::{ConsoleFile, ConsoleCommand};
+
+mod region;
+pub use region::BlockRegionFile;
+
+mod cipher;
+pub use cipher::{Cipher, CipherFile};
+
+mod compress;
+pub use compress::{Codec, CompressedFile, CompressingFile, compress_chunks};
+#[cfg(feature = "lz4_flex")]
+pub use compress::Lz4;
+#[cfg(feature = "heatshrink")]
+pub use compress::Heatshrink;
+
+mod hash;
+pub use hash::Hasher;
+#[cfg(feature = "crc32fast")]
+pub use hash::Crc32;
+#[cfg(feature = "sha2")]
+pub use hash::Sha256;
+
+mod perms;
+pub use perms::{Access, AccessRange};
+
+mod pacing;
+use pacing::Pacer;
+
+mod session;
+use session::Watchdog;
+
+mod cache;
+use cache::{DirCache, WarmCache, Sector};
+
+mod clusters;
+use clusters::{ClusterTable, FatEntries};
+
+mod metrics;
+pub use metrics::Metrics;
+use metrics::Area;
+
+mod program;
+pub use program::FlashWriter;
+
+mod uf2;
+pub use uf2::{Uf2Flasher, UF2_BLOCK_LEN};
+
+mod ihex;
+pub use ihex::IntelHexFlasher;
+
+mod rawbin;
+pub use rawbin::BinFlasher;
+
+mod route;
+pub use route::{HostFileSink, Route, Router, ScratchSink};
+
+mod capture;
+use capture::Capture;
+
+mod reassembly;
+use reassembly::Reassembly;
+
+mod shadow;
+use shadow::Shadow;
+
+mod complete;
+use complete::Completion;
+
+mod status;
+pub use status::StatusFile;
+use status::Status;
+
+mod changes;
+pub use changes::FileChange;
+
+mod scatter;
+pub use scatter::Segment;
+
+mod check;
+use check::DiskCheck;
+
+mod format;
+
+mod label;
+
+mod metadata;
+pub use metadata::{FileMetadata, Timestamp};
+
+mod builder;
+pub use builder::{GhostFatBuilder, LayoutError};
+
+#[cfg(any(feature = "alloc", feature = "heapless"))]
+mod registry;
+
+mod resolve;
+pub use resolve::Region;
+
+mod split;
+pub use split::{GhostFatReader, GhostFatWriter, UpdateGuard, UpdateSeq};
+
+#[cfg(feature = "critical-section")]
+mod shared;
+#[cfg(feature = "critical-section")]
+pub use shared::SharedGhostFat;
+
+#[cfg(feature = "alloc")]
+mod owned;
+#[cfg(feature = "alloc")]
+pub use owned::{OwnedFile, GhostFatOwned};
+
+#[cfg(feature = "littlefs")]
+mod littlefs;
+#[cfg(feature = "littlefs")]
+pub use littlefs::{LittleFs, LittleFsFile};
+
+#[cfg(feature = "static_cell")]
+mod statics;
+#[cfg(feature = "static_cell")]
+pub use statics::StaticGhostFat;
+#[cfg(feature = "static_cell")]
+pub use static_cell::StaticCell;
+
+mod asynch;
+pub use asynch::{AsyncBlockDevice, AsyncDynamicFile, AsyncGhostFat};
+
+#[cfg(feature = "usb-device")]
+pub mod scsi;
+#[cfg(feature = "usb-device")]
+pub use scsi::Scsi;
+
+#[cfg(feature = "embedded-sdmmc")]
+mod sdcard;
+#[cfg(feature = "embedded-sdmmc")]
+pub use sdcard::SdCardFile;
+
+#[cfg(feature = "embedded-sdmmc")]
+mod sdmmc;
+#[cfg(feature = "embedded-sdmmc")]
+pub use sdmmc::SdmmcVolume;
+
+#[cfg(feature = "embedded-storage")]
+mod eeprom;
+#[cfg(feature = "embedded-storage")]
+pub use eeprom::EepromFile;
+
+#[cfg(feature = "embedded-storage")]
+mod storage;
+
+#[cfg(feature = "std")]
+mod io;
+#[cfg(feature = "std")]
+pub use io::GhostFatIo;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "embedded-storage-async")]
+mod flash;
+#[cfg(feature = "embedded-storage-async")]
+pub use flash::AsyncFlashFile;
+
 const ASCII_SPACE: u8 = 0x20;
 
 
 /// Virtual FAT16 File System
-pub struct GhostFat<'a, const BLOCK_SIZE: usize = 512> {
+pub struct GhostFat<'a, const BLOCK_SIZE: usize = 512, const FILES: usize = 0> {
     config: Config<BLOCK_SIZE>,
-    fat_boot_block: FatBootBlock,
-    pub(crate) fat_files: &'a mut [File<'a, BLOCK_SIZE>],
+    boot_block: [u8; FatBootBlock::BYTES],
+    dir_cache: Option<DirCache<'a, BLOCK_SIZE>>,
+    clusters: ClusterTable<'a>,
+    pacer: Pacer,
+    watchdog: Watchdog,
+    warm_cache: Option<WarmCache<'a>>,
+    metrics: Option<&'a Metrics>,
+    observer: Option<&'a mut dyn WriteObserver>,
+    router: Option<&'a mut Router<'a>>,
+    capture: Option<Capture>,
+    reassembly: Option<Reassembly<'a, BLOCK_SIZE>>,
+    dir_shadow: Option<Shadow<'a, BLOCK_SIZE>>,
+    fat_shadow: Option<Shadow<'a, BLOCK_SIZE>>,
+    scratch: Option<Shadow<'a, BLOCK_SIZE>>,
+    completion: Option<Completion>,
+    status: Option<&'a dyn Status>,
+    changed: bool,
+    generation: u32,
+    check: DiskCheck,
+    formatting: bool,
+    layout: u64,
+    pub(crate) fat_files: Files<'a, BLOCK_SIZE, FILES>,
 }
 
+/// Virtual FAT16 File System owning an array of `FILES` files, see [`GhostFat::new_array`]
+pub type GhostFatArray<'a, const FILES: usize, const BLOCK_SIZE: usize = 512> = GhostFat<'a, BLOCK_SIZE, FILES>;
+
 impl <'a, const BLOCK_SIZE: usize> GhostFat<'a, BLOCK_SIZE> {
     /// Create a new file system instance with the provided files and configuration
     pub fn new(files: &'a mut [File<'a, BLOCK_SIZE>], config: Config<BLOCK_SIZE>) -> Self {
+        Self::with_files(Files::Borrowed(files), config)
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Create a new file system instance owning an array of files, so the
+    /// file system may be moved (ie. into a `static` or RTIC resource)
+    /// without borrowing a separate file table
+    pub fn new_array(files: [File<'a, BLOCK_SIZE>; FILES], config: Config<BLOCK_SIZE>) -> Self {
+        Self::with_files(Files::Array(files), config)
+    }
+
+    /// Create a new file system instance over the provided file table
+    fn with_files(files: Files<'a, BLOCK_SIZE, FILES>, config: Config<BLOCK_SIZE>) -> Self {
+
+        // Manifest lengths depend on the number of listed files
+        #[cfg(feature = "crc32fast")]
+        let files = manifest::update_entries(files);
 
         debug!("Configuring ghostfat with {} {} byte sectors ({} byte total), {} sector FATs", config.num_blocks, BLOCK_SIZE, config.num_blocks as usize * BLOCK_SIZE, config.sectors_per_fat());
 
         Self {
-            fat_boot_block: FatBootBlock::new(&config),
+            boot_block: FatBootBlock::new(&config).encode(),
+            dir_cache: None,
+            pacer: Pacer::new(config.bytes_per_interval),
+            watchdog: Watchdog::new(config.host_timeout),
+            warm_cache: None,
+            metrics: None,
+            observer: None,
+            router: None,
+            capture: None,
+            reassembly: None,
+            dir_shadow: None,
+            fat_shadow: None,
+            scratch: None,
+            completion: None,
+            status: None,
+            changed: false,
+            generation: 0,
+            check: DiskCheck::default(),
+            formatting: false,
+            layout: Self::layout(&files),
+            clusters: ClusterTable::default(),
             fat_files: files,
             config,
         }
     }
 
-    fn fat(id: usize, files: &[File<BLOCK_SIZE>], block: &mut [u8]){
-        let mut index = 0;
+    /// Attach a root directory cache buffer, with the generated root
+    /// directory sector cached until file lengths change rather than
+    /// regenerated on each read.
+    pub fn with_dir_cache(mut self, cache: &'a mut [u8; BLOCK_SIZE]) -> Self {
+        let cache = DirCache::new(cache);
+
+        // Pre-generate root directory entries, with invalid files reported on read
+        let _ = cache.fill(self.layout, |b| self.generate_dir(b));
+
+        self.dir_cache = Some(cache);
+        self
+    }
+
+    /// Attach a warm cache buffer, pre-generating root directory and leading
+    /// FAT sectors in one pass when the host reads the boot sector at mount.
+    /// 
+    /// The buffer is split into `BLOCK_SIZE` sectors, with root directory
+    /// sectors cached first followed by as many FAT sectors as will fit.
+    /// Cached sectors are refreshed on each boot sector read, so changes
+    /// in file lengths are picked up at the next mount.
+    pub fn with_warm_cache(mut self, cache: &'a mut [u8]) -> Self {
+        self.warm_cache = Some(WarmCache::new::<BLOCK_SIZE>(
+            cache,
+            self.config.root_dir_sectors as usize,
+            self.config.sectors_per_fat() as usize,
+        ));
+        self
+    }
+
+    /// Attach [`Metrics`] counters, updated on each block device access.
+    /// 
+    /// The same metrics object may be registered as a generated file
+    /// (ie. `METRICS.TXT`) to expose device health to the host
+    pub fn with_metrics(mut self, metrics: &'a Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Attach a [`WriteObserver`], called with each host write to the
+    /// data region (ie. a [`Uf2Flasher`])
+    pub fn with_observer(mut self, observer: &'a mut dyn WriteObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
 
-        // Clear block
-        for b in block.iter_mut() {
-            *b = 0;
+    /// Advance file system timers, starting a new pacing interval and
+    /// checking for host session timeouts.
+    /// 
+    /// This should be called periodically (ie. from a timer) when
+    /// [`Config::bytes_per_interval`] or [`Config::host_timeout`] are set,
+    /// returning true if the host session timed out during this tick
+    pub fn tick(&mut self) -> bool {
+        self.pacer.reset();
+
+        if !self.watchdog.tick() {
+            return false;
         }
 
-        // First FAT contains media and file end marker in clusters 0 and 1
-        if id == 0 {
-            block[0] = 0xf0;
-            block[1] = 0xff;
-            block[2] = 0xff;
-            block[3] = 0xff;
-            index = 2;
+        warn!("Host session timed out after {} ticks", self.watchdog.idle());
+
+        if let Some(m) = self.metrics {
+            m.timeout();
         }
 
-        // Compute cluster offset from FAT ID
-        let cluster_offset = id * BLOCK_SIZE / 2;
-        // Allocated blocks start at two to avoid reserved sectors
-        let mut block_index = 2;
+        self.complete_timeout();
 
-        // Iterate through available files to allocate blocks
-        for f in files.iter() {
-            // Determine number of blocks required for each file
-            let block_count = f.num_blocks();
+        if self.config.remount_on_timeout {
+            self.remount();
+        }
 
-            // Skip entries where file does not overlap FAT
-            //#[cfg(nope)]
-            if (block_index + block_count < cluster_offset) || (block_index > cluster_offset + BLOCK_SIZE/1) {
-                block_index += block_count;
-                continue;
-            }
+        true
+    }
 
-            if cluster_offset >= block_index + block_count {
-                block_index += block_count;
-                continue;
-            }
-            
-            debug!("FAT {} File: '{}' {} clusters starting at cluster {}", id, f.name(), block_count, block_index);
+    /// Check whether the volume is write-protected
+    pub fn is_write_protected(&self) -> bool {
+        self.config.write_protected
+    }
 
-            let (file_offset, remainder) = if cluster_offset > block_index {
-                (cluster_offset - block_index, block_count + block_index - cluster_offset)
-            } else {
-                (0, block_count)
-            };
+    /// Enable or disable volume write protection at runtime, ie. while
+    /// applying an update. Hosts should be signalled to re-read the volume
+    /// status (ie. by remounting) for changes to be reflected to users.
+    pub fn set_write_protected(&mut self, write_protected: bool) {
+        self.config.write_protected = write_protected;
+    }
 
-            let blocks = usize::min(remainder, (BLOCK_SIZE / 2) - (index % BLOCK_SIZE));
+    /// Check whether a host session is active, ie. the host has accessed
+    /// the file system within the configured [`Config::host_timeout`]
+    pub fn session_active(&self) -> bool {
+        self.watchdog.active()
+    }
 
-            debug!("FAT offset: {} file offset: {} remainder: {} clusters: {}", cluster_offset, file_offset, remainder, blocks);
+    /// Soft-eject and remount the file system, discarding in-progress
+    /// host session state so a new session starts from a clean slate
+    pub fn remount(&mut self) {
+        debug!("Remounting file system");
 
-            for i in 0..blocks {
-                let j = i * 2;
+        self.pacer.reset();
+        self.watchdog.reset();
 
-                let v: u16 = if remainder == blocks && i == blocks-1 {
-                    0xFFFF
-                } else {
-                    (block_index + file_offset + i + 1) as u16
-                };
+        if let Some(c) = &self.warm_cache {
+            c.invalidate();
+        }
+        self.invalidate_dir();
+        self.clusters.update(&self.fat_files);
 
-                block[index * 2 + j] =  v as u8;
-                block[index * 2 + j + 1] = (v >> 8) as u8;
-            }
+        self.capture = None;
+        if let Some(r) = self.reassembly.as_mut() {
+            r.reset();
+        }
 
-            // Increase FAT index
-            index += blocks;
+        if let Some(s) = self.dir_shadow.as_mut() {
+            s.clear();
+        }
 
-            // Increase block index
-            block_index += blocks;
+        if let Some(s) = self.fat_shadow.as_mut() {
+            s.clear();
         }
-    } 
 
-}
+        if let Some(s) = self.scratch.as_mut() {
+            s.clear();
+        }
 
-/// [`BlockDevice`] implementation for use with [`usbd_scsi`]
-impl <'a, const BLOCK_SIZE: usize>BlockDevice for GhostFat<'a, BLOCK_SIZE> {
-    const BLOCK_BYTES: usize = BLOCK_SIZE;
+        self.completion = None;
+        self.check = DiskCheck::default();
+        self.formatting = false;
+        self.mark_changed();
+    }
 
-    /// Read a file system block
-    fn read_block(&self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError> {
-        assert_eq!(block.len(), Self::BLOCK_BYTES);
+    /// Signal that volume contents have changed, so the host should discard
+    /// cached data and re-read the volume.
+    /// 
+    /// This is reported to the host as a medium change (UNIT ATTENTION) by
+    /// the SCSI layer, see [`Scsi::with_media_change`](crate::scsi::Scsi::with_media_change).
+    /// [`GhostFat::remount`] and [`GhostFat::refresh`] mark the volume changed.
+    pub fn mark_changed(&mut self) {
+        self.changed = true;
+    }
 
-        trace!("GhostFAT reading lba: {} ({} bytes)", lba, block.len());
+    /// Fetch and clear the volume changed flag, see [`GhostFat::mark_changed`]
+    pub fn take_changed(&mut self) -> bool {
+        core::mem::take(&mut self.changed)
+    }
 
-        // Clear the buffer since we're sending all of it
-        for b in block.iter_mut() {
-            *b = 0
+    /// Check for changes in file lengths since construction or the last
+    /// refresh, remounting the file system and returning true if the
+    /// volume has changed.
+    /// 
+    /// Directory entries and FAT chains are generated from live file
+    /// lengths, however hosts cache these on mount so should be signalled
+    /// to re-read the volume (ie. by re-enumerating the USB device) when
+    /// this returns true. Files that may grow should reserve clusters via
+    /// [`File::with_reserved`].
+    pub fn refresh(&mut self) -> bool {
+        let layout = Self::layout(&self.fat_files);
+        if layout == self.layout {
+            return false;
         }
 
-        // Block 0 is the fat boot block
-        if lba == 0 {
-            self.fat_boot_block
-                .pack(&mut block[..FatBootBlock::BYTES])
-                .unwrap();
-            block[510] = 0x55;
-            block[511] = 0xAA;
+        debug!("File lengths changed, volume refresh required");
 
-        // File allocation table(s) follow the boot block
-        } else if lba < self.config.start_rootdir() {
-            let mut section_index = lba - self.config.start_fat0();
+        self.layout = layout;
+        self.remount();
 
-            debug!("Read FAT section index: {} (lba: {})", section_index, lba);
+        true
+    }
 
-            // The file system contains two copies of the FAT
-            // wrap the section index to overlap these
-            if section_index >= self.config.sectors_per_fat() {
-                section_index -= self.config.sectors_per_fat();
-            }
+    /// Fetch the registered files
+    pub fn files(&self) -> &[File<'a, BLOCK_SIZE>] {
+        &self.fat_files
+    }
 
-            Self::fat(section_index as usize, &self.fat_files, block);
-            trace!("FAT {}: {:?}", section_index, &block);
+    /// Fetch the registered files for modification.
+    /// 
+    /// Changes to file lengths are presented to the host following a
+    /// [`GhostFat::refresh`]
+    pub fn files_mut(&mut self) -> &mut [File<'a, BLOCK_SIZE>] {
+        self.invalidate_dir();
+        &mut self.fat_files
+    }
 
-        // Directory entries follow
-        } else if lba < self.config.start_clusters() {
-            let section_index = lba - self.config.start_rootdir();
-            if section_index == 0 {
-                let mut dir = DirectoryEntry::default();
-                dir.name.copy_from_slice(&self.fat_boot_block.volume_label);
-                dir.attrs = 0x28;
-
-                let len = DirectoryEntry::BYTES;
-                dir.pack(&mut block[..len]).unwrap();
-                dir.attrs = 0;
-
-                // Starting cluster index (after BBL and FAT)
-                let mut cluster_index = 2;
-
-                // Generate directory entries for registered files
-                for (i, info) in self.fat_files.iter().enumerate() {
-                    // Determine number of blocks required for each file
-                    let mut block_count = info.len() / Self::BLOCK_BYTES;
-                    if info.len() % Self::BLOCK_BYTES != 0 {
-                        block_count += 1;
-                    }
-                    dir.start_cluster = cluster_index as u16;
+    /// Find a registered file by name, ignoring case as for FAT names
+    pub fn file(&self, name: &str) -> Option<&File<'a, BLOCK_SIZE>> {
+        self.fat_files.iter().find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Find a registered file by name for modification (ie. to update
+    /// writable buffer contents), ignoring case as for FAT names
+    pub fn file_mut(&mut self, name: &str) -> Option<&mut File<'a, BLOCK_SIZE>> {
+        self.invalidate_dir();
+        self.fat_files.iter_mut().find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Compute a fingerprint of file lengths for change detection
+    fn layout(files: &[File<BLOCK_SIZE>]) -> u64 {
+        files.iter().fold(0xcbf2_9ce4_8422_2325, |h, f| {
+            (h ^ f.len() as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Check whether the file system has remaining service budget for the
+    /// current interval
+    /// 
+    /// When this returns false the USB device should not be polled until
+    /// the next [`GhostFat::tick`], so the host is NAK'd rather than
+    /// starving other firmware tasks
+    pub fn ready(&self) -> bool {
+        self.pacer.ready()
+    }
 
-                    // Write attributes
-                    dir.name.copy_from_slice(&info.short_name().unwrap());
-                    dir.size = info.len() as u32;
-                    dir.attrs = info.attrs().bits();
+    /// Perform a bounded step of deferred work outside of interrupt context,
+    /// returning true while work remains.
+    /// 
+    /// This incrementally generates warm cache sectors when
+    /// [`Config::bounded_time`] is set, and polls [`DynamicFile`]s for
+    /// maintenance (ie. compaction), and should be called from the main loop
+    pub fn poll(&mut self) -> bool {
+        let mut pending = false;
+
+        if let Some(c) = &self.warm_cache {
+            match c.fill_step::<BLOCK_SIZE, _>(
+                |i, block| self.dir(SectorIndex(i as u32), block),
+                |i, block| Self::fat_range(i, &self.fat_files, &self.clusters, block),
+            ) {
+                Ok(p) => pending |= p,
+                Err(_) => error!("Failed to generate warm cache sector"),
+            }
+        }
+
+        for f in self.fat_files.iter_mut() {
+            if let FileContent::Dynamic(d) = &mut f.data {
+                pending |= d.poll();
+            }
+        }
 
-                    // Encode to block
-                    let start = (i + 1) * len;
-                    dir.pack(&mut block[start..(start + len)]).unwrap();
+        pending
+    }
 
-                    // Increment cluster index
-                    cluster_index += block_count;
+    /// Commit buffered writes for all [`DynamicFile`]s and call
+    /// [`FileHooks::on_flush`] (ie. on host cache sync), returning the
+    /// last error encountered
+    pub fn flush(&mut self) -> Result<(), FileError> {
+        let mut res = Ok(());
+
+        for f in self.fat_files.iter_mut() {
+            if let FileContent::Dynamic(d) = &mut f.data {
+                if let Err(e) = d.flush() {
+                    error!("Failed to flush file: {}", f.name());
+                    res = Err(e);
                 }
             }
 
-        // Then finally clusters (containing actual data)
-        } else {
-            let section_index = (lba - self.config.start_clusters()) as usize;
+            if let Some(h) = f.hooks {
+                h.on_flush();
+            }
+        }
 
-            debug!("Read cluster index: 0x{:04x} (lba: 0x{:04x})", section_index, lba);
+        res
+    }
 
-            // Iterate through files to find matching block
-            let mut block_index = 0;
-            for f in self.fat_files.iter() {
+    /// Handle host eject (ie. "Safely Remove"), flushing files, completing
+    /// in-progress host writes and remounting so the next host session
+    /// starts from a clean slate, returning the last flush error encountered
+    pub fn eject(&mut self) -> Result<(), FileError> {
+        debug!("Host eject");
 
-                // Determine number of blocks required for each file
-                let mut block_count = f.len() / Self::BLOCK_BYTES;
-                if f.len() % Self::BLOCK_BYTES != 0 {
-                    block_count += 1;
-                }
+        let res = self.flush();
+        self.complete_timeout();
+        self.remount();
 
-                // If the LBA is within the file, return data
-                if section_index < block_count + block_index {
-                    let offset = section_index - block_index;
+        res
+    }
 
-                    debug!("Read file: {} chunk: 0x{:02x}", f.name(), offset);
+    /// Fetch a reference to the content of the block at `lba` where it may
+    /// be served without copying (see [`File::chunk_ref`]), ie. for transports
+    /// supporting zero-copy transmission of block-aligned flash content.
+    ///
+    /// Returns `None` where the block must be generated, in which case it
+    /// should be read via [`BlockDevice::read_block`]
+    pub fn read_ref(&self, lba: u32) -> Option<&[u8]> {
+        let (index, offset) = self.ref_chunk(Lba(lba))?;
+        let d = self.fat_files[index].chunk_ref(offset)?;
 
-                    if f.chunk(offset, block) == 0 {
-                        warn!("Failed to read file: {} chunk: {}", f.name(), offset);
-                    }
+        self.read_refs(index, offset, 1);
 
-                    return Ok(())
-                }
+        Some(d)
+    }
 
-                // Otherwise, continue
-                block_index += block_count;
-            }
+    /// Locate the file index and chunk offset for a block that may be
+    /// referenced in place, see [`GhostFat::read_ref`]
+    pub(crate) fn ref_chunk(&self, lba: Lba) -> Option<(usize, usize)> {
+        if lba < self.config.start_clusters() || perms::access(self.config.access_map, lba) == Access::NoAccess {
+            return None;
+        }
 
-            warn!("Unhandled cluster read 0x{:04x} (lba: 0x{:04x})", section_index, lba);
+        let section_index = lba - self.config.start_clusters();
+        if self.scratch.as_ref().is_some_and(|s| s.contains(section_index)) {
+            return None;
         }
-        Ok(())
-    }
 
-    /// Write a file system block
-    fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError> {
-        debug!("GhostFAT writing lba: {} ({} bytes)", lba, block.len());
+        let (index, offset) = self.locate(section_index)?;
+        self.fat_files[index].chunk_ref(offset)?;
 
-        if lba == 0 {
-            warn!("Attempted write to boot sector");
-            return Ok(());
+        Some((index, offset))
+    }
 
-        // Write to FAT
-        } else if lba < self.config.start_rootdir() {
-            // TODO: should we support this?
-            warn!("Attempted to write to FAT");
+    /// Account for `n` blocks from chunk `offset` of a file read by reference
+    pub(crate) fn read_refs(&self, index: usize, offset: usize, n: usize) {
+        let f = &self.fat_files[index];
 
-        // Write directory entry
-        } else if lba < self.config.start_clusters() {
-            // TODO: do we need to wrap this somehow to remap writes?
-            // it _appears_ it's okay to assume the FAT driver will use existing
-            // allocated blocks so this is not required provided files do not exceed
-            // configured sizes
-            warn!("Attempted to write directory entries");
+        trace!("GhostFAT referencing file: {} chunks: 0x{:02x} (count: {})", f.name(), offset, n);
 
-            let section_index = lba - self.config.start_rootdir();
-            if section_index == 0 {
+        self.pacer.consume(n * BLOCK_SIZE);
+        self.watchdog.access();
 
+        for o in offset..offset + n {
+            if let Some(h) = f.hooks {
+                h.on_read(o);
+            }
 
+            if let Some(m) = self.metrics {
+                m.read(Area::Data, false);
             }
+        }
+    }
 
-        // Write cluster data
+    /// Resolve the file system area containing an LBA
+    fn area(&self, lba: Lba) -> Area {
+        if lba == Lba(0) {
+            Area::Boot
+        } else if lba < self.config.start_rootdir() {
+            Area::Fat
+        } else if lba < self.config.start_clusters() {
+            Area::Dir
         } else {
-            let section_index = (lba - self.config.start_clusters()) as usize;
+            Area::Data
+        }
+    }
 
-            // Iterate through files to find matching block
-            let mut block_index = 0;
-            for f in self.fat_files.iter_mut() {
+    /// Locate the file index and chunk offset for a data cluster section
+    fn locate(&self, section_index: SectorIndex) -> Option<(usize, usize)> {
+        self.clusters.locate(&self.fat_files, section_index)
+    }
 
-                // Determine number of blocks required for each file
-                let mut block_count = f.len() / Self::BLOCK_BYTES;
-                if f.len() % Self::BLOCK_BYTES != 0 {
-                    block_count += 1;
-                }
+    /// Detect host deletion of files in a write to the first root directory
+    /// sector, where deleted entries have the first name byte set to 0xE5
+    fn detect_deletions(&mut self, block: &[u8]) {
+        // Generated entries follow the volume label
+        let entries = block.chunks_exact(DirectoryEntry::BYTES).skip(1);
 
-                // If the LBA is within the file, write data
-                if section_index < block_count + block_index {
-                    let offset = section_index - block_index;
+        for (f, e) in self.fat_files.iter_mut().zip(entries) {
+            let name = match f.short_name() {
+                Ok(n) => n,
+                Err(_) => continue,
+            };
 
-                    debug!("Write file: {} block: {}, {} bytes", f.name(), offset, block.len());
+            let deleted = match e[0] {
+                0xE5 => e[1..11] == name[1..],
+                _ => false,
+            };
 
-                    if f.chunk_mut(offset, &block) == 0 {
-                        error!("Attempted to write to read-only file");
-                        return Err(BlockDeviceError::WriteError);
-                    }
+            if deleted && !f.deleted {
+                debug!("Host deleted file: {}", f.name());
 
-                    return Ok(())
+                if let Some(h) = f.hooks {
+                    h.on_delete();
                 }
+            }
+
+            // Files listed again (ie. following a remount) are restored
+            if deleted || e[..11] == name {
+                f.deleted = deleted;
+            }
+        }
+    }
+
+    /// Detect host renames in a root directory write, where an entry with a
+    /// new name references the start cluster of a file
+    fn detect_renames(&mut self, block: &[u8]) {
+        for i in 0..self.fat_files.len() {
+            let start = self.clusters.start(&self.fat_files, i);
+            let f = &mut self.fat_files[i];
+
+            let name = match f.short_name() {
+                Ok(n) if !f.is_empty() && !f.deleted => n,
+                _ => continue,
+            };
+
+            let entry = block.chunks_exact(DirectoryEntry::BYTES)
+                .filter_map(|e| DirectoryEntry::unpack(e).ok())
+                .find(|e| capture::is_file_entry(e) && e.start_cluster as u32 == start.0);
+
+            // Compare against the last name seen from the host
+            let e = match entry {
+                Some(e) if e.name != f.renamed.unwrap_or(name) => e,
+                _ => continue,
+            };
+
+            let mut buff = [0u8; 12];
+            let new = capture::display_name(&e.name, &mut buff);
+
+            debug!("Host renamed file: {} to {}", f.name(), new);
+
+            if let Some(h) = f.hooks {
+                h.on_rename(new);
+            }
 
-                // Otherwise, continue
-                block_index += block_count;
+            f.renamed = Some(e.name).filter(|n| *n != name);
+
+            #[cfg(feature = "alloc")]
+            if self.config.apply_renames {
+                f.name = file::Name::Owned(new.into());
+                f.renamed = None;
+                self.invalidate_dir();
+            }
+        }
+    }
+
+    /// Pre-generate cached sectors for the mount sequence
+    fn warm(&self) {
+        if let Some(c) = &self.warm_cache {
+            // Defer generation to `poll` to bound the boot sector read
+            if self.config.bounded_time {
+                c.invalidate();
+                return;
+            }
+
+            debug!("Warming mount cache");
+
+            let r = c.fill::<BLOCK_SIZE, _>(
+                |i, block| self.dir(SectorIndex(i as u32), block),
+                |block| Self::fat_range(0, &self.fat_files, &self.clusters, block),
+            );
+            if r.is_err() {
+                error!("Failed to generate warm cache");
             }
+        }
+    }
+
+    /// Generate contiguous FAT sectors from `start`, with entries generated
+    /// incrementally from the first cluster covered by the sectors
+    fn fat_range(start: usize, files: &[File<BLOCK_SIZE>], clusters: &ClusterTable, block: &mut [u8]) {
+        let first = start * BLOCK_SIZE / 2;
+        let entries = FatEntries::new(files, clusters, first as u32);
+
+        for (e, v) in block.chunks_exact_mut(2).zip(entries) {
+            e.copy_from_slice(&v.to_le_bytes());
+        }
+    }
 
-            debug!("Unhandled write section: {}", section_index);
+    /// Generate the boot sector
+    fn boot(&self, block: &mut [u8]) -> Result<(), Error> {
+        // Boot sectors require at least 512 bytes for the signature
+        if block.len() < 512 {
+            return Err(Error::InvalidLength(block.len()));
         }
 
+        block[..FatBootBlock::BYTES].copy_from_slice(&self.boot_block);
+        block[FatBootBlock::BYTES..].fill(0);
+        block[510] = 0x55;
+        block[511] = 0xAA;
+
         Ok(())
     }
 
-    /// Report the maximum block index for the file system
-    fn max_lba(&self) -> u32 {
-        self.config.num_blocks - 1
+    /// Fetch a root directory sector, with generated entries cached until
+    /// file lengths change or the cache is invalidated
+    fn dir(&self, section_index: SectorIndex, block: &mut [u8]) -> Result<(), Error> {
+        if section_index != SectorIndex(0) {
+            block.fill(0);
+            return Ok(());
+        }
+
+        match &self.dir_cache {
+            Some(c) => c.read(Self::layout(&self.fat_files), block, |b| self.generate_dir(b)),
+            None => self.generate_dir(&mut block[..BLOCK_SIZE]),
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::{GhostFat, File};
+    /// Invalidate the cached root directory sector, ie. where file names
+    /// or the volume label have changed
+    fn invalidate_dir(&self) {
+        if let Some(c) = &self.dir_cache {
+            c.invalidate();
+        }
+    }
 
+    /// Generate the first root directory sector, listing the volume label
+    /// and registered files
+    fn generate_dir(&self, block: &mut [u8]) -> Result<(), Error> {
+        block.fill(0);
 
-    #[test]
-    fn file_offsets() {
-        let data = [0xAAu8; 64];
-        let f = [File::<8>::new_ro("test.bin", &data)];
-        assert_eq!(f[0].len(), data.len());
+        let len = DirectoryEntry::BYTES;
+        let mut entries = block.chunks_exact_mut(len);
 
-        let mut block = [0u8; 8];
-        GhostFat::fat(0, &f, &mut block);
-        println!("FAT0: {:02x?}", block);
+        let mut dir = DirectoryEntry::default();
+        dir.name.copy_from_slice(&self.boot_block[label::BOOT_LABEL]);
+        dir.attrs = 0x28;
 
-        assert_eq!(&block, &[
-            0xf0, 0xff, 0xff, 0xff, 
-            0x03, 0x00, 0x04, 0x00]);
+        let e = entries.next().ok_or(Error::DirectoryFull)?;
+        dir.pack(e).map_err(|_| Error::Encode)?;
+        dir.attrs = 0;
 
+        // Generate directory entries for registered files
+        for (i, info) in self.fat_files.iter().enumerate() {
+            // Empty files have no start cluster
+            dir.start_cluster = match info.is_empty() {
+                true => 0,
+                false => self.clusters.start(&self.fat_files, i).0 as u16,
+            };
 
-        GhostFat::fat(1, &f, &mut block);
-        println!("FAT1: {:02x?}", block);
-        assert_eq!(&block, &[
-            0x05, 0x00, 0x06, 0x00, 
-            0x07, 0x00, 0x08, 0x00]);
+            // Write attributes
+            dir.name = info.short_name().map_err(|_| Error::InvalidName(i))?;
+            dir.size = info.len() as u32;
+            dir.attrs = info.attrs().bits();
 
-        GhostFat::fat(2, &f, &mut block);
-        println!("FAT2: {:02x?}", block);
-        assert_eq!(&block, &[
-            0x09, 0x00, 0xff, 0xff, 
-            0x00, 0x00, 0x00, 0x00]);
+            // Encode to block
+            let e = entries.next().ok_or(Error::DirectoryFull)?;
+            dir.pack(e).map_err(|_| Error::Encode)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Read a file system block
+    fn read_lba(&self, lba: Lba, block: &mut [u8]) -> Result<(), BlockDeviceError> {
+        if block.len() != Self::BLOCK_BYTES {
+            error!("Invalid read length {} from lba: {} (expected {})", block.len(), lba, Self::BLOCK_BYTES);
+            return Err(Error::InvalidLength(block.len()).into());
+        }
+
+        trace!("GhostFAT reading lba: {} ({} bytes)", lba, block.len());
 
-        assert!(true);
+        self.pacer.consume(block.len());
+        self.watchdog.access();
+
+        if perms::access(self.config.access_map, lba) == Access::NoAccess {
+            warn!("Attempted read from no-access lba: {}", lba);
+            return Err(BlockDeviceError::InvalidAddress);
+        }
+
+        // Branches clear any part of the buffer they do not write, since
+        // we're sending all of it
+        // Block 0 is the fat boot block
+        if lba == Lba(0) {
+            self.boot(block)?;
+            self.check_boot_read(block);
+
+            // Boot sector reads start the mount sequence
+            self.warm();
+
+        // File allocation table(s) follow the boot block
+        } else if lba < self.config.start_rootdir() {
+            let mut section_index = lba - self.config.start_fat0();
+
+            debug!("Read FAT section index: {} (lba: {})", section_index, lba);
+
+            // The file system contains two copies of the FAT
+            // wrap the section index to overlap these
+            if section_index.0 >= self.config.sectors_per_fat() {
+                section_index.0 -= self.config.sectors_per_fat();
+            }
+
+            if let Some(s) = &self.fat_shadow {
+                if s.read(section_index, block) {
+                    return Ok(());
+                }
+            }
+
+            if let Some(c) = &self.warm_cache {
+                if c.read::<BLOCK_SIZE>(Sector::Fat(section_index.as_usize()), block) {
+                    self.check_fat_read(section_index, block);
+                    return Ok(());
+                }
+            }
+
+            Self::fat_range(section_index.as_usize(), &self.fat_files, &self.clusters, block);
+            self.check_fat_read(section_index, block);
+            trace!("FAT {}: {:?}", section_index, &block);
+
+        // Directory entries follow
+        } else if lba < self.config.start_clusters() {
+            let section_index = lba - self.config.start_rootdir();
+
+            if let Some(s) = &self.dir_shadow {
+                if s.read(section_index, block) {
+                    return Ok(());
+                }
+            }
+
+            if let Some(c) = &self.warm_cache {
+                if c.read::<BLOCK_SIZE>(Sector::Dir(section_index.as_usize()), block) {
+                    return Ok(());
+                }
+            }
+
+            self.dir(section_index, block)?;
+
+        // Then finally clusters (containing actual data)
+        } else {
+            let section_index = lba - self.config.start_clusters();
+
+            debug!("Read cluster index: 0x{:04x} (lba: 0x{:04x})", section_index.0, lba.0);
+
+            // Host writes outside device files are served from scratch
+            if let Some(s) = &self.scratch {
+                if s.read(section_index, block) {
+                    return Ok(());
+                }
+            }
+
+            // If the LBA is within a file, return data
+            if let Some((index, offset)) = self.locate(section_index) {
+                let f = &self.fat_files[index];
+
+                debug!("Read file: {} chunk: 0x{:02x}", f.name(), offset);
+
+                if let Some(h) = f.hooks {
+                    h.on_read(offset);
+                }
+
+                if let FileContent::Companion(c) = f.data {
+                    let len = usize::min(BLOCK_SIZE, c.len().saturating_sub(offset * BLOCK_SIZE));
+                    let n = c.generate(&self.fat_files, offset * BLOCK_SIZE, &mut block[..len]);
+                    block[n..].fill(0);
+                    return Ok(());
+                }
+
+                #[cfg(feature = "crc32fast")]
+                if let FileContent::Manifest{ format, .. } = f.data {
+                    let n = self.manifest(format, offset * BLOCK_SIZE, block);
+                    block[n..].fill(0);
+                    return Ok(());
+                }
+
+                // Only the bytes read from the file are written
+                match f.chunk(offset, block) {
+                    Ok(0) => {
+                        warn!("Empty read from file: {} chunk: {}", f.name(), offset);
+                        block.fill(0);
+                    },
+                    Ok(n) => if let Some(b) = block.get_mut(n..) {
+                        b.fill(0);
+                    },
+                    Err(e) => {
+                        error!("Failed to read file: {} chunk: {}", f.name(), offset);
+                        return Err(e.into());
+                    },
+                }
+
+                return Ok(())
+            }
+
+            warn!("Unhandled cluster read 0x{:04x} (lba: 0x{:04x})", section_index.0, lba.0);
+            block.fill(0);
+        }
+        Ok(())
+    }
+
+    /// Find the number of blocks from `lba` (up to `max`) that may be read
+    /// from or written to a single [`DynamicFile`] in one call, returning
+    /// the file index and chunk offset of the run
+    fn dynamic_run(&self, lba: Lba, max: usize, write: bool) -> Option<(usize, usize, usize)> {
+        if lba < self.config.start_clusters() {
+            return None;
+        }
+
+        // Captured host files may claim any cluster
+        if write && (self.config.write_protected || self.router.is_some()) {
+            return None;
+        }
+
+        let section_index = lba - self.config.start_clusters();
+        let (index, offset) = self.locate(section_index)?;
+        let f = &self.fat_files[index];
+
+        // Blocks with per-chunk handling are processed individually
+        if !matches!(f.data, FileContent::Dynamic(_)) || (write && f.deleted) || (!write && f.virgin.is_some()) {
+            return None;
+        }
+
+        // Reads are limited to the file length, writes to the allocation
+        let end = match write {
+            true => self.clusters.sectors(&self.fat_files, index).len(),
+            false => usize::min(self.clusters.sectors(&self.fat_files, index).len(), f.num_blocks()),
+        };
+
+        // Reads stop at host writes held in scratch (ie. to reused clusters of deleted files)
+        let n = (0..usize::min(max, end.saturating_sub(offset)))
+            .take_while(|i| match perms::access(self.config.access_map, Lba(lba.0 + *i as u32)) {
+                Access::ReadWrite => true,
+                Access::ReadOnly => !write,
+                Access::NoAccess => false,
+            })
+            .take_while(|i| write || !self.scratch.as_ref().is_some_and(|s| s.contains(SectorIndex(section_index.0 + *i as u32))))
+            .count();
+
+        Some((index, offset, n))
+    }
+
+    /// Read a run of blocks from a single [`DynamicFile`], see [`GhostFat::dynamic_run`]
+    fn read_run(&self, index: usize, offset: usize, blocks: &mut [u8]) -> Result<(), BlockDeviceError> {
+        let f = &self.fat_files[index];
+        let n = blocks.len() / BLOCK_SIZE;
+
+        debug!("Read file: {} chunks: 0x{:02x} (count: {})", f.name(), offset, n);
+
+        self.pacer.consume(blocks.len());
+        self.watchdog.access();
+
+        if let Some(h) = f.hooks {
+            for o in offset..offset + n {
+                h.on_read(o);
+            }
+        }
+
+        let d = match &f.data {
+            FileContent::Dynamic(d) => d,
+            _ => return Err(BlockDeviceError::HardwareError),
+        };
+
+        // Only the bytes read from the file are written
+        match d.read_chunks(offset, blocks) {
+            Ok(0) => {
+                warn!("Empty read from file: {} chunks: {}", f.name(), offset);
+                blocks.fill(0);
+            },
+            Ok(n) => if let Some(b) = blocks.get_mut(n..) {
+                b.fill(0);
+            },
+            Err(e) => {
+                error!("Failed to read file: {} chunks: {} (count: {})", f.name(), offset, n);
+                return Err(e.into());
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Write a run of blocks to a single [`DynamicFile`], see [`GhostFat::dynamic_run`]
+    fn write_run(&mut self, lba: Lba, index: usize, offset: usize, data: &[u8]) -> Result<(), BlockDeviceError> {
+        let section_index = lba - self.config.start_clusters();
+
+        self.pacer.consume(data.len());
+        self.watchdog.access();
+
+        if let Some(o) = self.observer.as_mut() {
+            for (i, b) in data.chunks(BLOCK_SIZE).enumerate() {
+                o.on_write(section_index.as_usize() + i, b);
+            }
+        }
+
+        let f = &mut self.fat_files[index];
+
+        debug!("Write file: {} blocks: {} (count: {}), {} bytes", f.name(), offset, data.len() / BLOCK_SIZE, data.len());
+
+        // Validate the whole run prior to applying any of it
+        if let Some(h) = f.hooks {
+            for (i, b) in data.chunks(BLOCK_SIZE).enumerate() {
+                if let Err(e) = h.validate(offset + i, b) {
+                    warn!("Rejected write to file: {} chunk: {}", f.name(), offset + i);
+                    if let Some(s) = self.status {
+                        s.report(e as u32, format_args!("{} rejected: {:?}", f.name(), e));
+                    }
+                    return Err(e.into());
+                }
+            }
+        }
+
+        let r = match &mut f.data {
+            FileContent::Dynamic(d) => d.write_chunks(offset, data),
+            _ => return Err(BlockDeviceError::HardwareError),
+        };
+
+        let n = match r {
+            Ok(0) => {
+                error!("Attempted to write to read-only file");
+                return Err(BlockDeviceError::WriteError);
+            },
+            Ok(n) => n,
+            Err(e) => {
+                error!("Failed to write file: {} chunks: {}", f.name(), offset);
+                if let Some(s) = self.status {
+                    s.report(e as u32, format_args!("{} write failed: {:?}", f.name(), e));
+                }
+                return Err(e.into());
+            },
+        };
+
+        for (i, b) in data[..n].chunks(BLOCK_SIZE).enumerate() {
+            if let Some(v) = &f.virgin {
+                v.mark(offset + i);
+            }
+            if let Some(h) = f.hooks {
+                h.on_write(offset + i, b);
+            }
+        }
+
+        for i in 0..n.div_ceil(BLOCK_SIZE) {
+            self.track_change(index, offset + i);
+            self.complete_write(index, offset + i);
+        }
+
+        Ok(())
+    }
+
+    /// Write a file system block
+    fn write_lba(&mut self, lba: Lba, block: &[u8]) -> Result<(), BlockDeviceError> {
+        debug!("GhostFAT writing lba: {} ({} bytes)", lba, block.len());
+
+        if block.is_empty() {
+            warn!("Ignoring zero-length write to lba: {}", lba);
+            return Ok(());
+        }
+
+        if block.len() != Self::BLOCK_BYTES {
+            error!("Invalid write length {} to lba: {} (expected {})", block.len(), lba, Self::BLOCK_BYTES);
+            return Err(Error::InvalidLength(block.len()).into());
+        }
+
+        self.pacer.consume(block.len());
+        self.watchdog.access();
+
+        if self.config.write_protected {
+            warn!("Attempted write to write-protected volume, lba: {}", lba);
+            return Err(BlockDeviceError::WriteError);
+        }
+
+        if perms::access(self.config.access_map, lba) != Access::ReadWrite {
+            warn!("Attempted write to protected lba: {}", lba);
+            return Err(BlockDeviceError::WriteError);
+        }
+
+        if lba == Lba(0) {
+            // Host relabels update the boot sector label
+            let relabelled = self.label_boot(block);
+
+            // Host disk checks update the volume dirty flags
+            if self.config.disk_check {
+                self.check_boot(block);
+            } else if !relabelled {
+                warn!("Attempted write to boot sector");
+            }
+
+            self.format_boot(block);
+            return Ok(());
+
+        // Write to FAT
+        } else if lba < self.config.start_rootdir() {
+            debug!("Write FAT");
+
+            // Both FAT copies map to the same sectors
+            let mut section_index = lba - self.config.start_fat0();
+            if section_index.0 >= self.config.sectors_per_fat() {
+                section_index.0 -= self.config.sectors_per_fat();
+            }
+
+            // Host chains are tracked to reassemble uploads
+            self.capture_fat(section_index, block);
+            self.format_fat(section_index, block);
+
+            // Host disk checks rewrite unmodified sectors, which need not
+            // be shadowed
+            let unchanged = self.config.disk_check && self.check_fat(section_index, block);
+
+            // Host allocations are served back from the shadow
+            if let Some(s) = self.fat_shadow.as_mut() {
+                if unchanged {
+                    s.remove(section_index);
+                } else if !s.write(section_index, block) {
+                    warn!("FAT shadow full, discarding write to sector {}", section_index);
+                }
+            }
+
+        // Write directory entry
+        } else if lba < self.config.start_clusters() {
+            // TODO: do we need to wrap this somehow to remap writes?
+            // it _appears_ it's okay to assume the FAT driver will use existing
+            // allocated blocks so this is not required provided files do not exceed
+            // configured sizes
+            debug!("Write directory entries");
+
+            // Host file size updates are applied to vector backed files
+            let section_index = lba - self.config.start_rootdir();
+            if section_index == SectorIndex(0) {
+                #[cfg(feature = "heapless")]
+                self.update_lengths(block);
+
+                self.detect_deletions(block);
+                self.label_dir(block);
+            }
+
+            self.detect_renames(block);
+            self.complete_dir(block);
+
+            let unchanged = self.config.disk_check && self.check_dir(section_index, block);
+
+            // Host metadata updates are served back from the shadow
+            if let Some(s) = self.dir_shadow.as_mut() {
+                if unchanged {
+                    s.remove(section_index);
+                } else if !s.write(section_index, block) {
+                    warn!("Directory shadow full, discarding write to sector {}", section_index);
+                }
+            }
+
+            // Detect files created by the host
+            self.capture_dir(block);
+
+            // Detect host formats, which may remount the volume
+            self.format_dir(section_index, block);
+
+        // Write cluster data
+        } else {
+            let section_index = lba - self.config.start_clusters();
+
+            if let Some(o) = self.observer.as_mut() {
+                o.on_write(section_index.as_usize(), block);
+            }
+
+            // Locate the file for the LBA, ignoring deleted files as the
+            // host may re-allocate their clusters
+            let mapped = self.locate(section_index).filter(|(i, _)| !self.fat_files[*i].deleted);
+
+            // Keep writes outside device files (ie. host metadata such as
+            // `.fseventsd` or `System Volume Information`) readable
+            if let (None, UnmappedWrites::Scratch, Some(s)) = (mapped, self.config.unmapped_writes, self.scratch.as_mut()) {
+                if !s.write(section_index, block) {
+                    debug!("Scratch region full, discarding write section: {}", section_index);
+                }
+            }
+
+            // Deliver writes to host-created files
+            if self.capture_write(section_index, block) {
+                return Ok(())
+            }
+
+            // If the LBA is within a file, write data
+            if let Some((index, offset)) = mapped {
+                let f = &mut self.fat_files[index];
+
+                debug!("Write file: {} block: {}, {} bytes", f.name(), offset, block.len());
+
+                if let Some(Err(e)) = f.hooks.map(|h| h.validate(offset, block)) {
+                    warn!("Rejected write to file: {} chunk: {}", f.name(), offset);
+                    if let Some(s) = self.status {
+                        s.report(e as u32, format_args!("{} rejected: {:?}", f.name(), e));
+                    }
+                    return Err(e.into());
+                }
+
+                match f.chunk_mut(offset, block) {
+                    Ok(0) => {
+                        error!("Attempted to write to read-only file");
+                        return Err(BlockDeviceError::WriteError);
+                    },
+                    Ok(n) => if let Some(h) = f.hooks {
+                        h.on_write(offset, &block[..n]);
+                    },
+                    Err(e) => {
+                        error!("Failed to write file: {} chunk: {}", f.name(), offset);
+                        if let Some(s) = self.status {
+                            s.report(e as u32, format_args!("{} write failed: {:?}", f.name(), e));
+                        }
+                        return Err(e.into());
+                    },
+                }
+
+                self.track_change(index, offset);
+                self.complete_write(index, offset);
+
+                return Ok(())
+            }
+
+            match self.config.unmapped_writes {
+                UnmappedWrites::Error => {
+                    warn!("Rejected write to unmapped section: {}", section_index);
+                    return Err(BlockDeviceError::WriteError);
+                },
+                UnmappedWrites::Callback(f) => return f(section_index, block).map_err(Into::into),
+                _ => debug!("Unhandled write section: {}", section_index),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// [`BlockDevice`] implementation, see also the `usbd_scsi` adapter
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> BlockDevice for GhostFat<'a, BLOCK_SIZE, FILES> {
+    const BLOCK_BYTES: usize = BLOCK_SIZE;
+
+    /// Read a file system block
+    /// 
+    /// Reads that are not exactly one block are rejected with [`BlockDeviceError::InvalidAddress`]
+    fn read_block(&self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError> {
+        let lba = Lba(lba);
+        let r = self.read_lba(lba, block);
+
+        if let Some(m) = self.metrics {
+            m.read(self.area(lba), r.is_err());
+        }
+
+        r
+    }
+
+    /// Read consecutive file system blocks
+    ///
+    /// Runs of blocks within a [`DynamicFile`] are passed to
+    /// [`DynamicFile::read_chunks`] in a single call, all other blocks are
+    /// read individually. Reads that are not a multiple of the block size are
+    /// rejected with [`BlockDeviceError::InvalidAddress`]
+    fn read_blocks(&self, lba: u32, blocks: &mut [u8]) -> Result<(), BlockDeviceError> {
+        if !blocks.len().is_multiple_of(BLOCK_SIZE) {
+            error!("Invalid read length {} from lba: {} (expected multiple of {})", blocks.len(), lba, BLOCK_SIZE);
+            return Err(Error::InvalidLength(blocks.len()).into());
+        }
+
+        if !device::in_range(lba, blocks.len() / BLOCK_SIZE, self.max_lba()) {
+            error!("Invalid read of {} blocks from lba: {}", blocks.len() / BLOCK_SIZE, lba);
+            return Err(BlockDeviceError::InvalidAddress);
+        }
+
+        let mut lba = Lba(lba);
+        let mut blocks = blocks;
+
+        while !blocks.is_empty() {
+            let run = self.dynamic_run(lba, blocks.len() / BLOCK_SIZE, false).filter(|(_, _, n)| *n > 1);
+            let n = run.map(|(_, _, n)| n).unwrap_or(1);
+            let (head, tail) = blocks.split_at_mut(n * BLOCK_SIZE);
+
+            let r = match run {
+                Some((index, offset, _)) => self.read_run(index, offset, head),
+                None => self.read_lba(lba, head),
+            };
+
+            if let Some(m) = self.metrics {
+                for _ in 0..n {
+                    m.read(self.area(lba), r.is_err());
+                }
+            }
+
+            r?;
+
+            lba = Lba(lba.0 + n as u32);
+            blocks = tail;
+        }
+
+        Ok(())
+    }
+
+    /// Write a file system block
+    /// 
+    /// Zero-length writes are accepted as no-ops, any other write that is not
+    /// exactly one block is rejected with [`BlockDeviceError::InvalidAddress`]
+    fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError> {
+        let lba = Lba(lba);
+        let r = self.write_lba(lba, block);
+
+        if let Some(m) = self.metrics {
+            m.write(self.area(lba), r.is_err());
+        }
+
+        r
+    }
+
+    /// Write consecutive file system blocks
+    ///
+    /// Runs of blocks within a [`DynamicFile`] are passed to
+    /// [`DynamicFile::write_chunks`] in a single call, with file hooks
+    /// validating each block of the run before any are written, all other
+    /// blocks are written individually. Writes that are not a multiple of the
+    /// block size are rejected with [`BlockDeviceError::InvalidAddress`]
+    fn write_blocks(&mut self, lba: u32, blocks: &[u8]) -> Result<(), BlockDeviceError> {
+        if !blocks.len().is_multiple_of(BLOCK_SIZE) {
+            error!("Invalid write length {} to lba: {} (expected multiple of {})", blocks.len(), lba, BLOCK_SIZE);
+            return Err(Error::InvalidLength(blocks.len()).into());
+        }
+
+        if !device::in_range(lba, blocks.len() / BLOCK_SIZE, self.max_lba()) {
+            error!("Invalid write of {} blocks to lba: {}", blocks.len() / BLOCK_SIZE, lba);
+            return Err(BlockDeviceError::InvalidAddress);
+        }
+
+        let mut lba = Lba(lba);
+        let mut blocks = blocks;
+
+        while !blocks.is_empty() {
+            let run = self.dynamic_run(lba, blocks.len() / BLOCK_SIZE, true).filter(|(_, _, n)| *n > 1);
+            let n = run.map(|(_, _, n)| n).unwrap_or(1);
+            let (head, tail) = blocks.split_at(n * BLOCK_SIZE);
+
+            let r = match run {
+                Some((index, offset, _)) => self.write_run(lba, index, offset, head),
+                None => self.write_lba(lba, head),
+            };
+
+            if let Some(m) = self.metrics {
+                for _ in 0..n {
+                    m.write(self.area(lba), r.is_err());
+                }
+            }
+
+            r?;
+
+            lba = Lba(lba.0 + n as u32);
+            blocks = tail;
+        }
+
+        Ok(())
+    }
+
+    /// Report the maximum block index for the file system
+    fn max_lba(&self) -> u32 {
+        self.config.num_blocks - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{BlockDevice, BlockDeviceError};
+
+    use crate::{ClusterTable, GhostFat, GhostFatArray, File, FileContent, FileError, FileHooks, DynamicFile, GeneratedFile, GeneratorFn, Config, SectorIndex, UnmappedWrites};
+
+    #[test]
+    fn odd_write_sizes() {
+        let mut data = [0u8; 16];
+        let mut f = [File::<8>::new("test.bin", &mut data).unwrap()];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        // Zero-length writes are ignored
+        assert_eq!(fs.write_block(lba, &[]), Ok(()));
+
+        // Short and long writes are rejected
+        assert_eq!(fs.write_block(lba, &[0xAA; 3]), Err(BlockDeviceError::InvalidAddress));
+        assert_eq!(fs.write_block(lba, &[0xAA; 13]), Err(BlockDeviceError::InvalidAddress));
+
+        // Full block writes succeed
+        assert_eq!(fs.write_block(lba + 1, &[0xAA; 8]), Ok(()));
+
+        assert_eq!(data, [0, 0, 0, 0, 0, 0, 0, 0, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA]);
+    }
+
+    #[test]
+    fn file_accessors() {
+        let info = [0u8; 8];
+        let mut data = [0u8; 16];
+        let mut f = [
+            File::<8>::new_ro("INFO.TXT", &info),
+            File::new("data.bin", &mut data).unwrap(),
+        ];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        assert_eq!(fs.files().len(), 2);
+        assert_eq!(fs.file("info.txt").map(|f| f.len()), Some(8));
+        assert!(fs.file("MISSING.TXT").is_none());
+
+        // Firmware updates are read back by the host
+        fs.file_mut("DATA.BIN").unwrap().write_at(8, &[0xAA; 8]).unwrap();
+        let mut block = [0u8; 8];
+        fs.read_block(lba + 2, &mut block).unwrap();
+        assert_eq!(block, [0xAA; 8]);
+
+        assert!(fs.files_mut().iter().all(|f| !f.is_deleted()));
+    }
+
+    #[test]
+    fn array_files() {
+        static DATA: [u8; 16] = [0xAA; 16];
+
+        // File systems owning their files may be returned (or stored) by value
+        fn build() -> GhostFatArray<'static, 2> {
+            GhostFat::new_array([
+                File::new_ro("README.TXT", b"Hello World!"),
+                File::new_ro("DATA.BIN", &DATA),
+            ], Config::default())
+        }
+
+        let fs = build();
+        assert_eq!(fs.files().len(), 2);
+
+        let mut block = [0u8; 512];
+        fs.read_block(fs.config.start_clusters().0 + 1, &mut block).unwrap();
+        assert_eq!(&block[..16], &DATA);
+    }
+
+    #[test]
+    fn malformed_reads() {
+        let data = [0u8; 16];
+        let mut f = [File::<512>::new_ro("test.bin", &data), File::new_ro("invalid.name", &data)];
+        let fs = GhostFat::new(&mut f, Config::default());
+        let (rootdir, lba) = (fs.config.start_rootdir().0, fs.config.start_clusters().0);
+
+        // Odd read sizes are rejected
+        assert_eq!(fs.read_block(lba, &mut [0u8; 13]), Err(BlockDeviceError::InvalidAddress));
+
+        // Invalid files fail directory reads without affecting other reads
+        let mut block = [0u8; 512];
+        assert_eq!(fs.read_block(rootdir, &mut block), Err(BlockDeviceError::HardwareError));
+        assert_eq!(fs.read_block(lba, &mut block), Ok(()));
+
+        // As do small blocks without space for a boot sector
+        let mut data = [0u8; 16];
+        let mut f = [File::<8>::new("test.bin", &mut data).unwrap()];
+        let fs = GhostFat::new(&mut f, Config::default());
+        assert_eq!(fs.read_block(0, &mut [0u8; 8]), Err(BlockDeviceError::InvalidAddress));
+    }
+
+    struct FailingFile;
+
+    impl DynamicFile<8> for FailingFile {
+        fn len(&self) -> usize {
+            16
+        }
+
+        fn read_chunk(&self, _chunk_index: usize, _buff: &mut [u8]) -> Result<usize, FileError> {
+            Err(FileError::ReadError)
+        }
+
+        fn write_chunk(&mut self, _chunk_index: usize, _data: &[u8]) -> Result<usize, FileError> {
+            Err(FileError::EraseError)
+        }
+    }
+
+    #[test]
+    fn file_errors() {
+        let mut d = FailingFile;
+        let mut f = [File::<8>::new("test.bin", FileContent::Dynamic(&mut d)).unwrap()];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        let mut block = [0u8; 8];
+        assert_eq!(fs.read_block(lba, &mut block), Err(BlockDeviceError::HardwareError));
+        assert_eq!(fs.write_block(lba, &block), Err(BlockDeviceError::EraseError));
+    }
+
+    /// Dynamic file of `len` bytes counting range reads
+    struct RangeFile<'c>(&'c AtomicUsize, usize);
+
+    impl <const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for RangeFile<'_> {
+        fn len(&self) -> usize {
+            self.1
+        }
+
+        fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+            let n = usize::min(BLOCK_SIZE, self.1.saturating_sub(chunk_index * BLOCK_SIZE));
+            buff[..n].fill(chunk_index as u8 + 1);
+            Ok(n)
+        }
+
+        fn read_chunks(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            let n = usize::min(buff.len(), self.1.saturating_sub(chunk_index * BLOCK_SIZE));
+            for (i, b) in buff[..n].chunks_mut(BLOCK_SIZE).enumerate() {
+                b.fill((chunk_index + i) as u8 + 1);
+            }
+            Ok(n)
+        }
+
+        fn write_chunk(&mut self, _chunk_index: usize, _data: &[u8]) -> Result<usize, FileError> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn read_blocks() {
+        let calls = AtomicUsize::new(0);
+        let mut d = RangeFile(&calls, 32);
+        let data = [0xAAu8; 8];
+        let mut f = [
+            File::<8>::new("A.BIN", FileContent::Dynamic(&mut d)).unwrap(),
+            File::new_ro("B.BIN", &data),
+        ];
+        let fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        // Ranges match individual block reads
+        let mut expected = [0u8; 8 * 8];
+        for (i, b) in expected.chunks_mut(8).enumerate() {
+            fs.read_block(lba - 1 + i as u32, b).unwrap();
+        }
+
+        let mut blocks = [0u8; 8 * 8];
+        fs.read_blocks(lba - 1, &mut blocks).unwrap();
+        assert_eq!(blocks, expected);
+
+        // With the file range passed through in a single call
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(&blocks[8..40], &[[1u8; 8], [2; 8], [3; 8], [4; 8]].concat()[..]);
+        assert_eq!(&blocks[40..48], &data);
+
+        assert_eq!(fs.read_blocks(lba, &mut [0u8; 12]), Err(BlockDeviceError::InvalidAddress));
+
+        // Runs beyond the volume are rejected without overflowing
+        let max = fs.max_lba();
+        assert_eq!(fs.read_blocks(max, &mut [0u8; 8]), Ok(()));
+        assert_eq!(fs.read_blocks(max, &mut [0u8; 16]), Err(BlockDeviceError::InvalidAddress));
+        assert_eq!(fs.read_blocks(u32::MAX, &mut [0u8; 16]), Err(BlockDeviceError::InvalidAddress));
+    }
+
+    #[test]
+    fn read_blocks_scratch() {
+        let calls = AtomicUsize::new(0);
+        let mut d = RangeFile(&calls, 4 * 512);
+        let mut f = [File::<512>::new("A.BIN", FileContent::Dynamic(&mut d)).unwrap()];
+        let mut scratch = [0u8; 516 * 2];
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_scratch(&mut scratch);
+        let (rootdir, lba) = (fs.config.start_rootdir().0, fs.config.start_clusters().0);
+
+        // Host deletes the file and reuses one of its clusters
+        let mut dir = [0u8; 512];
+        fs.read_block(rootdir, &mut dir).unwrap();
+        dir[32] = 0xE5;
+        fs.write_block(rootdir, &dir).unwrap();
+        fs.write_block(lba + 2, &[0x55; 512]).unwrap();
+
+        // Ranges match individual block reads
+        let mut expected = [0u8; 512 * 4];
+        for (i, b) in expected.chunks_mut(512).enumerate() {
+            fs.read_block(lba + i as u32, b).unwrap();
+        }
+        assert_eq!(&expected[1024..1536], &[0x55; 512]);
+
+        let mut blocks = [0u8; 512 * 4];
+        fs.read_blocks(lba, &mut blocks).unwrap();
+        assert_eq!(blocks, expected);
+    }
+
+    #[test]
+    fn read_refs() {
+        let data: [u8; 20] = core::array::from_fn(|i| i as u8);
+        let generator = GeneratorFn::new(8, |_o, b: &mut [u8]| { b.fill(0xAA); b.len() });
+        let mut f = [
+            File::<8>::new_ro("A.BIN", &data),
+            File::new_gen("B.TXT", &generator),
+        ];
+        let fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        // Complete chunks are referenced in place
+        let r = fs.read_ref(lba + 1).unwrap();
+        assert_eq!(r.as_ptr(), data[8..].as_ptr());
+        assert_eq!(fs.files()[0].chunk_ref(1), Some(&data[8..16]));
+
+        let mut block = [0u8; 8];
+        fs.read_block(lba + 1, &mut block).unwrap();
+        assert_eq!(r, &block);
+
+        // Partial chunks, generated content and metadata are not
+        assert_eq!(fs.read_ref(lba + 2), None);
+        assert_eq!(fs.read_ref(lba + 3), None);
+        assert_eq!(fs.read_ref(0), None);
+    }
+
+    #[test]
+    fn dirty_buffers() {
+        let data = [0xAAu8; 12];
+        let generator = GeneratorFn::new(4, |_o, b: &mut [u8]| { b.fill(0x55); b.len() });
+        let calls = AtomicUsize::new(0);
+        let mut d = RangeFile(&calls, 1100);
+        let mut f = [
+            File::<512>::new_ro("A.BIN", &data),
+            File::new_gen("B.TXT", &generator),
+            File::new("C.BIN", FileContent::Dynamic(&mut d)).unwrap(),
+        ];
+        let (mut cache, mut dir, mut fat) = ([0u8; 512 * 8], [0u8; 516], [0u8; 516]);
+        let mut fs = GhostFat::new(&mut f, Config::default())
+            .with_warm_cache(&mut cache)
+            .with_dir_shadow(&mut dir)
+            .with_fat_shadow(&mut fat);
+        let (fat0, dir0, start) = (fs.config.start_fat0().0, fs.config.start_rootdir().0, fs.config.start_clusters().0);
+        let blocks = start + 6;
+
+        // Shadowed sectors are served from RAM
+        fs.write_block(fat0 + 1, &[0x11; 512]).unwrap();
+        fs.write_block(dir0 + 1, &[0x22; 512]).unwrap();
+
+        // Boot sector read warms the cache
+        let mut clean = vec![0u8; blocks as usize * 512];
+        for (lba, b) in clean.chunks_mut(512).enumerate() {
+            fs.read_block(lba as u32, b).unwrap();
+        }
+        assert_eq!(&clean[(fat0 as usize + 1) * 512..][..512], &[0x11; 512]);
+        assert_eq!(&clean[(dir0 as usize + 1) * 512..][..512], &[0x22; 512]);
+
+        // Reads into dirty buffers match reads into clean buffers
+        let mut dirty = [0xFFu8; 512];
+        for (lba, b) in clean.chunks(512).enumerate() {
+            dirty.fill(0xFF);
+            fs.read_block(lba as u32, &mut dirty).unwrap();
+            assert_eq!(b, &dirty, "lba {}", lba);
+        }
+
+        // Including multi-block reads, with the dynamic file read as a run
+        let mut dirty = vec![0xFFu8; clean.len()];
+        fs.read_blocks(0, &mut dirty).unwrap();
+        assert_eq!(clean, dirty);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn warm_cache_sectors() {
+        let d1 = vec![0u8; 200_000];
+        let d2 = [0u8; 1000];
+        let mut f = [
+            File::<512>::new("A.BIN", d1.as_slice()).unwrap(),
+            File::new("B.BIN", &d2).unwrap(),
+        ];
+        let mut cache = [0u8; 512 * 8];
+        let fs = GhostFat::new(&mut f, Config::default()).with_warm_cache(&mut cache);
+
+        // Generate root directory sectors without the cache
+        let (fat0, dir0) = (fs.config.start_fat0().0, fs.config.start_rootdir().0);
+        let mut expected = vec![[0u8; 512]; fs.config.start_clusters().0 as usize];
+        for lba in dir0..fs.config.start_clusters().0 {
+            fs.read_block(lba, &mut expected[lba as usize]).unwrap();
+        }
+
+        // And FAT sectors individually
+        for lba in fat0..dir0 {
+            let section = (lba - fat0) % fs.config.sectors_per_fat();
+            GhostFat::<512, 0>::fat_range(section as usize, &fs.fat_files, &fs.clusters, &mut expected[lba as usize]);
+        }
+
+        // Boot sector read warms the cache
+        let mut block = [0u8; 512];
+        fs.read_block(0, &mut block).unwrap();
+
+        for lba in fat0..fs.config.start_clusters().0 {
+            fs.read_block(lba, &mut block).unwrap();
+            assert_eq!(block, expected[lba as usize], "lba {}", lba);
+        }
+
+        // Second file starts in the second FAT sector (clusters 393 and 394)
+        fs.read_block(fat0 + 1, &mut block).unwrap();
+        assert_eq!(&block[272..278], &[0xff, 0xff, 0x8a, 0x01, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn dir_cache() {
+        let status = crate::StatusFile::<64>::new();
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("INFO.TXT", &data), status.file()];
+        let mut cache = [0u8; 512];
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_dir_cache(&mut cache);
+        let rootdir = fs.config.start_rootdir().0;
+
+        // Entries are generated when the cache is attached
+        let mut block = [0u8; 512];
+        assert!(fs.dir_cache.as_ref().unwrap().fill(GhostFat::<512>::layout(&fs.fat_files), |_| Err(())).is_ok());
+        fs.read_block(rootdir, &mut block).unwrap();
+        assert_eq!(&block[64..75], b"FAIL    TXT");
+        assert_eq!(&block[92..96], &0u32.to_le_bytes());
+
+        // Length changes are reflected on the next read
+        status.fail(1, "Bad image");
+        fs.read_block(rootdir, &mut block).unwrap();
+        assert_eq!(&block[92..96], &(status.len() as u32).to_le_bytes());
+
+        // As are label changes
+        fs.set_label("UPDATE").unwrap();
+        fs.read_block(rootdir, &mut block).unwrap();
+        assert_eq!(&block[..11], b"UPDATE     ");
+    }
+
+    #[test]
+    fn bounded_warm_cache() {
+        let d1 = [0u8; 20_000];
+        let mut f = [File::<512>::new("A.BIN", &d1).unwrap()];
+        let mut cache = [0u8; 512 * 4];
+        let mut config = Config::default();
+        config.bounded_time = true;
+        let mut fs = GhostFat::new(&mut f, config).with_warm_cache(&mut cache);
+
+        let fat0 = fs.config.start_fat0().0;
+        let mut expected = [0u8; 512];
+        fs.read_block(fat0, &mut expected).unwrap();
+
+        // Boot sector reads defer warming to poll, one sector per call
+        let mut block = [0u8; 512];
+        fs.read_block(0, &mut block).unwrap();
+        assert_eq!(fs.warm_cache.as_ref().map(|c| c.filled()), Some(0));
+
+        let mut polls = 1;
+        while fs.poll() {
+            polls += 1;
+        }
+        assert_eq!(polls, 4);
+        assert!(!fs.poll());
+
+        fs.read_block(fat0, &mut block).unwrap();
+        assert_eq!(block, expected);
+    }
+
+    /// Hooks recording the last accessed blocks
+    #[derive(Default)]
+    struct Events {
+        read: AtomicUsize,
+        written: AtomicUsize,
+        deleted: AtomicUsize,
+        renamed: AtomicUsize,
+    }
+
+    impl FileHooks for Events {
+        fn on_read(&self, block_index: usize) {
+            self.read.store(block_index, Ordering::Relaxed);
+        }
+
+        fn on_write(&self, block_index: usize, data: &[u8]) {
+            self.written.store(block_index * 1000 + data.len(), Ordering::Relaxed);
+        }
+
+        fn on_delete(&self) {
+            let n = self.deleted.load(Ordering::Relaxed);
+            self.deleted.store(n + 1, Ordering::Relaxed);
+        }
+
+        fn on_rename(&self, name: &str) {
+            assert_eq!(name, "DATA.BAK");
+            let n = self.renamed.load(Ordering::Relaxed);
+            self.renamed.store(n + 1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn file_hooks() {
+        let events = Events::default();
+        let mut data = [0u8; 2048];
+        let mut f = [
+            File::<512>::new("DATA.BIN", &mut data).unwrap().with_hooks(&events),
+        ];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        let mut block = [0u8; 512];
+        fs.read_block(lba + 2, &mut block).unwrap();
+        assert_eq!(events.read.load(Ordering::Relaxed), 2);
+
+        fs.write_block(lba + 3, &block).unwrap();
+        assert_eq!(events.written.load(Ordering::Relaxed), 3512);
+    }
+
+    /// Hooks accepting firmware images starting with a magic value
+    struct Magic;
+
+    impl FileHooks for Magic {
+        fn validate(&self, block_index: usize, data: &[u8]) -> Result<(), FileError> {
+            match (block_index, data.starts_with(b"FW")) {
+                (0, false) => Err(FileError::InvalidData),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn file_validation() {
+        let mut data = [0u8; 1024];
+        let mut f = [File::<512>::new("FIRMWARE.BIN", &mut data).unwrap().with_hooks(&Magic)];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        assert_eq!(fs.write_block(lba, &[0xAA; 512]), Err(BlockDeviceError::WriteError));
+        assert_eq!(fs.write_block(lba + 1, &[0xBB; 512]), Ok(()));
+
+        let mut block = [0u8; 512];
+        block[..2].copy_from_slice(b"FW");
+        assert_eq!(fs.write_block(lba, &block), Ok(()));
+        drop(fs);
+
+        assert_eq!(&data[..3], b"FW\0");
+        assert_eq!(data[512], 0xBB);
+    }
+
+    #[test]
+    fn file_deletion() {
+        let events = Events::default();
+        let mut data = [0u8; 512];
+        let mut f = [
+            File::<512>::new_ro("INFO.TXT", b"info"),
+            File::<512>::new("DATA.BIN", &mut data).unwrap().with_hooks(&events),
+        ];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let (rootdir, lba) = (fs.config.start_rootdir().0, fs.config.start_clusters().0);
+
+        // Host marks the entry deleted, then rewrites the sector
+        let mut block = [0u8; 512];
+        fs.read_block(rootdir, &mut block).unwrap();
+        block[64] = 0xE5;
+        fs.write_block(rootdir, &block).unwrap();
+        fs.write_block(rootdir, &block).unwrap();
+
+        assert!(fs.fat_files[1].is_deleted());
+        assert!(!fs.fat_files[0].is_deleted());
+        assert_eq!(events.deleted.load(Ordering::Relaxed), 1);
+
+        // Freed clusters are no longer written to the file
+        fs.write_block(lba + 1, &[0xAA; 512]).unwrap();
+
+        // Until listed again
+        fs.read_block(rootdir, &mut block).unwrap();
+        fs.write_block(rootdir, &block).unwrap();
+        assert!(!fs.fat_files[1].is_deleted());
+        drop(fs);
+
+        assert_eq!(data, [0u8; 512]);
+    }
+
+    #[test]
+    fn unmapped_writes() {
+        let mut data = [0u8; 512];
+        let mut f = [File::<512>::new("DATA.BIN", &mut data).unwrap()];
+
+        let mut config = Config::default();
+        config.unmapped_writes = UnmappedWrites::Error;
+        let mut fs = GhostFat::new(&mut f, config);
+        let lba = fs.config.start_clusters().0;
+
+        fs.write_block(lba, &[0xAA; 512]).unwrap();
+        assert_eq!(fs.write_block(lba + 1, &[0xAA; 512]), Err(BlockDeviceError::WriteError));
+
+        fs.config.unmapped_writes = UnmappedWrites::Callback(|index, _data| match index {
+            SectorIndex(1) => Ok(()),
+            _ => Err(FileError::NoSpace),
+        });
+        assert_eq!(fs.write_block(lba + 1, &[0xAA; 512]), Ok(()));
+        assert_eq!(fs.write_block(lba + 2, &[0xAA; 512]), Err(BlockDeviceError::WriteError));
+
+        fs.config.unmapped_writes = UnmappedWrites::Ignore;
+        assert_eq!(fs.write_block(lba + 2, &[0xAA; 512]), Ok(()));
+    }
+
+    #[test]
+    fn file_rename() {
+        let events = Events::default();
+        let mut data = [0u8; 512];
+        let mut f = [
+            File::<512>::new("DATA.BIN", &mut data).unwrap().with_hooks(&events),
+        ];
+
+        let mut config = Config::default();
+        #[cfg(feature = "alloc")]
+        { config.apply_renames = true; }
+
+        let mut fs = GhostFat::new(&mut f, config);
+        let rootdir = fs.config.start_rootdir().0;
+
+        // Host renames the entry in place, then rewrites the sector
+        let mut block = [0u8; 512];
+        fs.read_block(rootdir, &mut block).unwrap();
+        block[40..43].copy_from_slice(b"BAK");
+        fs.write_block(rootdir, &block).unwrap();
+        fs.write_block(rootdir, &block).unwrap();
+
+        assert_eq!(events.renamed.load(Ordering::Relaxed), 1);
+        assert_eq!(events.deleted.load(Ordering::Relaxed), 0);
+
+        #[cfg(feature = "alloc")]
+        assert_eq!(fs.fat_files[0].name(), "DATA.BAK");
+    }
+
+    /// Generated file with a runtime-variable length
+    struct Growing(AtomicUsize);
+
+    impl GeneratedFile for Growing {
+        fn len(&self) -> usize {
+            self.0.load(Ordering::Relaxed)
+        }
+
+        fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+            let n = usize::min(buff.len(), self.len().saturating_sub(offset));
+            buff[..n].fill(0x55);
+            n
+        }
+    }
+
+    #[test]
+    fn growable_files() {
+        let log = Growing(AtomicUsize::new(0));
+        let data = [0xAAu8; 8];
+        let mut f = [
+            File::<8>::new_gen("LOG.TXT", &log).with_reserved(32),
+            File::new_ro("A.BIN", &data),
+        ];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let mut block = [0u8; 8];
+
+        // Empty files have no chain, following files start after the reservation
+        GhostFat::<8, 0>::fat_range(0, &fs.fat_files, &fs.clusters, &mut block);
+        assert_eq!(&block, &[0xf0, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00]);
+        GhostFat::<8, 0>::fat_range(1, &fs.fat_files, &fs.clusters, &mut block);
+        assert_eq!(&block, &[0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00]);
+        assert!(!fs.refresh());
+        assert!(!fs.take_changed());
+
+        // Growing chains follow the live length without moving other files
+        log.0.store(12, Ordering::Relaxed);
+        GhostFat::<8, 0>::fat_range(0, &fs.fat_files, &fs.clusters, &mut block);
+        assert_eq!(&block, &[0xf0, 0xff, 0xff, 0xff, 0x03, 0x00, 0xff, 0xff]);
+        GhostFat::<8, 0>::fat_range(1, &fs.fat_files, &fs.clusters, &mut block);
+        assert_eq!(&block, &[0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00]);
+
+        let lba = fs.config.start_clusters().0;
+        fs.read_block(lba + 4, &mut block).unwrap();
+        assert_eq!(block, data);
+
+        assert!(fs.refresh());
+        assert!(!fs.refresh());
+        assert!(fs.take_changed());
+        assert!(!fs.take_changed());
+    }
+
+    #[test]
+    fn fat_sector_boundaries() {
+        let log = Growing(AtomicUsize::new(0));
+        let data = [0xAAu8; 24];
+        let mut f = [
+            File::<8>::new_ro("A.BIN", &data[..16]),
+            File::new_ro("B.BIN", &data),
+            File::new_gen("LOG.TXT", &log).with_reserved(16),
+            File::new_ro("C.BIN", &data[..8]),
+        ];
+        let mut ends = [0u32; 4];
+        let fs = GhostFat::new(&mut f, Config::default()).with_cluster_table(&mut ends);
+        let fat = |i| {
+            let mut block = [0u8; 8];
+            GhostFat::<8, 0>::fat_range(i, &fs.fat_files, &fs.clusters, &mut block);
+            block
+        };
+
+        // Terminators on the last entry of a sector, and chains spanning sectors
+        assert_eq!(fat(0), [0xf0, 0xff, 0xff, 0xff, 0x03, 0x00, 0xff, 0xff]);
+        assert_eq!(fat(1), [0x05, 0x00, 0x06, 0x00, 0xff, 0xff, 0x00, 0x00]);
+        assert_eq!(fat(2), [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00]);
+
+        // Multi-sector generation matches per-sector generation
+        let mut block = [0u8; 24];
+        GhostFat::<8, 0>::fat_range(0, &fs.fat_files, &fs.clusters, &mut block);
+        assert_eq!(block, [fat(0), fat(1), fat(2)].concat()[..]);
+
+        // Files growing beyond their allocation are clamped rather than
+        // overlapping following files
+        log.0.store(24, Ordering::Relaxed);
+        assert_eq!(fat(1), [0x05, 0x00, 0x06, 0x00, 0xff, 0xff, 0x08, 0x00]);
+        assert_eq!(fat(2), [0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn file_offsets() {
+        let data = [0xAAu8; 64];
+        let f = [File::<8>::new_ro("test.bin", &data)];
+        assert_eq!(f[0].len(), data.len());
+        let clusters = ClusterTable::default();
+
+        let mut block = [0u8; 8];
+        GhostFat::<8, 0>::fat_range(0, &f, &clusters, &mut block);
+        println!("FAT0: {:02x?}", block);
+
+        assert_eq!(&block, &[
+            0xf0, 0xff, 0xff, 0xff, 
+            0x03, 0x00, 0x04, 0x00]);
+
+
+        GhostFat::<8, 0>::fat_range(1, &f, &clusters, &mut block);
+        println!("FAT1: {:02x?}", block);
+        assert_eq!(&block, &[
+            0x05, 0x00, 0x06, 0x00, 
+            0x07, 0x00, 0x08, 0x00]);
+
+        GhostFat::<8, 0>::fat_range(2, &f, &clusters, &mut block);
+        println!("FAT2: {:02x?}", block);
+        assert_eq!(&block, &[
+            0x09, 0x00, 0xff, 0xff, 
+            0x00, 0x00, 0x00, 0x00]);
     }
 
 }
@@ -0,0 +1,112 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::{File, FileError, GeneratedFile};
+
+/// Read access to a littlefs file system, implemented over the driver in
+/// use (ie. `littlefs2::fs::Filesystem`) to export stored files via
+/// [`LittleFsFile`]
+///
+/// littlefs drivers commonly use a `RefCell` internally, so must be wrapped
+/// in a `Sync` type (ie. a critical section mutex) for use with files.
+pub trait LittleFs: Sync + Send {
+    /// Enumerate files in the root directory, calling `f` with the name
+    /// and size of each
+    fn list(&self, f: &mut dyn FnMut(&str, usize)) -> Result<(), FileError>;
+
+    /// Read file content from the provided byte offset, returning the read length
+    fn read(&self, name: &str, offset: usize, buff: &mut [u8]) -> Result<usize, FileError>;
+}
+
+/// Read-only file backed by a file stored in littlefs, with content read
+/// from littlefs on each host read
+pub struct LittleFsFile<'a, L> {
+    fs: &'a L,
+    name: String,
+    len: usize,
+}
+
+impl <'a, L: LittleFs> LittleFsFile<'a, L> {
+    /// Enumerate files stored in littlefs at mount time, skipping files
+    /// without valid 8.3 short names
+    pub fn enumerate(fs: &'a L) -> Result<Vec<Self>, FileError> {
+        let mut files = Vec::new();
+
+        fs.list(&mut |name, len| {
+            if File::<512>::new(name, &[]).is_err() {
+                crate::warn!("Skipping littlefs file with invalid short name: {}", name);
+                return;
+            }
+
+            files.push(Self { fs, name: String::from(name), len });
+        })?;
+
+        Ok(files)
+    }
+
+    /// Fetch the stored file name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Create a read-only file exposing the stored file
+    pub fn file<const BLOCK_SIZE: usize>(&self) -> File<'_, BLOCK_SIZE> {
+        File::new_gen(&self.name, self)
+    }
+}
+
+impl <'a, L: LittleFs> GeneratedFile for LittleFsFile<'a, L> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        match self.fs.read(&self.name, offset, buff) {
+            Ok(n) => n,
+            Err(_) => {
+                crate::error!("Failed to read littlefs file: {} offset: {}", self.name.as_str(), offset);
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, GhostFat};
+    use super::*;
+
+    /// Stored file list
+    struct Stored(&'static [(&'static str, &'static [u8])]);
+
+    impl LittleFs for Stored {
+        fn list(&self, f: &mut dyn FnMut(&str, usize)) -> Result<(), FileError> {
+            self.0.iter().for_each(|(n, d)| f(n, d.len()));
+            Ok(())
+        }
+
+        fn read(&self, name: &str, offset: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+            let (_, d) = self.0.iter().find(|(n, _)| *n == name).ok_or(FileError::ReadError)?;
+            let n = usize::min(buff.len(), d.len().saturating_sub(offset));
+            buff[..n].copy_from_slice(&d[offset..][..n]);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn export_stored() {
+        let lfs = Stored(&[("CONFIG.TXT", b"rate=10\n"), ("not-a-short-name.log", b""), ("LOG.TXT", b"boot\n")]);
+
+        let stored = LittleFsFile::enumerate(&lfs).unwrap();
+        assert_eq!(stored.len(), 2);
+
+        let mut f: Vec<File> = stored.iter().map(|s| s.file()).collect();
+        let fs = GhostFat::new(&mut f, Config::default());
+        let start = fs.config.start_clusters().0;
+
+        let mut block = [0u8; 512];
+        fs.read_block(start + 1, &mut block).unwrap();
+        assert_eq!(&block[..5], b"boot\n");
+    }
+}
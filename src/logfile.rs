@@ -0,0 +1,130 @@
+use core::fmt;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use crate::GeneratedFile;
+
+/// Ring buffer backed log file
+/// 
+/// The device appends to the log via a shared reference (from tasks or ISRs),
+/// and the host reads the most recent `N` bytes as a normal text file, with
+/// the directory entry length tracking the buffered content.
+/// 
+/// Appends must come from a single context at a time, and as the log may
+/// grow or wrap between host reads the window seen by the host may shift
+/// between blocks. As changes in length move the clusters of following files,
+/// log files should be placed at the end of the file table.
+pub struct LogFile<const N: usize> {
+    buff: [AtomicU8; N],
+    written: AtomicUsize,
+}
+
+impl <const N: usize> LogFile<N> {
+    /// Create a new empty log file
+    pub const fn new() -> Self {
+        Self {
+            buff: [const { AtomicU8::new(0) }; N],
+            written: AtomicUsize::new(0),
+        }
+    }
+
+    /// Append data to the log, overwriting the oldest data when full
+    pub fn append(&self, data: &[u8]) {
+        let written = self.written.load(Ordering::Acquire);
+
+        // Only the final N bytes will be retained
+        let skip = data.len().saturating_sub(N);
+        let start = written.wrapping_add(skip);
+
+        for (i, b) in data[skip..].iter().enumerate() {
+            self.buff[start.wrapping_add(i) % N].store(*b, Ordering::Relaxed);
+        }
+
+        self.written.store(written.wrapping_add(data.len()), Ordering::Release);
+    }
+
+    /// Clear the log
+    pub fn clear(&self) {
+        self.written.store(0, Ordering::Release);
+    }
+
+    /// Fetch the total number of bytes appended since creation or clearing
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::Acquire)
+    }
+}
+
+impl <const N: usize> Default for LogFile<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Allow formatted appends via [`core::fmt::Write`] on shared references
+impl <const N: usize> fmt::Write for &LogFile<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.append(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl <const N: usize> GeneratedFile for LogFile<N> {
+    fn len(&self) -> usize {
+        usize::min(self.written(), N)
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        let written = self.written();
+        let len = usize::min(written, N);
+        if offset >= len {
+            return 0;
+        }
+
+        // Window starts at the oldest retained byte
+        let start = written.wrapping_sub(len).wrapping_add(offset);
+        let n = usize::min(buff.len(), len - offset);
+        for (i, b) in buff[..n].iter_mut().enumerate() {
+            *b = self.buff[start.wrapping_add(i) % N].load(Ordering::Relaxed);
+        }
+
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use crate::{File, FileContent};
+    use super::LogFile;
+
+    #[test]
+    fn log_window() {
+        let log = LogFile::<8>::new();
+        let f = File::<4>::new("LOG.TXT", FileContent::Generated(&log)).unwrap();
+        assert_eq!(f.len(), 0);
+
+        log.append(b"abc");
+        assert_eq!(f.len(), 3);
+
+        let mut buff = [0u8; 4];
+        assert_eq!(f.chunk(0, &mut buff), Ok(3));
+        assert_eq!(&buff[..3], b"abc");
+
+        // Wrap the ring buffer, retaining the most recent window
+        write!(&log, "defghij").unwrap();
+        assert_eq!(f.len(), 8);
+
+        assert_eq!(f.chunk(0, &mut buff), Ok(4));
+        assert_eq!(&buff, b"cdef");
+        assert_eq!(f.chunk(1, &mut buff), Ok(4));
+        assert_eq!(&buff, b"ghij");
+
+        // Oversized appends keep the tail
+        log.append(b"0123456789");
+        assert_eq!(f.chunk(0, &mut buff), Ok(4));
+        assert_eq!(&buff, b"2345");
+
+        log.clear();
+        assert_eq!(f.len(), 0);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{BlockDevice, BlockDeviceError};
+
+/// Block device presenting one or more SCSI logical units, implemented
+/// for all [`BlockDevice`]s (as a single unit) and by [`LunMux`]
+pub trait LunDevice {
+    /// Number of bytes per block, shared by all logical units
+    const LUN_BLOCK_BYTES: usize;
+
+    /// Fetch the number of logical units
+    fn luns(&self) -> u8;
+
+    /// Read the block at `lba` of logical unit `lun`
+    fn read_lun(&self, lun: u8, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError>;
+
+    /// Write the block at `lba` of logical unit `lun`
+    fn write_lun(&mut self, lun: u8, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError>;
+
+    /// Fetch the maximum valid LBA of logical unit `lun`
+    fn max_lun_lba(&self, lun: u8) -> u32;
+
+    /// Check whether logical unit `lun` is ready for access, see [`BlockDevice::check_ready`]
+    fn check_lun_ready(&self, _lun: u8) -> Result<(), BlockDeviceError> {
+        Ok(())
+    }
+}
+
+impl <D: BlockDevice> LunDevice for D {
+    const LUN_BLOCK_BYTES: usize = D::BLOCK_BYTES;
+
+    fn luns(&self) -> u8 {
+        1
+    }
+
+    fn read_lun(&self, _lun: u8, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError> {
+        self.read_block(lba, block)
+    }
+
+    fn write_lun(&mut self, _lun: u8, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError> {
+        self.write_block(lba, block)
+    }
+
+    fn max_lun_lba(&self, _lun: u8) -> u32 {
+        self.max_lba()
+    }
+
+    fn check_lun_ready(&self, _lun: u8) -> Result<(), BlockDeviceError> {
+        self.check_ready()
+    }
+}
+
+/// Object-safe [`BlockDevice`] with `BLOCK_SIZE` byte blocks, for use with [`LunMux`]
+pub trait DynBlockDevice<const BLOCK_SIZE: usize> {
+    /// Read the block at `lba`, see [`BlockDevice::read_block`]
+    fn read_dyn(&self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError>;
+
+    /// Write the block at `lba`, see [`BlockDevice::write_block`]
+    fn write_dyn(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError>;
+
+    /// Fetch the maximum valid LBA, see [`BlockDevice::max_lba`]
+    fn max_lba_dyn(&self) -> u32;
+
+    /// Check whether the device is ready for access, see [`BlockDevice::check_ready`]
+    fn check_ready_dyn(&self) -> Result<(), BlockDeviceError>;
+}
+
+impl <D: BlockDevice, const BLOCK_SIZE: usize> DynBlockDevice<BLOCK_SIZE> for D {
+    fn read_dyn(&self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError> {
+        const { assert!(D::BLOCK_BYTES == BLOCK_SIZE, "logical units must share a block size") };
+        self.read_block(lba, block)
+    }
+
+    fn write_dyn(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError> {
+        const { assert!(D::BLOCK_BYTES == BLOCK_SIZE, "logical units must share a block size") };
+        self.write_block(lba, block)
+    }
+
+    fn max_lba_dyn(&self) -> u32 {
+        self.max_lba()
+    }
+
+    fn check_ready_dyn(&self) -> Result<(), BlockDeviceError> {
+        self.check_ready()
+    }
+}
+
+/// Multiplexer presenting several block devices (ie. a [`GhostFat`](crate::GhostFat)
+/// volume and a raw SD card) as separate logical units behind one SCSI
+/// interface (see [`Scsi`](crate::scsi::Scsi)), with reads and writes
+/// routed by LUN.
+/// 
+/// ```
+/// use ghostfat::{File, Config, GhostFat, LunMux, LunDevice};
+/// 
+/// let (a, b) = ([0u8; 8], [0u8; 8]);
+/// let (mut fa, mut fb) = ([File::<512>::new_ro("A.TXT", &a)], [File::<512>::new_ro("B.TXT", &b)]);
+/// let (mut va, mut vb) = (GhostFat::new(&mut fa, Config::default()), GhostFat::new(&mut fb, Config::default()));
+/// 
+/// let mux = LunMux::<2>::new([&mut va, &mut vb]);
+/// assert_eq!(mux.luns(), 2);
+/// ```
+pub struct LunMux<'a, const N: usize, const BLOCK_SIZE: usize = 512> {
+    luns: [&'a mut dyn DynBlockDevice<BLOCK_SIZE>; N],
+}
+
+impl <'a, const N: usize, const BLOCK_SIZE: usize> LunMux<'a, N, BLOCK_SIZE> {
+    /// Create a new multiplexer, with devices presented as LUNs in order
+    pub fn new(luns: [&'a mut dyn DynBlockDevice<BLOCK_SIZE>; N]) -> Self {
+        const { assert!(N > 0 && N <= 16, "SCSI bulk-only transport supports 1 to 16 logical units") };
+        Self { luns }
+    }
+
+    /// Fetch the device for logical unit `lun`
+    pub fn lun(&self, lun: u8) -> Option<&dyn DynBlockDevice<BLOCK_SIZE>> {
+        self.luns.get(lun as usize).map(|d| &**d)
+    }
+
+    /// Fetch the device for logical unit `lun` for modification
+    pub fn lun_mut(&mut self, lun: u8) -> Option<&mut dyn DynBlockDevice<BLOCK_SIZE>> {
+        match self.luns.get_mut(lun as usize) {
+            Some(d) => Some(&mut **d),
+            None => None,
+        }
+    }
+}
+
+impl <'a, const N: usize, const BLOCK_SIZE: usize> LunDevice for LunMux<'a, N, BLOCK_SIZE> {
+    const LUN_BLOCK_BYTES: usize = BLOCK_SIZE;
+
+    fn luns(&self) -> u8 {
+        N as u8
+    }
+
+    fn read_lun(&self, lun: u8, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError> {
+        match self.lun(lun) {
+            Some(d) => d.read_dyn(lba, block),
+            None => Err(BlockDeviceError::InvalidAddress),
+        }
+    }
+
+    fn write_lun(&mut self, lun: u8, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError> {
+        match self.lun_mut(lun) {
+            Some(d) => d.write_dyn(lba, block),
+            None => Err(BlockDeviceError::InvalidAddress),
+        }
+    }
+
+    fn max_lun_lba(&self, lun: u8) -> u32 {
+        self.lun(lun).map(|d| d.max_lba_dyn()).unwrap_or(0)
+    }
+
+    fn check_lun_ready(&self, lun: u8) -> Result<(), BlockDeviceError> {
+        match self.lun(lun) {
+            Some(d) => d.check_ready_dyn(),
+            None => Err(BlockDeviceError::InvalidAddress),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, File, GhostFat};
+    use super::*;
+
+    #[test]
+    fn lun_routing() {
+        let (a, b) = ([0xAAu8; 8], [0x55u8; 8]);
+        let (mut fa, mut fb) = ([File::<512>::new_ro("A.TXT", &a)], [File::<512>::new_ro("B.TXT", &b)]);
+        let mut va = GhostFat::new(&mut fa, Config::default());
+
+        let mut config = Config::default();
+        config.num_blocks = 16000;
+        let mut vb = GhostFat::new(&mut fb, config);
+        let lba = vb.config.start_clusters().0;
+
+        let mut mux = LunMux::<2>::new([&mut va, &mut vb]);
+        assert_eq!((mux.max_lun_lba(0), mux.max_lun_lba(1)), (7999, 15999));
+
+        let mut block = [0u8; 512];
+        mux.read_lun(1, lba, &mut block).unwrap();
+        assert_eq!(&block[..8], &b);
+
+        assert_eq!(mux.read_lun(2, 0, &mut block), Err(BlockDeviceError::InvalidAddress));
+        assert_eq!(mux.write_lun(2, lba, &block), Err(BlockDeviceError::InvalidAddress));
+    }
+}
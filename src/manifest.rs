@@ -0,0 +1,152 @@
+use core::fmt::{self, Write};
+
+use crate::{Crc32, File, FileContent, GhostFat};
+use crate::file::{Files, Name};
+use crate::text::SliceWriter;
+
+/// Manifest file formats, see [`File::new_manifest`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature="defmt", derive(defmt::Format))]
+pub enum ManifestFormat {
+    /// One `NAME SIZE ATTRS CRC32` line per file (ie. `MANIFEST.TXT`)
+    Text,
+    /// JSON array of `{"name", "size", "attrs", "crc32"}` objects (ie. `MANIFEST.JSN`)
+    Json,
+}
+
+impl ManifestFormat {
+    /// Fetch the rendered manifest length for the provided number of entries
+    pub(crate) fn len(&self, entries: usize) -> usize {
+        let (header, footer) = self.wrapper();
+        let mut w = SliceWriter::new(0, &mut []);
+        let _ = self.line(&mut w, "", 0, 0, None, true);
+
+        header.len() + entries * w.len() + footer.len()
+    }
+
+    /// Fetch the manifest header and footer
+    fn wrapper(&self) -> (&'static str, &'static str) {
+        match self {
+            ManifestFormat::Text => ("", ""),
+            ManifestFormat::Json => ("[\n", "]\n"),
+        }
+    }
+
+    /// Render a fixed length manifest line, with names padded to 8.3 length
+    fn line(&self, w: &mut dyn Write, name: &str, size: usize, attrs: u8, crc: Option<u32>, last: bool) -> fmt::Result {
+        let pad = 12usize.saturating_sub(name.len());
+
+        match self {
+            ManifestFormat::Text => write!(w, "{}{:pad$} {:>10} {:02x} ", name, "", size, attrs)?,
+            ManifestFormat::Json => write!(w, "  {{\"name\":\"{}\",{:pad$}\"size\":{:>10},\"attrs\":{:>3},\"crc32\":\"", name, "", size, attrs)?,
+        }
+
+        // Files that cannot be read synchronously have no CRC
+        match crc {
+            Some(c) => write!(w, "{:08x}", c)?,
+            None => w.write_str("--------")?,
+        }
+
+        match self {
+            ManifestFormat::Text => writeln!(w),
+            ManifestFormat::Json => writeln!(w, "\"}}{}", if last { " " } else { "," }),
+        }
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Render a chunk of the manifest from the provided byte offset,
+    /// computing CRCs only for files listed within the chunk
+    pub(crate) fn manifest(&self, format: ManifestFormat, offset: usize, buff: &mut [u8]) -> usize {
+        let (header, footer) = format.wrapper();
+        let line_len = format.len(1) - header.len() - footer.len();
+        let end = offset + buff.len();
+
+        let mut w = SliceWriter::new(offset, buff);
+        let _ = w.write_str(header);
+
+        let files = self.fat_files.iter().filter(|f| !f.is_manifest());
+        let entries = files.clone().count();
+
+        for (i, f) in files.enumerate() {
+            let pos = header.len() + i * line_len;
+            let crc = match pos < end && pos + line_len > offset {
+                true => f.hash(&mut Crc32::new()).ok().map(u32::from_be_bytes),
+                false => Some(0),
+            };
+
+            let _ = format.line(&mut w, f.name(), f.len(), f.attrs().bits(), crc, i + 1 == entries);
+        }
+
+        let _ = w.write_str(footer);
+        w.written()
+    }
+}
+
+/// Update manifest entry counts to match the file table
+pub(crate) fn update_entries<'a, const BLOCK_SIZE: usize, const FILES: usize>(mut files: Files<'a, BLOCK_SIZE, FILES>) -> Files<'a, BLOCK_SIZE, FILES> {
+    update_counts(&mut files);
+    files
+}
+
+/// Update manifest entry counts in place, ie. following runtime changes
+/// to the file table
+pub(crate) fn update_counts<const BLOCK_SIZE: usize>(files: &mut [File<BLOCK_SIZE>]) {
+    let entries = files.iter().filter(|f| !f.is_manifest()).count();
+    for f in files.iter_mut() {
+        if let FileContent::Manifest{ entries: e, .. } = &mut f.data {
+            *e = entries;
+        }
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize> File<'a, BLOCK_SIZE> {
+    /// Constant helper to create manifest files, listing the name, size,
+    /// attributes and CRC32 of each other file in the file system.
+    ///
+    /// CRCs are computed when the manifest is read.
+    /// Beware this function will not check short file name creation
+    pub const fn new_manifest(name: &'a str, format: ManifestFormat) -> Self {
+        Self{ name: Name::Borrowed(name), data: FileContent::Manifest{ format, entries: 0 }, virgin: None, hooks: None, reserved: 0, deleted: false, renamed: None, dirty: None }
+    }
+
+    /// Check whether this is a manifest file
+    pub(crate) fn is_manifest(&self) -> bool {
+        matches!(self.data, FileContent::Manifest{ .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::Config;
+    use super::*;
+
+    #[test]
+    fn render_manifest() {
+        let data = *b"123456789";
+        let mut f = [
+            File::<512>::new_manifest("MANIFEST.TXT", ManifestFormat::Text),
+            File::new_ro("A.BIN", &data),
+            File::new_sparse("SPARSE.BIN", 600, 0),
+            File::new_manifest("MANIFEST.JSN", ManifestFormat::Json),
+        ];
+        let fs = GhostFat::new(&mut f, Config::default());
+        let start = fs.config.start_clusters().0;
+
+        let mut block = [0u8; 512];
+        fs.read_block(start, &mut block).unwrap();
+
+        let text = "A.BIN                 9 01 cbf43926\nSPARSE.BIN          600 01 ";
+        assert_eq!(fs.fat_files[0].len(), 72);
+        assert_eq!(&block[..text.len()], text.as_bytes());
+
+        // JSON manifest follows the sparse file
+        fs.read_block(start + 4, &mut block).unwrap();
+        let len = fs.fat_files[3].len();
+        let json = core::str::from_utf8(&block[..len]).unwrap();
+        assert!(json.starts_with("[\n  {\"name\":\"A.BIN\",       \"size\":         9,\"attrs\":  1,\"crc32\":\"cbf43926\"},\n"));
+        assert!(json.ends_with("\"} \n]\n"));
+    }
+}
@@ -0,0 +1,115 @@
+use packing::{Packed, PackedSize};
+
+use crate::GhostFat;
+use crate::dir::DirectoryEntry;
+use crate::file::Attrs;
+
+/// File metadata written by the host to the root directory,
+/// see [`GhostFat::metadata`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileMetadata {
+    /// Last modification time
+    pub modified: Timestamp,
+    /// Archive flag, set by hosts on modification
+    pub archive: bool,
+}
+
+/// FAT directory entry timestamp, with two second resolution
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Timestamp {
+    /// Decode a timestamp from FAT date and time fields
+    pub const fn from_fat(date: u16, time: u16) -> Self {
+        Self {
+            year: 1980 + (date >> 9),
+            month: ((date >> 5) & 0x0F) as u8,
+            day: (date & 0x1F) as u8,
+            hour: (time >> 11) as u8,
+            minute: ((time >> 5) & 0x3F) as u8,
+            second: ((time & 0x1F) * 2) as u8,
+        }
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Fetch metadata written by the host for the file at the provided
+    /// index, ie. to synchronise only files modified by the host.
+    /// 
+    /// Requires a root directory shadow (see [`GhostFat::with_dir_shadow`]),
+    /// returning `None` where the host has not written the file's directory
+    /// entry since the last [`GhostFat::remount`]
+    pub fn metadata(&self, index: usize) -> Option<FileMetadata> {
+        let s = self.dir_shadow.as_ref()?;
+        let f = self.fat_files.get(index).filter(|f| !f.deleted)?;
+        let name = match f.renamed {
+            Some(n) => n,
+            None => f.short_name().ok()?,
+        };
+
+        let mut block = [0u8; BLOCK_SIZE];
+        for i in 0..self.config.root_dir_sectors {
+            if !s.read(crate::SectorIndex(i), &mut block) {
+                continue;
+            }
+
+            let entry = block.chunks_exact(DirectoryEntry::BYTES)
+                .filter_map(|e| DirectoryEntry::unpack(e).ok())
+                .find(|e| e.name == name && e.attrs & Attrs::VOLUME_LABEL.bits() == 0);
+
+            if let Some(e) = entry {
+                return Some(FileMetadata {
+                    modified: Timestamp::from_fat(e.update_date, e.update_time),
+                    archive: e.attrs & Attrs::ARCHIVE.bits() != 0,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, File};
+    use super::*;
+
+    #[test]
+    fn file_metadata() {
+        let mut buff = [0u8; 516];
+        let (mut a, mut b) = ([0u8; 512], [0u8; 512]);
+        let mut f = [
+            File::<512>::new("A.BIN", &mut a).unwrap(),
+            File::<512>::new("B.BIN", &mut b).unwrap(),
+        ];
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_dir_shadow(&mut buff);
+        let rootdir = fs.config.start_rootdir().0;
+        assert_eq!(fs.metadata(0), None);
+
+        // Host updates the second file, 2024-03-15 13:45:30
+        let mut block = [0u8; 512];
+        fs.read_block(rootdir, &mut block).unwrap();
+        block[64 + 11] |= Attrs::ARCHIVE.bits();
+        block[64 + 22..][..2].copy_from_slice(&(13u16 << 11 | 45 << 5 | 15).to_le_bytes());
+        block[64 + 24..][..2].copy_from_slice(&(44u16 << 9 | 3 << 5 | 15).to_le_bytes());
+        fs.write_block(rootdir, &block).unwrap();
+
+        assert_eq!(fs.metadata(0).map(|m| m.archive), Some(false));
+        assert_eq!(fs.metadata(1), Some(FileMetadata {
+            modified: Timestamp { year: 2024, month: 3, day: 15, hour: 13, minute: 45, second: 30 },
+            archive: true,
+        }));
+        assert_eq!(fs.metadata(2), None);
+    }
+}
@@ -0,0 +1,189 @@
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::GeneratedFile;
+use crate::text::SliceWriter;
+
+/// File system areas for metric collection
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Area {
+    Boot,
+    Fat,
+    Dir,
+    Data,
+}
+
+/// File system access counters, attached via [`GhostFat::with_metrics`](crate::GhostFat::with_metrics)
+///
+/// This implements [`GeneratedFile`] rendering a Prometheus-style text
+/// file with one `name value` pair per line, so fleet tooling can collect
+/// device health by copying a single file. Values are right-aligned so the
+/// file length does not change as counters increase.
+///
+/// Counters are updated from the block device context and wrap on overflow.
+pub struct Metrics {
+    sessions: AtomicU32,
+    timeouts: AtomicU32,
+    reads: [AtomicU32; 3],
+    writes: [AtomicU32; 4],
+    read_errors: AtomicU32,
+    write_errors: AtomicU32,
+}
+
+impl Metrics {
+    /// Create a new set of zeroed metrics
+    pub const fn new() -> Self {
+        Self {
+            sessions: AtomicU32::new(0),
+            timeouts: AtomicU32::new(0),
+            reads: [const { AtomicU32::new(0) }; 3],
+            writes: [const { AtomicU32::new(0) }; 4],
+            read_errors: AtomicU32::new(0),
+            write_errors: AtomicU32::new(0),
+        }
+    }
+
+    /// Fetch the number of host sessions, counted by boot sector reads
+    pub fn sessions(&self) -> u32 {
+        self.sessions.load(Ordering::Relaxed)
+    }
+
+    /// Fetch the number of host session timeouts
+    pub fn timeouts(&self) -> u32 {
+        self.timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Fetch the number of failed block reads and writes
+    pub fn errors(&self) -> (u32, u32) {
+        (self.read_errors.load(Ordering::Relaxed), self.write_errors.load(Ordering::Relaxed))
+    }
+
+    /// Reset all counters
+    pub fn reset(&self) {
+        for c in self.counters() {
+            c.1.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Record a block read
+    pub(crate) fn read(&self, area: Area, err: bool) {
+        match area {
+            Area::Boot => inc(&self.sessions),
+            Area::Fat => inc(&self.reads[0]),
+            Area::Dir => inc(&self.reads[1]),
+            Area::Data => inc(&self.reads[2]),
+        }
+
+        if err {
+            inc(&self.read_errors);
+        }
+    }
+
+    /// Record a block write
+    pub(crate) fn write(&self, area: Area, err: bool) {
+        let i = match area {
+            Area::Boot => 0,
+            Area::Fat => 1,
+            Area::Dir => 2,
+            Area::Data => 3,
+        };
+        inc(&self.writes[i]);
+
+        if err {
+            inc(&self.write_errors);
+        }
+    }
+
+    /// Record a host session timeout
+    pub(crate) fn timeout(&self) {
+        inc(&self.timeouts);
+    }
+
+    /// Fetch named counters in render order
+    fn counters(&self) -> [(&'static str, &AtomicU32); 11] {
+        [
+            ("sessions", &self.sessions),
+            ("timeouts", &self.timeouts),
+            ("reads_fat", &self.reads[0]),
+            ("reads_dir", &self.reads[1]),
+            ("reads_data", &self.reads[2]),
+            ("writes_boot", &self.writes[0]),
+            ("writes_fat", &self.writes[1]),
+            ("writes_dir", &self.writes[2]),
+            ("writes_data", &self.writes[3]),
+            ("read_errors", &self.read_errors),
+            ("write_errors", &self.write_errors),
+        ]
+    }
+
+    /// Render metrics as text
+    fn render(&self, w: &mut dyn Write) -> fmt::Result {
+        for (name, c) in self.counters() {
+            writeln!(w, "ghostfat_{:<12} {:>10}", name, c.load(Ordering::Relaxed))?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeneratedFile for Metrics {
+    fn len(&self) -> usize {
+        let mut w = SliceWriter::new(0, &mut []);
+        let _ = self.render(&mut w);
+        w.len()
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        let mut w = SliceWriter::new(offset, buff);
+        let _ = self.render(&mut w);
+        w.written()
+    }
+}
+
+/// Increment a counter, without requiring atomic read-modify-write support
+fn inc(c: &AtomicU32) {
+    c.store(c.load(Ordering::Relaxed).wrapping_add(1), Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, File, GhostFat};
+    use super::*;
+
+    #[test]
+    fn count_accesses() {
+        let metrics = Metrics::new();
+        let data = [0u8; 16];
+        let mut f = [
+            File::<512>::new_ro("DATA.BIN", &data),
+            File::new_gen("METRICS.TXT", &metrics),
+        ];
+        let len = f[1].len();
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_metrics(&metrics);
+
+        let mut block = [0u8; 512];
+        fs.read_block(0, &mut block).unwrap();
+        fs.read_block(fs.config.start_fat0().0, &mut block).unwrap();
+        fs.read_block(fs.config.start_clusters().0, &mut block).unwrap();
+        assert!(fs.write_block(fs.config.start_clusters().0, &block).is_err());
+
+        assert_eq!(metrics.sessions(), 1);
+        assert_eq!(metrics.errors(), (0, 1));
+
+        // Rendered length is fixed
+        fs.read_block(fs.config.start_clusters().0 + 1, &mut block).unwrap();
+        assert_eq!(metrics.len(), len);
+
+        let text = core::str::from_utf8(&block[..len]).unwrap();
+        assert!(text.contains("ghostfat_sessions              1\n"));
+        assert!(text.contains("ghostfat_reads_data            1\n"));
+        assert!(text.contains("ghostfat_write_errors          1\n"));
+    }
+}
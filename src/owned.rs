@@ -0,0 +1,88 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::{Config, File, FileContent, FileError, GhostFat};
+use crate::file::{Files, Name};
+
+/// Owned read/write file, for use with [`GhostFatOwned`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnedFile {
+    /// File name
+    pub name: String,
+    /// File content
+    pub data: Vec<u8>,
+}
+
+impl OwnedFile {
+    /// Create a new owned file, checking short file name creation
+    pub fn new(name: impl Into<String>, data: impl Into<Vec<u8>>) -> Result<Self, FileError> {
+        let f = Self { name: name.into(), data: data.into() };
+
+        // Check short name generation
+        File::<512>::new(&f.name, &[])?;
+
+        Ok(f)
+    }
+}
+
+impl <const BLOCK_SIZE: usize> From<OwnedFile> for File<'static, BLOCK_SIZE> {
+    fn from(f: OwnedFile) -> Self {
+        Self {
+            name: Name::Owned(f.name),
+            data: FileContent::Owned(f.data),
+            virgin: None,
+            hooks: None,
+            reserved: 0,
+            deleted: false,
+            renamed: None,
+            dirty: None,
+        }
+    }
+}
+
+/// Virtual FAT16 File System owning its file table
+pub type GhostFatOwned<const BLOCK_SIZE: usize = 512> = GhostFat<'static, BLOCK_SIZE>;
+
+impl <const BLOCK_SIZE: usize> GhostFat<'static, BLOCK_SIZE> {
+    /// Create a new file system instance owning the provided files
+    pub fn new_owned(files: Vec<OwnedFile>, config: Config<BLOCK_SIZE>) -> Self {
+        let files = files.into_iter().map(File::from).collect();
+        Self::with_files(Files::Owned(files), config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use crate::BlockDevice;
+
+    use crate::{Config, FileError};
+    use super::*;
+
+    #[test]
+    fn owned_files() {
+        assert_eq!(OwnedFile::new("INVALID.NAME", vec![]), Err(FileError::InvalidName));
+
+        let mut fs: GhostFatOwned = GhostFat::new_owned(vec![
+            OwnedFile::new("README.TXT", "Hello World!").unwrap(),
+            OwnedFile::new("DATA.BIN", vec![0u8; 16]).unwrap(),
+        ], Config::default());
+
+        assert_eq!(fs.fat_files[0].name(), "README.TXT");
+
+        // Write and read back the second file
+        let lba = fs.config.start_clusters().0 + 1;
+        let mut block = [0xAAu8; 512];
+        fs.write_block(lba, &block).unwrap();
+
+        block.fill(0);
+        fs.read_block(lba, &mut block).unwrap();
+        assert_eq!(&block[..16], &[0xAA; 16]);
+        assert_eq!(&block[16..], &[0x00; 512 - 16]);
+
+        // Owned files may be added and removed at runtime
+        assert_eq!(fs.add_file(OwnedFile::new("LOG.TXT", "Started").unwrap().into()), Ok(2));
+        assert!(fs.remove_file("README.TXT").is_some());
+        assert_eq!(fs.files()[1].name(), "LOG.TXT");
+    }
+}
@@ -0,0 +1,55 @@
+use core::cell::Cell;
+
+/// Service rate limiter, tracking bytes serviced in the current poll interval
+pub(crate) struct Pacer {
+    limit: Option<u32>,
+    used: Cell<u32>,
+}
+
+impl Pacer {
+    /// Create a new pacer with an optional bytes-per-interval limit
+    pub fn new(limit: Option<u32>) -> Self {
+        Self { limit, used: Cell::new(0) }
+    }
+
+    /// Record serviced bytes against the current interval
+    pub fn consume(&self, bytes: usize) {
+        self.used.set(self.used.get().saturating_add(bytes as u32));
+    }
+
+    /// Start a new interval
+    pub fn reset(&self) {
+        self.used.set(0);
+    }
+
+    /// Check whether budget remains in the current interval
+    pub fn ready(&self) -> bool {
+        match self.limit {
+            Some(l) => self.used.get() < l,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pacer;
+
+    #[test]
+    fn pacing_budget() {
+        let p = Pacer::new(Some(1024));
+        assert!(p.ready());
+
+        p.consume(512);
+        assert!(p.ready());
+        p.consume(512);
+        assert!(!p.ready());
+
+        p.reset();
+        assert!(p.ready());
+
+        let p = Pacer::new(None);
+        p.consume(usize::MAX);
+        assert!(p.ready());
+    }
+}
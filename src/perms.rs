@@ -0,0 +1,71 @@
+use crate::Lba;
+
+/// Block access permissions, ordered from most to least restrictive
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature="defmt", derive(defmt::Format))]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Access {
+    /// Blocks may not be read or written
+    NoAccess,
+    /// Blocks may be read but not written
+    ReadOnly,
+    /// Blocks may be read and written
+    ReadWrite,
+}
+
+/// Access permissions for a range of LBAs
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature="defmt", derive(defmt::Format))]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccessRange {
+    /// First LBA in the range
+    pub start: Lba,
+    /// LBA following the end of the range (exclusive)
+    pub end: Lba,
+    /// Permissions applied to the range
+    pub access: Access,
+}
+
+impl AccessRange {
+    /// Create a new access range covering `start..end`
+    pub const fn new(start: Lba, end: Lba, access: Access) -> Self {
+        Self { start, end, access }
+    }
+
+    /// Check whether the range contains the provided LBA
+    pub const fn contains(&self, lba: Lba) -> bool {
+        lba.0 >= self.start.0 && lba.0 < self.end.0
+    }
+}
+
+/// Resolve permissions for an LBA, applying the most restrictive matching range.
+/// 
+/// LBAs not covered by any range default to [`Access::ReadWrite`]
+pub(crate) fn access(map: &[AccessRange], lba: Lba) -> Access {
+    map.iter()
+        .filter(|r| r.contains(lba))
+        .map(|r| r.access)
+        .min()
+        .unwrap_or(Access::ReadWrite)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_access() {
+        let map = [
+            AccessRange::new(Lba(0), Lba(10), Access::ReadOnly),
+            AccessRange::new(Lba(5), Lba(6), Access::NoAccess),
+            AccessRange::new(Lba(8), Lba(20), Access::ReadWrite),
+        ];
+
+        assert_eq!(access(&map, Lba(0)), Access::ReadOnly);
+        assert_eq!(access(&map, Lba(5)), Access::NoAccess);
+        assert_eq!(access(&map, Lba(9)), Access::ReadOnly);
+        assert_eq!(access(&map, Lba(10)), Access::ReadWrite);
+        assert_eq!(access(&map, Lba(100)), Access::ReadWrite);
+        assert_eq!(access(&[], Lba(0)), Access::ReadWrite);
+    }
+}
@@ -0,0 +1,21 @@
+use crate::FileError;
+
+/// Flash target for drag-and-drop programming (see [`Uf2Flasher`](crate::Uf2Flasher))
+///
+/// Methods are called from the block device context, so implementations
+/// should defer long-running erase or program operations where required.
+pub trait FlashWriter {
+    /// Write decoded data at the provided target address
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), FileError>;
+
+    /// Called as data is written, with the completed and total amount in
+    /// programmer-specific units (ie. blocks or bytes), where known
+    fn progress(&mut self, done: usize, total: Option<usize>) {
+        let _ = (done, total);
+    }
+
+    /// Called once programming completes, or fails with an error
+    fn complete(&mut self, result: Result<(), FileError>) {
+        let _ = result;
+    }
+}
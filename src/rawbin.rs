@@ -0,0 +1,194 @@
+use crate::{DynamicFile, FileError, FlashWriter, HostFileSink};
+
+/// Raw binary drag-and-drop flasher, streaming host-written data directly
+/// to the [`FlashWriter`] `W` at a configured base address
+///
+/// This may be registered as a designated slot file (ie. `FIRMWARE.BIN`
+/// via [`FileContent::Dynamic`](crate::FileContent::Dynamic)) spanning the
+/// target window, completing on [`DynamicFile::flush`], or routed host files
+/// matching a name pattern (ie. `*.BIN`) as a [`HostFileSink`], completing
+/// once the full file has been written.
+pub struct BinFlasher<W> {
+    writer: W,
+    base: u32,
+    window: usize,
+    size: Option<usize>,
+    written: usize,
+}
+
+impl <W: FlashWriter> BinFlasher<W> {
+    /// Create a new flasher writing up to `window` bytes from `base`
+    pub fn new(writer: W, base: u32, window: usize) -> Self {
+        Self { writer, base, window, size: None, written: 0 }
+    }
+
+    /// Fetch a reference to the flash writer
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    /// Fetch a mutable reference to the flash writer
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Fetch the number of bytes written since the last completion
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Write data at the provided offset within the window
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<usize, FileError> {
+        let limit = usize::min(self.window, self.size.unwrap_or(self.window));
+        if offset >= limit {
+            return Ok(0);
+        }
+
+        let n = usize::min(data.len(), limit - offset);
+        if let Err(e) = self.writer.write(self.base + offset as u32, &data[..n]) {
+            crate::error!("Binary write failed at offset {}", offset);
+            self.finish(Err(e));
+            return Err(e);
+        }
+
+        self.written += n;
+        self.writer.progress(self.written, self.size);
+
+        Ok(n)
+    }
+
+    /// Complete the current transfer
+    fn finish(&mut self, result: Result<(), FileError>) {
+        crate::debug!("Binary transfer complete, {} bytes", self.written);
+
+        self.writer.complete(result);
+        self.size = None;
+        self.written = 0;
+    }
+}
+
+impl <W: FlashWriter + Sync + Send, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for BinFlasher<W> {
+    fn len(&self) -> usize {
+        self.window
+    }
+
+    /// Slots read as erased flash
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        let n = usize::min(usize::min(buff.len(), BLOCK_SIZE), self.window.saturating_sub(chunk_index * BLOCK_SIZE));
+        buff[..n].fill(0xFF);
+        Ok(n)
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        self.program(chunk_index * BLOCK_SIZE, data)
+    }
+
+    /// Multi-block host writes are passed to the writer in a single call
+    fn write_chunks(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        self.program(chunk_index * BLOCK_SIZE, data)
+    }
+
+    fn flush(&mut self) -> Result<(), FileError> {
+        if self.written > 0 {
+            self.finish(Ok(()));
+        }
+        Ok(())
+    }
+}
+
+impl <W: FlashWriter + Send> HostFileSink for BinFlasher<W> {
+    fn create(&mut self, _name: &str, size: usize) -> Result<(), FileError> {
+        if size > self.window {
+            crate::warn!("Binary of {} bytes exceeds target window of {} bytes", size, self.window);
+            return Err(FileError::NoSpace);
+        }
+
+        self.size = Some(size);
+        self.written = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<usize, FileError> {
+        let n = self.program(offset, data)?;
+
+        if self.size.is_some_and(|s| self.written >= s) {
+            self.finish(Ok(()));
+        }
+
+        Ok(n)
+    }
+
+    fn close(&mut self) -> Result<(), FileError> {
+        if self.size.is_some() {
+            crate::warn!("Binary file closed after {} bytes", self.written);
+            self.finish(Err(FileError::WriteError));
+            return Err(FileError::WriteError);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BlockDevice, BlockDeviceError};
+
+    use crate::{Config, File, FileContent, GhostFat};
+    use crate::uf2::tests::MockFlash;
+    use super::*;
+
+    #[test]
+    fn slot_writes() {
+        let mut bin = BinFlasher::new(MockFlash::default(), 0x100, 768);
+        let mut f = [File::<512>::new("FIRMWARE.BIN", FileContent::Dynamic(&mut bin)).unwrap()];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let start = fs.config.start_clusters().0;
+
+        fs.write_block(start, &[0x11; 512]).unwrap();
+        fs.write_block(start + 1, &[0x22; 512]).unwrap();
+        fs.flush().unwrap();
+        drop(fs);
+
+        let flash = bin.writer();
+        assert_eq!(flash.done, Some(Ok(())));
+        assert_eq!(&flash.mem[0x0FF..0x101], &[0x00, 0x11]);
+        assert_eq!(&flash.mem[0x2FF..0x301], &[0x11, 0x22]);
+        assert_eq!(&flash.mem[0x3FF..], &[0x22]);
+    }
+
+    #[test]
+    fn slot_range_writes() {
+        let mut bin = BinFlasher::new(MockFlash::default(), 0x100, 768);
+        let mut f = [File::<512>::new("FIRMWARE.BIN", FileContent::Dynamic(&mut bin)).unwrap()];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let start = fs.config.start_clusters().0;
+
+        // Ranges within the slot are programmed in a single write
+        let data = [[0x11; 512], [0x22; 512]].concat();
+        fs.write_blocks(start, &data).unwrap();
+        assert_eq!(fs.write_blocks(start, &data[..100]), Err(BlockDeviceError::InvalidAddress));
+        assert_eq!(fs.write_blocks(fs.max_lba(), &data), Err(BlockDeviceError::InvalidAddress));
+        assert_eq!(fs.write_blocks(u32::MAX, &data), Err(BlockDeviceError::InvalidAddress));
+        fs.flush().unwrap();
+        drop(fs);
+
+        let flash = bin.writer();
+        assert_eq!(flash.writes, 1);
+        assert_eq!(flash.done, Some(Ok(())));
+        assert_eq!(&flash.mem[0x100..], &data[..768]);
+    }
+
+    #[test]
+    fn routed_writes() {
+        let mut bin = BinFlasher::new(MockFlash::default(), 0, 64);
+        assert_eq!(bin.create("FW.BIN", 128), Err(FileError::NoSpace));
+
+        bin.create("FW.BIN", 40).unwrap();
+        assert_eq!(HostFileSink::write(&mut bin, 0, &[0xAA; 32]), Ok(32));
+        assert_eq!(bin.writer().done, None);
+        assert_eq!(HostFileSink::write(&mut bin, 32, &[0xBB; 32]), Ok(8));
+        assert_eq!(bin.writer().done, Some(Ok(())));
+        assert_eq!(bin.close(), Ok(()));
+        assert_eq!(&bin.writer().mem[39..41], &[0xBB, 0x00]);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::Cluster;
+
+/// Maximum number of tracked non-contiguous chain links
+const MAX_LINKS: usize = 32;
+
+/// Size of a stored chain link (cluster and following cluster)
+const LINK_SIZE: usize = 8;
+
+/// Size of the cluster number stored for each buffered cluster
+const SLOT_INDEX_SIZE: usize = 4;
+
+/// Stored cluster number marking an unused slot or link, or a chain end
+const NONE: u32 = u32::MAX;
+
+/// FAT16 end of chain marker
+const END_OF_CHAIN: u16 = 0xFFF8;
+
+/// Host upload reassembly state, buffering clusters written ahead of
+/// their position in a file and tracking host FAT chains so clusters
+/// may be ordered.
+///
+/// Only chain links that break contiguous allocation (and chain ends)
+/// are stored, with other clusters assumed to be followed by the next
+/// cluster, so a small table suffices for typical host allocations.
+///
+/// The buffer holds `BLOCK_SIZE` cluster slots followed by the cluster
+/// number stored in each slot, with the chain link table in the last
+/// `MAX_LINKS * LINK_SIZE` bytes, so no state is stored outside the buffer.
+pub(crate) struct Reassembly<'a, const BLOCK_SIZE: usize> {
+    buff: &'a mut [u8],
+}
+
+impl <'a, const BLOCK_SIZE: usize> Reassembly<'a, BLOCK_SIZE> {
+    /// Create reassembly state over the provided buffer
+    pub fn new(buff: &'a mut [u8]) -> Self {
+        let mut r = Self { buff };
+        r.reset();
+        r
+    }
+
+    /// Discard buffered clusters and chain links
+    pub fn reset(&mut self) {
+        let (slots, links) = (self.slots(), self.links());
+        self.buff[slots * BLOCK_SIZE..][..slots * SLOT_INDEX_SIZE].fill(0xFF);
+
+        let n = self.buff.len();
+        self.buff[n - links * LINK_SIZE..].fill(0xFF);
+    }
+
+    /// Number of chain links that fit in the buffer
+    fn links(&self) -> usize {
+        match self.buff.len() >= MAX_LINKS * LINK_SIZE {
+            true => MAX_LINKS,
+            false => 0,
+        }
+    }
+
+    /// Number of cluster slots that fit in the buffer alongside the link table
+    fn slots(&self) -> usize {
+        (self.buff.len() - self.links() * LINK_SIZE) / (BLOCK_SIZE + SLOT_INDEX_SIZE)
+    }
+
+    /// Read a stored cluster number
+    fn get(&self, offset: usize) -> Option<Cluster> {
+        match u32::from_le_bytes([self.buff[offset], self.buff[offset + 1], self.buff[offset + 2], self.buff[offset + 3]]) {
+            NONE => None,
+            c => Some(Cluster(c)),
+        }
+    }
+
+    /// Write a stored cluster number
+    fn set(&mut self, offset: usize, cluster: Option<Cluster>) {
+        let c = cluster.map(|c| c.0).unwrap_or(NONE);
+        self.buff[offset..][..4].copy_from_slice(&c.to_le_bytes());
+    }
+
+    /// Fetch the offset of a chain link in the buffer
+    fn link_offset(&self, link: usize) -> usize {
+        self.buff.len() - (self.links() - link) * LINK_SIZE
+    }
+
+    /// Fetch a chain link, as a cluster and the following cluster
+    fn link(&self, link: usize) -> Option<(Cluster, Option<Cluster>)> {
+        let offset = self.link_offset(link);
+        self.get(offset).map(|c| (c, self.get(offset + 4)))
+    }
+
+    /// Store or clear a chain link
+    fn set_link(&mut self, link: usize, value: Option<(Cluster, Option<Cluster>)>) {
+        let offset = self.link_offset(link);
+        self.set(offset, value.map(|(c, _)| c));
+        self.set(offset + 4, value.and_then(|(_, n)| n));
+    }
+
+    /// Fetch the cluster buffered in a slot
+    fn slot_cluster(&self, slot: usize) -> Option<Cluster> {
+        self.get(self.slots() * BLOCK_SIZE + slot * SLOT_INDEX_SIZE)
+    }
+
+    /// Set the cluster buffered in a slot
+    fn set_slot_cluster(&mut self, slot: usize, cluster: Option<Cluster>) {
+        let offset = self.slots() * BLOCK_SIZE + slot * SLOT_INDEX_SIZE;
+        self.set(offset, cluster);
+    }
+
+    /// Update chain links from a host FAT sector, where `first` is the
+    /// cluster of the first entry in the sector and `owned` matches
+    /// clusters allocated to device files (and thus not tracked)
+    pub fn update_links(&mut self, first: u32, block: &[u8], owned: impl Fn(Cluster) -> bool) {
+        for (i, e) in block.chunks_exact(2).enumerate() {
+            let cluster = Cluster(first + i as u32);
+            if cluster < Cluster::FIRST || owned(cluster) {
+                continue;
+            }
+
+            let next = match u16::from_le_bytes([e[0], e[1]]) {
+                0 => {
+                    self.unlink(cluster);
+                    continue;
+                },
+                n if n >= END_OF_CHAIN => None,
+                n => Some(Cluster(n as u32)),
+            };
+
+            if next == Some(Cluster(cluster.0 + 1)) {
+                self.unlink(cluster);
+                continue;
+            }
+
+            let links = 0..self.links();
+            let slot = links.clone().find(|l| matches!(self.link(*l), Some((c, _)) if c == cluster))
+                .or_else(|| links.clone().find(|l| self.link(*l).is_none()));
+
+            match slot {
+                Some(s) => self.set_link(s, Some((cluster, next))),
+                None => crate::warn!("Reassembly link table full, assuming cluster {} is contiguous", cluster),
+            }
+        }
+    }
+
+    /// Remove any link for the provided cluster
+    fn unlink(&mut self, cluster: Cluster) {
+        for l in 0..self.links() {
+            if matches!(self.link(l), Some((c, _)) if c == cluster) {
+                self.set_link(l, None);
+            }
+        }
+    }
+
+    /// Fetch the cluster following the provided cluster in a host chain,
+    /// returning `None` at the end of the chain
+    pub fn next(&self, cluster: Cluster) -> Option<Cluster> {
+        match (0..self.links()).find_map(|l| self.link(l).filter(|(c, _)| *c == cluster)) {
+            Some((_, n)) => n,
+            None => Some(Cluster(cluster.0 + 1)),
+        }
+    }
+
+    /// Fetch the position of `cluster` in the `count` cluster chain
+    /// starting at `start`
+    pub fn position(&self, start: Cluster, count: u32, cluster: Cluster) -> Option<u32> {
+        let mut c = start;
+        for p in 0..count {
+            if c == cluster {
+                return Some(p);
+            }
+            c = self.next(c)?;
+        }
+        None
+    }
+
+    /// Select a slot to buffer a cluster, evicting buffered clusters not
+    /// matching `keep` where required, returning `None` if no slot is available
+    pub fn slot(&self, cluster: Cluster, keep: impl Fn(Cluster) -> bool) -> Option<usize> {
+        let slots = || (0..self.slots()).map(|i| self.slot_cluster(i));
+
+        slots().position(|s| s == Some(cluster))
+            .or_else(|| slots().position(|s| s.is_none()))
+            .or_else(|| slots().position(|s| !s.is_some_and(&keep)))
+    }
+
+    /// Buffer a cluster in the provided slot
+    pub fn store(&mut self, slot: usize, cluster: Cluster, data: &[u8]) {
+        crate::trace!("Buffering cluster {} in reassembly slot {}", cluster, slot);
+
+        self.buff[slot * BLOCK_SIZE..][..BLOCK_SIZE].copy_from_slice(&data[..BLOCK_SIZE]);
+        self.set_slot_cluster(slot, Some(cluster));
+    }
+
+    /// Find a buffered cluster matching the provided predicate
+    pub fn find(&self, f: impl Fn(Cluster) -> bool) -> Option<(usize, Cluster)> {
+        (0..self.slots())
+            .find_map(|i| self.slot_cluster(i).filter(|c| f(*c)).map(|c| (i, c)))
+    }
+
+    /// Fetch the data for a buffered cluster
+    pub fn data(&self, slot: usize) -> &[u8] {
+        &self.buff[slot * BLOCK_SIZE..][..BLOCK_SIZE]
+    }
+
+    /// Release a buffered cluster slot
+    pub fn release(&mut self, slot: usize) {
+        self.set_slot_cluster(slot, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_links() {
+        let mut buff = [0u8; 256 + 12 * 4];
+        let mut r = Reassembly::<8>::new(&mut buff);
+
+        // Sector covering clusters 0..4, with a device file owning cluster 2
+        // and a host chain 3 -> 5 -> 9 -> end
+        let mut fat = [0u8; 8];
+        fat[4..6].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        fat[6..8].copy_from_slice(&5u16.to_le_bytes());
+        r.update_links(0, &fat, |c| c == Cluster(2));
+
+        let mut fat = [0u8; 8];
+        fat[2..4].copy_from_slice(&9u16.to_le_bytes());
+        fat[0..2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        r.update_links(4, &fat, |_| false);
+        r.update_links(8, &[0x00, 0x00, 0xFF, 0xFF], |_| false);
+
+        assert_eq!(r.next(Cluster(2)), Some(Cluster(3)));
+        assert_eq!(r.next(Cluster(3)), Some(Cluster(5)));
+        assert_eq!(r.next(Cluster(9)), None);
+        assert_eq!(r.position(Cluster(3), 3, Cluster(9)), Some(2));
+        assert_eq!(r.position(Cluster(3), 3, Cluster(4)), None);
+
+        // Buffered clusters are evicted when not kept
+        for c in 10..14 {
+            let s = r.slot(Cluster(c), |_| true).unwrap();
+            r.store(s, Cluster(c), &[c as u8; 8]);
+        }
+        assert_eq!(r.slot(Cluster(12), |_| true), Some(2));
+        assert_eq!(r.slot(Cluster(14), |_| true), None);
+        assert_eq!(r.slot(Cluster(14), |c| c != Cluster(11)), Some(1));
+        r.store(1, Cluster(14), &[14; 8]);
+
+        let (slot, c) = r.find(|c| c == Cluster(14)).unwrap();
+        assert_eq!((slot, c), (1, Cluster(14)));
+        assert_eq!(r.data(slot), &[14; 8]);
+        r.release(slot);
+        assert!(r.find(|c| c == Cluster(14)).is_none());
+
+        // Slots and links are stored in the buffer
+        assert_eq!(core::mem::size_of::<Option<Reassembly<512>>>(), core::mem::size_of::<&mut [u8]>());
+    }
+}
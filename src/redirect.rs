@@ -0,0 +1,69 @@
+use core::fmt::{self, Write};
+
+use crate::{File, GeneratedFile};
+use crate::text::SliceWriter;
+
+/// Generated `INDEX.HTM` file redirecting to the provided URL (mbed-style),
+/// giving users a clickable link to documentation or a web configurator
+///
+/// The URL is not escaped, and must not contain quotes or HTML markup.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexHtmFile<'a> {
+    url: &'a str,
+}
+
+impl <'a> IndexHtmFile<'a> {
+    /// Create a new redirect to the provided URL
+    pub const fn new(url: &'a str) -> Self {
+        Self { url }
+    }
+
+    /// Create a read-only `INDEX.HTM` file rendering this redirect
+    pub const fn file<const BLOCK_SIZE: usize>(&'a self) -> File<'a, BLOCK_SIZE> {
+        File::new_gen("INDEX.HTM", self)
+    }
+
+    /// Render redirect as HTML
+    fn render(&self, w: &mut dyn Write) -> fmt::Result {
+        write!(w, "<!doctype html>\n<html><head><meta http-equiv=\"refresh\" content=\"0; URL='{}'\"/>", self.url)?;
+        writeln!(w, "<title>Redirect</title></head><body>Redirecting to <a href=\"{0}\">{0}</a></body></html>", self.url)
+    }
+}
+
+impl <'a> GeneratedFile for IndexHtmFile<'a> {
+    fn len(&self) -> usize {
+        let mut w = SliceWriter::new(0, &mut []);
+        let _ = self.render(&mut w);
+        w.len()
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        let mut w = SliceWriter::new(offset, buff);
+        let _ = self.render(&mut w);
+        w.written()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_redirect() {
+        let r = IndexHtmFile::new("https://example.com/docs");
+        let f = r.file::<64>();
+        assert_eq!(f.name(), "INDEX.HTM");
+
+        let mut html = [0u8; 256];
+        let mut n = 0;
+        for i in 0..f.num_blocks() {
+            n += f.chunk(i, &mut html[n..][..64]).unwrap();
+        }
+        assert_eq!(n, f.len());
+
+        let html = core::str::from_utf8(&html[..n]).unwrap();
+        assert!(html.starts_with("<!doctype html>\n"));
+        assert!(html.contains("content=\"0; URL='https://example.com/docs'\""));
+        assert!(html.ends_with("<a href=\"https://example.com/docs\">https://example.com/docs</a></body></html>\n"));
+    }
+}
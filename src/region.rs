@@ -0,0 +1,189 @@
+use crate::BlockDevice;
+
+use crate::{DynamicFile, FileError};
+
+/// Adapter exposing a raw LBA range of another [`BlockDevice`] (ie. an SD
+/// card or external flash die) as a single file, so capture regions may be
+/// surfaced to the host without copying.
+///
+/// The underlying device block size must match the file system `BLOCK_SIZE`.
+pub struct BlockRegionFile<D, const BLOCK_SIZE: usize = 512> {
+    device: D,
+    start: u32,
+    blocks: u32,
+}
+
+impl <D: BlockDevice, const BLOCK_SIZE: usize> BlockRegionFile<D, BLOCK_SIZE> {
+    /// Create a new file over `blocks` blocks of the device from LBA `start`
+    pub fn new(device: D, start: u32, blocks: u32) -> Self {
+        const { assert!(D::BLOCK_BYTES == BLOCK_SIZE, "device block size must match the file system block size") };
+
+        Self { device, start, blocks }
+    }
+
+    /// Fetch a reference to the underlying device
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    /// Fetch a mutable reference to the underlying device
+    pub fn device_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    /// Fetch the first LBA and block count of the exposed region
+    pub fn region(&self) -> (u32, u32) {
+        (self.start, self.blocks)
+    }
+
+    /// Map a chunk index to a device LBA, returning `None` outside the region
+    fn lba(&self, chunk_index: usize) -> Option<u32> {
+        if chunk_index >= self.blocks as usize {
+            return None;
+        }
+
+        let lba = self.start + chunk_index as u32;
+        if lba > self.device.max_lba() {
+            crate::warn!("Region block {} exceeds device max lba {}", lba, self.device.max_lba());
+            return None;
+        }
+
+        Some(lba)
+    }
+}
+
+impl <D: BlockDevice + Sync + Send, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for BlockRegionFile<D, BLOCK_SIZE> {
+    fn len(&self) -> usize {
+        self.blocks as usize * BLOCK_SIZE
+    }
+
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        let lba = match self.lba(chunk_index) {
+            Some(l) => l,
+            None => return Ok(0),
+        };
+
+        self.device.read_block(lba, &mut buff[..BLOCK_SIZE])
+            .map_err(|_| FileError::ReadError)?;
+
+        Ok(BLOCK_SIZE)
+    }
+
+    fn read_chunks(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        let lba = match self.lba(chunk_index) {
+            Some(l) => l,
+            None => return Ok(0),
+        };
+
+        // Clamp to the end of the region and device
+        let n = (buff.len() / BLOCK_SIZE)
+            .min(self.blocks as usize - chunk_index)
+            .min((self.device.max_lba() - lba) as usize + 1);
+
+        self.device.read_blocks(lba, &mut buff[..n * BLOCK_SIZE])
+            .map_err(|_| FileError::ReadError)?;
+
+        Ok(n * BLOCK_SIZE)
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        let lba = match self.lba(chunk_index) {
+            Some(l) => l,
+            None => return Ok(0),
+        };
+
+        // Devices only support whole block writes
+        if data.len() < BLOCK_SIZE {
+            return Err(FileError::WriteError);
+        }
+
+        self.device.write_block(lba, &data[..BLOCK_SIZE])
+            .map_err(|_| FileError::WriteError)?;
+
+        Ok(BLOCK_SIZE)
+    }
+
+    fn write_chunks(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        let lba = match self.lba(chunk_index) {
+            Some(l) => l,
+            None => return Ok(0),
+        };
+
+        // Devices only support whole block writes
+        if data.len() < BLOCK_SIZE {
+            return Err(FileError::WriteError);
+        }
+
+        // Clamp to the end of the region and device
+        let n = (data.len() / BLOCK_SIZE)
+            .min(self.blocks as usize - chunk_index)
+            .min((self.device.max_lba() - lba) as usize + 1);
+
+        self.device.write_blocks(lba, &data[..n * BLOCK_SIZE])
+            .map_err(|_| FileError::WriteError)?;
+
+        Ok(n * BLOCK_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDeviceError;
+
+    use super::*;
+
+    /// RAM block device
+    struct Ram([u8; 64]);
+
+    impl BlockDevice for Ram {
+        const BLOCK_BYTES: usize = 8;
+
+        fn read_block(&self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError> {
+            block.copy_from_slice(&self.0[lba as usize * 8..][..8]);
+            Ok(())
+        }
+
+        fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError> {
+            self.0[lba as usize * 8..][..8].copy_from_slice(block);
+            Ok(())
+        }
+
+        fn max_lba(&self) -> u32 {
+            7
+        }
+    }
+
+    #[test]
+    fn region_blocks() {
+        let mut ram = Ram([0; 64]);
+        ram.0[16..24].fill(0xAA);
+
+        let mut f = BlockRegionFile::<_, 8>::new(ram, 2, 4);
+        assert_eq!(f.len(), 32);
+
+        let mut buff = [0u8; 8];
+        assert_eq!(f.read_chunk(0, &mut buff), Ok(8));
+        assert_eq!(buff, [0xAA; 8]);
+        assert_eq!(f.read_chunk(4, &mut buff), Ok(0));
+
+        assert_eq!(f.write_chunk(3, &[0x55; 8]), Ok(8));
+        assert_eq!(&f.device().0[40..48], &[0x55; 8]);
+
+        // Regions beyond the device end are not readable
+        let f = BlockRegionFile::<_, 8>::new(Ram([0; 64]), 6, 4);
+        assert_eq!(f.read_chunk(2, &mut buff), Ok(0));
+
+        // Ranges are clamped to the region and device
+        let mut buff = [0u8; 32];
+        assert_eq!(f.read_chunks(1, &mut buff), Ok(8));
+
+        let mut ram = Ram([0; 64]);
+        assert_eq!(ram.write_blocks(7, &[0x55; 16]), Err(BlockDeviceError::InvalidAddress));
+        assert_eq!(ram.write_blocks(u32::MAX, &[0x55; 16]), Err(BlockDeviceError::InvalidAddress));
+        assert_eq!(ram.0, [0; 64]);
+
+        let mut f = BlockRegionFile::<_, 8>::new(ram, 2, 4);
+        assert_eq!(f.write_chunks(2, &[0x55; 24]), Ok(16));
+        assert_eq!(&f.device().0[32..], &[[0x55; 16], [0; 16]].concat()[..]);
+    }
+}
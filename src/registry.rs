@@ -0,0 +1,158 @@
+#[cfg(feature = "heapless")]
+use heapless::Vec;
+
+use crate::{File, FileError, GhostFat};
+use crate::file::Files;
+
+/// Fixed capacity file table storage, allowing files to be added and
+/// removed at runtime
+#[cfg(feature = "heapless")]
+pub(crate) trait Registry<'a, const BLOCK_SIZE: usize>: Send {
+    /// Fetch registered files
+    fn files(&self) -> &[File<'a, BLOCK_SIZE>];
+
+    /// Fetch registered files for modification
+    fn files_mut(&mut self) -> &mut [File<'a, BLOCK_SIZE>];
+
+    /// Append a file, failing with [`FileError::NoSpace`] if the registry is full
+    fn push(&mut self, file: File<'a, BLOCK_SIZE>) -> Result<(), FileError>;
+
+    /// Remove the file at the provided index
+    fn remove(&mut self, index: usize) -> File<'a, BLOCK_SIZE>;
+}
+
+#[cfg(feature = "heapless")]
+impl <'a, const BLOCK_SIZE: usize, const N: usize> Registry<'a, BLOCK_SIZE> for Vec<File<'a, BLOCK_SIZE>, N> {
+    fn files(&self) -> &[File<'a, BLOCK_SIZE>] {
+        self
+    }
+
+    fn files_mut(&mut self) -> &mut [File<'a, BLOCK_SIZE>] {
+        self
+    }
+
+    fn push(&mut self, file: File<'a, BLOCK_SIZE>) -> Result<(), FileError> {
+        Vec::push(self, file).map_err(|_| FileError::NoSpace)
+    }
+
+    fn remove(&mut self, index: usize) -> File<'a, BLOCK_SIZE> {
+        Vec::remove(self, index)
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize> GhostFat<'a, BLOCK_SIZE> {
+    /// Create a new file system instance over a fixed capacity file registry,
+    /// allowing files to be added and removed at runtime (see
+    /// [`GhostFat::add_file`] and [`GhostFat::remove_file`]).
+    /// 
+    /// As the registry holds files borrowing for the file system lifetime it
+    /// must outlive the file system, ie. stored in a `static` via
+    /// [`StaticCell`](https://docs.rs/static_cell)
+    #[cfg(feature = "heapless")]
+    pub fn new_registry<const N: usize>(files: &'a mut Vec<File<'a, BLOCK_SIZE>, N>, config: crate::Config<BLOCK_SIZE>) -> Self {
+        Self::with_files(Files::Registry(files), config)
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+
+    /// Register a new file at runtime, returning the file index.
+    /// 
+    /// Files may only be added to registry ([`GhostFat::new_registry`]) or
+    /// owned ([`GhostFat::new_owned`]) file tables, with [`FileError::NoSpace`]
+    /// returned for borrowed file tables or where the registry is full.
+    /// The volume is remounted to notify the host of the change.
+    pub fn add_file(&mut self, file: File<'a, BLOCK_SIZE>) -> Result<usize, FileError> {
+        let name = file.short_name()?;
+        if self.fat_files.iter().any(|f| f.short_name() == Ok(name)) {
+            return Err(FileError::InvalidName);
+        }
+
+        let index = self.fat_files.len();
+        match &mut self.fat_files {
+            #[cfg(feature = "heapless")]
+            Files::Registry(r) => r.push(file)?,
+            #[cfg(feature = "alloc")]
+            Files::Owned(v) => v.push(file),
+            _ => return Err(FileError::NoSpace),
+        }
+
+        crate::debug!("Added file: {}", self.fat_files[index].name());
+        self.update_files();
+
+        Ok(index)
+    }
+
+    /// Remove a file by name at runtime, returning the removed file.
+    /// 
+    /// Files may only be removed from registry or owned file tables, with
+    /// `None` returned for borrowed file tables or where no file matches.
+    /// The volume is remounted to notify the host of the change.
+    pub fn remove_file(&mut self, name: &str) -> Option<File<'a, BLOCK_SIZE>> {
+        let index = self.fat_files.iter().position(|f| f.name().eq_ignore_ascii_case(name))?;
+
+        let file = match &mut self.fat_files {
+            #[cfg(feature = "heapless")]
+            Files::Registry(r) => r.remove(index),
+            #[cfg(feature = "alloc")]
+            Files::Owned(v) => v.remove(index),
+            _ => return None,
+        };
+
+        crate::debug!("Removed file: {}", file.name());
+        self.update_files();
+
+        Some(file)
+    }
+
+    /// Regenerate the volume following changes to the file table
+    fn update_files(&mut self) {
+        #[cfg(feature = "crc32fast")]
+        crate::manifest::update_counts(&mut self.fat_files);
+
+        self.layout = Self::layout(&self.fat_files);
+        self.remount();
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "heapless")]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::Config;
+    use super::*;
+
+    #[test]
+    fn file_registry() {
+        let (a, b, c): (&[u8], &[u8], &[u8]) = (&[0u8; 8], &[0u8; 600], &[0u8; 8]);
+        let files: &mut Vec<File<512>, 2> = std::boxed::Box::leak(Default::default());
+        let _ = files.push(File::new_ro("A.TXT", a));
+
+        let mut fs = GhostFat::new_registry(files, Config::default());
+        let rootdir = fs.config.start_rootdir().0;
+
+        // Added files are listed following a media change
+        assert_eq!(fs.add_file(File::new_ro("A.TXT", c)).err(), Some(FileError::InvalidName));
+        assert_eq!(fs.add_file(File::new_ro("B.BIN", b)), Ok(1));
+        assert!(fs.take_changed());
+
+        let mut block = [0u8; 512];
+        fs.read_block(rootdir, &mut block).unwrap();
+        assert_eq!(&block[64..75], b"B       BIN");
+
+        // Up to the registry capacity
+        assert_eq!(fs.add_file(File::new_ro("C.TXT", c)).err(), Some(FileError::NoSpace));
+
+        // Removed files are no longer listed, with following files moved
+        let f = fs.remove_file("a.txt").unwrap();
+        assert_eq!(f.name(), "A.TXT");
+        assert!(fs.take_changed());
+        assert!(fs.remove_file("A.TXT").is_none());
+
+        fs.read_block(rootdir, &mut block).unwrap();
+        assert_eq!(&block[32..43], b"B       BIN");
+        assert_eq!(&block[58..60], &[0x02, 0x00]);
+        assert_eq!(block[64], 0);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{GhostFat, Lba, SectorIndex};
+
+/// File system region containing an LBA, see [`GhostFat::resolve_lba`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature="defmt", derive(defmt::Format))]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Region {
+    /// Boot sector
+    Boot,
+    /// Reserved sector following the boot sector
+    Reserved(SectorIndex),
+    /// FAT sector, with the FAT copy (0 or 1) and sector index within the FAT
+    Fat { copy: u8, sector: SectorIndex },
+    /// Root directory sector
+    RootDir(SectorIndex),
+    /// Data cluster within a file, with the file index and block offset
+    File { index: usize, offset: usize },
+    /// Data cluster outside device files
+    Unmapped(SectorIndex),
+    /// Beyond the end of the volume
+    OutOfRange,
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Resolve the file system region containing an LBA, ie. for debugging
+    /// host access patterns or SCSI layer optimisations
+    pub fn resolve_lba(&self, lba: impl Into<Lba>) -> Region {
+        let lba = lba.into();
+        let c = &self.config;
+
+        if lba == Lba(0) {
+            Region::Boot
+        } else if lba.0 >= c.num_blocks {
+            Region::OutOfRange
+        } else if lba < c.start_fat0() {
+            Region::Reserved(lba - Lba(1))
+        } else if lba < c.start_fat1() {
+            Region::Fat { copy: 0, sector: lba - c.start_fat0() }
+        } else if lba < c.start_rootdir() {
+            Region::Fat { copy: 1, sector: lba - c.start_fat1() }
+        } else if lba < c.start_clusters() {
+            Region::RootDir(lba - c.start_rootdir())
+        } else {
+            let section_index = lba - c.start_clusters();
+            match self.locate(section_index) {
+                Some((index, offset)) => Region::File { index, offset },
+                None => Region::Unmapped(section_index),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, File};
+    use super::*;
+
+    #[test]
+    fn resolve_regions() {
+        let (a, b) = ([0u8; 1024], [0u8; 8]);
+        let mut f = [File::<512>::new_ro("A.BIN", &a), File::new_ro("B.TXT", &b)];
+
+        let mut config = Config::default();
+        config.reserved_sectors = 2;
+        let fs = GhostFat::new(&mut f, config);
+        let c = &fs.config;
+
+        assert_eq!(fs.resolve_lba(0), Region::Boot);
+        assert_eq!(fs.resolve_lba(1), Region::Reserved(SectorIndex(0)));
+        assert_eq!(fs.resolve_lba(c.start_fat0().0 + 3), Region::Fat { copy: 0, sector: SectorIndex(3) });
+        assert_eq!(fs.resolve_lba(c.start_fat1()), Region::Fat { copy: 1, sector: SectorIndex(0) });
+        assert_eq!(fs.resolve_lba(c.start_rootdir().0 + 1), Region::RootDir(SectorIndex(1)));
+
+        let start = c.start_clusters().0;
+        assert_eq!(fs.resolve_lba(start + 1), Region::File { index: 0, offset: 1 });
+        assert_eq!(fs.resolve_lba(start + 2), Region::File { index: 1, offset: 0 });
+        assert_eq!(fs.resolve_lba(start + 3), Region::Unmapped(SectorIndex(3)));
+        assert_eq!(fs.resolve_lba(c.num_blocks), Region::OutOfRange);
+    }
+}
@@ -0,0 +1,188 @@
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use crate::{File, GeneratedFile};
+
+/// Rotating append-only log, presented to the host as a sequence of
+/// `FILES` files (`LOG0001.TXT`, `LOG0002.TXT`, …) of up to `CAP` bytes
+///
+/// The device appends records via a shared reference, with records kept
+/// whole and the log rolling to the next file when a record does not fit
+/// in the current one. Once all files are in use the oldest is dropped and
+/// content shifts down, so `LOG0001.TXT` always holds the oldest retained
+/// records and the highest numbered non-empty file the most recent.
+///
+/// Clusters are reserved for `CAP` bytes per file so files may grow
+/// without moving other files, see [`GhostFat::refresh`](crate::GhostFat::refresh)
+/// to detect changes. Appends must come from a single context at a time.
+pub struct RotatingLog<const FILES: usize, const CAP: usize> {
+    names: [[u8; 11]; FILES],
+    buff: [[AtomicU8; CAP]; FILES],
+    lens: [AtomicUsize; FILES],
+    seq: AtomicUsize,
+}
+
+impl <const FILES: usize, const CAP: usize> RotatingLog<FILES, CAP> {
+    /// Create a new empty log
+    pub const fn new() -> Self {
+        const { assert!(FILES > 0 && FILES <= 9999, "log file count must be within 1..=9999") };
+
+        // Generate `LOGnnnn.TXT` names
+        let mut names = [*b"LOG0000.TXT"; FILES];
+        let mut i = 0;
+        while i < FILES {
+            let (mut n, mut d) = (i + 1, 6);
+            while n > 0 {
+                names[i][d] = b'0' + (n % 10) as u8;
+                n /= 10;
+                d -= 1;
+            }
+            i += 1;
+        }
+
+        Self {
+            names,
+            buff: [const { [const { AtomicU8::new(0) }; CAP] }; FILES],
+            lens: [const { AtomicUsize::new(0) }; FILES],
+            seq: AtomicUsize::new(0),
+        }
+    }
+
+    /// Append a record, rolling to the next file if the record does not
+    /// fit in the current file. Records longer than `CAP` are truncated.
+    pub fn append(&self, record: &[u8]) {
+        let record = &record[..usize::min(record.len(), CAP)];
+
+        let mut seq = self.seq.load(Ordering::Acquire);
+        let mut len = self.lens[seq % FILES].load(Ordering::Acquire);
+
+        if len > 0 && len + record.len() > CAP {
+            seq += 1;
+            len = 0;
+
+            crate::debug!("Rolling log to segment {}", seq);
+
+            self.lens[seq % FILES].store(0, Ordering::Release);
+            self.seq.store(seq, Ordering::Release);
+        }
+
+        let slot = &self.buff[seq % FILES];
+        for (a, b) in slot[len..].iter().zip(record) {
+            a.store(*b, Ordering::Relaxed);
+        }
+
+        self.lens[seq % FILES].store(len + record.len(), Ordering::Release);
+    }
+
+    /// Clear the log
+    pub fn clear(&self) {
+        for l in &self.lens {
+            l.store(0, Ordering::Release);
+        }
+        self.seq.store(0, Ordering::Release);
+    }
+
+    /// Fetch the number of files rolled since creation or clearing
+    pub fn rolled(&self) -> usize {
+        self.seq.load(Ordering::Acquire)
+    }
+
+    /// Fetch per-file views of the log, for registration as files
+    pub fn files(&self) -> [RotatingLogFile<'_, FILES, CAP>; FILES] {
+        core::array::from_fn(|index| RotatingLogFile { log: self, index })
+    }
+
+    /// Map a file index to the buffer slot holding its content
+    fn slot(&self, index: usize) -> Option<usize> {
+        let seq = self.seq.load(Ordering::Acquire);
+        let segment = seq.saturating_sub(FILES - 1) + index;
+
+        match segment <= seq {
+            true => Some(segment % FILES),
+            false => None,
+        }
+    }
+}
+
+impl <const FILES: usize, const CAP: usize> Default for RotatingLog<FILES, CAP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// View of a single file of a [`RotatingLog`]
+pub struct RotatingLogFile<'a, const FILES: usize, const CAP: usize> {
+    log: &'a RotatingLog<FILES, CAP>,
+    index: usize,
+}
+
+impl <'a, const FILES: usize, const CAP: usize> RotatingLogFile<'a, FILES, CAP> {
+    /// Fetch the file name
+    pub fn name(&self) -> &'a str {
+        core::str::from_utf8(&self.log.names[self.index]).unwrap_or_default()
+    }
+
+    /// Create a read-only file exposing this view, with clusters reserved
+    /// for the file cap
+    pub fn file<const BLOCK_SIZE: usize>(&self) -> File<'_, BLOCK_SIZE> {
+        File::new_gen(self.name(), self).with_reserved(CAP)
+    }
+}
+
+impl <'a, const FILES: usize, const CAP: usize> GeneratedFile for RotatingLogFile<'a, FILES, CAP> {
+    fn len(&self) -> usize {
+        match self.log.slot(self.index) {
+            Some(s) => self.log.lens[s].load(Ordering::Acquire),
+            None => 0,
+        }
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        let slot = match self.log.slot(self.index) {
+            Some(s) => s,
+            None => return 0,
+        };
+
+        let len = self.log.lens[slot].load(Ordering::Acquire);
+        let n = usize::min(buff.len(), len.saturating_sub(offset));
+        for (b, a) in buff[..n].iter_mut().zip(self.log.buff[slot].iter().skip(offset)) {
+            *b = a.load(Ordering::Relaxed);
+        }
+
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_logs() {
+        let log = RotatingLog::<3, 8>::new();
+        let views = log.files();
+        let files = views.each_ref().map(|v| v.file::<8>());
+        assert_eq!(files.each_ref().map(|f| f.name()), ["LOG0001.TXT", "LOG0002.TXT", "LOG0003.TXT"]);
+        assert_eq!(files[0].alloc_blocks(), 1);
+
+        let mut buff = [0u8; 8];
+        let mut read = |i: usize| {
+            let n = files[i].chunk(0, &mut buff).unwrap();
+            String::from_utf8(buff[..n].to_vec()).unwrap()
+        };
+
+        // Records are not split across files
+        log.append(b"abc\n");
+        log.append(b"def\n");
+        log.append(b"ghi\n");
+        assert_eq!((read(0), read(1), read(2)), ("abc\ndef\n".into(), "ghi\n".into(), "".into()));
+
+        // The oldest file is dropped once all files are in use
+        log.append(b"jklmn\n");
+        log.append(b"op\n");
+        assert_eq!(log.rolled(), 3);
+        assert_eq!((read(0), read(1), read(2)), ("ghi\n".into(), "jklmn\n".into(), "op\n".into()));
+
+        log.clear();
+        assert_eq!(read(0), "");
+    }
+}
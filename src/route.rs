@@ -0,0 +1,226 @@
+use crate::{FileError, LogFile};
+
+/// Backend receiving the content of files created on the volume by the host
+pub trait HostFileSink: Send {
+    /// Start receiving a new file with the provided name and size,
+    /// returning an error to reject the file
+    fn create(&mut self, name: &str, size: usize) -> Result<(), FileError>;
+
+    /// Write received file data at the provided byte offset,
+    /// returning the write length
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<usize, FileError>;
+
+    /// Complete the received file
+    fn close(&mut self) -> Result<(), FileError> {
+        Ok(())
+    }
+}
+
+/// Route host-created files matching a name pattern to a [`HostFileSink`]
+pub struct Route<'a> {
+    /// Case-insensitive file name pattern, supporting `*` and `?` wildcards
+    pub pattern: &'a str,
+    /// Sink for matching files
+    pub sink: &'a mut dyn HostFileSink,
+}
+
+impl <'a> Route<'a> {
+    /// Create a new route for files matching `pattern` (ie. `*.UF2`)
+    pub fn new(pattern: &'a str, sink: &'a mut dyn HostFileSink) -> Self {
+        Self { pattern, sink }
+    }
+}
+
+/// Router selecting a [`HostFileSink`] for host-created files by name,
+/// with routes checked in order and the first match used
+pub struct Router<'a> {
+    routes: &'a mut [Route<'a>],
+}
+
+impl <'a> Router<'a> {
+    /// Create a new router over the provided routes
+    pub fn new(routes: &'a mut [Route<'a>]) -> Self {
+        Self { routes }
+    }
+
+    /// Find the sink for the provided file name, if any
+    pub fn route(&mut self, name: &str) -> Option<&mut dyn HostFileSink> {
+        match self.routes.iter_mut().find(|r| matches(r.pattern, name)) {
+            Some(r) => Some(&mut *r.sink),
+            None => {
+                crate::debug!("No route for host file: {}", name);
+                None
+            },
+        }
+    }
+}
+
+/// Match a file name against a case-insensitive pattern with `*`
+/// (any sequence) and `?` (any single character) wildcards
+pub(crate) fn matches(pattern: &str, name: &str) -> bool {
+    let (p, n) = (pattern.as_bytes(), name.as_bytes());
+    let (mut pi, mut ni) = (0, 0);
+
+    // Last `*` position in the pattern and matching name position,
+    // used to backtrack on mismatch
+    let mut star = None;
+
+    while ni < n.len() {
+        match p.get(pi) {
+            Some(b'*') => {
+                star = Some((pi, ni));
+                pi += 1;
+            },
+            Some(c) if *c == b'?' || c.eq_ignore_ascii_case(&n[ni]) => {
+                pi += 1;
+                ni += 1;
+            },
+            _ => match star {
+                Some((sp, sn)) => {
+                    pi = sp + 1;
+                    ni = sn + 1;
+                    star = Some((sp, sn + 1));
+                },
+                None => return false,
+            },
+        }
+    }
+
+    // Remaining pattern must be wildcards
+    p[pi..].iter().all(|c| *c == b'*')
+}
+
+/// [`HostFileSink`] storing the most recently received file in RAM,
+/// ie. for use as a scratch area
+pub struct ScratchSink<'a> {
+    buff: &'a mut [u8],
+    name: [u8; 12],
+    name_len: usize,
+    len: usize,
+}
+
+impl <'a> ScratchSink<'a> {
+    /// Create a new scratch sink over the provided buffer
+    pub fn new(buff: &'a mut [u8]) -> Self {
+        Self { buff, name: [0u8; 12], name_len: 0, len: 0 }
+    }
+
+    /// Fetch the name of the received file
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    /// Fetch the content of the received file
+    pub fn data(&self) -> &[u8] {
+        &self.buff[..self.len]
+    }
+
+    /// Move the received file into the provided archive and clear the scratch
+    /// area, ie. at the end of a host session.
+    /// 
+    /// Registering the archive as a generated file (ie. `NOTES.OLD`) exposes
+    /// the previous session's upload read-only, so users can confirm what
+    /// the device received after a replug.
+    pub fn archive<const N: usize>(&mut self, archive: &LogFile<N>) {
+        archive.clear();
+        archive.append(self.data());
+
+        self.name_len = 0;
+        self.len = 0;
+    }
+}
+
+impl <'a> HostFileSink for ScratchSink<'a> {
+    fn create(&mut self, name: &str, size: usize) -> Result<(), FileError> {
+        if name.len() > self.name.len() {
+            return Err(FileError::InvalidName);
+        }
+        if size > self.buff.len() {
+            return Err(FileError::NoSpace);
+        }
+
+        self.name[..name.len()].copy_from_slice(name.as_bytes());
+        self.name_len = name.len();
+        self.len = size;
+        self.buff[..size].fill(0);
+
+        Ok(())
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<usize, FileError> {
+        if offset >= self.len {
+            return Ok(0);
+        }
+
+        let len = usize::min(data.len(), self.len - offset);
+        self.buff[offset..][..len].copy_from_slice(&data[..len]);
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::File;
+    use crate::file::Attrs;
+    use super::*;
+
+    #[test]
+    fn match_patterns() {
+        assert!(matches("*.UF2", "FIRMWARE.UF2"));
+        assert!(matches("*.uf2", "firmware.UF2"));
+        assert!(!matches("*.UF2", "FIRMWARE.BIN"));
+        assert!(matches("CONFIG.TXT", "config.txt"));
+        assert!(matches("LOG?.TXT", "LOG1.TXT"));
+        assert!(!matches("LOG?.TXT", "LOG12.TXT"));
+        assert!(matches("*", "ANY.BIN"));
+        assert!(matches("A*B*.BIN", "AXXBYY.BIN"));
+        assert!(!matches("A*B", "AXXBYY"));
+    }
+
+    #[test]
+    fn route_files() {
+        let (mut b1, mut b2) = ([0u8; 16], [0u8; 16]);
+        let mut uf2 = ScratchSink::new(&mut b1);
+        let mut txt = ScratchSink::new(&mut b2);
+
+        {
+            let mut routes = [
+                Route::new("*.UF2", &mut uf2),
+                Route::new("*.TXT", &mut txt),
+            ];
+            let mut router = Router::new(&mut routes);
+
+            let s = router.route("NOTES.TXT").unwrap();
+            s.create("NOTES.TXT", 4).unwrap();
+            assert_eq!(s.write(0, b"abcdef"), Ok(4));
+            s.close().unwrap();
+
+            assert!(router.route("DATA.BIN").is_none());
+            assert_eq!(router.route("FW.UF2").unwrap().create("FW.UF2", 32), Err(FileError::NoSpace));
+        }
+
+        assert_eq!(txt.name(), "NOTES.TXT");
+        assert_eq!(txt.data(), b"abcd");
+        assert_eq!(uf2.data(), b"");
+    }
+
+    #[test]
+    fn archive_previous() {
+        let mut b = [0u8; 16];
+        let mut s = ScratchSink::new(&mut b);
+        let archive = LogFile::<16>::new();
+        let f = File::<8>::new_gen("NOTES.OLD", &archive);
+
+        s.create("NOTES.TXT", 6).unwrap();
+        s.write(0, b"abcdef").unwrap();
+        s.archive(&archive);
+        assert_eq!(s.data(), b"");
+
+        let mut buff = [0u8; 8];
+        assert_eq!(f.len(), 6);
+        assert_eq!(f.chunk(0, &mut buff), Ok(6));
+        assert_eq!(&buff[..6], b"abcdef");
+        assert_eq!(f.attrs(), Attrs::READ_ONLY);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{BlockDeviceError, GhostFat, Lba};
+
+/// Source descriptor for a run of blocks in a scatter read,
+/// see [`GhostFat::scatter`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Segment<'s> {
+    /// Blocks available in place (ie. memory-mapped flash or RAM), which
+    /// may be transferred directly from the slice address
+    Slice(&'s [u8]),
+    /// Blocks to be generated into a buffer via
+    /// [`BlockDevice::read_blocks`](crate::BlockDevice::read_blocks)
+    Generate {
+        /// First LBA of the run
+        lba: u32,
+        /// Length of the run in bytes
+        len: usize,
+    },
+}
+
+impl <'s> Segment<'s> {
+    /// Fetch the length of the segment in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            Segment::Slice(s) => s.len(),
+            Segment::Generate { len, .. } => *len,
+        }
+    }
+
+    /// Check whether the segment is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Describe the sources of `blocks` blocks from `lba`, so DMA capable
+    /// USB drivers may queue transfers without intermediate copies.
+    ///
+    /// Consecutive blocks referenced in place (see [`GhostFat::read_ref`]) are
+    /// merged into a single [`Segment::Slice`], with all other blocks merged
+    /// into [`Segment::Generate`] runs to be read into a buffer. Referenced
+    /// blocks are accounted as read as the iterator is consumed.
+    pub fn scatter(&self, lba: u32, blocks: u32) -> Result<impl Iterator<Item = Segment<'_>> + use<'_, 'a, BLOCK_SIZE, FILES>, BlockDeviceError> {
+        let end = match lba.checked_add(blocks) {
+            Some(e) if e <= self.config.num_blocks => e,
+            _ => {
+                crate::warn!("Invalid scatter read of {} blocks from lba: {}", blocks, lba);
+                return Err(BlockDeviceError::InvalidAddress);
+            },
+        };
+
+        let mut next = lba;
+
+        Ok(core::iter::from_fn(move || {
+            if next >= end {
+                return None;
+            }
+
+            let start = next;
+            let chunk = self.ref_chunk(Lba(start));
+
+            // Extend the run while blocks share the same source
+            next += 1;
+            while next < end {
+                let c = self.ref_chunk(Lba(next));
+                match (chunk, c) {
+                    (Some((i, o)), Some(c)) if c == (i, o + (next - start) as usize) => (),
+                    (None, None) => (),
+                    _ => break,
+                }
+                next += 1;
+            }
+
+            let n = (next - start) as usize;
+            let s = match chunk {
+                Some((index, offset)) => {
+                    let d = self.fat_files[index].chunks_ref(offset, n)?;
+                    self.read_refs(index, offset, n);
+                    Segment::Slice(d)
+                },
+                None => Segment::Generate { lba: start, len: n * BLOCK_SIZE },
+            };
+
+            Some(s)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, File};
+    use super::*;
+
+    #[test]
+    fn scatter_reads() {
+        let a: [u8; 24] = core::array::from_fn(|i| i as u8);
+        let b = [0xAAu8; 12];
+        let mut f = [
+            File::<8>::new_ro("A.BIN", &a),
+            File::new_ro("B.BIN", &b),
+        ];
+        let fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        let segments: [Segment; 4] = core::array::from_fn({
+            let mut s = fs.scatter(lba - 1, 8).unwrap();
+            move |_| s.next().unwrap()
+        });
+        assert_eq!(segments, [
+            Segment::Generate { lba: lba - 1, len: 8 },
+            Segment::Slice(&a),
+            Segment::Slice(&b[..8]),
+            Segment::Generate { lba: lba + 4, len: 24 },
+        ]);
+        assert_eq!(segments.iter().map(|s| s.len()).sum::<usize>(), 64);
+
+        // Segments match the block device content
+        let mut blocks = [0u8; 64];
+        fs.read_blocks(lba - 1, &mut blocks).unwrap();
+        assert_eq!(&blocks[8..40], &[&a[..], &b[..8]].concat()[..]);
+
+        assert_eq!(fs.scatter(lba, 1).unwrap().count(), 1);
+        assert!(fs.scatter(fs.max_lba(), 2).is_err());
+    }
+}
@@ -0,0 +1,820 @@
+//! SCSI Bulk-Only Transport mass storage class for `usb-device` 0.3
+//!
+//! `usbd_scsi` depends on `usb-device` 0.2, which prevents use of GhostFAT
+//! alongside current USB stacks. This provides a drop-in replacement for
+//! `usbd_scsi::Scsi` implementing the minimal SCSI transparent command set
+//! required by common hosts, backed by any [`BlockDevice`], or several
+//! devices presented as separate logical units via a [`LunMux`](crate::LunMux).
+
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, RequestType};
+use usb_device::Result as UsbResult;
+use crate::{BlockDeviceError, LunDevice};
+
+use crate::FileError;
+use crate::config::inquiry_data;
+
+const CLASS_MASS_STORAGE: u8 = 0x08;
+const SUBCLASS_SCSI: u8 = 0x06;
+const PROTOCOL_BULK_ONLY: u8 = 0x50;
+
+const REQ_GET_MAX_LUN: u8 = 0xFE;
+const REQ_BULK_ONLY_RESET: u8 = 0xFF;
+
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+const CBW_LEN: usize = 31;
+const CSW_LEN: usize = 13;
+
+/// Transport buffer size, blocks must fit within this buffer
+const BUFFER_BYTES: usize = 512;
+
+/// SCSI operation codes
+mod op {
+    pub const TEST_UNIT_READY: u8 = 0x00;
+    pub const REQUEST_SENSE: u8 = 0x03;
+    pub const INQUIRY: u8 = 0x12;
+    pub const MODE_SENSE_6: u8 = 0x1A;
+    pub const START_STOP_UNIT: u8 = 0x1B;
+    pub const PREVENT_ALLOW_MEDIUM_REMOVAL: u8 = 0x1E;
+    pub const READ_FORMAT_CAPACITIES: u8 = 0x23;
+    pub const READ_CAPACITY_10: u8 = 0x25;
+    pub const READ_10: u8 = 0x28;
+    pub const WRITE_10: u8 = 0x2A;
+    pub const VERIFY_10: u8 = 0x2F;
+    pub const SYNCHRONIZE_CACHE_10: u8 = 0x35;
+    pub const MODE_SENSE_10: u8 = 0x5A;
+}
+
+/// SCSI sense data (key, additional sense code, qualifier)
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Sense(u8, u8, u8);
+
+impl Sense {
+    const OK: Sense = Sense(0x00, 0x00, 0x00);
+    const INVALID_OPCODE: Sense = Sense(0x05, 0x20, 0x00);
+    const LBA_OUT_OF_RANGE: Sense = Sense(0x05, 0x21, 0x00);
+    const INVALID_LUN: Sense = Sense(0x05, 0x25, 0x00);
+    const WRITE_PROTECTED: Sense = Sense(0x07, 0x27, 0x00);
+    const MEDIUM_CHANGED: Sense = Sense(0x06, 0x28, 0x00);
+    const BECOMING_READY: Sense = Sense(0x02, 0x04, 0x01);
+}
+
+impl From<BlockDeviceError> for Sense {
+    fn from(e: BlockDeviceError) -> Self {
+        match e {
+            BlockDeviceError::HardwareError => Sense(0x04, 0x00, 0x00),
+            BlockDeviceError::WriteError => Sense(0x03, 0x0C, 0x00),
+            BlockDeviceError::EraseError => Sense(0x03, 0x51, 0x00),
+            BlockDeviceError::InvalidAddress => Sense::LBA_OUT_OF_RANGE,
+            BlockDeviceError::NotReady => Sense::BECOMING_READY,
+        }
+    }
+}
+
+/// Bulk-Only Transport state
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    /// Waiting for a command block wrapper
+    Command,
+    /// Sending data to the host
+    DataIn,
+    /// Receiving data from the host
+    DataOut,
+    /// Sending a zero length packet to terminate a short data phase
+    Zlp,
+    /// Sending the command status wrapper
+    Status,
+}
+
+/// Block operation in progress
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Op {
+    None,
+    Read,
+    Write,
+}
+
+/// Device hook called on host cache sync or eject
+type DeviceHook<D> = fn(&mut D) -> Result<(), FileError>;
+
+/// Mode parameter header write protect bit
+const MODE_WP: u8 = 0x80;
+
+/// Transport and command state, independent of the USB endpoints
+struct Transport<D> {
+    device: D,
+    write_protect: Option<fn(&D) -> bool>,
+    flush: Option<DeviceHook<D>>,
+    eject: Option<DeviceHook<D>>,
+    changed: Option<fn(&mut D) -> bool>,
+    inquiry: [u8; 36],
+    state: State,
+    tag: u32,
+    lun: u8,
+    residue: u32,
+    failed: bool,
+    sense: Sense,
+    op: Op,
+    lba: u32,
+    lba_end: u32,
+    buff: [u8; BUFFER_BYTES],
+    len: usize,
+    pos: usize,
+}
+
+impl <D: LunDevice> Transport<D> {
+    fn new(device: D, vendor: &[u8], product: &[u8], revision: &[u8]) -> Self {
+        const { assert!(D::LUN_BLOCK_BYTES <= BUFFER_BYTES) };
+
+        Self {
+            device,
+            write_protect: None,
+            flush: None,
+            eject: None,
+            changed: None,
+            inquiry: inquiry_data(vendor, product, revision),
+            state: State::Command,
+            tag: 0,
+            lun: 0,
+            residue: 0,
+            failed: false,
+            sense: Sense::OK,
+            op: Op::None,
+            lba: 0,
+            lba_end: 0,
+            buff: [0u8; BUFFER_BYTES],
+            len: 0,
+            pos: 0,
+        }
+    }
+
+    /// Reset transport state, discarding any in-progress command
+    fn reset(&mut self) {
+        self.state = State::Command;
+        self.failed = false;
+        self.sense = Sense::OK;
+        self.op = Op::None;
+        self.len = 0;
+        self.pos = 0;
+    }
+
+    /// Fetch the buffer for the next OUT packet, if one is expected
+    fn out_buffer(&mut self) -> Option<&mut [u8]> {
+        match self.state {
+            State::Command => Some(&mut self.buff[..]),
+            State::DataOut => Some(&mut self.buff[self.len..D::LUN_BLOCK_BYTES]),
+            _ => None,
+        }
+    }
+
+    /// Handle an OUT packet of `n` bytes received into the [`Transport::out_buffer`]
+    fn received(&mut self, n: usize) {
+        match self.state {
+            State::Command => self.command(n),
+            State::DataOut => self.data_out(n),
+            _ => (),
+        }
+    }
+
+    /// Fetch the next IN packet to be sent, if any
+    fn transmit(&mut self, max_packet: usize) -> Option<&[u8]> {
+        match self.state {
+            State::DataIn => {
+                let n = (self.len - self.pos).min(max_packet).min(self.residue as usize);
+                Some(&self.buff[self.pos..][..n])
+            },
+            State::Zlp => Some(&[]),
+            State::Status => {
+                let b = &mut self.buff[..CSW_LEN];
+                b[0..4].copy_from_slice(&CSW_SIGNATURE.to_le_bytes());
+                b[4..8].copy_from_slice(&self.tag.to_le_bytes());
+                b[8..12].copy_from_slice(&self.residue.to_le_bytes());
+                b[12] = self.failed as u8;
+                Some(b)
+            },
+            _ => None,
+        }
+    }
+
+    /// Handle completion of an IN packet of `n` bytes
+    fn sent(&mut self, n: usize, max_packet: usize) {
+        match self.state {
+            State::DataIn => {
+                self.pos += n;
+                self.residue -= n as u32;
+
+                if self.residue == 0 {
+                    self.state = State::Status;
+                } else if self.pos == self.len {
+                    self.next_block(n == max_packet);
+                }
+            },
+            State::Zlp => self.state = State::Status,
+            State::Status => {
+                self.state = State::Command;
+                self.op = Op::None;
+            },
+            _ => (),
+        }
+    }
+
+    /// Parse and execute a command block wrapper
+    fn command(&mut self, n: usize) {
+        let b = &self.buff[..n];
+        if n != CBW_LEN || u32::from_le_bytes([b[0], b[1], b[2], b[3]]) != CBW_SIGNATURE {
+            crate::warn!("Invalid CBW ({} bytes)", n);
+            return;
+        }
+
+        self.tag = u32::from_le_bytes([b[4], b[5], b[6], b[7]]);
+        self.residue = u32::from_le_bytes([b[8], b[9], b[10], b[11]]);
+        self.lun = b[13] & 0x0F;
+        self.failed = false;
+
+        let mut cb = [0u8; 16];
+        cb.copy_from_slice(&b[15..31]);
+
+        crate::trace!("SCSI command: 0x{:02x}, tag: {}, len: {}", cb[0], self.tag, self.residue);
+
+        let dir_in = b[12] & 0x80 != 0;
+        if let Err(sense) = self.execute(&cb) {
+            crate::debug!("SCSI command 0x{:02x} failed (sense: {:02x} {:02x})", cb[0], sense.0, sense.1);
+            self.fail(sense);
+
+            self.op = Op::None;
+            self.len = 0;
+            self.pos = 0;
+            self.state = match self.residue {
+                0 => State::Status,
+                _ if dir_in => State::Zlp,
+                _ => State::DataOut,
+            };
+        }
+    }
+
+    /// Execute a SCSI command, setting up the data phase
+    fn execute(&mut self, cb: &[u8; 16]) -> Result<(), Sense> {
+        let be16 = |i: usize| u16::from_be_bytes([cb[i], cb[i + 1]]) as u32;
+        let be32 = |i: usize| u32::from_be_bytes([cb[i], cb[i + 1], cb[i + 2], cb[i + 3]]);
+        if !matches!(cb[0], op::INQUIRY | op::REQUEST_SENSE) && self.lun >= self.device.luns() {
+            return Err(Sense::INVALID_LUN);
+        }
+
+        // Report not ready (ie. uninitialised or updating) for retry by the host
+        if !matches!(cb[0], op::INQUIRY | op::REQUEST_SENSE) {
+            self.device.check_lun_ready(self.lun)?;
+        }
+        let blocks = self.device.max_lun_lba(self.lun) + 1;
+
+        // Report medium changes prior to executing commands
+        if !matches!(cb[0], op::INQUIRY | op::REQUEST_SENSE) && self.changed.is_some_and(|f| f(&mut self.device)) {
+            return Err(Sense::MEDIUM_CHANGED);
+        }
+
+        match cb[0] {
+            op::TEST_UNIT_READY | op::VERIFY_10 => self.data_in(&[]),
+            // Hosts sync caches and allow removal prior to ejecting
+            op::SYNCHRONIZE_CACHE_10 | op::PREVENT_ALLOW_MEDIUM_REMOVAL => {
+                if cb[0] == op::SYNCHRONIZE_CACHE_10 || cb[4] & 0x01 == 0 {
+                    Self::call(self.flush, &mut self.device)?;
+                }
+                self.data_in(&[]);
+            },
+            op::START_STOP_UNIT => {
+                // Load / eject with start cleared requests an eject
+                if cb[4] & 0x03 == 0x02 {
+                    Self::call(self.eject, &mut self.device)?;
+                }
+                self.data_in(&[]);
+            },
+            op::REQUEST_SENSE => {
+                let Sense(key, asc, ascq) = self.sense;
+                self.sense = Sense::OK;
+                self.data_in(&[0x70, 0, key, 0, 0, 0, 0, 10, 0, 0, 0, 0, asc, ascq, 0, 0, 0, 0]);
+            },
+            op::INQUIRY => {
+                let inquiry = self.inquiry;
+                let n = inquiry.len().min(be16(3) as usize);
+                self.data_in(&inquiry[..n]);
+            },
+            op::MODE_SENSE_6 => {
+                let wp = self.write_protected() as u8 * MODE_WP;
+                self.data_in(&[3, 0, wp, 0]);
+            },
+            op::MODE_SENSE_10 => {
+                let wp = self.write_protected() as u8 * MODE_WP;
+                self.data_in(&[0, 6, 0, wp, 0, 0, 0, 0]);
+            },
+            op::READ_FORMAT_CAPACITIES => {
+                let mut d = [0, 0, 0, 8, 0, 0, 0, 0, 0x02, 0, 0, 0];
+                d[4..8].copy_from_slice(&blocks.to_be_bytes());
+                d[9..12].copy_from_slice(&(D::LUN_BLOCK_BYTES as u32).to_be_bytes()[1..]);
+                self.data_in(&d);
+            },
+            op::READ_CAPACITY_10 => {
+                let mut d = [0u8; 8];
+                d[..4].copy_from_slice(&(blocks - 1).to_be_bytes());
+                d[4..].copy_from_slice(&(D::LUN_BLOCK_BYTES as u32).to_be_bytes());
+                self.data_in(&d);
+            },
+            op::READ_10 | op::WRITE_10 => {
+                let (lba, count) = (be32(2), be16(7));
+                if lba.checked_add(count).map(|end| end > blocks).unwrap_or(true) {
+                    return Err(Sense::LBA_OUT_OF_RANGE);
+                }
+
+                if cb[0] == op::WRITE_10 && self.write_protected() {
+                    return Err(Sense::WRITE_PROTECTED);
+                }
+
+                self.lba = lba;
+                self.lba_end = lba + count;
+                self.len = 0;
+                self.pos = 0;
+
+                if cb[0] == op::READ_10 {
+                    self.op = Op::Read;
+                    self.state = State::DataIn;
+                    self.next_block(true);
+                } else {
+                    self.op = Op::Write;
+                    self.state = match self.residue {
+                        0 => State::Status,
+                        _ => State::DataOut,
+                    };
+                }
+            },
+            _ => return Err(Sense::INVALID_OPCODE),
+        }
+
+        Ok(())
+    }
+
+    /// Call an optional device hook, mapping errors to sense data
+    fn call(f: Option<DeviceHook<D>>, device: &mut D) -> Result<(), Sense> {
+        match f.map(|f| f(device)) {
+            Some(Err(e)) => Err(BlockDeviceError::from(e).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check whether the device reports write protection
+    fn write_protected(&self) -> bool {
+        self.write_protect.map(|f| f(&self.device)).unwrap_or(false)
+    }
+
+    /// Begin a data-in phase with the provided response
+    fn data_in(&mut self, data: &[u8]) {
+        self.buff[..data.len()].copy_from_slice(data);
+        self.len = data.len();
+        self.pos = 0;
+
+        self.state = if self.residue == 0 {
+            State::Status
+        } else if self.len == 0 {
+            State::Zlp
+        } else {
+            State::DataIn
+        };
+    }
+
+    /// Load the next block for a read, or end the data-in phase
+    fn next_block(&mut self, full: bool) {
+        if self.op == Op::Read && self.lba < self.lba_end && !self.failed {
+            self.len = 0;
+            self.pos = 0;
+
+            match self.device.read_lun(self.lun, self.lba, &mut self.buff[..D::LUN_BLOCK_BYTES]) {
+                Ok(_) => {
+                    self.len = D::LUN_BLOCK_BYTES;
+                    self.lba += 1;
+                    return;
+                },
+                Err(e) => self.fail(e.into()),
+            }
+        }
+
+        // Short transfers are terminated with a zero length packet
+        // where the last packet sent was full
+        self.state = match self.residue {
+            0 => State::Status,
+            _ if full => State::Zlp,
+            _ => State::Status,
+        };
+    }
+
+    /// Handle data received from the host
+    fn data_out(&mut self, n: usize) {
+        self.len += n;
+        self.residue = self.residue.saturating_sub(n as u32);
+
+        if self.len == D::LUN_BLOCK_BYTES || self.residue == 0 {
+            // Write complete blocks, discarding data following a failure
+            if self.op == Op::Write && self.lba < self.lba_end && !self.failed && self.len == D::LUN_BLOCK_BYTES {
+                match self.device.write_lun(self.lun, self.lba, &self.buff[..D::LUN_BLOCK_BYTES]) {
+                    Ok(_) => self.lba += 1,
+                    Err(e) => self.fail(e.into()),
+                }
+            }
+            self.len = 0;
+        }
+
+        if self.residue == 0 {
+            self.state = State::Status;
+        }
+    }
+
+    /// Mark the current command as failed
+    fn fail(&mut self, sense: Sense) {
+        self.failed = true;
+        self.sense = sense;
+    }
+}
+
+/// SCSI Bulk-Only Transport mass storage class for `usb-device` 0.3,
+/// replacing `usbd_scsi::Scsi`
+pub struct Scsi<'a, B: UsbBus, D: LunDevice> {
+    interface: InterfaceNumber,
+    read_ep: EndpointOut<'a, B>,
+    write_ep: EndpointIn<'a, B>,
+    inner: Transport<D>,
+}
+
+impl <'a, B: UsbBus, D: LunDevice> Scsi<'a, B, D> {
+    /// Create a new SCSI mass storage class over the provided block device,
+    /// or a [`LunMux`](crate::LunMux) presenting multiple logical units
+    ///
+    /// `vendor`, `product` and `revision` form the SCSI inquiry response,
+    /// and are truncated to 8, 16 and 4 bytes respectively. See
+    /// [`Scsi::with_inquiry`] to use the identity from a [`Config`](crate::Config).
+    pub fn new<V: AsRef<[u8]>, P: AsRef<[u8]>, R: AsRef<[u8]>>(
+        alloc: &'a UsbBusAllocator<B>,
+        max_packet_size: u16,
+        block_device: D,
+        vendor: V,
+        product: P,
+        revision: R,
+    ) -> Self {
+        Self {
+            interface: alloc.interface(),
+            read_ep: alloc.bulk(max_packet_size),
+            write_ep: alloc.bulk(max_packet_size),
+            inner: Transport::new(block_device, vendor.as_ref(), product.as_ref(), revision.as_ref()),
+        }
+    }
+
+    /// Replace the SCSI inquiry response, ie. with [`Config::inquiry`](crate::Config::inquiry)
+    /// so the device identity is configured alongside the volume
+    pub fn with_inquiry(mut self, inquiry: [u8; 36]) -> Self {
+        self.inner.inquiry = inquiry;
+        self
+    }
+
+    /// Report write protection to the host via the provided function,
+    /// setting the write protect bit in MODE SENSE responses and failing
+    /// writes with a DATA PROTECT sense where it returns true.
+    ///
+    /// For [`GhostFat`](crate::GhostFat) devices use
+    /// [`GhostFat::is_write_protected`](crate::GhostFat::is_write_protected).
+    pub fn with_write_protect(mut self, f: fn(&D) -> bool) -> Self {
+        self.inner.write_protect = Some(f);
+        self
+    }
+
+    /// Call the provided function when the host syncs caches or allows
+    /// medium removal (SYNCHRONIZE CACHE / PREVENT ALLOW MEDIUM REMOVAL),
+    /// ie. [`GhostFat::flush`](crate::GhostFat::flush)
+    pub fn with_flush(mut self, f: fn(&mut D) -> Result<(), FileError>) -> Self {
+        self.inner.flush = Some(f);
+        self
+    }
+
+    /// Call the provided function when the host ejects the medium
+    /// (START STOP UNIT), ie. [`GhostFat::eject`](crate::GhostFat::eject)
+    pub fn with_eject(mut self, f: fn(&mut D) -> Result<(), FileError>) -> Self {
+        self.inner.eject = Some(f);
+        self
+    }
+
+    /// Report medium changes to the host where the provided function returns
+    /// true, failing the next command with UNIT ATTENTION so the host
+    /// re-reads the volume, ie. [`GhostFat::take_changed`](crate::GhostFat::take_changed)
+    pub fn with_media_change(mut self, f: fn(&mut D) -> bool) -> Self {
+        self.inner.changed = Some(f);
+        self
+    }
+
+    /// Fetch a reference to the underlying block device
+    pub fn block_device(&self) -> &D {
+        &self.inner.device
+    }
+
+    /// Fetch a mutable reference to the underlying block device
+    pub fn block_device_mut(&mut self) -> &mut D {
+        &mut self.inner.device
+    }
+}
+
+impl <'a, B: UsbBus, D: LunDevice> UsbClass<B> for Scsi<'a, B, D> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> UsbResult<()> {
+        writer.interface(self.interface, CLASS_MASS_STORAGE, SUBCLASS_SCSI, PROTOCOL_BULK_ONLY)?;
+        writer.endpoint(&self.read_ep)?;
+        writer.endpoint(&self.write_ep)?;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = xfer.request();
+        if req.request_type == RequestType::Class && req.recipient == Recipient::Interface
+                && req.index == u8::from(self.interface) as u16 && req.request == REQ_GET_MAX_LUN {
+            let _ = xfer.accept_with(&[self.inner.device.luns() - 1]);
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = xfer.request();
+        if req.request_type == RequestType::Class && req.recipient == Recipient::Interface
+                && req.index == u8::from(self.interface) as u16 && req.request == REQ_BULK_ONLY_RESET {
+            self.inner.reset();
+            let _ = xfer.accept();
+        }
+    }
+
+    fn poll(&mut self) {
+        // Receive commands or data from the host
+        if let Some(buff) = self.inner.out_buffer() {
+            match self.read_ep.read(buff) {
+                Ok(n) => self.inner.received(n),
+                Err(UsbError::WouldBlock) => (),
+                Err(_e) => crate::error!("SCSI endpoint read failed"),
+            }
+        }
+
+        // Send responses and status to the host
+        let max_packet = self.write_ep.max_packet_size() as usize;
+        while let Some(data) = self.inner.transmit(max_packet) {
+            match self.write_ep.write(data) {
+                Ok(n) => self.inner.sent(n, max_packet),
+                Err(UsbError::WouldBlock) => break,
+                Err(_e) => {
+                    crate::error!("SCSI endpoint write failed");
+                    break;
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BlockDevice, Config, File, GhostFat, LunMux};
+    use super::*;
+
+    fn cbw<D: LunDevice>(t: &mut Transport<D>, tag: u32, len: u32, dir_in: bool, cb: &[u8]) {
+        cbw_lun(t, 0, tag, len, dir_in, cb);
+    }
+
+    fn cbw_lun<D: LunDevice>(t: &mut Transport<D>, lun: u8, tag: u32, len: u32, dir_in: bool, cb: &[u8]) {
+        let b = t.out_buffer().unwrap();
+        b[..31].fill(0);
+        b[0..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+        b[4..8].copy_from_slice(&tag.to_le_bytes());
+        b[8..12].copy_from_slice(&len.to_le_bytes());
+        b[12] = if dir_in { 0x80 } else { 0x00 };
+        b[13] = lun;
+        b[14] = cb.len() as u8;
+        b[15..][..cb.len()].copy_from_slice(cb);
+        t.received(31);
+    }
+
+    fn data_in<D: LunDevice>(t: &mut Transport<D>) -> ([u8; 1024], usize, [u8; 13]) {
+        let (mut d, mut n) = ([0u8; 1024], 0);
+        while t.state != State::Status {
+            let p = t.transmit(64).unwrap();
+            let l = p.len();
+            d[n..][..l].copy_from_slice(p);
+            n += l;
+            t.sent(l, 64);
+        }
+
+        let mut csw = [0u8; 13];
+        csw.copy_from_slice(t.transmit(64).unwrap());
+        t.sent(13, 64);
+        assert_eq!(t.state, State::Command);
+
+        (d, n, csw)
+    }
+
+    #[test]
+    fn bulk_only_commands() {
+        let data = [0xAAu8; 600];
+        let mut f = [File::<512>::new_ro("DATA.BIN", &data)];
+        let fs = GhostFat::new(&mut f, Config::default());
+        let max_lba = fs.max_lba();
+        let start = fs.config.start_clusters().0;
+
+        let mut t = Transport::new(fs, b"GhostFAT", b"Test", b"1.0");
+
+        // Inquiry
+        cbw(&mut t, 1, 36, true, &[op::INQUIRY, 0, 0, 0, 36, 0]);
+        let (d, n, csw) = data_in(&mut t);
+        assert_eq!(n, 36);
+        assert_eq!(&d[8..20], b"GhostFATTest");
+        assert_eq!(&csw[4..], &[1, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        // Capacity
+        cbw(&mut t, 2, 8, true, &[op::READ_CAPACITY_10]);
+        let (d, _n, _csw) = data_in(&mut t);
+        assert_eq!(&d[..8], &[&max_lba.to_be_bytes()[..], &[0, 0, 2, 0]].concat());
+
+        // Multi-block read
+        let lba = start.to_be_bytes();
+        cbw(&mut t, 3, 1024, true, &[op::READ_10, 0, lba[0], lba[1], lba[2], lba[3], 0, 0, 2]);
+        let (d, n, csw) = data_in(&mut t);
+        assert_eq!(n, 1024);
+        assert_eq!(&d[..600], &data);
+        assert_eq!(csw[12], 0);
+
+        // Write to read-only file fails
+        cbw(&mut t, 4, 512, false, &[op::WRITE_10, 0, lba[0], lba[1], lba[2], lba[3], 0, 0, 1]);
+        for _ in 0..8 {
+            assert_eq!(t.out_buffer().unwrap().len() % 64, 0);
+            t.received(64);
+        }
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 1);
+
+        // Unsupported commands fail with a zero length data phase
+        cbw(&mut t, 5, 16, true, &[0xFF]);
+        assert_eq!(t.transmit(64), Some(&[][..]));
+        let (_d, n, csw) = data_in(&mut t);
+        assert_eq!((n, &csw[8..]), (0, &[16, 0, 0, 0, 1][..]));
+
+        // And report sense
+        cbw(&mut t, 6, 18, true, &[op::REQUEST_SENSE, 0, 0, 0, 18, 0]);
+        let (d, _n, _csw) = data_in(&mut t);
+        assert_eq!((d[2], d[12]), (0x05, 0x20));
+    }
+
+    #[test]
+    fn multiple_luns() {
+        let (a, b) = ([0xAAu8; 8], [0x55u8; 8]);
+        let (mut fa, mut fb) = ([File::<512>::new_ro("A.TXT", &a)], [File::<512>::new_ro("B.TXT", &b)]);
+        let mut va = GhostFat::new(&mut fa, Config::default());
+
+        let mut config = Config::default();
+        config.num_blocks = 16000;
+        let mut vb = GhostFat::new(&mut fb, config);
+        let start = vb.config.start_clusters().0;
+
+        let mut t = Transport::new(LunMux::<2>::new([&mut va, &mut vb]), b"GhostFAT", b"Test", b"1.0");
+        assert_eq!(t.device.luns(), 2);
+
+        // Capacity is reported per LUN
+        cbw_lun(&mut t, 1, 1, 8, true, &[op::READ_CAPACITY_10]);
+        let (d, _n, _csw) = data_in(&mut t);
+        assert_eq!(&d[..4], &15999u32.to_be_bytes());
+
+        // Reads are routed by LUN
+        let lba = start.to_be_bytes();
+        cbw_lun(&mut t, 1, 2, 512, true, &[op::READ_10, 0, lba[0], lba[1], lba[2], lba[3], 0, 0, 1]);
+        let (d, n, csw) = data_in(&mut t);
+        assert_eq!((n, csw[12]), (512, 0));
+        assert_eq!(&d[..8], &b);
+
+        // Unknown LUNs fail with an invalid LUN sense
+        cbw_lun(&mut t, 2, 3, 0, false, &[op::TEST_UNIT_READY]);
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 1);
+
+        cbw(&mut t, 4, 18, true, &[op::REQUEST_SENSE, 0, 0, 0, 18, 0]);
+        let (d, _n, _csw) = data_in(&mut t);
+        assert_eq!((d[2], d[12]), (0x05, 0x25));
+    }
+
+    #[test]
+    fn write_protect() {
+        let data = [0xAAu8; 8];
+        let mut f = [File::<512>::new_ro("DATA.BIN", &data)];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        fs.set_write_protected(true);
+        let start = fs.config.start_clusters().0;
+
+        let mut t = Transport::new(fs, b"GhostFAT", b"Test", b"1.0");
+        t.write_protect = Some(GhostFat::is_write_protected);
+
+        // Mode sense reports write protection
+        cbw(&mut t, 1, 4, true, &[op::MODE_SENSE_6, 0, 0x3F, 0, 4, 0]);
+        let (d, _n, _csw) = data_in(&mut t);
+        assert_eq!(d[2], MODE_WP);
+
+        // Writes fail with a data protect sense
+        let lba = start.to_be_bytes();
+        cbw(&mut t, 2, 512, false, &[op::WRITE_10, 0, lba[0], lba[1], lba[2], lba[3], 0, 0, 1]);
+        for _ in 0..8 {
+            t.received(64);
+        }
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 1);
+
+        cbw(&mut t, 3, 18, true, &[op::REQUEST_SENSE, 0, 0, 0, 18, 0]);
+        let (d, _n, _csw) = data_in(&mut t);
+        assert_eq!((d[2], d[12]), (0x07, 0x27));
+
+        // Including direct block device writes
+        assert_eq!(t.device.write_block(start, &[0u8; 512]), Err(BlockDeviceError::WriteError));
+    }
+
+    #[test]
+    fn eject_hooks() {
+        let mut data = [0u8; 8];
+        let mut f = [File::<512>::new("DATA.BIN", &mut data).unwrap()];
+        let fs = GhostFat::new(&mut f, Config::default());
+
+        let mut t = Transport::new(fs, b"GhostFAT", b"Test", b"1.0");
+        t.flush = Some(GhostFat::flush);
+        t.eject = Some(|_fs| Err(FileError::WriteError));
+
+        // Allowing removal flushes
+        cbw(&mut t, 1, 0, false, &[op::PREVENT_ALLOW_MEDIUM_REMOVAL, 0, 0, 0, 0, 0]);
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 0);
+
+        // Eject failures are reported
+        cbw(&mut t, 2, 0, false, &[op::START_STOP_UNIT, 0, 0, 0, 0x02, 0]);
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 1);
+
+        // Starting the unit does not eject
+        cbw(&mut t, 3, 0, false, &[op::START_STOP_UNIT, 0, 0, 0, 0x01, 0]);
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 0);
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[test]
+    fn not_ready() {
+        use crate::SharedGhostFat;
+
+        static DATA: [u8; 8] = [0xAA; 8];
+        static FS: SharedGhostFat<'static, 512, 1> = SharedGhostFat::empty();
+
+        let mut t = Transport::new(&FS, b"GhostFAT", b"Test", b"1.0");
+
+        // Uninitialised file systems report not ready
+        cbw(&mut t, 1, 0, false, &[op::TEST_UNIT_READY]);
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 1);
+
+        cbw(&mut t, 2, 18, true, &[op::REQUEST_SENSE, 0, 0, 0, 18, 0]);
+        let (d, _n, _csw) = data_in(&mut t);
+        assert_eq!((d[2], d[12], d[13]), (0x02, 0x04, 0x01));
+
+        cbw(&mut t, 3, 8, true, &[op::READ_CAPACITY_10]);
+        let (_d, n, csw) = data_in(&mut t);
+        assert_eq!((n, csw[12]), (0, 1));
+
+        // And ready once initialised
+        FS.init(GhostFat::new_array([File::new_ro("DATA.BIN", &DATA)], Config::default()));
+        cbw(&mut t, 4, 0, false, &[op::TEST_UNIT_READY]);
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 0);
+
+        cbw(&mut t, 5, 8, true, &[op::READ_CAPACITY_10]);
+        let (d, _n, _csw) = data_in(&mut t);
+        assert_eq!(&d[..4], &7999u32.to_be_bytes());
+    }
+
+    #[test]
+    fn media_change() {
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("DATA.BIN", &data)];
+        let fs = GhostFat::new(&mut f, Config::default());
+
+        let mut t = Transport::new(fs, b"GhostFAT", b"Test", b"1.0");
+        t.changed = Some(GhostFat::take_changed);
+
+        cbw(&mut t, 1, 0, false, &[op::TEST_UNIT_READY]);
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 0);
+
+        // Changes are reported once as a unit attention
+        t.device.mark_changed();
+        cbw(&mut t, 2, 0, false, &[op::TEST_UNIT_READY]);
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 1);
+
+        cbw(&mut t, 3, 18, true, &[op::REQUEST_SENSE, 0, 0, 0, 18, 0]);
+        let (d, _n, _csw) = data_in(&mut t);
+        assert_eq!((d[2], d[12]), (0x06, 0x28));
+
+        cbw(&mut t, 4, 0, false, &[op::TEST_UNIT_READY]);
+        let (_d, _n, csw) = data_in(&mut t);
+        assert_eq!(csw[12], 0);
+    }
+}
@@ -0,0 +1,140 @@
+use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+
+use crate::{DynamicFile, FileError};
+
+/// Passthrough file exposing an attached SD card (or any other
+/// [`embedded_sdmmc::BlockDevice`]), so data loggers can offload bulk
+/// storage while keeping virtual file system metadata.
+///
+/// Files reference a shared device, allowing the whole card to be exposed
+/// as a single image (ie. `SDCARD.IMG`, see [`SdCardFile::image`]) or
+/// separate regions of the card to back a set of files (see
+/// [`SdCardFile::new`]).
+///
+/// SD card drivers commonly use a `RefCell` internally, so must be wrapped
+/// in a `Sync` type (ie. a critical section mutex) for use as a file.
+pub struct SdCardFile<'a, D> {
+    device: &'a D,
+    start: u32,
+    blocks: u32,
+}
+
+impl <'a, D: BlockDevice> SdCardFile<'a, D> {
+    /// Create a new file over `blocks` blocks of the device from block `start`
+    pub const fn new(device: &'a D, start: u32, blocks: u32) -> Self {
+        Self { device, start, blocks }
+    }
+
+    /// Create a new file exposing the whole device
+    pub fn image(device: &'a D) -> Result<Self, FileError> {
+        let BlockCount(blocks) = device.num_blocks()
+            .map_err(|_| FileError::ReadError)?;
+
+        Ok(Self::new(device, 0, blocks))
+    }
+
+    /// Fetch the first block and block count of the exposed region
+    pub fn region(&self) -> (u32, u32) {
+        (self.start, self.blocks)
+    }
+
+    /// Map a chunk index to a device block, returning `None` outside the region
+    fn block(&self, chunk_index: usize) -> Option<BlockIdx> {
+        match chunk_index < self.blocks as usize {
+            true => Some(BlockIdx(self.start + chunk_index as u32)),
+            false => None,
+        }
+    }
+}
+
+impl <'a, D: BlockDevice + Sync + Send, const BLOCK_SIZE: usize> DynamicFile<BLOCK_SIZE> for SdCardFile<'a, D> {
+    fn len(&self) -> usize {
+        self.blocks as usize * Block::LEN
+    }
+
+    fn read_chunk(&self, chunk_index: usize, buff: &mut [u8]) -> Result<usize, FileError> {
+        const { assert!(BLOCK_SIZE == Block::LEN, "SD card files require 512 byte blocks") };
+
+        let idx = match self.block(chunk_index) {
+            Some(i) => i,
+            None => return Ok(0),
+        };
+
+        let mut b = [Block::new()];
+        self.device.read(&mut b, idx, "ghostfat")
+            .map_err(|_| FileError::ReadError)?;
+
+        let n = usize::min(buff.len(), Block::LEN);
+        buff[..n].copy_from_slice(&b[0].contents[..n]);
+        Ok(n)
+    }
+
+    fn write_chunk(&mut self, chunk_index: usize, data: &[u8]) -> Result<usize, FileError> {
+        let idx = match self.block(chunk_index) {
+            Some(i) => i,
+            None => return Ok(0),
+        };
+
+        // Devices only support whole block writes
+        if data.len() < Block::LEN {
+            return Err(FileError::WriteError);
+        }
+
+        let mut b = [Block::new()];
+        b[0].contents.copy_from_slice(&data[..Block::LEN]);
+        self.device.write(&b, idx)
+            .map_err(|_| FileError::WriteError)?;
+
+        Ok(Block::LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// RAM card
+    struct Card(Mutex<[u8; 4 * 512]>);
+
+    impl BlockDevice for Card {
+        type Error = ();
+
+        fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx, _reason: &str) -> Result<(), Self::Error> {
+            let d = self.0.lock().unwrap();
+            for (i, b) in blocks.iter_mut().enumerate() {
+                b.contents.copy_from_slice(&d[(start_block_idx.0 as usize + i) * 512..][..512]);
+            }
+            Ok(())
+        }
+
+        fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+            let mut d = self.0.lock().unwrap();
+            for (i, b) in blocks.iter().enumerate() {
+                d[(start_block_idx.0 as usize + i) * 512..][..512].copy_from_slice(&b.contents);
+            }
+            Ok(())
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+            Ok(BlockCount(4))
+        }
+    }
+
+    #[test]
+    fn card_regions() {
+        let card = Card(Mutex::new([0; 4 * 512]));
+        let img = SdCardFile::image(&card).unwrap();
+        assert_eq!(DynamicFile::<512>::len(&img), 2048);
+
+        // Regions share the device
+        let mut log = SdCardFile::new(&card, 2, 2);
+        assert_eq!(DynamicFile::<512>::write_chunk(&mut log, 1, &[0xAA; 512]), Ok(512));
+        assert_eq!(DynamicFile::<512>::write_chunk(&mut log, 2, &[0xAA; 512]), Ok(0));
+
+        let mut buff = [0u8; 512];
+        assert_eq!(DynamicFile::<512>::read_chunk(&img, 3, &mut buff), Ok(512));
+        assert_eq!(buff, [0xAA; 512]);
+    }
+}
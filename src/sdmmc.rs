@@ -0,0 +1,108 @@
+use core::cell::RefCell;
+
+use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+
+use crate::{BlockDeviceError, GhostFat};
+
+/// MBR partition table entry offset
+const MBR_PARTITION: usize = 446;
+
+/// FAT16 (LBA) partition type
+const PARTITION_FAT16_LBA: u8 = 0x0E;
+
+/// [`embedded_sdmmc::BlockDevice`] adapter for [`GhostFat`], allowing firmware
+/// to mount its own virtual volume via `embedded_sdmmc::VolumeManager`
+/// (ie. for self-tests), see [`GhostFat::sdmmc`].
+///
+/// `embedded-sdmmc` only supports partitioned devices, so the volume is
+/// presented as a single FAT16 partition following a generated MBR at
+/// block 0, with the file system starting at block 1.
+pub struct SdmmcVolume<'f, 'a, const FILES: usize = 0> {
+    fs: RefCell<&'f mut GhostFat<'a, 512, FILES>>,
+}
+
+impl <'a, const FILES: usize> GhostFat<'a, 512, FILES> {
+    /// Borrow the file system as an [`embedded_sdmmc::BlockDevice`]
+    pub fn sdmmc(&mut self) -> SdmmcVolume<'_, 'a, FILES> {
+        SdmmcVolume { fs: RefCell::new(self) }
+    }
+}
+
+impl <'f, 'a, const FILES: usize> SdmmcVolume<'f, 'a, FILES> {
+    /// Generate the MBR describing the file system partition
+    fn mbr(&self, block: &mut [u8]) {
+        let num_blocks = self.fs.borrow().config.num_blocks;
+
+        block.fill(0);
+
+        let p = &mut block[MBR_PARTITION..][..16];
+        p[4] = PARTITION_FAT16_LBA;
+        p[8..12].copy_from_slice(&1u32.to_le_bytes());
+        p[12..16].copy_from_slice(&num_blocks.to_le_bytes());
+
+        block[510] = 0x55;
+        block[511] = 0xAA;
+    }
+}
+
+impl <'f, 'a, const FILES: usize> BlockDevice for SdmmcVolume<'f, 'a, FILES> {
+    type Error = BlockDeviceError;
+
+    fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx, _reason: &str) -> Result<(), Self::Error> {
+        for (i, b) in blocks.iter_mut().enumerate() {
+            match start_block_idx.0 + i as u32 {
+                0 => self.mbr(&mut b.contents),
+                lba => crate::BlockDevice::read_block(&**self.fs.borrow(), lba - 1, &mut b.contents)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        for (i, b) in blocks.iter().enumerate() {
+            match start_block_idx.0 + i as u32 {
+                0 => return Err(BlockDeviceError::WriteError),
+                lba => crate::BlockDevice::write_block(&mut **self.fs.borrow_mut(), lba - 1, &b.contents)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        Ok(BlockCount(self.fs.borrow().config.num_blocks + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_sdmmc::{Mode, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+
+    use crate::{Config, File};
+    use super::*;
+
+    struct Clock;
+
+    impl TimeSource for Clock {
+        fn get_timestamp(&self) -> Timestamp {
+            Timestamp::from_calendar(2024, 1, 1, 0, 0, 0).unwrap()
+        }
+    }
+
+    #[test]
+    fn sdmmc_mount() {
+        let data = *b"Hello from ghostfat";
+        let mut f = [File::<512>::new_ro("HELLO.TXT", &data)];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+
+        let mut mgr: VolumeManager<_, _> = VolumeManager::new(fs.sdmmc(), Clock);
+        let mut volume = mgr.open_volume(VolumeIdx(0)).unwrap();
+        let mut root = volume.open_root_dir().unwrap();
+        let mut file = root.open_file_in_dir("HELLO.TXT", Mode::ReadOnly).unwrap();
+
+        let mut buff = [0u8; 64];
+        let n = file.read(&mut buff).unwrap();
+        assert_eq!(&buff[..n], &data);
+    }
+}
@@ -0,0 +1,84 @@
+use core::cell::Cell;
+
+/// Host session watchdog, tracking ticks since the last host access
+pub(crate) struct Watchdog {
+    timeout: Option<u32>,
+    idle: Cell<u32>,
+    active: Cell<bool>,
+}
+
+impl Watchdog {
+    /// Create a new watchdog with an optional timeout in ticks
+    pub fn new(timeout: Option<u32>) -> Self {
+        Self { timeout, idle: Cell::new(0), active: Cell::new(false) }
+    }
+
+    /// Record a host access, (re)starting the session
+    pub fn access(&self) {
+        self.idle.set(0);
+        self.active.set(true);
+    }
+
+    /// Advance the watchdog, returning true when an active session has timed out
+    pub fn tick(&self) -> bool {
+        if !self.active.get() {
+            return false;
+        }
+
+        let idle = self.idle.get().saturating_add(1);
+        self.idle.set(idle);
+
+        match self.timeout {
+            Some(t) if idle >= t => {
+                self.active.set(false);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Check whether a host session is active
+    pub fn active(&self) -> bool {
+        self.active.get()
+    }
+
+    /// Fetch the number of ticks since the last host access
+    pub fn idle(&self) -> u32 {
+        self.idle.get()
+    }
+
+    /// Reset the watchdog, ending any active session
+    pub fn reset(&self) {
+        self.idle.set(0);
+        self.active.set(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Watchdog;
+
+    #[test]
+    fn watchdog_timeout() {
+        let w = Watchdog::new(Some(3));
+
+        // No timeout without an active session
+        for _ in 0..5 {
+            assert!(!w.tick());
+        }
+
+        // Accesses hold the session open
+        w.access();
+        assert!(!w.tick());
+        assert!(!w.tick());
+        w.access();
+        assert!(!w.tick());
+        assert!(!w.tick());
+        assert!(w.active());
+
+        // Timeout fires once
+        assert!(w.tick());
+        assert!(!w.active());
+        assert!(!w.tick());
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{GhostFat, SectorIndex};
+
+/// Size of the sector index stored for each shadowed sector
+const INDEX_SIZE: usize = 4;
+
+/// Sector index marking an unused slot
+const UNUSED: u32 = u32::MAX;
+
+/// RAM shadow of host-written sectors, served in place of generated
+/// sectors until cleared (ie. on remount)
+///
+/// The buffer holds `BLOCK_SIZE` sector slots followed by the index of
+/// the sector stored in each slot, so the shadow itself stores nothing
+/// beyond the buffer reference.
+pub(crate) struct Shadow<'a, const BLOCK_SIZE: usize> {
+    buff: &'a mut [u8],
+}
+
+impl <'a, const BLOCK_SIZE: usize> Shadow<'a, BLOCK_SIZE> {
+    /// Create a new shadow over the provided buffer
+    pub fn new(buff: &'a mut [u8]) -> Self {
+        let mut s = Self { buff };
+        s.clear();
+        s
+    }
+
+    /// Number of sector slots available in the buffer
+    fn slots(&self) -> usize {
+        self.buff.len() / (BLOCK_SIZE + INDEX_SIZE)
+    }
+
+    /// Fetch the index entry for a slot
+    fn entry(&self, slot: usize) -> &[u8] {
+        &self.buff[self.slots() * BLOCK_SIZE + slot * INDEX_SIZE..][..INDEX_SIZE]
+    }
+
+    /// Set the index entry for a slot
+    fn set_entry(&mut self, slot: usize, index: u32) {
+        let offset = self.slots() * BLOCK_SIZE + slot * INDEX_SIZE;
+        self.buff[offset..][..INDEX_SIZE].copy_from_slice(&index.to_le_bytes());
+    }
+
+    /// Find the slot storing a sector (or an unused slot for [`UNUSED`])
+    fn position(&self, index: u32) -> Option<usize> {
+        (0..self.slots()).find(|i| self.entry(*i) == index.to_le_bytes())
+    }
+
+    /// Discard shadowed sectors
+    pub fn clear(&mut self) {
+        let slots = self.slots();
+        self.buff[slots * BLOCK_SIZE..][..slots * INDEX_SIZE].fill(0xFF);
+    }
+
+    /// Discard a shadowed sector, ie. where the host restores generated content
+    pub fn remove(&mut self, index: SectorIndex) {
+        if let Some(i) = self.position(index.0) {
+            self.set_entry(i, UNUSED);
+        }
+    }
+
+    /// Check whether a sector is shadowed
+    pub fn contains(&self, index: SectorIndex) -> bool {
+        self.position(index.0).is_some()
+    }
+
+    /// Read a shadowed sector, returning false if the sector is not shadowed
+    pub fn read(&self, index: SectorIndex, block: &mut [u8]) -> bool {
+        match self.position(index.0) {
+            Some(i) => {
+                block[..BLOCK_SIZE].copy_from_slice(&self.buff[i * BLOCK_SIZE..][..BLOCK_SIZE]);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Store a host-written sector, returning false if the shadow is full
+    pub fn write(&mut self, index: SectorIndex, block: &[u8]) -> bool {
+        let i = match self.position(index.0).or_else(|| self.position(UNUSED)) {
+            Some(i) => i,
+            None => return false,
+        };
+
+        self.buff[i * BLOCK_SIZE..][..BLOCK_SIZE].copy_from_slice(&block[..BLOCK_SIZE]);
+        self.set_entry(i, index.0);
+
+        true
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Attach a root directory shadow buffer, storing host writes to root
+    /// directory sectors (ie. timestamp, attribute and size updates) and
+    /// serving them back on reads so the host view remains self-consistent.
+    ///
+    /// The buffer is split into `BLOCK_SIZE + 4` byte slots, each storing a
+    /// sector and its index, with host writes to further sectors discarded
+    /// once full. Shadowed sectors take precedence
+    /// over generated entries until the next [`GhostFat::remount`].
+    pub fn with_dir_shadow(mut self, buff: &'a mut [u8]) -> Self {
+        self.dir_shadow = Some(Shadow::new(buff));
+        self
+    }
+
+    /// Attach a FAT shadow buffer, storing host-modified FAT sectors (ie.
+    /// cluster allocations for new files) layered over the generated FAT,
+    /// so host allocations remain visible when the FAT is read back.
+    ///
+    /// Writes to either FAT copy update the same shadowed sector. The buffer
+    /// is split into `BLOCK_SIZE + 4` byte slots, each storing a sector and
+    /// its index, only sectors modified by the host are stored, and shadowed
+    /// sectors are discarded on [`GhostFat::remount`].
+    pub fn with_fat_shadow(mut self, buff: &'a mut [u8]) -> Self {
+        self.fat_shadow = Some(Shadow::new(buff));
+        self
+    }
+
+    /// Attach a scratch region, storing host writes to clusters outside
+    /// device files and serving them back on reads.
+    ///
+    /// Hosts create metadata files and directories on mount (ie. `.Trashes`,
+    /// `.fseventsd` or `System Volume Information`) and expect to read them
+    /// back, so these land harmlessly in RAM rather than reading as zeros.
+    /// The buffer is split into `BLOCK_SIZE + 4` byte slots, each storing a
+    /// cluster and its index, with further writes discarded with success
+    /// once full, and is cleared on [`GhostFat::remount`]. The scratch
+    /// region is used with the default
+    /// [`UnmappedWrites::Scratch`](crate::UnmappedWrites::Scratch) policy.
+    pub fn with_scratch(mut self, buff: &'a mut [u8]) -> Self {
+        self.scratch = Some(Shadow::new(buff));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, File};
+    use super::*;
+
+    #[test]
+    fn dir_shadow() {
+        let mut buff = [0u8; 516];
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("INFO.TXT", &data)];
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_dir_shadow(&mut buff);
+        let rootdir = fs.config.start_rootdir().0;
+
+        let mut generated = [0u8; 512];
+        fs.read_block(rootdir, &mut generated).unwrap();
+
+        // Host updates the file timestamp
+        let mut block = generated;
+        block[54..56].copy_from_slice(&0x1234u16.to_le_bytes());
+        fs.write_block(rootdir, &block).unwrap();
+
+        let mut read = [0u8; 512];
+        fs.read_block(rootdir, &mut read).unwrap();
+        assert_eq!(read, block);
+
+        // Further sectors are discarded once full
+        fs.write_block(rootdir + 1, &block).unwrap();
+        fs.read_block(rootdir + 1, &mut read).unwrap();
+        assert_eq!(read, [0u8; 512]);
+
+        // Remounting restores generated entries
+        fs.remount();
+        fs.read_block(rootdir, &mut read).unwrap();
+        assert_eq!(read, generated);
+
+        // Sector indices are stored in the buffer
+        assert_eq!(core::mem::size_of::<Option<Shadow<512>>>(), core::mem::size_of::<&mut [u8]>());
+    }
+
+    #[test]
+    fn fat_shadow() {
+        let mut buff = [0u8; 516];
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("INFO.TXT", &data)];
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_fat_shadow(&mut buff);
+        let (fat0, fat1) = (fs.config.start_fat0().0, fs.config.start_fat1().0);
+
+        let mut generated = [0u8; 512];
+        fs.read_block(fat0, &mut generated).unwrap();
+
+        // Host allocates a cluster chain via the second FAT copy
+        let mut block = generated;
+        block[20..24].copy_from_slice(&[0x0B, 0x00, 0xFF, 0xFF]);
+        fs.write_block(fat1, &block).unwrap();
+
+        let mut read = [0u8; 512];
+        fs.read_block(fat0, &mut read).unwrap();
+        assert_eq!(read, block);
+
+        // Unmodified sectors are generated
+        fs.read_block(fat0 + 1, &mut read).unwrap();
+        assert_eq!(read, [0u8; 512]);
+
+        // Rewrites update the shadowed sector once full, further sectors are discarded
+        block[24..28].copy_from_slice(&[0x0D, 0x00, 0xFF, 0xFF]);
+        fs.write_block(fat0, &block).unwrap();
+        fs.write_block(fat0 + 1, &[0x11; 512]).unwrap();
+
+        fs.read_block(fat1, &mut read).unwrap();
+        assert_eq!(read, block);
+        fs.read_block(fat0 + 1, &mut read).unwrap();
+        assert_eq!(read, [0u8; 512]);
+
+        fs.remount();
+        fs.read_block(fat1, &mut read).unwrap();
+        assert_eq!(read, generated);
+    }
+
+    #[test]
+    fn scratch_region() {
+        let mut buff = [0u8; 516 * 2];
+        let mut data = [0u8; 512];
+        let mut f = [File::<512>::new("DATA.BIN", &mut data).unwrap()];
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_scratch(&mut buff);
+        let start = fs.config.start_clusters().0;
+
+        // Host creates a metadata directory cluster and reads it back
+        let mut dir = [0u8; 512];
+        dir[..11].copy_from_slice(b".          ");
+        fs.write_block(start + 4, &dir).unwrap();
+
+        let mut read = [0u8; 512];
+        fs.read_block(start + 4, &mut read).unwrap();
+        assert_eq!(read, dir);
+
+        // File writes are not stored in scratch
+        fs.write_block(start, &[0xAA; 512]).unwrap();
+        fs.write_block(start + 5, &[0x55; 512]).unwrap();
+        fs.write_block(start + 6, &[0x11; 512]).unwrap();
+        fs.read_block(start + 5, &mut read).unwrap();
+        assert_eq!(read, [0x55; 512]);
+
+        fs.read_block(start + 6, &mut read).unwrap();
+        assert_eq!(read, [0u8; 512]);
+        drop(fs);
+
+        assert_eq!(data, [0xAA; 512]);
+    }
+
+    #[test]
+    fn scratch_capacity() {
+        let mut buff = [0u8; 516 * 40];
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("INFO.TXT", &data)];
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_scratch(&mut buff);
+        let start = fs.config.start_clusters().0 + 1;
+
+        // Scratch capacity is bounded only by the buffer
+        for i in 0..41 {
+            fs.write_block(start + i, &[i as u8; 512]).unwrap();
+        }
+
+        let mut read = [0u8; 512];
+        for i in 0..41 {
+            fs.read_block(start + i, &mut read).unwrap();
+            assert_eq!(read, [if i < 40 { i as u8 } else { 0 }; 512], "cluster {}", i);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use crate::{BlockDevice, BlockDeviceError};
+
+use crate::GhostFat;
+
+/// [`GhostFat`] shared between the USB interrupt and application code via
+/// a [`critical_section`] mutex.
+/// 
+/// [`BlockDevice`] is implemented on `&SharedGhostFat`, so a shared reference
+/// (ie. to a `static`) may be passed to the SCSI layer, with application
+/// code accessing the file system via [`SharedGhostFat::lock`]. Each block
+/// device access runs within a critical section.
+pub struct SharedGhostFat<'a, const BLOCK_SIZE: usize = 512, const FILES: usize = 0> {
+    inner: Mutex<RefCell<Option<GhostFat<'a, BLOCK_SIZE, FILES>>>>,
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> SharedGhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Create a new (uninitialised) shared file system, ie. for use in a `static`
+    pub const fn empty() -> Self {
+        Self { inner: Mutex::new(RefCell::new(None)) }
+    }
+
+    /// Create a new shared file system
+    pub const fn new(fs: GhostFat<'a, BLOCK_SIZE, FILES>) -> Self {
+        Self { inner: Mutex::new(RefCell::new(Some(fs))) }
+    }
+
+    /// Initialise the shared file system, replacing any existing instance
+    pub fn init(&self, fs: GhostFat<'a, BLOCK_SIZE, FILES>) {
+        critical_section::with(|cs| {
+            self.inner.borrow_ref_mut(cs).replace(fs);
+        });
+    }
+
+    /// Run the provided function with exclusive access to the file system
+    /// within a critical section, returning `None` if uninitialised
+    pub fn lock<R>(&self, f: impl FnOnce(&mut GhostFat<'a, BLOCK_SIZE, FILES>) -> R) -> Option<R> {
+        critical_section::with(|cs| {
+            self.inner.borrow_ref_mut(cs).as_mut().map(f)
+        })
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> Default for SharedGhostFat<'a, BLOCK_SIZE, FILES> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl <'r, 'a, const BLOCK_SIZE: usize, const FILES: usize> BlockDevice for &'r SharedGhostFat<'a, BLOCK_SIZE, FILES> {
+    const BLOCK_BYTES: usize = BLOCK_SIZE;
+
+    /// Read a file system block, failing with [`BlockDeviceError::NotReady`] if uninitialised
+    fn read_block(&self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError> {
+        self.lock(|fs| fs.read_block(lba, block))
+            .unwrap_or(Err(BlockDeviceError::NotReady))
+    }
+
+    /// Read consecutive file system blocks, failing with [`BlockDeviceError::NotReady`] if uninitialised
+    fn read_blocks(&self, lba: u32, blocks: &mut [u8]) -> Result<(), BlockDeviceError> {
+        self.lock(|fs| fs.read_blocks(lba, blocks))
+            .unwrap_or(Err(BlockDeviceError::NotReady))
+    }
+
+    /// Write a file system block, failing with [`BlockDeviceError::NotReady`] if uninitialised
+    fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError> {
+        self.lock(|fs| fs.write_block(lba, block))
+            .unwrap_or(Err(BlockDeviceError::NotReady))
+    }
+
+    /// Write consecutive file system blocks, failing with [`BlockDeviceError::NotReady`] if uninitialised
+    fn write_blocks(&mut self, lba: u32, blocks: &[u8]) -> Result<(), BlockDeviceError> {
+        self.lock(|fs| fs.write_blocks(lba, blocks))
+            .unwrap_or(Err(BlockDeviceError::NotReady))
+    }
+
+    /// Report the maximum block index for the file system, zero if
+    /// uninitialised (see [`BlockDevice::check_ready`])
+    fn max_lba(&self) -> u32 {
+        self.lock(|fs| fs.max_lba()).unwrap_or(0)
+    }
+
+    /// Report the file system as not ready until initialised
+    fn check_ready(&self) -> Result<(), BlockDeviceError> {
+        self.lock(|_| ()).ok_or(BlockDeviceError::NotReady)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, File};
+    use super::*;
+
+    static DATA: [u8; 16] = [0xAA; 16];
+    static FS: SharedGhostFat<'static, 512, 1> = SharedGhostFat::empty();
+
+    #[test]
+    fn shared_access() {
+        let mut dev = &FS;
+        let mut block = [0u8; 512];
+        assert_eq!(dev.check_ready(), Err(BlockDeviceError::NotReady));
+        assert_eq!(dev.read_block(0, &mut block), Err(BlockDeviceError::NotReady));
+
+        FS.init(GhostFat::new_array([File::new_ro("DATA.BIN", &DATA)], Config::default()));
+        assert_eq!(dev.check_ready(), Ok(()));
+        let lba = FS.lock(|fs| fs.config.start_clusters().0).unwrap();
+
+        dev.read_block(lba, &mut block).unwrap();
+        assert_eq!(&block[..16], &DATA);
+
+        // Application code shares the file system
+        assert_eq!(dev.write_block(lba + 1, &block), Ok(()));
+        assert_eq!(FS.lock(|fs| fs.take_changed()), Some(false));
+        assert_eq!(dev.max_lba(), 7999);
+    }
+}
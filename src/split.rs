@@ -0,0 +1,259 @@
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{BlockDevice, BlockDeviceError};
+
+use crate::{File, GhostFat};
+
+/// Set while the writer is updating files
+const WRITING: u32 = 1 << 0;
+/// Set while the reader is accessing the file system
+const READING: u32 = 1 << 1;
+/// Generation increment for each completed update
+const GENERATION: u32 = 1 << 2;
+
+/// Update state shared between [`GhostFatReader`] and [`GhostFatWriter`]
+/// halves, see [`GhostFat::split`].
+///
+/// Tracks whether either half is accessing the file system along with a
+/// count of completed updates, so accesses overlapping an update fail with
+/// [`BlockDeviceError::NotReady`] and the host does not see partially
+/// updated content.
+#[derive(Default)]
+pub struct UpdateSeq(AtomicU32);
+
+impl UpdateSeq {
+    /// Create a new update sequence
+    pub const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+}
+
+/// Reader half of a split [`GhostFat`], servicing block device accesses
+/// (ie. from the USB interrupt)
+pub struct GhostFatReader<'s, 'a, const BLOCK_SIZE: usize, const FILES: usize> {
+    fs: *mut GhostFat<'a, BLOCK_SIZE, FILES>,
+    seq: &'s UpdateSeq,
+    seen: u32,
+    max_lba: u32,
+    _fs: PhantomData<&'s mut GhostFat<'a, BLOCK_SIZE, FILES>>,
+}
+
+/// Writer half of a split [`GhostFat`], applying updates to files
+/// (ie. from application tasks)
+pub struct GhostFatWriter<'s, 'a, const BLOCK_SIZE: usize, const FILES: usize> {
+    fs: *mut GhostFat<'a, BLOCK_SIZE, FILES>,
+    seq: &'s UpdateSeq,
+    _fs: PhantomData<&'s mut GhostFat<'a, BLOCK_SIZE, FILES>>,
+}
+
+// Safety: access to the file system is serialised via the shared `UpdateSeq`
+unsafe impl <'s, 'a, const BLOCK_SIZE: usize, const FILES: usize> Send for GhostFatReader<'s, 'a, BLOCK_SIZE, FILES>
+where GhostFat<'a, BLOCK_SIZE, FILES>: Send {}
+
+// Safety: access to the file system is serialised via the shared `UpdateSeq`
+unsafe impl <'s, 'a, const BLOCK_SIZE: usize, const FILES: usize> Send for GhostFatWriter<'s, 'a, BLOCK_SIZE, FILES>
+where GhostFat<'a, BLOCK_SIZE, FILES>: Send {}
+
+/// In-progress file update, completed on drop, see [`GhostFatWriter::begin`]
+pub struct UpdateGuard<'w, 's, 'a, const BLOCK_SIZE: usize, const FILES: usize> {
+    writer: &'w mut GhostFatWriter<'s, 'a, BLOCK_SIZE, FILES>,
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Split the file system into a reader half servicing block device
+    /// accesses and a writer half applying file updates, synchronised
+    /// via the provided [`UpdateSeq`] (ie. stored in a `static`).
+    ///
+    /// Files are updated via [`GhostFatWriter::update`], with reader
+    /// accesses overlapping an update failing with [`BlockDeviceError::NotReady`]
+    /// (reported to the host as NOT READY for retry), and the host signalled
+    /// to re-read the volume once the update is complete.
+    pub fn split<'s>(&'s mut self, seq: &'s UpdateSeq) -> (GhostFatReader<'s, 'a, BLOCK_SIZE, FILES>, GhostFatWriter<'s, 'a, BLOCK_SIZE, FILES>) {
+        let seen = seq.0.load(Ordering::Acquire) / GENERATION;
+        let max_lba = self.max_lba();
+        let fs: *mut Self = self;
+
+        (
+            GhostFatReader { fs, seq, seen, max_lba, _fs: PhantomData },
+            GhostFatWriter { fs, seq, _fs: PhantomData },
+        )
+    }
+}
+
+impl <'s, 'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFatReader<'s, 'a, BLOCK_SIZE, FILES> {
+    /// Run the provided access to the file system, failing with
+    /// [`BlockDeviceError::NotReady`] where this overlaps a file update
+    fn access<R>(&self, f: impl FnOnce(&mut GhostFat<'a, BLOCK_SIZE, FILES>) -> R) -> Result<R, BlockDeviceError> {
+        let seq = self.seq.0.load(Ordering::Relaxed);
+        if seq & WRITING != 0 || self.seq.0.compare_exchange(seq, seq | READING, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            crate::debug!("Access overlapped file update");
+            return Err(BlockDeviceError::NotReady);
+        }
+
+        // Safety: the writer does not access the file system while the reading flag is set
+        let r = f(unsafe { &mut *self.fs });
+
+        self.seq.0.fetch_and(!READING, Ordering::Release);
+        Ok(r)
+    }
+
+    /// Check whether the volume has changed since the last call, including
+    /// file updates completed by the writer, see [`GhostFat::take_changed`]
+    pub fn take_changed(&mut self) -> bool {
+        let generation = self.seq.0.load(Ordering::Acquire) / GENERATION;
+        let updated = generation != self.seen;
+
+        let changed = self.access(|fs| {
+            if updated {
+                crate::debug!("File updates complete, refreshing volume");
+                fs.refresh();
+                fs.mark_changed();
+            }
+            fs.take_changed()
+        });
+
+        match changed {
+            Ok(c) => {
+                self.seen = generation;
+                c
+            },
+            Err(_) => false,
+        }
+    }
+}
+
+impl <'s, 'a, const BLOCK_SIZE: usize, const FILES: usize> BlockDevice for GhostFatReader<'s, 'a, BLOCK_SIZE, FILES> {
+    const BLOCK_BYTES: usize = BLOCK_SIZE;
+
+    /// Read a file system block, failing where this overlaps a file update
+    fn read_block(&self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError> {
+        self.access(|fs| fs.read_block(lba, block))?
+    }
+
+    /// Read consecutive file system blocks, failing where this overlaps a file update
+    fn read_blocks(&self, lba: u32, blocks: &mut [u8]) -> Result<(), BlockDeviceError> {
+        self.access(|fs| fs.read_blocks(lba, blocks))?
+    }
+
+    /// Write a file system block, failing where this overlaps a file update
+    fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError> {
+        self.access(|fs| fs.write_block(lba, block))?
+    }
+
+    /// Write consecutive file system blocks, failing where this overlaps a file update
+    fn write_blocks(&mut self, lba: u32, blocks: &[u8]) -> Result<(), BlockDeviceError> {
+        self.access(|fs| fs.write_blocks(lba, blocks))?
+    }
+
+    /// Report the maximum block index for the file system
+    fn max_lba(&self) -> u32 {
+        self.max_lba
+    }
+
+    /// Report the file system as not ready while a file update is in progress
+    fn check_ready(&self) -> Result<(), BlockDeviceError> {
+        match self.seq.0.load(Ordering::Relaxed) & WRITING {
+            0 => Ok(()),
+            _ => Err(BlockDeviceError::NotReady),
+        }
+    }
+}
+
+impl <'s, 'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFatWriter<'s, 'a, BLOCK_SIZE, FILES> {
+    /// Begin a file update, waiting for any in-progress reader access to
+    /// complete, with reader accesses failing until the returned guard is
+    /// dropped.
+    ///
+    /// As this waits on the reader the writer must not preempt reader
+    /// accesses (ie. run from an interrupt of higher priority than USB).
+    pub fn begin(&mut self) -> UpdateGuard<'_, 's, 'a, BLOCK_SIZE, FILES> {
+        loop {
+            let seq = self.seq.0.load(Ordering::Relaxed);
+            if seq & READING == 0 && self.seq.0.compare_exchange(seq, seq | WRITING, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+
+        UpdateGuard { writer: self }
+    }
+
+    /// Run the provided update on the file system files
+    pub fn update<R>(&mut self, f: impl FnOnce(&mut [File<'a, BLOCK_SIZE>]) -> R) -> R {
+        let mut guard = self.begin();
+        f(guard.files())
+    }
+}
+
+impl <'w, 's, 'a, const BLOCK_SIZE: usize, const FILES: usize> UpdateGuard<'w, 's, 'a, BLOCK_SIZE, FILES> {
+    /// Fetch the file system files for modification, see [`GhostFat::files_mut`]
+    pub fn files(&mut self) -> &mut [File<'a, BLOCK_SIZE>] {
+        // Safety: the reader does not access the file system while the writing flag is set
+        unsafe { &mut *self.writer.fs }.files_mut()
+    }
+}
+
+impl <'w, 's, 'a, const BLOCK_SIZE: usize, const FILES: usize> Drop for UpdateGuard<'w, 's, 'a, BLOCK_SIZE, FILES> {
+    fn drop(&mut self) {
+        let _ = self.writer.seq.0.fetch_update(Ordering::Release, Ordering::Relaxed, |seq| {
+            Some((seq & !WRITING).wrapping_add(GENERATION))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, GeneratedFile, StatusFile};
+    use super::*;
+
+    #[test]
+    fn split_halves() {
+        static SEQ: UpdateSeq = UpdateSeq::new();
+
+        let status = StatusFile::<64>::new();
+        let mut f = [status.file::<512>()];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        let (mut reader, mut writer) = fs.split(&SEQ);
+        assert!(!reader.take_changed());
+
+        // Accesses fail while an update is in progress
+        let guard = writer.begin();
+        let mut block = [0u8; 512];
+        assert_eq!(reader.read_block(lba, &mut block), Err(BlockDeviceError::NotReady));
+        assert_eq!(reader.write_block(lba, &block), Err(BlockDeviceError::NotReady));
+        assert!(!reader.take_changed());
+        drop(guard);
+
+        // Completed updates are signalled to the host
+        writer.update(|_| status.fail(1, "Sensor fault"));
+        assert!(reader.take_changed());
+        assert!(!reader.take_changed());
+
+        reader.read_block(lba, &mut block).unwrap();
+        assert_eq!(&block[..status.len()], b"Sensor fault\r\nError code: 1\r\n");
+    }
+
+    #[test]
+    fn split_file_updates() {
+        static SEQ: UpdateSeq = UpdateSeq::new();
+
+        let mut data = [0u8; 16];
+        let mut f = [File::<512>::new("DATA.BIN", &mut data).unwrap()];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let lba = fs.config.start_clusters().0;
+
+        let (mut reader, mut writer) = fs.split(&SEQ);
+
+        // Files are modified via the writer
+        let n = writer.update(|files| files[0].write_at(4, b"ABCD")).unwrap();
+        assert_eq!(n, 4);
+        assert!(reader.take_changed());
+
+        let mut block = [0u8; 512];
+        reader.read_block(lba, &mut block).unwrap();
+        assert_eq!(&block[..8], b"\0\0\0\0ABCD");
+    }
+}
@@ -0,0 +1,81 @@
+use static_cell::StaticCell;
+
+use crate::{Config, File, GhostFat};
+
+/// Static storage for a [`GhostFat`] instance and its file table
+/// 
+/// This allows a `GhostFat<'static>` to be constructed from a `static`
+/// without `unsafe` or nightly features, with writable file buffers
+/// provided using [`StaticCell`].
+/// 
+/// ```
+/// use ghostfat::{File, Config, StaticCell, StaticGhostFat};
+/// 
+/// static FS: StaticGhostFat<2> = StaticGhostFat::new();
+/// static BUFF: StaticCell<[u8; 512]> = StaticCell::new();
+/// 
+/// let buff = BUFF.init([0u8; 512]);
+/// let fs = FS.init([
+///     File::new_ro("README.TXT", b"Hello World!"),
+///     File::new("DATA.BIN", buff).unwrap(),
+/// ], Config::default());
+/// ```
+pub struct StaticGhostFat<const N: usize, const BLOCK_SIZE: usize = 512> {
+    files: StaticCell<[File<'static, BLOCK_SIZE>; N]>,
+    fs: StaticCell<GhostFat<'static, BLOCK_SIZE>>,
+}
+
+impl <const N: usize, const BLOCK_SIZE: usize> StaticGhostFat<N, BLOCK_SIZE> {
+    /// Create new (uninitialised) static storage
+    pub const fn new() -> Self {
+        Self {
+            files: StaticCell::new(),
+            fs: StaticCell::new(),
+        }
+    }
+
+    /// Initialise the file system with the provided files and configuration.
+    /// 
+    /// Panics if already initialised, see [`StaticGhostFat::try_init`]
+    pub fn init(&'static self, files: [File<'static, BLOCK_SIZE>; N], config: Config<BLOCK_SIZE>) -> &'static mut GhostFat<'static, BLOCK_SIZE> {
+        match self.try_init(files, config) {
+            Some(fs) => fs,
+            None => panic!("StaticGhostFat already initialised"),
+        }
+    }
+
+    /// Initialise the file system with the provided files and configuration,
+    /// returning `None` if already initialised
+    pub fn try_init(&'static self, files: [File<'static, BLOCK_SIZE>; N], config: Config<BLOCK_SIZE>) -> Option<&'static mut GhostFat<'static, BLOCK_SIZE>> {
+        let files = self.files.try_init(files)?;
+        self.fs.try_init(GhostFat::new(files, config))
+    }
+}
+
+impl <const N: usize, const BLOCK_SIZE: usize> Default for StaticGhostFat<N, BLOCK_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{File, Config};
+    use super::StaticGhostFat;
+
+    #[test]
+    fn static_init() {
+        static FS: StaticGhostFat<1> = StaticGhostFat::new();
+
+        let fs = FS.init([File::new_ro("TEST.TXT", b"abc")], Config::default());
+
+        let mut block = [0u8; 512];
+        let lba = fs.config.start_clusters().0;
+        fs.read_block(lba, &mut block).unwrap();
+        assert_eq!(&block[..3], b"abc");
+
+        assert!(FS.try_init([File::new_ro("TEST.TXT", b"abc")], Config::default()).is_none());
+    }
+}
@@ -0,0 +1,158 @@
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, AtomicU32, AtomicUsize, Ordering};
+
+use crate::{File, GeneratedFile, GhostFat};
+use crate::text::SliceWriter;
+
+/// Status file reporting the reason for failed host operations, following
+/// the bootloader `FAIL.TXT` convention.
+///
+/// The file is empty until a failure is reported, either by the device (see
+/// [`StatusFile::fail`]) or automatically for rejected or failed host writes
+/// when attached via [`GhostFat::with_status`]. Reports are truncated to `N`
+/// bytes, and as the file length changes hosts will see the report following
+/// a volume refresh (see [`GhostFat::refresh`]).
+pub struct StatusFile<const N: usize = 128> {
+    buff: [AtomicU8; N],
+    len: AtomicUsize,
+    code: AtomicU32,
+}
+
+/// Failure reporting for status files attached to the file system
+pub(crate) trait Status: Sync {
+    /// Report a failure with the provided code and reason
+    fn report(&self, code: u32, reason: fmt::Arguments);
+}
+
+impl <const N: usize> StatusFile<N> {
+    /// Create a new empty status file
+    pub const fn new() -> Self {
+        Self {
+            buff: [const { AtomicU8::new(0) }; N],
+            len: AtomicUsize::new(0),
+            code: AtomicU32::new(0),
+        }
+    }
+
+    /// Create a read-only `FAIL.TXT` file exposing failure reports
+    pub fn file<const BLOCK_SIZE: usize>(&self) -> File<'_, BLOCK_SIZE> {
+        File::new_gen("FAIL.TXT", self).with_reserved(N)
+    }
+
+    /// Report a failure with the provided error code and reason,
+    /// replacing any previous report
+    pub fn fail(&self, code: u32, reason: &str) {
+        self.report(code, format_args!("{}", reason));
+    }
+
+    /// Clear the failure report, ie. following a successful operation
+    pub fn clear(&self) {
+        self.len.store(0, Ordering::Release);
+        self.code.store(0, Ordering::Relaxed);
+    }
+
+    /// Fetch the error code of the current failure report, if any
+    pub fn code(&self) -> Option<u32> {
+        match self.len.load(Ordering::Acquire) {
+            0 => None,
+            _ => Some(self.code.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl <const N: usize> Status for StatusFile<N> {
+    fn report(&self, code: u32, reason: fmt::Arguments) {
+        crate::debug!("Reporting failure code: {}", code);
+
+        let mut r = [0u8; N];
+        let mut w = SliceWriter::new(0, &mut r);
+        let _ = write!(w, "{}\r\nError code: {}\r\n", reason, code);
+        let n = w.written();
+
+        self.len.store(0, Ordering::Release);
+        for (a, b) in self.buff.iter().zip(&r[..n]) {
+            a.store(*b, Ordering::Relaxed);
+        }
+        self.code.store(code, Ordering::Relaxed);
+        self.len.store(n, Ordering::Release);
+    }
+}
+
+impl <const N: usize> Default for StatusFile<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <const N: usize> GeneratedFile for StatusFile<N> {
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        let n = usize::min(buff.len(), self.len().saturating_sub(offset));
+        for (b, a) in buff[..n].iter_mut().zip(self.buff.iter().skip(offset)) {
+            *b = a.load(Ordering::Relaxed);
+        }
+        n
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Attach a [`StatusFile`], automatically reporting rejected or failed
+    /// host writes to device files and captured host files
+    pub fn with_status<const N: usize>(mut self, status: &'a StatusFile<N>) -> Self {
+        self.status = Some(status);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, FileError, FileHooks};
+    use super::*;
+
+    /// Hooks rejecting all writes
+    struct Reject;
+
+    impl FileHooks for Reject {
+        fn validate(&self, _block_index: usize, _data: &[u8]) -> Result<(), FileError> {
+            Err(FileError::InvalidData)
+        }
+    }
+
+    #[test]
+    fn status_reports() {
+        let status = StatusFile::<64>::new();
+        assert_eq!(status.len(), 0);
+
+        status.fail(3, "Bad image");
+        assert_eq!(status.code(), Some(3));
+
+        let mut b = [0u8; 64];
+        let n = status.generate(0, &mut b);
+        assert_eq!(&b[..n], b"Bad image\r\nError code: 3\r\n");
+
+        status.clear();
+        assert_eq!(status.code(), None);
+        assert_eq!(status.len(), 0);
+
+        // Rejected host writes are reported automatically
+        let mut data = [0u8; 512];
+        let mut f = [
+            File::<512>::new("FW.BIN", &mut data).unwrap().with_hooks(&Reject),
+            status.file(),
+        ];
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_status(&status);
+        let lba = fs.config.start_clusters().0;
+
+        assert!(fs.write_block(lba, &[0u8; 512]).is_err());
+        assert!(fs.refresh());
+        drop(fs);
+
+        let n = status.generate(0, &mut b);
+        assert_eq!(&b[..n], b"FW.BIN rejected: InvalidData\r\nError code: 6\r\n");
+    }
+}
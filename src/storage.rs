@@ -0,0 +1,59 @@
+use embedded_storage::{ReadStorage, Storage};
+
+use crate::{BlockDeviceError, GhostFat};
+
+/// Byte-addressable access to the generated volume image, ie. for streaming
+/// the image over a non-USB transport.
+/// 
+/// Reads and writes may span blocks, with partial block writes merged
+/// with the current block content.
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> ReadStorage for GhostFat<'a, BLOCK_SIZE, FILES> {
+    type Error = BlockDeviceError;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.read_bytes(offset as u64, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        self.volume_len() as usize
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> Storage for GhostFat<'a, BLOCK_SIZE, FILES> {
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.write_bytes(offset as u64, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BlockDevice, Config, File};
+    use super::*;
+
+    #[test]
+    fn volume_storage() {
+        let mut data = [0u8; 1024];
+        let mut f = [File::<512>::new("DATA.BIN", &mut data).unwrap()];
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        assert_eq!(fs.capacity(), 8000 * 512);
+
+        // Reads match the block device and may span blocks
+        let (mut a, mut b) = ([0u8; 1024], [0u8; 700]);
+        fs.read_block(0, &mut a[..512]).unwrap();
+        fs.read_block(1, &mut a[512..]).unwrap();
+        fs.read(100, &mut b).unwrap();
+        assert_eq!(&a[100..800], &b);
+
+        // Partial writes are merged into file blocks
+        let offset = fs.config.start_clusters().0 * 512;
+        fs.write(offset + 500, &[0xAA; 24]).unwrap();
+        fs.read(offset, &mut a).unwrap();
+        assert_eq!(&a[500..524], &[0xAA; 24]);
+        assert_eq!(&a[..500], &[0u8; 500]);
+
+        assert_eq!(fs.read(8000 * 512 - 4, &mut b), Err(BlockDeviceError::InvalidAddress));
+        drop(fs);
+
+        assert_eq!(&data[500..524], &[0xAA; 24]);
+    }
+}
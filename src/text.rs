@@ -0,0 +1,112 @@
+use core::fmt::{self, Write};
+
+use crate::GeneratedFile;
+
+/// Text file rendered on demand via [`core::fmt::Write`]
+/// 
+/// The render function is called on each read, with the output sliced to
+/// the requested block, so live values can be exposed without buffering the
+/// full text. The file length is computed by rendering into a counter, so
+/// rendered output should be stable between calls.
+pub struct TextFile<F> {
+    render: F,
+}
+
+impl <F: Fn(&mut dyn Write) -> fmt::Result + Sync + Send> TextFile<F> {
+    /// Create a new text file using the provided render function
+    pub const fn new(render: F) -> Self {
+        Self { render }
+    }
+}
+
+impl <F: Fn(&mut dyn Write) -> fmt::Result + Sync + Send> GeneratedFile for TextFile<F> {
+    fn len(&self) -> usize {
+        let mut w = SliceWriter::new(0, &mut []);
+        let _ = (self.render)(&mut w);
+        w.len()
+    }
+
+    fn generate(&self, offset: usize, buff: &mut [u8]) -> usize {
+        let mut w = SliceWriter::new(offset, buff);
+        // Rendering is aborted with an error once the buffer is full
+        let _ = (self.render)(&mut w);
+        w.written()
+    }
+}
+
+/// [`Write`] implementation copying the window of rendered output
+/// following `skip` bytes into `buff`, while counting the total length.
+pub(crate) struct SliceWriter<'a> {
+    skip: usize,
+    buff: &'a mut [u8],
+    index: usize,
+    len: usize,
+}
+
+impl <'a> SliceWriter<'a> {
+    /// Create a writer capturing output following `skip` bytes into `buff`
+    pub fn new(skip: usize, buff: &'a mut [u8]) -> Self {
+        Self { skip, buff, index: 0, len: 0 }
+    }
+
+    /// Fetch the number of bytes written into the buffer
+    pub fn written(&self) -> usize {
+        self.index
+    }
+
+    /// Fetch the total length of rendered output
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl <'a> Write for SliceWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut d = s.as_bytes();
+        self.len += d.len();
+
+        // Skip output prior to the requested window
+        let skip = usize::min(self.skip, d.len());
+        self.skip -= skip;
+        d = &d[skip..];
+
+        // Copy output into the window
+        let n = usize::min(d.len(), self.buff.len() - self.index);
+        self.buff[self.index..][..n].copy_from_slice(&d[..n]);
+        self.index += n;
+
+        // Stop rendering once a non-empty window is full
+        if !self.buff.is_empty() && self.index == self.buff.len() {
+            return Err(fmt::Error);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use crate::{File, FileContent};
+    use super::TextFile;
+
+    #[test]
+    fn render_text_chunks() {
+        let value = 42;
+        let t = TextFile::new(|w: &mut dyn Write| {
+            writeln!(w, "Value: {}", value)?;
+            writeln!(w, "Status: OK")
+        });
+        let f = File::<8>::new("STATUS.TXT", FileContent::Generated(&t)).unwrap();
+
+        let text = "Value: 42\nStatus: OK\n";
+        assert_eq!(f.len(), text.len());
+
+        let mut buff = [0u8; 8];
+        for (i, c) in text.as_bytes().chunks(8).enumerate() {
+            assert_eq!(f.chunk(i, &mut buff), Ok(c.len()));
+            assert_eq!(&buff[..c.len()], c);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use core::fmt;
+use core::ops::{Add, Sub};
+
+/// Logical block address on the virtual block device
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature="defmt", derive(defmt::Format))]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(transparent)]
+pub struct Lba(pub u32);
+
+/// Sector index relative to the start of a file system region
+/// (ie. the FAT, root directory or data clusters)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature="defmt", derive(defmt::Format))]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(transparent)]
+pub struct SectorIndex(pub u32);
+
+/// FAT cluster number, with data clusters starting at [`Cluster::FIRST`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature="defmt", derive(defmt::Format))]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(transparent)]
+pub struct Cluster(pub u32);
+
+impl Lba {
+    /// Fetch the sector index of this LBA within the region starting at `start`,
+    /// returning `None` if the LBA precedes the region
+    pub const fn index_from(self, start: Lba) -> Option<SectorIndex> {
+        match self.0.checked_sub(start.0) {
+            Some(i) => Some(SectorIndex(i)),
+            None => None,
+        }
+    }
+}
+
+impl SectorIndex {
+    /// Fetch the index as a `usize` for buffer and table offsets
+    pub const fn as_usize(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Cluster {
+    /// First data cluster, as clusters 0 and 1 are reserved
+    pub const FIRST: Cluster = Cluster(2);
+
+    /// Fetch the cluster containing a data region sector (one sector per cluster)
+    pub const fn from_index(index: SectorIndex) -> Self {
+        Cluster(index.0 + Self::FIRST.0)
+    }
+
+    /// Fetch the data region sector index for the cluster
+    pub const fn index(self) -> SectorIndex {
+        SectorIndex(self.0 - Self::FIRST.0)
+    }
+}
+
+/// Offset an LBA by a sector index
+impl Add<SectorIndex> for Lba {
+    type Output = Lba;
+
+    fn add(self, rhs: SectorIndex) -> Lba {
+        Lba(self.0 + rhs.0)
+    }
+}
+
+/// Compute the sector index between two LBAs
+impl Sub<Lba> for Lba {
+    type Output = SectorIndex;
+
+    fn sub(self, rhs: Lba) -> SectorIndex {
+        SectorIndex(self.0 - rhs.0)
+    }
+}
+
+impl From<u32> for Lba {
+    fn from(v: u32) -> Self {
+        Lba(v)
+    }
+}
+
+impl From<Lba> for u32 {
+    fn from(v: Lba) -> Self {
+        v.0
+    }
+}
+
+impl fmt::Display for Lba {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for SectorIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_math() {
+        let start = Lba(10);
+        assert_eq!(Lba(15) - start, SectorIndex(5));
+        assert_eq!(Lba(15).index_from(start), Some(SectorIndex(5)));
+        assert_eq!(Lba(5).index_from(start), None);
+        assert_eq!(start + SectorIndex(3), Lba(13));
+
+        assert_eq!(Cluster::from_index(SectorIndex(0)), Cluster::FIRST);
+        assert_eq!(Cluster(7).index(), SectorIndex(5));
+    }
+}
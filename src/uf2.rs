@@ -0,0 +1,267 @@
+use crate::{FlashWriter, WriteObserver};
+
+/// UF2 block length in bytes
+pub const UF2_BLOCK_LEN: usize = 512;
+
+const MAGIC_START0: u32 = 0x0A32_4655;
+const MAGIC_START1: u32 = 0x9E5D_5157;
+const MAGIC_END: u32 = 0x0AB1_6F30;
+
+/// Block is not intended for main flash and should be skipped
+const FLAG_NOT_MAIN_FLASH: u32 = 0x0000_0001;
+/// Block contains a family ID in place of the file size
+const FLAG_FAMILY_ID: u32 = 0x0000_2000;
+
+/// Maximum UF2 payload length
+const MAX_PAYLOAD: usize = 476;
+
+/// UF2 drag-and-drop flashing front-end
+///
+/// Attached via [`GhostFat::with_observer`](crate::GhostFat::with_observer),
+/// host writes are scanned for UF2 blocks (at 512 byte alignment) whatever
+/// the cluster or file they are written to, with decoded `(address, payload)`
+/// pairs passed to the [`FlashWriter`] `W`.
+///
+/// As blocks may arrive out of order or be re-written, received blocks are
+/// tracked in a `WORDS * 32` block bitmap with duplicates ignored, and
+/// [`FlashWriter::complete`] called once every block of the file has been
+/// received.
+pub struct Uf2Flasher<W, const WORDS: usize = 32> {
+    writer: W,
+    family: Option<u32>,
+    seen: [u32; WORDS],
+    total: u32,
+    count: u32,
+}
+
+/// Decoded UF2 block header
+struct Header {
+    flags: u32,
+    address: u32,
+    len: usize,
+    index: u32,
+    total: u32,
+    family: u32,
+}
+
+impl Header {
+    /// Parse a UF2 block, returning `None` where magic values do not match
+    fn parse(b: &[u8]) -> Option<Self> {
+        let word = |i: usize| u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+
+        if b.len() < UF2_BLOCK_LEN || word(0) != MAGIC_START0 || word(4) != MAGIC_START1 || word(508) != MAGIC_END {
+            return None;
+        }
+
+        Some(Self {
+            flags: word(8),
+            address: word(12),
+            len: word(16) as usize,
+            index: word(20),
+            total: word(24),
+            family: word(28),
+        })
+    }
+}
+
+impl <W: FlashWriter, const WORDS: usize> Uf2Flasher<W, WORDS> {
+    /// Create a new UF2 flasher writing to the provided target
+    pub fn new(writer: W) -> Self {
+        Self { writer, family: None, seen: [0; WORDS], total: 0, count: 0 }
+    }
+
+    /// Only accept blocks with the provided UF2 family ID
+    pub fn with_family(mut self, family: u32) -> Self {
+        self.family = Some(family);
+        self
+    }
+
+    /// Fetch a reference to the flash writer
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    /// Fetch a mutable reference to the flash writer
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Fetch the number of received and total blocks for the current file
+    pub fn received(&self) -> (u32, u32) {
+        (self.count, self.total)
+    }
+
+    /// Check whether all blocks of the current file have been received
+    pub fn is_complete(&self) -> bool {
+        self.total > 0 && self.count == self.total
+    }
+
+    /// Reset reassembly state, ie. to accept a new file with the same block count
+    pub fn reset(&mut self) {
+        self.seen = [0; WORDS];
+        self.total = 0;
+        self.count = 0;
+    }
+
+    /// Scan written data for UF2 blocks, returning the number of blocks handled
+    pub fn process(&mut self, data: &[u8]) -> usize {
+        data.chunks_exact(UF2_BLOCK_LEN)
+            .filter(|b| self.block(b))
+            .count()
+    }
+
+    /// Handle a single UF2 block, returning true if the block was written
+    fn block(&mut self, b: &[u8]) -> bool {
+        let h = match Header::parse(b) {
+            Some(h) => h,
+            None => return false,
+        };
+
+        if h.flags & FLAG_NOT_MAIN_FLASH != 0 {
+            return false;
+        }
+
+        if let (Some(f), true) = (self.family, h.flags & FLAG_FAMILY_ID != 0) {
+            if h.family != f {
+                crate::warn!("Ignoring UF2 block for family 0x{:08x}", h.family);
+                return false;
+            }
+        }
+
+        if h.len > MAX_PAYLOAD || h.index >= h.total {
+            crate::warn!("Ignoring invalid UF2 block {} of {}", h.index, h.total);
+            return false;
+        }
+
+        // Block count changes indicate a new file
+        if h.total != self.total {
+            self.reset();
+            self.total = h.total;
+        }
+
+        // Skip re-written blocks
+        let (word, bit) = (h.index as usize / 32, 1 << (h.index % 32));
+        match self.seen.get(word) {
+            Some(w) if w & bit != 0 => return false,
+            Some(_) => (),
+            None => crate::warn!("UF2 block {} exceeds reassembly map", h.index),
+        }
+
+        crate::trace!("UF2 block {} of {}, {} bytes at 0x{:08x}", h.index, h.total, h.len, h.address);
+
+        if let Err(e) = self.writer.write(h.address, &b[32..][..h.len]) {
+            crate::error!("UF2 write failed at 0x{:08x}", h.address);
+            self.writer.complete(Err(e));
+            self.reset();
+            return false;
+        }
+
+        if let Some(w) = self.seen.get_mut(word) {
+            *w |= bit;
+        }
+        self.count += 1;
+
+        self.writer.progress(self.count as usize, Some(self.total as usize));
+        if self.is_complete() {
+            crate::debug!("UF2 transfer complete, {} blocks", self.total);
+            self.writer.complete(Ok(()));
+        }
+
+        true
+    }
+}
+
+impl <W: FlashWriter + Send, const WORDS: usize> WriteObserver for Uf2Flasher<W, WORDS> {
+    fn on_write(&mut self, _block_index: usize, data: &[u8]) {
+        self.process(data);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::BlockDevice;
+
+    use crate::{Config, File, FileError, GhostFat};
+    use super::*;
+
+    /// Encode a UF2 block, ie. for testing
+    pub(crate) fn encode(address: u32, index: u32, total: u32, payload: &[u8]) -> [u8; UF2_BLOCK_LEN] {
+        let mut b = [0u8; UF2_BLOCK_LEN];
+        for (i, w) in [MAGIC_START0, MAGIC_START1, 0, address, payload.len() as u32, index, total, 0].iter().enumerate() {
+            b[i * 4..][..4].copy_from_slice(&w.to_le_bytes());
+        }
+        b[32..][..payload.len()].copy_from_slice(payload);
+        b[508..].copy_from_slice(&MAGIC_END.to_le_bytes());
+        b
+    }
+
+    /// Flash writer recording writes, ie. for testing
+    pub(crate) struct MockFlash {
+        pub mem: [u8; 1024],
+        pub writes: usize,
+        pub done: Option<Result<(), FileError>>,
+    }
+
+    impl Default for MockFlash {
+        fn default() -> Self {
+            Self { mem: [0; 1024], writes: 0, done: None }
+        }
+    }
+
+    impl FlashWriter for MockFlash {
+        fn write(&mut self, address: u32, data: &[u8]) -> Result<(), FileError> {
+            let a = address as usize;
+            let d = self.mem.get_mut(a..a + data.len()).ok_or(FileError::NoSpace)?;
+            d.copy_from_slice(data);
+            self.writes += 1;
+            Ok(())
+        }
+
+        fn complete(&mut self, result: Result<(), FileError>) {
+            self.done = Some(result);
+        }
+    }
+
+    #[test]
+    fn uf2_blocks() {
+        let mut uf2 = Uf2Flasher::<_, 1>::new(MockFlash::default());
+        let data = [0u8; 8];
+        let mut f = [File::<512>::new_ro("INFO.TXT", &data)];
+        let mut fs = GhostFat::new(&mut f, Config::default()).with_observer(&mut uf2);
+        let start = fs.config.start_clusters().0;
+
+        // Out of order and duplicate blocks, written to unallocated clusters
+        fs.write_block(start + 11, &encode(0x100, 1, 3, &[0x22; 256])).unwrap();
+        fs.write_block(start + 10, &encode(0x000, 0, 3, &[0x11; 256])).unwrap();
+        fs.write_block(start + 11, &encode(0x100, 1, 3, &[0x22; 256])).unwrap();
+        fs.write_block(start + 5, &[0xFF; 512]).unwrap();
+        fs.write_block(start + 12, &encode(0x200, 2, 3, &[0x33; 256])).unwrap();
+        drop(fs);
+
+        assert!(uf2.is_complete());
+        let flash = uf2.writer();
+        assert_eq!(flash.writes, 3);
+        assert_eq!(flash.done, Some(Ok(())));
+        assert_eq!(&flash.mem[0x0FF..0x101], &[0x11, 0x22]);
+        assert_eq!(&flash.mem[0x2FF..0x301], &[0x33, 0x00]);
+    }
+
+    #[test]
+    fn uf2_errors() {
+        let mut uf2 = Uf2Flasher::<_, 1>::new(MockFlash::default()).with_family(0xE48B_FF56);
+
+        // Out of range writes fail the transfer
+        assert_eq!(uf2.process(&encode(0x1000, 0, 2, &[0; 256])), 0);
+        assert_eq!(uf2.writer().done, Some(Err(FileError::NoSpace)));
+
+        // Mismatched families are ignored
+        let mut b = encode(0, 0, 1, &[0; 256]);
+        b[8..12].copy_from_slice(&FLAG_FAMILY_ID.to_le_bytes());
+        b[28..32].copy_from_slice(&0x1234u32.to_le_bytes());
+        assert_eq!(uf2.process(&b), 0);
+
+        b[28..32].copy_from_slice(&0xE48B_FF56u32.to_le_bytes());
+        assert_eq!(uf2.process(&b), 1);
+        assert!(uf2.is_complete());
+    }
+}
@@ -0,0 +1,153 @@
+use packing::{Packed, PackedSize};
+
+use crate::{File, FileContent, FileError, GhostFat};
+use crate::dir::DirectoryEntry;
+use crate::file::Attrs;
+
+/// Byte buffer with a used length separate from a fixed capacity,
+/// backing [`FileContent::Vec`] (ie. [`heapless::Vec<u8, N>`])
+pub trait VecBuffer: Sync + Send {
+    /// Fetch the used portion of the buffer
+    fn as_slice(&self) -> &[u8];
+
+    /// Fetch the used portion of the buffer for modification
+    fn as_mut_slice(&mut self) -> &mut [u8];
+
+    /// Fetch the buffer capacity in bytes
+    fn capacity(&self) -> usize;
+
+    /// Set the used length of the buffer, zero-filling on growth
+    fn resize(&mut self, len: usize) -> Result<(), FileError>;
+}
+
+impl <const N: usize> VecBuffer for heapless::Vec<u8, N> {
+    fn as_slice(&self) -> &[u8] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn resize(&mut self, len: usize) -> Result<(), FileError> {
+        heapless::Vec::resize(self, len, 0).map_err(|_| FileError::NoSpace)
+    }
+}
+
+/// Create a file from a heapless vector, with clusters allocated for the
+/// full capacity and the file length following the used length
+impl <'a, const BLOCK_SIZE: usize, const N: usize>From<&'a mut heapless::Vec<u8, N>> for FileContent<'a, BLOCK_SIZE> {
+    fn from(d: &'a mut heapless::Vec<u8, N>) -> Self {
+        FileContent::Vec(d)
+    }
+}
+
+/// Write a chunk to a vector backed file, growing the used length to
+/// cover the written data
+pub(crate) fn write_chunk<const BLOCK_SIZE: usize>(v: &mut dyn VecBuffer, index: usize, data: &[u8]) -> Result<usize, FileError> {
+    let offset = index * BLOCK_SIZE;
+    if offset >= v.capacity() {
+        return Ok(0);
+    }
+
+    let len = usize::min(data.len(), usize::min(BLOCK_SIZE, v.capacity() - offset));
+    if v.as_slice().len() < offset + len {
+        v.resize(offset + len)?;
+    }
+
+    v.as_mut_slice()[offset..][..len].copy_from_slice(&data[..len]);
+    Ok(len)
+}
+
+impl <'a, const BLOCK_SIZE: usize> File<'a, BLOCK_SIZE> {
+    /// Check whether the file is backed by a [`VecBuffer`]
+    pub(crate) fn is_vec(&self) -> bool {
+        matches!(self.data, FileContent::Vec(_))
+    }
+}
+
+impl <'a, const BLOCK_SIZE: usize, const FILES: usize> GhostFat<'a, BLOCK_SIZE, FILES> {
+    /// Apply host directory entry updates, setting the length of vector
+    /// backed files to the size written by the host
+    pub(crate) fn update_lengths(&mut self, block: &[u8]) {
+        for e in block.chunks_exact(DirectoryEntry::BYTES) {
+            let entry = match DirectoryEntry::unpack(e) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            // Skip free, deleted, label and long name entries
+            if entry.name[0] == 0x00 || entry.name[0] == 0xE5 || entry.attrs & Attrs::VOLUME_LABEL.bits() != 0 {
+                continue;
+            }
+
+            let f = match self.fat_files.iter_mut().find(|f| f.short_name().ok() == Some(entry.name)) {
+                Some(f) => f,
+                None => continue,
+            };
+
+            let len = entry.size as usize;
+            if !f.is_vec() || f.len() == len {
+                continue;
+            }
+
+            crate::debug!("Resizing file: {} to {} bytes", f.name(), len);
+
+            if let FileContent::Vec(v) = &mut f.data {
+                if v.resize(len).is_err() {
+                    crate::warn!("Host file size {} exceeds capacity {}", len, v.capacity());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BlockDevice;
+
+    use crate::Config;
+    use super::*;
+
+    #[test]
+    fn vec_lengths() {
+        let mut data = heapless::Vec::<u8, 1200>::new();
+        data.extend_from_slice(b"hello").unwrap();
+
+        let mut f = [
+            File::<512>::new("LOG.TXT", &mut data).unwrap(),
+            File::new_ro("A.BIN", &[0xAA; 8]),
+        ];
+        assert_eq!(f[0].len(), 5);
+        assert_eq!(f[0].alloc_blocks(), 3);
+
+        let mut fs = GhostFat::new(&mut f, Config::default());
+        let start = fs.config.start_clusters().0;
+
+        // Following files are allocated after the capacity
+        let mut block = [0u8; 512];
+        fs.read_block(start + 3, &mut block).unwrap();
+        assert_eq!(&block[..8], &[0xAA; 8]);
+
+        // Host writes grow the used length
+        fs.write_block(start + 1, &[0x11; 512]).unwrap();
+        assert_eq!(fs.fat_files[0].len(), 1024);
+
+        // Directory entry updates set the exact length
+        let rootdir = fs.config.start_rootdir().0;
+        fs.read_block(rootdir, &mut block).unwrap();
+        assert_eq!(DirectoryEntry::unpack(&block[32..64]).unwrap().size, 1024);
+
+        block[32 + 28..][..4].copy_from_slice(&600u32.to_le_bytes());
+        fs.write_block(rootdir, &block).unwrap();
+        assert_eq!(fs.fat_files[0].len(), 600);
+
+        fs.read_block(start + 1, &mut block).unwrap();
+        assert_eq!(&block[..88], &[0x11; 88]);
+        assert_eq!(&block[88..], &[0x00; 424]);
+    }
+}
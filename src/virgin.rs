@@ -0,0 +1,99 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Policy for reads of writable file blocks that have never been written,
+/// attached via [`File::with_virgin`](crate::File::with_virgin)
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature="defmt", derive(defmt::Format))]
+pub enum VirginPolicy {
+    /// Serve never-written blocks as zeros
+    Zeros,
+    /// Serve never-written blocks as the provided fill byte (ie. 0xFF to match erased flash)
+    Fill(u8),
+    /// Serve never-written blocks from the file backend
+    Backend,
+}
+
+/// Tracks which blocks of a file have been written, by the host via the
+/// block device or by firmware via [`WriteMap::mark`]
+///
+/// Blocks beyond `N` are not tracked and are always served from the file backend.
+pub struct WriteMap<const N: usize> {
+    written: [AtomicBool; N],
+}
+
+impl <const N: usize> WriteMap<N> {
+    /// Create a new map with no blocks written
+    pub const fn new() -> Self {
+        Self { written: [const { AtomicBool::new(false) }; N] }
+    }
+
+    /// Mark a block as written
+    pub fn mark(&self, index: usize) {
+        mark(&self.written, index)
+    }
+
+    /// Check whether a block has been written
+    pub fn is_written(&self, index: usize) -> bool {
+        is_written(&self.written, index)
+    }
+
+    /// Fetch the number of written blocks
+    pub fn count(&self) -> usize {
+        self.written.iter().filter(|w| w.load(Ordering::Relaxed)).count()
+    }
+
+    /// Clear all written blocks
+    pub fn clear(&self) {
+        for w in &self.written {
+            w.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Fetch the underlying map for attachment to a file
+    pub(crate) fn as_slice(&self) -> &[AtomicBool] {
+        &self.written
+    }
+}
+
+impl <const N: usize> Default for WriteMap<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Virgin block policy and write map attached to a file
+pub(crate) struct Virgin<'a> {
+    pub policy: VirginPolicy,
+    pub written: &'a [AtomicBool],
+}
+
+impl <'a> Virgin<'a> {
+    /// Fetch the fill byte for a block, or `None` if the block should be
+    /// served from the backend
+    pub fn fill(&self, index: usize) -> Option<u8> {
+        if is_written(self.written, index) {
+            return None;
+        }
+
+        match self.policy {
+            VirginPolicy::Zeros => Some(0),
+            VirginPolicy::Fill(b) => Some(b),
+            VirginPolicy::Backend => None,
+        }
+    }
+
+    /// Mark a block as written
+    pub fn mark(&self, index: usize) {
+        mark(self.written, index)
+    }
+}
+
+fn mark(written: &[AtomicBool], index: usize) {
+    if let Some(w) = written.get(index) {
+        w.store(true, Ordering::Relaxed);
+    }
+}
+
+fn is_written(written: &[AtomicBool], index: usize) -> bool {
+    written.get(index).map(|w| w.load(Ordering::Relaxed)).unwrap_or(true)
+}
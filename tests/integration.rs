@@ -1,141 +1,24 @@
-use std::io::{Read, Seek, Write, SeekFrom};
-use std::sync::{Arc, Mutex};
-use log::{trace, debug, info};
+use std::io::{Read, Seek, Write};
 
-use simplelog::{SimpleLogger, LevelFilter, Config as LogConfig};
+use simplelog::{LevelFilter, Config as LogConfig};
 
 use fatfs::{FsOptions, FatType};
-use usbd_scsi::BlockDevice;
+use ghostfat::{GhostFat, GhostFatIo, File, Config};
 
-use ghostfat::{GhostFat, File, Config};
-
-/// Mock disk implementation for fatfs support
-pub struct MockDisk<'a> {
-    pub index: usize,
-    pub disk: GhostFat<'a>,
-}
-
-// TODO: read/write do not yet handle multiple blocks
-
-impl <'a> Read for MockDisk<'a> {
-    fn read(&mut self, buff: &mut [u8]) -> std::io::Result<usize> {
-        // Map block to index and buff len
-        let mut lba = self.index as u32 / 512;
-        let offset = self.index as usize % 512;
-
-        let mut block = [0u8; 512];
-        let mut index = 0;
-
-        // If we're offset and reading > 1 block, handle partial block first
-        if offset > 0 && buff.len() > (512 - offset) {
-            trace!("Read offset chunk lba: {} offset: {} len: {}", lba, offset, 512-offset);
-
-            // Read entire block
-            self.disk.read_block(lba, &mut block).unwrap();
-
-            // Copy offset portion
-            buff[..512 - offset].copy_from_slice(&block[offset..]);
-
-            // Update indexes
-            index += 512 - offset;
-            lba += 1;
-        }
-
-        // Then read remaining aligned blocks
-        for c in (&mut buff[index..]).chunks_mut(512) {
-            // Read whole block
-            self.disk.read_block(lba, &mut block).unwrap();
-
-            // Copy back requested chunk
-            // Note offset can only be < BLOCK_SIZE when there's only one chunk
-            c.copy_from_slice(&block[offset..][..c.len()]);
-
-            // Update indexes
-            index += c.len();
-            lba += 1;
-        }
-        
-        trace!("Read {} bytes at index 0x{:02x} (lba: {} offset: 0x{:02x}), data: {:02x?}", buff.len(), self.index, lba, offset, buff);
-
-        // Increment index
-        self.index += buff.len();
-
-        Ok(buff.len())
-    }
-}
-
-impl <'a> Write for MockDisk<'a> {
-    fn write(&mut self, buff: &[u8]) -> std::io::Result<usize> {
-
-        // Map block to index and buff len
-        let lba = self.index as u32 / 512;
-        let offset = self.index as usize % 512;
-
-        trace!("Write {} bytes at index: 0x{:02x} (lba: {} offset: 0x{:02x}): data: {:02x?}", buff.len(), self.index, lba, offset, buff);
-
-
-        {
-            // Read whole block
-            let mut block = [0u8; 512];
-            self.disk.read_block(lba, &mut block).unwrap();
-
-            // Apply write to block
-            block[offset..][..buff.len()].copy_from_slice(buff);
-
-            // Write whole block
-            self.disk.write_block(lba, &block).unwrap();
-        }
-
-        #[cfg(nope)]
-        // Direct write to provide more information in tests
-        d.write(self.index as u32, buff).unwrap();
-
-        // Increment index
-        self.index += buff.len();
-
-        Ok(buff.len())
-    }
-
-    fn flush(&mut self) -> std::io::Result<()> {
-        // No flush required as we're immediately writing back
-        Ok(())
-    }
-}
-
-impl <'a> Seek for MockDisk<'a> {
-    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
-        // Handle seek mechanisms
-        match pos {
-            SeekFrom::Start(v) => self.index = v as usize,
-            SeekFrom::End(v) => {
-                todo!("Work out how long the disk is...");
-            },
-            SeekFrom::Current(v) => self.index = (self.index as i64 + v) as usize,
-        }
-
-        Ok(self.index as u64)
-    }
-}
-
-fn setup<'a>(files: &'a mut [File<'a>]) -> MockDisk<'a> {
+fn setup<'a>(files: &'a mut [File<'a>]) -> GhostFatIo<'a> {
     let _ = simplelog::TermLogger::init(LevelFilter::Info, LogConfig::default(), simplelog::TerminalMode::Mixed, simplelog::ColorChoice::Auto);
 
     let ghost_fat = GhostFat::new(files, Config::default());
 
-    // Setup mock disk for fatfs
-    let disk = MockDisk{
-        index: 0,
-        disk: ghost_fat,
-    };
-
-    disk
+    // Setup stream adapter for fatfs
+    GhostFatIo::new(ghost_fat)
 }
 
 fn read_file<const N: usize>() {
     // Setup data
     let mut data = [0u8; N];
-    for i in 0..data.len() {
-        data[i] = rand::random::<u8>();
+    for d in data.iter_mut() {
+        *d = rand::random::<u8>();
     }
 
     // GhostFAT files
@@ -164,7 +47,7 @@ fn read_file<const N: usize>() {
     let mut v0 = Vec::new();
     f0.read_to_end(&mut v0).unwrap();
 
-    assert_eq!(v0.as_slice() == data, true);
+    assert_eq!(v0.as_slice(), data);
 }
 
 #[test]
@@ -186,8 +69,8 @@ fn write_file<const N: usize>() {
 
     // Generate initial data
     let mut data = [0u8; N];
-    for i in 0..data.len() {
-        data[i] = rand::random::<u8>();
+    for d in data.iter_mut() {
+        *d = rand::random::<u8>();
     }
 
     // Setup GhostFAT
@@ -212,15 +95,15 @@ fn write_file<const N: usize>() {
 
     // Generate new data
     let mut d1 = [0u8; N];
-    for i in 0..d1.len() {
-        d1[i] = rand::random::<u8>();
+    for d in d1.iter_mut() {
+        *d = rand::random::<u8>();
     }
 
     // Rewind and write data
     let mut f0 = f[0].to_file();
-    f0.rewind();
+    f0.rewind().unwrap();
     f0.write_all(&d1).unwrap();
-    f0.flush();
+    f0.flush().unwrap();
     drop(f0);
 
     // Read back written data